@@ -87,6 +87,9 @@ export default defineConfig({
               { text: "mabo check", link: "/reference/cli/check" },
               { text: "mabo fmt", link: "/reference/cli/fmt" },
               { text: "mabo doc", link: "/reference/cli/doc" },
+              { text: "mabo diff", link: "/reference/cli/diff" },
+              { text: "mabo decode", link: "/reference/cli/decode" },
+              { text: "mabo encode", link: "/reference/cli/encode" },
             ],
           },
           {
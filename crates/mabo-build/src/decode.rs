@@ -4,48 +4,75 @@ use mabo_compiler::simplify::{
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::quote;
 
-use crate::{BytesType, Opts};
+use crate::{BytesType, Opts, RefType};
 
 pub(super) fn compile_struct(
     opts: &Opts,
     Struct {
+        attributes,
         name,
         generics,
         fields,
         ..
     }: &Struct<'_>,
 ) -> TokenStream {
+    let allow_deprecated = super::definition::allow_deprecated_struct(attributes, fields);
+    let (impl_generics, generics, generics_where) = compile_generics(opts, name, generics);
+    let validate = opts.decode_validation && opts.validated(name);
+    let ty = proc_macro2::Literal::string(name);
     let name = Ident::new(name, Span::call_site());
-    let (generics, generics_where) = compile_generics(generics);
+    let unknown = opts.unknown_fields && fields.kind == FieldKind::Named;
+    let unknown_allow = unknown.then(|| quote! { #[allow(clippy::used_underscore_binding)] });
     let field_vars = compile_field_vars(opts, &fields.fields);
-    let field_matches = compile_field_matches(opts, fields);
-    let field_assigns = compile_field_assigns(fields);
+    let field_matches = compile_field_matches(opts, fields, &TokenStream::new());
+    let field_assigns = compile_field_assigns(fields, unknown, &TokenStream::new());
+    let construct = compile_construct(validate, &quote! { Self #field_assigns });
 
     let body = if fields.kind == FieldKind::Unit {
         quote! { Ok(Self) }
     } else {
+        let (unknown_var, skip) = if unknown {
+            (
+                quote! { let mut _unknown_fields = ::mabo::UnknownFields::default(); },
+                quote! { ::mabo::buf::decode_unknown(r, id, &mut _unknown_fields)? },
+            )
+        } else {
+            (
+                TokenStream::new(),
+                quote! { ::mabo::buf::decode_skip(r, id.encoding)? },
+            )
+        };
+
         quote! {
             #field_vars
+            #unknown_var
 
             loop {
                 let id = ::mabo::buf::decode_id(r)?;
                 match id.value {
                     ::mabo::buf::END_MARKER => break,
                     #field_matches
-                    _ => ::mabo::buf::decode_skip(r, id.encoding)?,
+                    _ => #skip,
                 }
             }
 
-            Ok(Self #field_assigns)
+            #construct
         }
     };
 
+    let (decode_trait, decode_fn) = compile_decode_signature(opts);
+
     quote! {
         #[automatically_derived]
-        impl #generics ::mabo::Decode for #name #generics #generics_where {
+        #allow_deprecated
+        impl #impl_generics #decode_trait for #name #generics #generics_where {
             #[allow(clippy::type_complexity, clippy::too_many_lines)]
-            fn decode(r: &mut impl ::mabo::Buf) -> ::mabo::buf::Result<Self> {
-                #body
+            #unknown_allow
+            #decode_fn {
+                ::mabo::buf::decode_type(r, #ty, |r| {
+                    let _nested = ::mabo::buf::enter_nested()?;
+                    #body
+                })
             }
         }
     }
@@ -54,25 +81,34 @@ pub(super) fn compile_struct(
 pub(super) fn compile_enum(
     opts: &Opts,
     Enum {
+        attributes,
         name,
         generics,
         variants,
         ..
     }: &Enum<'_>,
 ) -> TokenStream {
+    let allow_deprecated = super::definition::allow_deprecated_enum(attributes, variants);
+    let (impl_generics, generics, generics_where) = compile_generics(opts, name, generics);
+    let validate = opts.decode_validation && opts.validated(name);
+    let ty = proc_macro2::Literal::string(name);
     let name = Ident::new(name, Span::call_site());
-    let (generics, generics_where) = compile_generics(generics);
-    let variants = variants.iter().map(|v| compile_variant(opts, v));
+    let variants = variants.iter().map(|v| compile_variant(opts, validate, v));
+    let (decode_trait, decode_fn) = compile_decode_signature(opts);
 
     quote! {
         #[automatically_derived]
-        impl #generics ::mabo::Decode for #name #generics #generics_where {
+        #allow_deprecated
+        impl #impl_generics #decode_trait for #name #generics #generics_where {
             #[allow(clippy::too_many_lines)]
-            fn decode(r: &mut impl ::mabo::Buf) -> ::mabo::buf::Result<Self> {
-                match ::mabo::buf::decode_variant_id(r)?.value {
-                    #(#variants,)*
-                    id => Err(::mabo::buf::Error::UnknownVariant(id)),
-                }
+            #decode_fn {
+                ::mabo::buf::decode_type(r, #ty, |r| {
+                    let _nested = ::mabo::buf::enter_nested()?;
+                    match ::mabo::buf::decode_variant_id(r)?.value {
+                        #(#variants,)*
+                        id => Err(::mabo::buf::Error::UnknownVariant(id)),
+                    }
+                })
             }
         }
     }
@@ -80,6 +116,7 @@ pub(super) fn compile_enum(
 
 fn compile_variant(
     opts: &Opts,
+    validate: bool,
     Variant {
         comment: _,
         name,
@@ -89,10 +126,13 @@ fn compile_variant(
     }: &Variant<'_>,
 ) -> TokenStream {
     let id = proc_macro2::Literal::u32_unsuffixed(*id);
+    let within = proc_macro2::Literal::string(name);
+    let within = quote! { .within(::mabo::buf::PathSegment::Variant(#within)) };
     let name = Ident::new(name, Span::call_site());
     let field_vars = compile_field_vars(opts, &fields.fields);
-    let field_matches = compile_field_matches(opts, fields);
-    let field_assigns = compile_field_assigns(fields);
+    let field_matches = compile_field_matches(opts, fields, &within);
+    let field_assigns = compile_field_assigns(fields, false, &within);
+    let construct = compile_construct(validate, &quote! { Self::#name #field_assigns });
 
     if fields.kind == FieldKind::Unit {
         quote! { #id => Ok(Self::#name) }
@@ -110,7 +150,7 @@ fn compile_variant(
                     }
                 }
 
-                Ok(Self::#name #field_assigns)
+                #construct
             }
         }
     }
@@ -135,19 +175,35 @@ fn compile_field_vars(opts: &Opts, fields: &[Field<'_>]) -> TokenStream {
     quote! { #(#vars)* }
 }
 
-fn compile_field_matches(opts: &Opts, fields: &Fields<'_>) -> TokenStream {
-    let calls = fields.fields.iter().map(|Field { name, ty, id, .. }| {
-        let id = proc_macro2::Literal::u32_unsuffixed(*id);
-        let name = proc_macro2::Ident::new(name, Span::call_site());
-        let ty = compile_data_type(opts, if let Type::Option(ty) = &ty { ty } else { ty }, true);
+/// Generate the decoding of each field, that attaches the field name to any error. The `within`
+/// suffix allows to extend the path of the error further, like adding the variant name for enums.
+fn compile_field_matches(opts: &Opts, fields: &Fields<'_>, within: &TokenStream) -> TokenStream {
+    let calls = fields
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, Field { name, ty, id, .. })| {
+            let path = if fields.kind == FieldKind::Named {
+                proc_macro2::Literal::string(name)
+            } else {
+                proc_macro2::Literal::string(&i.to_string())
+            };
+            let id = proc_macro2::Literal::u32_unsuffixed(*id);
+            let name = proc_macro2::Ident::new(name, Span::call_site());
+            let ty =
+                compile_data_type(opts, if let Type::Option(ty) = &ty { ty } else { ty }, true);
 
-        quote! { #id => #name = Some(#ty?) }
-    });
+            quote! {
+                #id => #name = Some(
+                    #ty.map_err(|e| e.within(::mabo::buf::PathSegment::Field(#path)) #within)?
+                )
+            }
+        });
 
     quote! { #(#calls,)* }
 }
 
-fn compile_field_assigns(fields: &Fields<'_>) -> TokenStream {
+fn compile_field_assigns(fields: &Fields<'_>, unknown: bool, within: &TokenStream) -> TokenStream {
     let assigns = fields.fields.iter().map(|Field { name, ty, id, .. }| {
         let name_lit = if fields.kind == FieldKind::Named {
             let lit = proc_macro2::Literal::string(name);
@@ -157,6 +213,7 @@ fn compile_field_assigns(fields: &Fields<'_>) -> TokenStream {
         };
         let name = Ident::new(name, Span::call_site());
         let id = proc_macro2::Literal::u32_unsuffixed(*id);
+        let within = (!within.is_empty()).then(|| quote! { .map_err(|e| e #within) });
 
         if matches!(ty, Type::Option(_)) {
             quote! { #name }
@@ -165,41 +222,93 @@ fn compile_field_assigns(fields: &Fields<'_>) -> TokenStream {
                 #name: #name.ok_or(::mabo::buf::Error::MissingField {
                     id: #id,
                     name: #name_lit,
-                })?
+                })#within?
             }
         } else {
             quote! {
                 #name.ok_or(::mabo::buf::Error::MissingField {
                    id: #id,
                    name: #name_lit,
-               })?
+               })#within?
             }
         }
     });
 
     if fields.kind == FieldKind::Named {
-        quote! { { #(#assigns,)* } }
+        let unknown = unknown.then(|| quote! { _unknown_fields, });
+        quote! { { #(#assigns,)* #unknown } }
     } else {
         quote! { (#(#assigns,)*) }
     }
 }
 
-fn compile_generics(types: &[&str]) -> (TokenStream, TokenStream) {
-    (!types.is_empty())
+/// Generate the final construction of the decoded value, which is validated first if requested
+/// and the type has any rules.
+fn compile_construct(validate: bool, value: &TokenStream) -> TokenStream {
+    if validate {
+        quote! {
+            let value = #value;
+            ::mabo::Validate::validate(&value)?;
+            Ok(value)
+        }
+    } else {
+        quote! { Ok(#value) }
+    }
+}
+
+/// Generate the implemented trait and function signature for decoding, which depends on whether
+/// reference types are borrowed from the input buffer.
+fn compile_decode_signature(opts: &Opts) -> (TokenStream, TokenStream) {
+    if opts.ref_type == RefType::Borrowed {
+        (
+            quote! { ::mabo::buf::DecodeBorrowed<'de> },
+            quote! { fn decode_borrowed(r: &mut &'de [u8]) -> ::mabo::buf::Result<Self> },
+        )
+    } else {
+        (
+            quote! { ::mabo::Decode },
+            quote! { fn decode(r: &mut impl ::mabo::Buf) -> ::mabo::buf::Result<Self> },
+        )
+    }
+}
+
+/// Generate the generics for the `impl` block, the type itself and the where clause.
+fn compile_generics(
+    opts: &Opts,
+    name: &str,
+    types: &[&str],
+) -> (TokenStream, TokenStream, TokenStream) {
+    let borrowed = opts.ref_type == RefType::Borrowed;
+    let impl_generics = super::definition::compile_generics(types, borrowed).unwrap_or_default();
+    let generics = super::definition::compile_generics(types, opts.borrowed.contains(name))
+        .unwrap_or_default();
+    let generics_where = (!types.is_empty())
         .then(|| {
             let types = types.iter().map(|ty| Ident::new(ty, Span::call_site()));
-            let types2 = types.clone();
-
-            (
-                quote! { <#(#types,)*> },
-                quote! { where #(#types2: ::std::fmt::Debug + ::mabo::buf::Decode,)* },
-            )
+            let validate = (opts.decode_validation && opts.validated(name))
+                .then(|| quote! { + ::mabo::Validate });
+            let core = opts.core();
+            if borrowed {
+                quote! {
+                    where #(#types: #core::fmt::Debug + ::mabo::buf::DecodeBorrowed<'de> #validate,)*
+                }
+            } else {
+                quote! { where #(#types: #core::fmt::Debug + ::mabo::buf::Decode #validate,)* }
+            }
         })
-        .unwrap_or_default()
+        .unwrap_or_default();
+
+    (impl_generics, generics, generics_where)
 }
 
 #[allow(clippy::needless_pass_by_value, clippy::too_many_lines)]
 fn compile_data_type(opts: &Opts, ty: &Type<'_>, root: bool) -> TokenStream {
+    if opts.ref_type == RefType::Borrowed {
+        if let Some(ty) = compile_data_type_borrowed(opts, ty) {
+            return ty;
+        }
+    }
+
     match ty {
         Type::Bool => quote! { ::mabo::buf::decode_bool(r) },
         Type::U8 => quote! { ::mabo::buf::decode_u8(r) },
@@ -232,6 +341,15 @@ fn compile_data_type(opts: &Opts, ty: &Type<'_>, root: bool) -> TokenStream {
             let ty = compile_data_type(opts, ty, false);
             quote! { ::mabo::buf::decode_hash_set(r, |r| { #ty }) }
         }
+        Type::BTreeMap(kv) => {
+            let ty_k = compile_data_type(opts, &kv.0, false);
+            let ty_v = compile_data_type(opts, &kv.1, false);
+            quote! { ::mabo::buf::decode_btree_map(r, |r| { #ty_k }, |r| { #ty_v }) }
+        }
+        Type::BTreeSet(ty) => {
+            let ty = compile_data_type(opts, ty, false);
+            quote! { ::mabo::buf::decode_btree_set(r, |r| { #ty }) }
+        }
         Type::Option(ty) => {
             let ty = compile_data_type(opts, ty, false);
             quote! { ::mabo::buf::decode_option(r, |r| { #ty }) }
@@ -271,10 +389,25 @@ fn compile_data_type(opts: &Opts, ty: &Type<'_>, root: bool) -> TokenStream {
                 let ty = compile_data_type(opts, ty, false);
                 quote! { ::mabo::buf::decode_non_zero_hash_set(r, |r| { #ty }) }
             }
+            Type::BTreeMap(kv) => {
+                let ty_k = compile_data_type(opts, &kv.0, false);
+                let ty_v = compile_data_type(opts, &kv.1, false);
+                quote! { ::mabo::buf::decode_non_zero_btree_map(r, |r| { #ty_k }, |r| { #ty_v }) }
+            }
+            Type::BTreeSet(ty) => {
+                let ty = compile_data_type(opts, ty, false);
+                quote! { ::mabo::buf::decode_non_zero_btree_set(r, |r| { #ty }) }
+            }
             ty => todo!("compiler should catch invalid {ty:?} type"),
         },
-        Type::BoxString => quote! { Box::<str>::decode(r) },
-        Type::BoxBytes => quote! { Box::<[u8]>::decode(r) },
+        Type::BoxString => {
+            let boxed = opts.boxed();
+            quote! { #boxed::<str>::decode(r) }
+        }
+        Type::BoxBytes => {
+            let boxed = opts.boxed();
+            quote! { #boxed::<[u8]>::decode(r) }
+        }
         Type::Tuple(types) => match types.len() {
             2..=12 => {
                 let types = types.iter().map(|ty| compile_data_type(opts, ty, false));
@@ -307,3 +440,72 @@ fn compile_data_type(opts: &Opts, ty: &Type<'_>, root: bool) -> TokenStream {
         }
     }
 }
+
+/// Generate the decoding logic for types that differ when borrowing from the input buffer. Any
+/// other types are decoded the same as in owned mode.
+fn compile_data_type_borrowed(opts: &Opts, ty: &Type<'_>) -> Option<TokenStream> {
+    Some(match ty {
+        Type::StringRef => quote! { ::mabo::buf::decode_string_borrowed(r) },
+        Type::BytesRef => quote! { ::mabo::buf::decode_bytes_borrowed(r) },
+        Type::Vec(ty) => {
+            let ty = compile_data_type(opts, ty, false);
+            quote! { ::mabo::buf::decode_vec_borrowed(r, |r| { #ty }) }
+        }
+        Type::HashMap(kv) => {
+            let ty_k = compile_data_type(opts, &kv.0, false);
+            let ty_v = compile_data_type(opts, &kv.1, false);
+            quote! { ::mabo::buf::decode_hash_map_borrowed(r, |r| { #ty_k }, |r| { #ty_v }) }
+        }
+        Type::HashSet(ty) => {
+            let ty = compile_data_type(opts, ty, false);
+            quote! { ::mabo::buf::decode_hash_set_borrowed(r, |r| { #ty }) }
+        }
+        Type::BTreeMap(kv) => {
+            let ty_k = compile_data_type(opts, &kv.0, false);
+            let ty_v = compile_data_type(opts, &kv.1, false);
+            quote! { ::mabo::buf::decode_btree_map_borrowed(r, |r| { #ty_k }, |r| { #ty_v }) }
+        }
+        Type::BTreeSet(ty) => {
+            let ty = compile_data_type(opts, ty, false);
+            quote! { ::mabo::buf::decode_btree_set_borrowed(r, |r| { #ty }) }
+        }
+        Type::NonZero(ty) => match &**ty {
+            Type::Vec(ty) => {
+                let ty = compile_data_type(opts, ty, false);
+                quote! { ::mabo::buf::decode_non_zero_vec_borrowed(r, |r| { #ty }) }
+            }
+            Type::HashMap(kv) => {
+                let ty_k = compile_data_type(opts, &kv.0, false);
+                let ty_v = compile_data_type(opts, &kv.1, false);
+                quote! {
+                    ::mabo::buf::decode_non_zero_hash_map_borrowed(r, |r| { #ty_k }, |r| { #ty_v })
+                }
+            }
+            Type::HashSet(ty) => {
+                let ty = compile_data_type(opts, ty, false);
+                quote! { ::mabo::buf::decode_non_zero_hash_set_borrowed(r, |r| { #ty }) }
+            }
+            Type::BTreeMap(kv) => {
+                let ty_k = compile_data_type(opts, &kv.0, false);
+                let ty_v = compile_data_type(opts, &kv.1, false);
+                quote! {
+                    ::mabo::buf::decode_non_zero_btree_map_borrowed(r, |r| { #ty_k }, |r| { #ty_v })
+                }
+            }
+            Type::BTreeSet(ty) => {
+                let ty = compile_data_type(opts, ty, false);
+                quote! { ::mabo::buf::decode_non_zero_btree_set_borrowed(r, |r| { #ty }) }
+            }
+            _ => return None,
+        },
+        Type::Array(ty, _size) => {
+            let ty = compile_data_type(opts, ty, false);
+            quote! { ::mabo::buf::decode_array_borrowed(r, |r| { #ty }) }
+        }
+        Type::External(_) => {
+            let ty = super::definition::compile_data_type(opts, ty);
+            quote! { <#ty as ::mabo::buf::DecodeBorrowed<'de>>::decode_borrowed(r) }
+        }
+        _ => return None,
+    })
+}
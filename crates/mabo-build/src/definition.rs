@@ -1,16 +1,40 @@
+use std::collections::HashSet;
+
 use mabo_compiler::simplify::{
-    Const, Definition, Enum, ExternalType, Field, FieldKind, Fields, Import, Literal, Module,
-    Schema, Struct, Type, TypeAlias, Variant,
+    Attributes, Const, Definition, Deprecated, Enum, ExternalType, Field, FieldKind, Fields,
+    Import, Literal, Module, Schema, Struct, Type, TypeAlias, Variant,
 };
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::{quote, ToTokens};
 
-use super::{decode, encode, size};
-use crate::{BytesType, Opts};
+use super::{decode, encode, size, tree, validate};
+use crate::{BytesType, EnumTagging, Opts, RefType};
 
 /// Take a single schema and convert it into Rust source code.
 #[must_use]
-pub fn compile_schema(opts: &Opts, Schema { definitions, .. }: &Schema<'_>) -> TokenStream {
+pub fn compile_schema(
+    opts: &Opts,
+    Schema {
+        source,
+        definitions,
+        ..
+    }: &Schema<'_>,
+) -> TokenStream {
+    let opts = &Opts {
+        borrowed: find_borrowed(opts, definitions),
+        unvalidated: Some(
+            opts.unvalidated
+                .clone()
+                .unwrap_or_else(|| validate::find_unvalidated(&[definitions])),
+        ),
+        path: source
+            .path
+            .as_deref()
+            .and_then(|path| path.file_stem()?.to_str())
+            .map(|stem| vec![stem.to_owned()])
+            .unwrap_or_default(),
+        ..opts.clone()
+    };
     let definitions = definitions.iter().map(|def| compile_definition(opts, def));
 
     quote! {
@@ -21,6 +45,125 @@ pub fn compile_schema(opts: &Opts, Schema { definitions, .. }: &Schema<'_>) -> T
     }
 }
 
+/// Generate only the `Encode` and `Size` implementations for all structs and enums of a schema,
+/// for types that are defined elsewhere, like through derive macros.
+#[must_use]
+pub fn compile_encode(opts: &Opts, Schema { definitions, .. }: &Schema<'_>) -> TokenStream {
+    let impls = definitions.iter().map(|definition| match definition {
+        Definition::Struct(s) => {
+            let encode = encode::compile_struct(opts, s);
+            let size = size::compile_struct(opts, s);
+            quote! { #encode #size }
+        }
+        Definition::Enum(e) => {
+            let encode = encode::compile_enum(opts, e);
+            let size = size::compile_enum(opts, e);
+            quote! { #encode #size }
+        }
+        _ => TokenStream::new(),
+    });
+
+    // The implementations rely on the traits being in scope, which they can't expect from the
+    // surrounding code, so they are placed in their own scope.
+    quote! {
+        const _: () = {
+            #[allow(unused_imports)]
+            use ::mabo::buf::{Decode, Encode, Size};
+
+            #(#impls)*
+        };
+    }
+}
+
+/// Generate only the `Decode` implementations for all structs and enums of a schema, for types
+/// that are defined elsewhere, like through derive macros.
+#[must_use]
+pub fn compile_decode(opts: &Opts, Schema { definitions, .. }: &Schema<'_>) -> TokenStream {
+    let impls = definitions.iter().map(|definition| match definition {
+        Definition::Struct(s) => decode::compile_struct(opts, s),
+        Definition::Enum(e) => decode::compile_enum(opts, e),
+        _ => TokenStream::new(),
+    });
+
+    quote! {
+        const _: () = {
+            #[allow(unused_imports)]
+            use ::mabo::buf::{Decode, Encode, Size};
+
+            #(#impls)*
+        };
+    }
+}
+
+/// Collect the names of all types that borrow from the input buffer, either directly through
+/// reference types or through other borrowing types of the same schema.
+fn find_borrowed(opts: &Opts, definitions: &[Definition<'_>]) -> HashSet<String> {
+    let mut borrowed = HashSet::new();
+
+    if opts.ref_type == RefType::Borrowed {
+        loop {
+            let count = borrowed.len();
+            collect_borrowed(definitions, &mut borrowed);
+
+            if borrowed.len() == count {
+                break;
+            }
+        }
+    }
+
+    borrowed
+}
+
+fn collect_borrowed(definitions: &[Definition<'_>], borrowed: &mut HashSet<String>) {
+    for definition in definitions {
+        let (name, borrows) = match definition {
+            Definition::Module(m) => {
+                collect_borrowed(&m.definitions, borrowed);
+                continue;
+            }
+            Definition::Struct(s) => (
+                s.name,
+                s.fields.fields.iter().any(|f| is_borrowed(borrowed, &f.ty)),
+            ),
+            Definition::Enum(e) => (
+                e.name,
+                e.variants
+                    .iter()
+                    .flat_map(|v| v.fields.fields.iter())
+                    .any(|f| is_borrowed(borrowed, &f.ty)),
+            ),
+            Definition::TypeAlias(a) => (a.name, is_borrowed(borrowed, &a.target)),
+            Definition::Const(_) | Definition::Import(_) => continue,
+        };
+
+        if borrows {
+            borrowed.insert(name.to_owned());
+        }
+    }
+}
+
+fn is_borrowed(borrowed: &HashSet<String>, ty: &Type<'_>) -> bool {
+    match ty {
+        Type::StringRef | Type::BytesRef => true,
+        Type::Vec(ty)
+        | Type::HashSet(ty)
+        | Type::BTreeSet(ty)
+        | Type::Option(ty)
+        | Type::Array(ty, _) => is_borrowed(borrowed, ty),
+        Type::HashMap(kv) | Type::BTreeMap(kv) => {
+            is_borrowed(borrowed, &kv.0) || is_borrowed(borrowed, &kv.1)
+        }
+        Type::NonZero(ty) => {
+            !matches!(**ty, Type::StringRef | Type::BytesRef) && is_borrowed(borrowed, ty)
+        }
+        Type::Tuple(types) => types.iter().any(|ty| is_borrowed(borrowed, ty)),
+        Type::External(ExternalType { name, generics, .. }) => {
+            borrowed.contains(*name) || generics.iter().any(|ty| is_borrowed(borrowed, ty))
+        }
+        _ => false,
+    }
+}
+
 fn compile_definition(opts: &Opts, definition: &Definition<'_>) -> TokenStream {
     match definition {
         Definition::Module(m) => compile_module(opts, m),
@@ -29,12 +172,14 @@ fn compile_definition(opts: &Opts, definition: &Definition<'_>) -> TokenStream {
             let encode = encode::compile_struct(opts, s);
             let decode = decode::compile_struct(opts, s);
             let size = size::compile_struct(opts, s);
+            let validate = validate::compile_struct(opts, s);
 
             quote! {
                 #def
                 #encode
                 #decode
                 #size
+                #validate
             }
         }
         Definition::Enum(e) => {
@@ -42,17 +187,19 @@ fn compile_definition(opts: &Opts, definition: &Definition<'_>) -> TokenStream {
             let encode = encode::compile_enum(opts, e);
             let decode = decode::compile_enum(opts, e);
             let size = size::compile_enum(opts, e);
+            let validate = validate::compile_enum(opts, e);
 
             quote! {
                 #def
                 #encode
                 #decode
                 #size
+                #validate
             }
         }
         Definition::TypeAlias(a) => compile_alias(opts, a),
         Definition::Const(c) => compile_const(c),
-        Definition::Import(i) => compile_import(i),
+        Definition::Import(i) => compile_import(opts, i),
     }
 }
 
@@ -66,6 +213,15 @@ fn compile_module(
     }: &Module<'_>,
 ) -> TokenStream {
     let comment = compile_comment(comment);
+    let opts = &Opts {
+        path: opts
+            .path
+            .iter()
+            .cloned()
+            .chain([(*name).to_owned()])
+            .collect(),
+        ..opts.clone()
+    };
     let name = Ident::new(name, Span::call_site());
     let definitions = definitions.iter().map(|def| compile_definition(opts, def));
 
@@ -84,6 +240,7 @@ fn compile_struct(
     opts: &Opts,
     Struct {
         comment,
+        attributes,
         name,
         generics,
         fields,
@@ -91,15 +248,37 @@ fn compile_struct(
     }: &Struct<'_>,
 ) -> TokenStream {
     let comment = compile_comment(comment);
+    let deprecated = compile_deprecated(attributes);
+    let (serde_derives, serde) = compile_serde(opts, name, None);
+    let (derives, custom) = compile_custom_attributes(opts, name);
+    let generics = compile_generics(generics, opts.borrowed.contains(*name));
     let name = Ident::new(name, Span::call_site());
-    let generics = compile_generics(generics);
     let semicolon = (fields.kind != FieldKind::Named).then_some(quote! {;});
-    let fields = compile_fields(opts, fields, true);
+    let fields = if opts.unknown_fields && fields.kind == FieldKind::Named {
+        let skip = opts.serde.then(|| quote! { #[serde(skip)] });
+        let fields = fields
+            .fields
+            .iter()
+            .map(|field| compile_field(opts, field, true, true));
+        quote! {
+            {
+                #(#fields,)*
+                #[doc(hidden)]
+                #skip
+                pub _unknown_fields: ::mabo::UnknownFields,
+            }
+        }
+    } else {
+        compile_fields(opts, fields, true)
+    };
 
     quote! {
         #comment
-        #[derive(Clone, Debug, PartialEq)]
+        #deprecated
+        #[derive(Clone, Debug, PartialEq #(, #serde_derives)* #(, #derives)*)]
         #[allow(clippy::module_name_repetitions, clippy::option_option)]
+        #serde
+        #custom
         pub struct #name #generics #fields #semicolon
     }
 }
@@ -108,6 +287,7 @@ fn compile_enum(
     opts: &Opts,
     Enum {
         comment,
+        attributes,
         name,
         generics,
         variants,
@@ -115,14 +295,20 @@ fn compile_enum(
     }: &Enum<'_>,
 ) -> TokenStream {
     let comment = compile_comment(comment);
+    let deprecated = compile_deprecated(attributes);
+    let (serde_derives, serde) = compile_serde(opts, name, Some(&opts.enum_tagging));
+    let (derives, custom) = compile_custom_attributes(opts, name);
+    let generics = compile_generics(generics, opts.borrowed.contains(*name));
     let name = Ident::new(name, Span::call_site());
-    let generics = compile_generics(generics);
     let variants = variants.iter().map(|v| compile_variant(opts, v));
 
     quote! {
         #comment
-        #[derive(Clone, Debug, PartialEq)]
+        #deprecated
+        #[derive(Clone, Debug, PartialEq #(, #serde_derives)* #(, #derives)*)]
         #[allow(clippy::module_name_repetitions, clippy::option_option)]
+        #serde
+        #custom
         pub enum #name #generics {
             #(#variants,)*
         }
@@ -133,17 +319,20 @@ fn compile_variant(
     opts: &Opts,
     Variant {
         comment,
+        attributes,
         name,
         fields,
         ..
     }: &Variant<'_>,
 ) -> TokenStream {
     let comment = compile_comment(comment);
+    let deprecated = compile_deprecated(attributes);
     let name = Ident::new(name, Span::call_site());
     let fields = compile_fields(opts, fields, false);
 
     quote! {
         #comment
+        #deprecated
         #name #fields
     }
 }
@@ -159,8 +348,8 @@ fn compile_alias(
     }: &TypeAlias<'_>,
 ) -> TokenStream {
     let comment = compile_comment(comment);
+    let generics = compile_generics(generics, opts.borrowed.contains(*name));
     let name = Ident::new(name, Span::call_site());
-    let generics = compile_generics(generics);
     let target = compile_data_type(opts, target);
 
     quote! {
@@ -192,18 +381,13 @@ fn compile_const(
 }
 
 fn compile_import(
+    opts: &Opts,
     Import {
         segments, element, ..
     }: &Import<'_>,
 ) -> TokenStream {
-    let segments = segments.iter().enumerate().map(|(i, segment)| {
-        let segment = Ident::new(segment, Span::call_site());
-        if i > 0 {
-            quote! {::#segment}
-        } else {
-            quote! {#segment}
-        }
-    });
+    let prefix = compile_import_prefix(opts, segments[0]);
+    let segments = segments.iter().map(|segment| tree::module_name(segment));
     let element = element.as_ref().map(|element| {
         let element = Ident::new(element, Span::call_site());
         quote! { ::#element}
@@ -211,43 +395,163 @@ fn compile_import(
 
     quote! {
         #[allow(unused_imports)]
-        use super::#(#segments)*#element;
+        use #prefix #(#segments)::* #element;
     }
 }
 
+/// Locate the imported schema in the module tree, which mirrors the directory layout of the
+/// project. Without any knowledge about the project, all schemas are assumed to be siblings.
+///
+/// If the schema name exists in several directories, the one next to the current schema is
+/// preferred. Any other ambiguous imports are already rejected while resolving the schemas.
+fn compile_import_prefix(opts: &Opts, schema: &str) -> TokenStream {
+    let Some(candidates) = opts.schemas.get(schema) else {
+        return quote! { super:: };
+    };
+
+    let directories = candidates
+        .iter()
+        .find(|directories| **directories == opts.directory)
+        .or(match candidates.as_slice() {
+            [only] => Some(only),
+            _ => None,
+        })
+        .expect("ambiguous imports are rejected during resolution")
+        .iter()
+        .map(|name| tree::module_name(name));
+    let supers = (0..opts.directory.len() + opts.path.len().max(1)).map(|_| quote! { super:: });
+
+    quote! { #(#supers)* #(#directories::)* }
+}
+
+/// Generate the `serde` derives and container attribute, if enabled. Types that borrow from the
+/// input buffer can only be serialized, as their `'de` lifetime collides with the one of `serde`.
+fn compile_serde(
+    opts: &Opts,
+    name: &str,
+    tagging: Option<&EnumTagging>,
+) -> (Vec<TokenStream>, Option<TokenStream>) {
+    if !opts.serde {
+        return (Vec::new(), None);
+    }
+
+    let mut derives = vec![quote! { ::mabo::serde::Serialize }];
+    if !opts.borrowed.contains(name) {
+        derives.push(quote! { ::mabo::serde::Deserialize });
+    }
+
+    let tagging = match tagging {
+        None | Some(EnumTagging::External) => None,
+        Some(EnumTagging::Internal { tag }) => Some(quote! { , tag = #tag }),
+        Some(EnumTagging::Adjacent { tag, content }) => {
+            Some(quote! { , tag = #tag, content = #content })
+        }
+        Some(EnumTagging::Untagged) => Some(quote! { , untagged }),
+    };
+
+    (
+        derives,
+        Some(quote! { #[serde(crate = "::mabo::serde" #tagging)] }),
+    )
+}
+
+/// Collect the custom derives and attributes that apply to the type with the given name. Derives
+/// that are already part of the default list are skipped.
+fn compile_custom_attributes(opts: &Opts, name: &str) -> (Vec<TokenStream>, TokenStream) {
+    let mut derives = Vec::<TokenStream>::new();
+
+    for (_, derive) in opts
+        .derives
+        .iter()
+        .filter(|(path, _)| matches_path(opts, path, name))
+    {
+        let text = derive.to_string();
+        if !["Clone", "Debug", "PartialEq"].contains(&text.as_str())
+            && !derives.iter().any(|d| d.to_string() == text)
+        {
+            derives.push(derive.clone());
+        }
+    }
+
+    let attributes = opts
+        .attributes
+        .iter()
+        .filter(|(path, _)| matches_path(opts, path, name))
+        .map(|(_, attribute)| attribute);
+
+    (derives, quote! { #(#attributes)* })
+}
+
+/// Whether the type path selects the type with the given name in the current module. The path
+/// selects all types within a schema or module if it stops at one, and all types if it's empty.
+pub(super) fn matches_path(opts: &Opts, path: &str, name: &str) -> bool {
+    if path.is_empty() {
+        return true;
+    }
+
+    let mut current = opts.path.iter().map(String::as_str).chain([name]);
+    path.split("::")
+        .all(|segment| current.next() == Some(segment))
+}
+
 fn compile_comment(lines: &[&str]) -> TokenStream {
     let lines = lines.iter().map(|line| format!(" {line}"));
     quote! { #(#[doc = #lines])* }
 }
 
-fn compile_generics(types: &[&str]) -> Option<TokenStream> {
-    (!types.is_empty()).then(|| {
-        let types = types.iter().map(|ty| Ident::new(ty, Span::call_site()));
-        quote! { <#(#types,)*> }
+fn compile_deprecated(attributes: &Attributes<'_>) -> Option<TokenStream> {
+    attributes.deprecated().map(|Deprecated { note }| {
+        note.map_or_else(
+            || quote! { #[deprecated] },
+            |note| quote! { #[deprecated(note = #note)] },
+        )
     })
 }
 
-fn compile_fields(opts: &Opts, fields: &Fields<'_>, for_struct: bool) -> TokenStream {
-    let values = fields.fields.iter().map(
-        |Field {
-             comment, name, ty, ..
-         }| {
-            let public = for_struct.then(|| quote! { pub });
-            let ty = compile_data_type(opts, ty);
+/// Allow the use of deprecated elements in the trait implementations of a struct, in case the
+/// struct itself or any of its fields is deprecated.
+pub(super) fn allow_deprecated_struct(
+    attributes: &Attributes<'_>,
+    fields: &Fields<'_>,
+) -> Option<TokenStream> {
+    (attributes.deprecated().is_some() || is_deprecated(fields))
+        .then(|| quote! { #[allow(deprecated)] })
+}
 
-            if fields.kind == FieldKind::Named {
-                let comment = compile_comment(comment);
-                let name = Ident::new(name, Span::call_site());
+/// Allow the use of deprecated elements in the trait implementations of an enum, in case the enum
+/// itself or any of its variants or their fields is deprecated.
+pub(super) fn allow_deprecated_enum(
+    attributes: &Attributes<'_>,
+    variants: &[Variant<'_>],
+) -> Option<TokenStream> {
+    (attributes.deprecated().is_some()
+        || variants.iter().any(|variant| {
+            variant.attributes.deprecated().is_some() || is_deprecated(&variant.fields)
+        }))
+    .then(|| quote! { #[allow(deprecated)] })
+}
 
-                quote! {
-                    #comment
-                    #public #name: #ty
-                }
-            } else {
-                quote! { #public #ty }
-            }
-        },
-    );
+fn is_deprecated(fields: &Fields<'_>) -> bool {
+    fields
+        .fields
+        .iter()
+        .any(|field| field.attributes.deprecated().is_some())
+}
+
+pub(super) fn compile_generics(types: &[&str], borrowed: bool) -> Option<TokenStream> {
+    (borrowed || !types.is_empty()).then(|| {
+        let lifetime = borrowed.then(|| quote! { 'de, });
+        let types = types.iter().map(|ty| Ident::new(ty, Span::call_site()));
+        quote! { <#lifetime #(#types,)*> }
+    })
+}
+
+fn compile_fields(opts: &Opts, fields: &Fields<'_>, for_struct: bool) -> TokenStream {
+    let named = fields.kind == FieldKind::Named;
+    let values = fields
+        .fields
+        .iter()
+        .map(|field| compile_field(opts, field, for_struct, named));
 
     match fields.kind {
         FieldKind::Named => quote! { {#(#values,)*} },
@@ -256,6 +560,37 @@ fn compile_fields(opts: &Opts, fields: &Fields<'_>, for_struct: bool) -> TokenSt
     }
 }
 
+fn compile_field(
+    opts: &Opts,
+    Field {
+        comment,
+        attributes,
+        name,
+        ty,
+        ..
+    }: &Field<'_>,
+    for_struct: bool,
+    named: bool,
+) -> TokenStream {
+    let public = for_struct.then(|| quote! { pub });
+    let ty = compile_data_type(opts, ty);
+
+    if named {
+        let comment = compile_comment(comment);
+        let deprecated = compile_deprecated(attributes);
+        let name = Ident::new(name, Span::call_site());
+
+        quote! {
+            #comment
+            #deprecated
+            #public #name: #ty
+        }
+    } else {
+        quote! { #public #ty }
+    }
+}
+
+#[allow(clippy::too_many_lines)]
 pub(super) fn compile_data_type(opts: &Opts, ty: &Type<'_>) -> TokenStream {
     match &ty {
         Type::Bool => quote! { bool },
@@ -271,61 +606,104 @@ pub(super) fn compile_data_type(opts: &Opts, ty: &Type<'_>) -> TokenStream {
         Type::I128 => quote! { i128 },
         Type::F32 => quote! { f32 },
         Type::F64 => quote! { f64 },
-        Type::String | Type::StringRef => quote! { String },
+        Type::StringRef if opts.ref_type == RefType::Borrowed => quote! { &'de str },
+        Type::BytesRef if opts.ref_type == RefType::Borrowed => quote! { &'de [u8] },
+        Type::String | Type::StringRef => opts.string(),
         Type::Bytes | Type::BytesRef => match opts.bytes_type {
-            BytesType::VecU8 => quote! { Vec<u8> },
+            BytesType::VecU8 => {
+                let vec = opts.vec();
+                quote! { #vec<u8> }
+            }
             BytesType::Bytes => quote! { ::mabo::buf::Bytes },
         },
         Type::Vec(ty) => {
+            let vec = opts.vec();
             let ty = compile_data_type(opts, ty);
-            quote! { Vec<#ty> }
+            quote! { #vec<#ty> }
         }
         Type::HashMap(kv) => {
             let k = compile_data_type(opts, &kv.0);
             let v = compile_data_type(opts, &kv.1);
-            quote! { ::std::collections::HashMap<#k, #v> }
+            if opts.no_std {
+                quote! { ::mabo::collections::HashMap<#k, #v> }
+            } else {
+                quote! { ::std::collections::HashMap<#k, #v> }
+            }
         }
         Type::HashSet(ty) => {
             let ty = compile_data_type(opts, ty);
-            quote! { ::std::collections::HashSet<#ty> }
+            if opts.no_std {
+                quote! { ::mabo::collections::HashSet<#ty> }
+            } else {
+                quote! { ::std::collections::HashSet<#ty> }
+            }
+        }
+        Type::BTreeMap(kv) => {
+            let alloc = opts.alloc();
+            let k = compile_data_type(opts, &kv.0);
+            let v = compile_data_type(opts, &kv.1);
+            quote! { #alloc::collections::BTreeMap<#k, #v> }
+        }
+        Type::BTreeSet(ty) => {
+            let alloc = opts.alloc();
+            let ty = compile_data_type(opts, ty);
+            quote! { #alloc::collections::BTreeSet<#ty> }
         }
         Type::Option(ty) => {
             let ty = compile_data_type(opts, ty);
             quote! { Option<#ty> }
         }
-        Type::NonZero(ty) => match &**ty {
-            Type::U8 => quote! { ::std::num::NonZeroU8 },
-            Type::U16 => quote! { ::std::num::NonZeroU16 },
-            Type::U32 => quote! { ::std::num::NonZeroU32 },
-            Type::U64 => quote! { ::std::num::NonZeroU64 },
-            Type::U128 => quote! { ::std::num::NonZeroU128 },
-            Type::I8 => quote! { ::std::num::NonZeroI8 },
-            Type::I16 => quote! { ::std::num::NonZeroI16 },
-            Type::I32 => quote! { ::std::num::NonZeroI32 },
-            Type::I64 => quote! { ::std::num::NonZeroI64 },
-            Type::I128 => quote! { ::std::num::NonZeroI128 },
-            Type::String | Type::StringRef => quote! { ::mabo::NonZeroString },
-            Type::Bytes | Type::BytesRef => match opts.bytes_type {
-                BytesType::VecU8 => quote! { ::mabo::NonZeroBytes },
-                BytesType::Bytes => quote! { ::mabo::NonZero<::mabo::buf::Bytes> },
-            },
-            Type::Vec(ty) => {
-                let ty = compile_data_type(opts, ty);
-                quote! { ::mabo::NonZeroVec<#ty> }
-            }
-            Type::HashMap(kv) => {
-                let k = compile_data_type(opts, &kv.0);
-                let v = compile_data_type(opts, &kv.1);
-                quote! { ::mabo::NonZeroHashMap<#k, #v> }
-            }
-            Type::HashSet(ty) => {
-                let ty = compile_data_type(opts, ty);
-                quote! { ::mabo::NonZeroHashSet<#ty> }
+        Type::NonZero(ty) => {
+            let core = opts.core();
+            match &**ty {
+                Type::U8 => quote! { #core::num::NonZeroU8 },
+                Type::U16 => quote! { #core::num::NonZeroU16 },
+                Type::U32 => quote! { #core::num::NonZeroU32 },
+                Type::U64 => quote! { #core::num::NonZeroU64 },
+                Type::U128 => quote! { #core::num::NonZeroU128 },
+                Type::I8 => quote! { #core::num::NonZeroI8 },
+                Type::I16 => quote! { #core::num::NonZeroI16 },
+                Type::I32 => quote! { #core::num::NonZeroI32 },
+                Type::I64 => quote! { #core::num::NonZeroI64 },
+                Type::I128 => quote! { #core::num::NonZeroI128 },
+                Type::String | Type::StringRef => quote! { ::mabo::NonZeroString },
+                Type::Bytes | Type::BytesRef => match opts.bytes_type {
+                    BytesType::VecU8 => quote! { ::mabo::NonZeroBytes },
+                    BytesType::Bytes => quote! { ::mabo::NonZero<::mabo::buf::Bytes> },
+                },
+                Type::Vec(ty) => {
+                    let ty = compile_data_type(opts, ty);
+                    quote! { ::mabo::NonZeroVec<#ty> }
+                }
+                Type::HashMap(kv) => {
+                    let k = compile_data_type(opts, &kv.0);
+                    let v = compile_data_type(opts, &kv.1);
+                    quote! { ::mabo::NonZeroHashMap<#k, #v> }
+                }
+                Type::HashSet(ty) => {
+                    let ty = compile_data_type(opts, ty);
+                    quote! { ::mabo::NonZeroHashSet<#ty> }
+                }
+                Type::BTreeMap(kv) => {
+                    let k = compile_data_type(opts, &kv.0);
+                    let v = compile_data_type(opts, &kv.1);
+                    quote! { ::mabo::NonZeroBTreeMap<#k, #v> }
+                }
+                Type::BTreeSet(ty) => {
+                    let ty = compile_data_type(opts, ty);
+                    quote! { ::mabo::NonZeroBTreeSet<#ty> }
+                }
+                ty => todo!("compiler should catch invalid {ty:?} type"),
             }
-            ty => todo!("compiler should catch invalid {ty:?} type"),
-        },
-        Type::BoxString => quote! { Box<str> },
-        Type::BoxBytes => quote! { Box<[u8]> },
+        }
+        Type::BoxString => {
+            let boxed = opts.boxed();
+            quote! { #boxed<str> }
+        }
+        Type::BoxBytes => {
+            let boxed = opts.boxed();
+            quote! { #boxed<[u8]> }
+        }
         Type::Tuple(types) => {
             let types = types.iter().map(|ty| compile_data_type(opts, ty));
             quote! { (#(#types,)*) }
@@ -341,10 +719,11 @@ pub(super) fn compile_data_type(opts: &Opts, ty: &Type<'_>) -> TokenStream {
             generics,
         }) => {
             let path = path.iter().map(|part| Ident::new(part, Span::call_site()));
+            let lifetime = opts.borrowed.contains(*name).then(|| quote! { 'de, });
             let name = Ident::new(name, Span::call_site());
-            let generics = (!generics.is_empty()).then(|| {
+            let generics = (lifetime.is_some() || !generics.is_empty()).then(|| {
                 let types = generics.iter().map(|ty| compile_data_type(opts, ty));
-                quote! { <#(#types,)*> }
+                quote! { <#lifetime #(#types,)*> }
             });
 
             quote! {
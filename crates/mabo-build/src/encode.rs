@@ -1,34 +1,43 @@
+use std::borrow::Cow;
+
 use mabo_compiler::simplify::{Enum, Field, FieldKind, Fields, Struct, Type, Variant};
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::{quote, ToTokens};
 
-use crate::{BytesType, Opts};
+use crate::{BytesType, Opts, RefType};
 
 pub(super) fn compile_struct(
     opts: &Opts,
     Struct {
+        attributes,
         name,
         generics,
         fields,
         ..
     }: &Struct<'_>,
 ) -> TokenStream {
+    let allow_deprecated = super::definition::allow_deprecated_struct(attributes, fields);
     let names = fields
         .fields
         .iter()
         .map(|field| Ident::new(&field.name, Span::call_site()));
+    let unknown = opts.unknown_fields && fields.kind == FieldKind::Named;
+    let unknown_name = unknown.then(|| quote! { _unknown_fields, });
+    let unknown_allow = unknown.then(|| quote! { #[allow(clippy::used_underscore_binding)] });
     let names = match fields.kind {
-        FieldKind::Named => quote! { {#(#names,)*} },
+        FieldKind::Named => quote! { {#(#names,)* #unknown_name} },
         FieldKind::Unnamed => quote! { (#(#names,)*) },
         FieldKind::Unit => quote! {},
     };
 
+    let (generics, generics_where) = compile_generics(generics, opts.borrowed.contains(*name));
+    let opts = &type_opts(opts, name);
     let name = Ident::new(name, Span::call_site());
-    let (generics, generics_where) = compile_generics(generics);
-    let fields = compile_fields(opts, fields);
+    let fields = compile_fields(opts, fields, unknown);
 
     quote! {
         #[automatically_derived]
+        #allow_deprecated
         impl #generics ::mabo::Encode for #name #generics #generics_where {
             #[allow(
                 clippy::borrow_deref_ref,
@@ -37,6 +46,7 @@ pub(super) fn compile_struct(
                 clippy::needless_borrow,
                 clippy::too_many_lines,
             )]
+            #unknown_allow
             fn encode(&self, w: &mut impl ::mabo::BufMut) {
                 let Self #names = self;
                 #fields
@@ -48,18 +58,22 @@ pub(super) fn compile_struct(
 pub(super) fn compile_enum(
     opts: &Opts,
     Enum {
+        attributes,
         name,
         generics,
         variants,
         ..
     }: &Enum<'_>,
 ) -> TokenStream {
+    let allow_deprecated = super::definition::allow_deprecated_enum(attributes, variants);
+    let (generics, generics_where) = compile_generics(generics, opts.borrowed.contains(*name));
+    let opts = &type_opts(opts, name);
     let name = Ident::new(name, Span::call_site());
-    let (generics, generics_where) = compile_generics(generics);
     let variants = variants.iter().map(|v| compile_variant(opts, v));
 
     quote! {
         #[automatically_derived]
+        #allow_deprecated
         impl #generics ::mabo::Encode for #name #generics #generics_where {
             #[allow(
                 clippy::borrow_deref_ref,
@@ -76,6 +90,23 @@ pub(super) fn compile_enum(
     }
 }
 
+/// Switch to the canonical encoding of maps and sets, if it's selected for the type with the
+/// given name.
+fn type_opts<'a>(opts: &'a Opts, name: &str) -> Cow<'a, Opts> {
+    if opts
+        .canonical
+        .iter()
+        .any(|path| super::definition::matches_path(opts, path, name))
+    {
+        Cow::Owned(Opts {
+            encode_canonical: true,
+            ..opts.clone()
+        })
+    } else {
+        Cow::Borrowed(opts)
+    }
+}
+
 fn compile_variant(
     opts: &Opts,
     Variant {
@@ -85,7 +116,7 @@ fn compile_variant(
     let id = proc_macro2::Literal::u32_unsuffixed(*id);
     let id = quote! { ::mabo::VariantId::new(#id) };
     let name = Ident::new(name, Span::call_site());
-    let fields_body = compile_fields(opts, fields);
+    let fields_body = compile_fields(opts, fields, false);
     let field_names = fields
         .fields
         .iter()
@@ -112,44 +143,97 @@ fn compile_variant(
     }
 }
 
-fn compile_fields(opts: &Opts, fields: &Fields<'_>) -> TokenStream {
+/// Encode all fields in the order of their IDs, followed by the end marker.
+///
+/// Unknown fields are appended after the known ones. In the canonical encoding, they're merged
+/// into the known fields instead, so all fields are written in the order of their IDs.
+fn compile_fields(opts: &Opts, fields: &Fields<'_>, unknown: bool) -> TokenStream {
     if fields.kind == FieldKind::Unit {
-        quote! {}
-    } else {
-        let calls = fields.fields.iter().map(|Field { name, ty, id, .. }| {
+        return quote! {};
+    }
+
+    let mut sorted = fields.fields.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|field| field.id);
+
+    let merge_unknown = |ids: TokenStream| {
+        unknown.then(|| {
+            let call = quote! {
+                ::mabo::buf::encode_unknown_fields_canonical(w, _unknown_fields, #ids);
+            };
+
+            if opts.encode_canonical {
+                call
+            } else {
+                quote! { if canonical { #call } }
+            }
+        })
+    };
+
+    let mut previous = None;
+    let calls = sorted
+        .into_iter()
+        .map(|Field { name, ty, id, .. }| {
             let id = proc_macro2::Literal::u32_unsuffixed(*id);
             let name = proc_macro2::Ident::new(name, Span::call_site());
+            let start = previous.replace(id.clone());
+            let merge = merge_unknown(quote! { #start..#id });
 
             if let Type::Option(ty) = &ty {
                 let (enc, ty) = compile_data_type(opts, ty, quote! { v }, true);
                 let id = quote! { ::mabo::FieldId::new(#id, #enc) };
-                quote! { ::mabo::buf::encode_field_option(w, #id, #name, |w, v| { #ty; }); }
+                quote! {
+                    #merge
+                    ::mabo::buf::encode_field_option(w, #id, #name, |w, v| { #ty; });
+                }
             } else {
                 let (enc, ty) = compile_data_type(opts, ty, name.into_token_stream(), true);
                 let id = quote! { ::mabo::FieldId::new(#id, #enc) };
-                quote! { ::mabo::buf::encode_field(w, #id, |w| { #ty; }); }
+                quote! {
+                    #merge
+                    ::mabo::buf::encode_field(w, #id, |w| { #ty; });
+                }
             }
-        });
+        })
+        .collect::<Vec<_>>();
+
+    let unknown = unknown.then(|| {
+        let call = quote! {
+            ::mabo::buf::encode_unknown_fields_canonical(w, _unknown_fields, #previous..);
+        };
 
-        quote! {
-           #(#calls)*
-           ::mabo::buf::encode_u32(w, ::mabo::buf::END_MARKER);
+        if opts.encode_canonical {
+            call
+        } else {
+            quote! {
+                if canonical {
+                    #call
+                } else {
+                    ::mabo::buf::encode_unknown_fields(w, _unknown_fields);
+                }
+            }
         }
+    });
+    let canonical = (unknown.is_some() && !opts.encode_canonical)
+        .then(|| quote! { let canonical = ::mabo::buf::is_canonical_encoding(); });
+
+    quote! {
+        #canonical
+        #(#calls)*
+        #unknown
+        ::mabo::buf::encode_u32(w, ::mabo::buf::END_MARKER);
     }
 }
 
-fn compile_generics(types: &[&str]) -> (TokenStream, TokenStream) {
-    (!types.is_empty())
+fn compile_generics(types: &[&str], borrowed: bool) -> (TokenStream, TokenStream) {
+    let generics = super::definition::compile_generics(types, borrowed).unwrap_or_default();
+    let generics_where = (!types.is_empty())
         .then(|| {
             let types = types.iter().map(|ty| Ident::new(ty, Span::call_site()));
-            let types2 = types.clone();
-
-            (
-                quote! { <#(#types,)*> },
-                quote! { where #(#types2: ::mabo::buf::Encode + ::mabo::buf::Size,)* },
-            )
+            quote! { where #(#types: ::mabo::buf::Encode + ::mabo::buf::Size,)* }
         })
-        .unwrap_or_default()
+        .unwrap_or_default();
+
+    (generics, generics_where)
 }
 
 #[allow(clippy::needless_pass_by_value, clippy::too_many_lines)]
@@ -216,6 +300,10 @@ fn compile_data_type(
             quote! { ::mabo::FieldEncoding::LengthPrefixed },
             quote! { ::mabo::buf::encode_string(w, #name) },
         ),
+        Type::BytesRef if opts.ref_type == RefType::Borrowed => (
+            quote! { ::mabo::FieldEncoding::LengthPrefixed },
+            quote! { ::mabo::buf::encode_bytes_std(w, #name) },
+        ),
         Type::Bytes | Type::BytesRef | Type::BoxBytes => match opts.bytes_type {
             BytesType::VecU8 => (
                 quote! { ::mabo::FieldEncoding::LengthPrefixed },
@@ -233,33 +321,10 @@ fn compile_data_type(
                 quote! { ::mabo::buf::encode_vec(w, #name, |v| { #size }, |w, v| { #encode; }) }
             })
         }
-        Type::HashMap(kv) => {
-            let size_k = super::size::compile_data_type(opts, &kv.0, quote! { k });
-            let size_v = super::size::compile_data_type(opts, &kv.1, quote! { v });
-            let (_, encode_k) = compile_data_type(opts, &kv.0, quote! { k }, false);
-            let (_, encode_v) = compile_data_type(opts, &kv.1, quote! { v }, false);
-            (
-                quote! { ::mabo::FieldEncoding::LengthPrefixed },
-                quote! {
-                    ::mabo::buf::encode_hash_map(
-                        w,
-                        #name,
-                        |k| { #size_k },
-                        |v| { #size_v },
-                        |w, k| { #encode_k; },
-                        |w, v| { #encode_v; },
-                    )
-                },
-            )
-        }
-        Type::HashSet(ty) => {
-            let size = super::size::compile_data_type(opts, ty, quote! { v });
-            let (_, encode) = compile_data_type(opts, ty, quote! { v }, false);
-            (
-                quote! { ::mabo::FieldEncoding::LengthPrefixed },
-                quote! { ::mabo::buf::encode_hash_set(w, #name, |v| { #size }, |w, v| { #encode; }) },
-            )
-        }
+        Type::HashMap(_) | Type::HashSet(_) | Type::BTreeMap(_) | Type::BTreeSet(_) => (
+            quote! { ::mabo::FieldEncoding::LengthPrefixed },
+            compile_collection(opts, ty, &name),
+        ),
         Type::Option(ty) => {
             let (_, encode) = compile_data_type(opts, ty, quote! { v }, false);
             (
@@ -314,7 +379,9 @@ fn compile_data_type(
             | Type::BytesRef
             | Type::Vec(_)
             | Type::HashMap(_)
-            | Type::HashSet(_) => compile_data_type(opts, ty, quote! { #name.get() }, false),
+            | Type::HashSet(_)
+            | Type::BTreeMap(_)
+            | Type::BTreeSet(_) => compile_data_type(opts, ty, quote! { #name.get() }, false),
             ty => todo!("compiler should catch invalid {ty:?} type"),
         },
         Type::Tuple(types) => match types.len() {
@@ -353,3 +420,92 @@ fn compile_data_type(
         ),
     }
 }
+
+/// Encode a map or set in its canonical form if the current type selected it. Otherwise, the
+/// generated code decides at runtime, whether it's called through `mabo::buf::encode_canonical`.
+fn compile_collection(opts: &Opts, ty: &Type<'_>, name: &TokenStream) -> TokenStream {
+    if opts.encode_canonical {
+        return compile_collection_canonical(opts, ty, name);
+    }
+
+    let canonical = compile_collection_canonical(
+        &Opts {
+            encode_canonical: true,
+            ..opts.clone()
+        },
+        ty,
+        name,
+    );
+    let regular = match ty {
+        Type::HashMap(kv) => {
+            let size_k = super::size::compile_data_type(opts, &kv.0, quote! { k });
+            let size_v = super::size::compile_data_type(opts, &kv.1, quote! { v });
+            let (_, encode_k) = compile_data_type(opts, &kv.0, quote! { k }, false);
+            let (_, encode_v) = compile_data_type(opts, &kv.1, quote! { v }, false);
+            quote! {
+                ::mabo::buf::encode_hash_map(
+                    w,
+                    #name,
+                    |k| { #size_k },
+                    |v| { #size_v },
+                    |w, k| { #encode_k; },
+                    |w, v| { #encode_v; },
+                )
+            }
+        }
+        Type::HashSet(ty) => {
+            let size = super::size::compile_data_type(opts, ty, quote! { v });
+            let (_, encode) = compile_data_type(opts, ty, quote! { v }, false);
+            quote! { ::mabo::buf::encode_hash_set(w, #name, |v| { #size }, |w, v| { #encode; }) }
+        }
+        Type::BTreeMap(kv) => {
+            let size_k = super::size::compile_data_type(opts, &kv.0, quote! { k });
+            let size_v = super::size::compile_data_type(opts, &kv.1, quote! { v });
+            let (_, encode_k) = compile_data_type(opts, &kv.0, quote! { k }, false);
+            let (_, encode_v) = compile_data_type(opts, &kv.1, quote! { v }, false);
+            quote! {
+                ::mabo::buf::encode_btree_map(
+                    w,
+                    #name,
+                    |k| { #size_k },
+                    |v| { #size_v },
+                    |w, k| { #encode_k; },
+                    |w, v| { #encode_v; },
+                )
+            }
+        }
+        Type::BTreeSet(ty) => {
+            let size = super::size::compile_data_type(opts, ty, quote! { v });
+            let (_, encode) = compile_data_type(opts, ty, quote! { v }, false);
+            quote! { ::mabo::buf::encode_btree_set(w, #name, |v| { #size }, |w, v| { #encode; }) }
+        }
+        _ => unreachable!("only maps and sets are collections"),
+    };
+
+    quote! {
+        ::mabo::buf::encode_canonical_or(w, |w| { #canonical; }, |w| { #regular; })
+    }
+}
+
+/// Encode a map or set in its canonical form, with the entries sorted by their encoded bytes.
+fn compile_collection_canonical(opts: &Opts, ty: &Type<'_>, name: &TokenStream) -> TokenStream {
+    match ty {
+        Type::HashMap(kv) | Type::BTreeMap(kv) => {
+            let (_, encode_k) = compile_data_type(opts, &kv.0, quote! { k }, false);
+            let (_, encode_v) = compile_data_type(opts, &kv.1, quote! { v }, false);
+            quote! {
+                ::mabo::buf::encode_map_canonical(
+                    w,
+                    #name,
+                    |w, k| { #encode_k; },
+                    |w, v| { #encode_v; },
+                )
+            }
+        }
+        Type::HashSet(ty) | Type::BTreeSet(ty) => {
+            let (_, encode) = compile_data_type(opts, ty, quote! { v }, false);
+            quote! { ::mabo::buf::encode_set_canonical(w, #name, |w, v| { #encode; }) }
+        }
+        _ => unreachable!("only maps and sets are collections"),
+    }
+}
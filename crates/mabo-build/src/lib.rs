@@ -1,17 +1,28 @@
 //! Code generator crate for Rust projects that can be used in `build.rs` build scripts.
 
-use std::{env, fmt::Debug, fs, path::PathBuf};
-
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fmt::Debug,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use mabo_compiler::simplify;
 use mabo_parser::Schema;
 use miette::Report;
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
 use thiserror::Error;
 
-pub use self::definition::compile_schema;
+pub use self::definition::{compile_decode, compile_encode, compile_schema};
 
 mod decode;
 mod definition;
 mod encode;
 mod size;
+mod tree;
+mod validate;
 
 /// Shorthand for the standard result type, that defaults to the crate level's [`Error`] type.
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -31,6 +42,38 @@ pub enum Error {
     /// The file name wasn't valid UTF-8.
     #[error("the file name was not encoded in valid UTF-8")]
     NonUtf8FileName,
+    /// A schema file at the root of the project is named `mod`, which would overwrite the
+    /// generated entry file.
+    #[error("the schema file {file:?} can't be named `mod` at the root of the project")]
+    ReservedFileName {
+        /// The problematic schema file.
+        file: PathBuf,
+    },
+    /// A field declares a `pattern` rule while generating code for `no_std` environments, but
+    /// regular expressions require the `regex` feature of the `mabo` crate, which needs the
+    /// standard library.
+    #[error(
+        "the `pattern` rule of `{field}` in {file:?} requires the `regex` feature of the `mabo` \
+         crate, which isn't available with `no_std`"
+    )]
+    PatternNoStd {
+        /// Path to the field, like `Type.field` or `Type::Variant.field`.
+        field: String,
+        /// The problematic schema file.
+        file: PathBuf,
+    },
+    /// Two entries of the same directory map to the same Rust module. That's either a directory
+    /// next to a schema file of the same name, or names that only differ in characters that aren't
+    /// allowed in identifiers, like `a-b` and `a_b`.
+    #[error("`{first}` and `{second}` both map to the Rust module `{module}`")]
+    ModuleCollision {
+        /// The first entry, relative to the project.
+        first: String,
+        /// The second entry, relative to the project.
+        second: String,
+        /// Name of the conflicting module.
+        module: String,
+    },
     /// Failed to create the output directory for generated Rust source files.
     #[error("failed creating output directory at {path:?}")]
     Create {
@@ -74,6 +117,15 @@ pub enum Error {
         /// The invalid Rust source code.
         code: String,
     },
+    /// A custom derive or attribute for generated types isn't valid Rust code.
+    #[error("failed parsing the custom attribute `{value}`")]
+    InvalidAttribute {
+        /// Source error of the problem.
+        #[source]
+        source: syn::Error,
+        /// The invalid derive or attribute.
+        value: String,
+    },
     /// Failed to write a generated Rust source file.
     #[error("failed writing Rust source file to {file:?}")]
     Write {
@@ -102,10 +154,30 @@ impl Debug for Error {
 }
 
 /// Instance of the compiler, which is responsible to generate Rust source code from schema files.
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Default)]
 pub struct Compiler {
     /// The data type to use for Mabo's `bytes` type.
     bytes_type: BytesType,
+    /// The way Mabo's reference types `&string` and `&bytes` are represented.
+    ref_type: RefType,
+    /// Whether to retain unknown fields when decoding structs.
+    unknown_fields: bool,
+    /// Whether to validate values right after decoding them.
+    decode_validation: bool,
+    /// Whether to generate `serde` implementations for all structs and enums.
+    serde: bool,
+    /// The way enums are represented in `serde` formats.
+    enum_tagging: EnumTagging,
+    /// Whether to generate code for `no_std` environments, that only refers to `core` and
+    /// `alloc`.
+    no_std: bool,
+    /// Paths of the generated types that encode maps and sets in their canonical form.
+    canonical: Vec<String>,
+    /// Additional derives for generated types, as pairs of type path and derive.
+    derives: Vec<(String, String)>,
+    /// Additional attributes for generated types, as pairs of type path and attribute.
+    attributes: Vec<(String, String)>,
 }
 
 /// The data type to use for Mabo's `bytes` type, that is used throughout all generated schemas.
@@ -118,10 +190,148 @@ pub enum BytesType {
     Bytes,
 }
 
+/// The representation of Mabo's reference types `&string` and `&bytes`, that is used throughout
+/// all generated schemas.
+#[derive(Clone, Copy, Default, Eq, PartialEq)]
+pub enum RefType {
+    /// Decode reference types into owned values, the same as `string` and `bytes`.
+    #[default]
+    Owned,
+    /// Borrow reference types directly from the input buffer as `&str` and `&[u8]`, without any
+    /// allocation.
+    ///
+    /// Generated types that contain reference types (directly or through other types of the same
+    /// schema) get an additional `'de` lifetime parameter. All generated types implement
+    /// [`mabo::DecodeBorrowed`](https://docs.rs/mabo/latest/mabo/buf/trait.DecodeBorrowed.html)
+    /// instead of `Decode` in this mode. `non_zero` strings and byte arrays are always owned.
+    Borrowed,
+}
+
+/// The way enums are represented in `serde` formats, which maps to the
+/// [enum representations](https://serde.rs/enum-representations.html) of `serde`.
+#[derive(Clone, Default)]
+pub enum EnumTagging {
+    /// Wrap the variant's fields in an object with the variant name as only key, like
+    /// `{"Variant": {"field": 1}}`.
+    #[default]
+    External,
+    /// Put the variant name into a field of the variant's object, like
+    /// `{"type": "Variant", "field": 1}`.
+    ///
+    /// This doesn't work for variants with unnamed fields, which `serde` rejects at compile time.
+    Internal {
+        /// Name of the field that holds the variant name.
+        tag: String,
+    },
+    /// Put the variant name and its fields into two separate fields, like
+    /// `{"type": "Variant", "content": {"field": 1}}`.
+    Adjacent {
+        /// Name of the field that holds the variant name.
+        tag: String,
+        /// Name of the field that holds the variant's fields.
+        content: String,
+    },
+    /// Don't include the variant name at all, but try each variant in order when deserializing.
+    Untagged,
+}
+
 /// Additional options to adjust the behavior of the Rust code generator.
-#[derive(Default)]
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Default)]
 pub struct Opts {
+    /// The data type to use for Mabo's `bytes` type.
     bytes_type: BytesType,
+    /// The way Mabo's reference types `&string` and `&bytes` are represented.
+    ref_type: RefType,
+    /// Whether to retain unknown fields when decoding structs.
+    unknown_fields: bool,
+    /// Whether to validate values right after decoding them.
+    decode_validation: bool,
+    /// Whether to generate `serde` implementations for all structs and enums.
+    serde: bool,
+    /// The way enums are represented in `serde` formats.
+    enum_tagging: EnumTagging,
+    /// Whether to generate code for `no_std` environments, that only refers to `core` and
+    /// `alloc`.
+    no_std: bool,
+    /// Paths of the generated structs and enums that encode maps and sets in their canonical
+    /// form.
+    canonical: Vec<String>,
+    /// Additional derives for generated structs and enums, as pairs of type path and derive.
+    derives: Vec<(String, TokenStream)>,
+    /// Additional attributes for generated structs and enums, as pairs of type path and
+    /// attribute.
+    attributes: Vec<(String, TokenStream)>,
+    /// Names of the types in the current schema that borrow from the input buffer.
+    borrowed: HashSet<String>,
+    /// Names of the types that don't need to be validated, and therefore don't implement
+    /// `Validate`. Collected across the whole project, or otherwise for each schema.
+    unvalidated: Option<HashSet<String>>,
+    /// Whether the type that is currently generated encodes maps and sets in their canonical
+    /// form.
+    encode_canonical: bool,
+    /// Path of the module that is currently generated, starting with the schema name.
+    path: Vec<String>,
+    /// Directories that contain each schema of the project, relative to the project and keyed by
+    /// schema name, to locate imported schemas in the module tree.
+    schemas: HashMap<String, Vec<Vec<String>>>,
+    /// Directories that contain the current schema, relative to the project.
+    directory: Vec<String>,
+}
+
+impl Opts {
+    /// Whether the type with the given name implements `Validate`.
+    fn validated(&self, name: &str) -> bool {
+        !self
+            .unvalidated
+            .as_ref()
+            .is_some_and(|unvalidated| unvalidated.contains(name))
+    }
+
+    /// Root of paths into the standard library, for types that are available in `core` as well.
+    fn core(&self) -> TokenStream {
+        if self.no_std {
+            quote! { ::core }
+        } else {
+            quote! { ::std }
+        }
+    }
+
+    /// Root of paths into the standard library, for types that are available in `alloc` as well.
+    fn alloc(&self) -> TokenStream {
+        if self.no_std {
+            quote! { ::alloc }
+        } else {
+            quote! { ::std }
+        }
+    }
+
+    /// Path to the `String` type, which isn't part of the prelude in `no_std` environments.
+    fn string(&self) -> TokenStream {
+        if self.no_std {
+            quote! { ::alloc::string::String }
+        } else {
+            quote! { String }
+        }
+    }
+
+    /// Path to the `Vec` type, which isn't part of the prelude in `no_std` environments.
+    fn vec(&self) -> TokenStream {
+        if self.no_std {
+            quote! { ::alloc::vec::Vec }
+        } else {
+            quote! { Vec }
+        }
+    }
+
+    /// Path to the `Box` type, which isn't part of the prelude in `no_std` environments.
+    fn boxed(&self) -> TokenStream {
+        if self.no_std {
+            quote! { ::alloc::boxed::Box }
+        } else {
+            quote! { Box }
+        }
+    }
 }
 
 impl Compiler {
@@ -132,6 +342,163 @@ impl Compiler {
         self
     }
 
+    /// Change the way that Mabo's reference types `&string` and `&bytes` are represented.
+    #[must_use]
+    pub fn with_ref_type(mut self, value: RefType) -> Self {
+        self.ref_type = value;
+        self
+    }
+
+    /// Retain fields that are not part of the schema when decoding structs, and write them back
+    /// out when encoding them again.
+    ///
+    /// Each generated struct with named fields gets an additional hidden `_unknown_fields` field
+    /// of type [`mabo::UnknownFields`](https://docs.rs/mabo/latest/mabo/struct.UnknownFields.html),
+    /// that keeps the raw encoded data of unknown fields. This prevents services, that use an
+    /// older version of a schema, from dropping any data written by newer versions.
+    #[must_use]
+    pub fn with_unknown_fields(mut self, value: bool) -> Self {
+        self.unknown_fields = value;
+        self
+    }
+
+    /// Check the constraints of the `#[validate(...)]` attributes as part of decoding, so invalid
+    /// payloads are rejected right away.
+    ///
+    /// Every generated type implements
+    /// [`mabo::Validate`](https://docs.rs/mabo/latest/mabo/validate/trait.Validate.html) either
+    /// way, but without this option it's up to the caller to invoke it. Failed checks are
+    /// reported as `mabo::buf::Error::Invalid`.
+    ///
+    /// The `pattern` rule requires the `regex` feature of the `mabo` crate in either case.
+    #[must_use]
+    pub fn with_decode_validation(mut self, value: bool) -> Self {
+        self.decode_validation = value;
+        self
+    }
+
+    /// Generate `serde::Serialize` and `serde::Deserialize` implementations for all structs and
+    /// enums, with the same field and variant names as in the schema.
+    ///
+    /// The generated code requires the `serde` feature of the `mabo` crate. Types that borrow
+    /// from the input buffer (see [`RefType::Borrowed`]) only implement `Serialize`, and unknown
+    /// fields (see [`Self::with_unknown_fields`]) are skipped.
+    #[must_use]
+    pub fn with_serde(mut self, value: bool) -> Self {
+        self.serde = value;
+        self
+    }
+
+    /// Change the way that enums are represented in `serde` formats, if enabled with
+    /// [`Self::with_serde`].
+    #[must_use]
+    pub fn with_enum_tagging(mut self, value: EnumTagging) -> Self {
+        self.enum_tagging = value;
+        self
+    }
+
+    /// Generate code for `no_std` environments, that refers to types of the `core` and `alloc`
+    /// crates instead of the standard library.
+    ///
+    /// The including crate must declare `extern crate alloc;`, and disable the default `std`
+    /// feature of the `mabo` crate. Hash maps and sets are represented by the types in
+    /// [`mabo::collections`](https://docs.rs/mabo/latest/mabo/collections/index.html) then.
+    ///
+    /// Schemas can't use the `pattern` validation rule, as the `regex` feature of the `mabo`
+    /// crate depends on the standard library.
+    #[must_use]
+    pub fn with_no_std(mut self, value: bool) -> Self {
+        self.no_std = value;
+        self
+    }
+
+    /// Encode the maps and sets of all generated structs and enums that match the given path in
+    /// their canonical form, with the entries sorted by the encoded bytes of their keys.
+    ///
+    /// The same value always encodes to the same bytes then, which is needed to hash or sign
+    /// payloads. The path is selected in the same way as in [`Self::with_type_derive`]. Decoders
+    /// can insist on the canonical form through the `canonical` flag of
+    /// [`mabo::buf::DecodeOptions`](https://docs.rs/mabo/latest/mabo/buf/struct.DecodeOptions.html).
+    ///
+    /// Single calls can encode any generated type in its canonical form as well, through
+    /// [`mabo::buf::encode_canonical`](https://docs.rs/mabo/latest/mabo/buf/fn.encode_canonical.html).
+    ///
+    /// ```
+    /// mabo_build::Compiler::default().with_canonical_encoding("sample::Signed");
+    /// ```
+    #[must_use]
+    pub fn with_canonical_encoding(mut self, path: impl Into<String>) -> Self {
+        self.canonical.push(path.into());
+        self
+    }
+
+    /// Add a derive to all generated structs and enums that match the given path, in addition
+    /// to the default `Clone`, `Debug` and `PartialEq`.
+    ///
+    /// The path is made up of the schema file name, followed by any modules and the type name,
+    /// like `sample::inner::Value`. A path that only names a schema or module applies to all
+    /// types within, and an empty path applies to every generated type.
+    ///
+    /// ```
+    /// mabo_build::Compiler::default()
+    ///     .with_type_derive("", "Eq")
+    ///     .with_type_derive("sample::Value", "Hash");
+    /// ```
+    #[must_use]
+    pub fn with_type_derive(mut self, path: impl Into<String>, derive: impl Into<String>) -> Self {
+        self.derives.push((path.into(), derive.into()));
+        self
+    }
+
+    /// Add an arbitrary attribute to all generated structs and enums that match the given path.
+    ///
+    /// The path is selected in the same way as in [`Self::with_type_derive`], and the attribute
+    /// is given in its full form, including the surrounding `#[...]`.
+    ///
+    /// ```
+    /// mabo_build::Compiler::default()
+    ///     .with_type_attribute("sample::Value", "#[non_exhaustive]");
+    /// ```
+    #[must_use]
+    pub fn with_type_attribute(
+        mut self,
+        path: impl Into<String>,
+        attribute: impl Into<String>,
+    ) -> Self {
+        self.attributes.push((path.into(), attribute.into()));
+        self
+    }
+
+    /// Turn the current settings into [`Opts`], to generate code for a single schema directly
+    /// with [`compile_schema`].
+    ///
+    /// # Errors
+    ///
+    /// Will return an `Err` if any of the custom derives or attributes isn't valid Rust code.
+    pub fn opts(&self) -> Result<Opts> {
+        Ok(Opts {
+            bytes_type: self.bytes_type,
+            ref_type: self.ref_type,
+            unknown_fields: self.unknown_fields,
+            decode_validation: self.decode_validation,
+            serde: self.serde,
+            enum_tagging: self.enum_tagging.clone(),
+            no_std: self.no_std,
+            canonical: self.canonical.clone(),
+            derives: self
+                .derives
+                .iter()
+                .map(|(path, derive)| Ok((path.clone(), parse_derive(derive)?)))
+                .collect::<Result<_>>()?,
+            attributes: self
+                .attributes
+                .iter()
+                .map(|(path, attribute)| Ok((path.clone(), parse_attribute(attribute)?)))
+                .collect::<Result<_>>()?,
+            ..Opts::default()
+        })
+    }
+
     /// Compile the given list of Mabo schema files (glob patterns) into Rust source code.
     ///
     /// # Errors
@@ -167,56 +534,198 @@ impl Compiler {
                 .ok_or(Error::NoFileName)?
                 .to_str()
                 .ok_or(Error::NonUtf8FileName)?;
+            let directories = directories(manifest_dir, path)?;
+            if directories.is_empty() && stem == "mod" {
+                return Err(Error::ReservedFileName { file: path.clone() });
+            }
 
-            let schema = Schema::parse(input, Some(path)).map_err(|e| Error::Parse {
-                report: Report::new(e),
-                file: path.clone(),
-            })?;
-
-            mabo_compiler::validate_schema(&schema).map_err(|e| Error::Compile {
-                report: Report::new(e),
-                file: path.clone(),
-            })?;
-
-            validated.push((stem, schema));
+            validated.push((directories, stem, self.load_schema(path, input)?));
         }
 
-        let validated = validated
+        let keys = validated
             .iter()
-            .map(|(name, schema)| (*name, schema))
+            .map(|(directories, name, _)| {
+                directories
+                    .iter()
+                    .map(String::as_str)
+                    .chain([*name])
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .collect::<Vec<_>>();
+        let resolved = keys
+            .iter()
+            .zip(&validated)
+            .map(|(key, (_, _, schema))| (key.as_str(), schema))
             .collect::<Vec<_>>();
 
-        mabo_compiler::resolve_schemas(&validated).map_err(|e| Error::Compile {
+        mabo_compiler::resolve_schemas(&resolved).map_err(|e| Error::Compile {
             report: Report::new(e),
             file: PathBuf::new(),
         })?;
 
+        let simplified = validated
+            .iter()
+            .map(|(_, _, schema)| mabo_compiler::simplify_schema(schema))
+            .collect::<Vec<_>>();
+        let definitions = simplified
+            .iter()
+            .map(|schema| &*schema.definitions)
+            .collect::<Vec<_>>();
+
         let opts = Opts {
-            bytes_type: self.bytes_type,
+            schemas: validated.iter().fold(
+                HashMap::<_, Vec<_>>::new(),
+                |mut schemas, (directories, name, _)| {
+                    schemas
+                        .entry((*name).to_owned())
+                        .or_default()
+                        .push(directories.clone());
+                    schemas
+                },
+            ),
+            unvalidated: Some(validate::find_unvalidated(&definitions)),
+            ..self.opts()?
         };
 
-        for (stem, schema) in validated {
-            let schema = mabo_compiler::simplify_schema(schema);
-            let code = definition::compile_schema(&opts, &schema);
-            let code = prettyplease::unparse(&syn::parse2(code.clone()).map_err(|source| {
-                Error::InvalidCode {
-                    source,
-                    code: code.to_string(),
-                }
-            })?);
-
-            let out_file = out_dir.join(format!("{stem}.rs"));
-
-            fs::write(&out_file, code).map_err(|source| Error::Write {
+        for ((directories, stem, _), schema) in validated.iter().zip(&simplified) {
+            let opts = Opts {
+                directory: directories.clone(),
+                ..opts.clone()
+            };
+            let code = definition::compile_schema(&opts, schema);
+
+            let nested_dir = directories
+                .iter()
+                .fold(out_dir.clone(), |dir, name| dir.join(name));
+            fs::create_dir_all(&nested_dir).map_err(|source| Error::Create {
                 source,
-                file: out_file,
+                path: nested_dir.clone(),
             })?;
+
+            write_code(nested_dir.join(format!("{stem}.rs")), &code)?;
+
+            // Schemas in sub-directories are written to the root of the output directory as
+            // well, where they were located before the module tree existed, so that
+            // `mabo::include!("<name>")` keeps working. That's skipped for ambiguous names.
+            if !directories.is_empty() && *stem != "mod" && opts.schemas[*stem].len() == 1 {
+                let opts = Opts {
+                    unvalidated: opts.unvalidated.clone(),
+                    ..self.opts()?
+                };
+                let code = definition::compile_schema(&opts, schema);
+                write_code(out_dir.join(format!("{stem}.rs")), &code)?;
+            }
         }
 
-        Ok(())
+        let entries = validated
+            .iter()
+            .map(|(directories, name, _)| (directories.clone(), *name))
+            .collect::<Vec<_>>();
+
+        write_code(out_dir.join("mod.rs"), &tree::compile_entry(&entries)?)
+    }
+
+    /// Parse and validate a single schema file.
+    fn load_schema<'a>(&self, path: &'a Path, input: &'a str) -> Result<Schema<'a>> {
+        let schema = Schema::parse(input, Some(path)).map_err(|e| Error::Parse {
+            report: Report::new(e),
+            file: path.to_owned(),
+        })?;
+
+        mabo_compiler::validate_schema(&schema).map_err(|e| Error::Compile {
+            report: Report::new(e),
+            file: path.to_owned(),
+        })?;
+
+        if self.no_std {
+            let simplified = mabo_compiler::simplify_schema(&schema);
+            if let Some(field) = find_pattern(&simplified.definitions) {
+                return Err(Error::PatternNoStd {
+                    field,
+                    file: path.to_owned(),
+                });
+            }
+        }
+
+        Ok(schema)
     }
 }
 
+/// Get the directories that contain the schema file, relative to the project.
+fn directories(manifest_dir: &str, path: &Path) -> Result<Vec<String>> {
+    path.strip_prefix(manifest_dir)
+        .ok()
+        .and_then(Path::parent)
+        .into_iter()
+        .flat_map(Path::components)
+        .map(|component| {
+            component
+                .as_os_str()
+                .to_str()
+                .map(ToOwned::to_owned)
+                .ok_or(Error::NonUtf8FileName)
+        })
+        .collect()
+}
+
+/// Find the first field that declares a `pattern` rule, and get its path within the schema.
+fn find_pattern(definitions: &[simplify::Definition<'_>]) -> Option<String> {
+    let fields = |prefix: String, fields: &simplify::Fields<'_>| {
+        fields
+            .fields
+            .iter()
+            .find(|field| field.attributes.validation().pattern.is_some())
+            .map(|field| format!("{prefix}.{}", field.name))
+    };
+
+    definitions.iter().find_map(|definition| match definition {
+        simplify::Definition::Module(m) => {
+            find_pattern(&m.definitions).map(|path| format!("{}::{path}", m.name))
+        }
+        simplify::Definition::Struct(s) => fields(s.name.to_owned(), &s.fields),
+        simplify::Definition::Enum(e) => e
+            .variants
+            .iter()
+            .find_map(|variant| fields(format!("{}::{}", e.name, variant.name), &variant.fields)),
+        _ => None,
+    })
+}
+
+/// Format the generated code and write it to the given file.
+fn write_code(out_file: PathBuf, code: &TokenStream) -> Result<()> {
+    let code =
+        prettyplease::unparse(
+            &syn::parse2(code.clone()).map_err(|source| Error::InvalidCode {
+                source,
+                code: code.to_string(),
+            })?,
+        );
+
+    fs::write(&out_file, code).map_err(|source| Error::Write {
+        source,
+        file: out_file,
+    })
+}
+
+fn parse_derive(value: &str) -> Result<TokenStream> {
+    syn::parse_str::<syn::Path>(value)
+        .map(ToTokens::into_token_stream)
+        .map_err(|source| Error::InvalidAttribute {
+            source,
+            value: value.to_owned(),
+        })
+}
+
+fn parse_attribute(value: &str) -> Result<TokenStream> {
+    syn::parse::Parser::parse_str(syn::Attribute::parse_outer, value)
+        .map(|attributes| quote! { #(#attributes)* })
+        .map_err(|source| Error::InvalidAttribute {
+            source,
+            value: value.to_owned(),
+        })
+}
+
 fn init_miette() {
     miette::set_hook(Box::new(|_| {
         Box::new(
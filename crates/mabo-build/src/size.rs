@@ -2,33 +2,39 @@ use mabo_compiler::simplify::{Enum, Field, FieldKind, Fields, Struct, Type, Vari
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::{quote, ToTokens};
 
-use crate::{BytesType, Opts};
+use crate::{BytesType, Opts, RefType};
 
 pub(super) fn compile_struct(
     opts: &Opts,
     Struct {
+        attributes,
         name,
         generics,
         fields,
         ..
     }: &Struct<'_>,
 ) -> TokenStream {
+    let allow_deprecated = super::definition::allow_deprecated_struct(attributes, fields);
     let names = fields
         .fields
         .iter()
         .map(|field| Ident::new(&field.name, Span::call_site()));
+    let unknown = opts.unknown_fields && fields.kind == FieldKind::Named;
+    let unknown_name = unknown.then(|| quote! { _unknown_fields, });
+    let unknown_allow = unknown.then(|| quote! { #[allow(clippy::used_underscore_binding)] });
     let names = match fields.kind {
-        FieldKind::Named => quote! { {#(#names,)*} },
+        FieldKind::Named => quote! { {#(#names,)* #unknown_name} },
         FieldKind::Unnamed => quote! { (#(#names,)*) },
         FieldKind::Unit => quote! {},
     };
 
+    let (generics, generics_where) = compile_generics(generics, opts.borrowed.contains(*name));
     let name = Ident::new(name, Span::call_site());
-    let (generics, generics_where) = compile_generics(generics);
-    let fields = compile_struct_fields(opts, fields);
+    let fields = compile_struct_fields(opts, fields, unknown);
 
     quote! {
         #[automatically_derived]
+        #allow_deprecated
         impl #generics ::mabo::buf::Size for #name #generics #generics_where {
             #[allow(
                 clippy::borrow_deref_ref,
@@ -37,6 +43,7 @@ pub(super) fn compile_struct(
                 clippy::needless_borrow,
                 clippy::too_many_lines,
             )]
+            #unknown_allow
             fn size(&self) -> usize {
                 let Self #names = self;
                 #fields
@@ -45,7 +52,7 @@ pub(super) fn compile_struct(
     }
 }
 
-fn compile_struct_fields(opts: &Opts, fields: &Fields<'_>) -> TokenStream {
+fn compile_struct_fields(opts: &Opts, fields: &Fields<'_>, unknown: bool) -> TokenStream {
     if fields.kind == FieldKind::Unit {
         quote! { 0 }
     } else {
@@ -54,18 +61,22 @@ fn compile_struct_fields(opts: &Opts, fields: &Fields<'_>) -> TokenStream {
             let name = proc_macro2::Ident::new(name, Span::call_site());
 
             if let Type::Option(ty) = &ty {
-                let ty = compile_data_type(opts, ty, quote! { v });
+                let ty = compile_field_type(opts, ty, quote! { v });
                 quote! {
                     ::mabo::buf::size_field_option(#id, #name.as_ref(), |v| { #ty })
                 }
             } else {
-                let ty = compile_data_type(opts, ty, name.into_token_stream());
+                let ty = compile_field_type(opts, ty, name.into_token_stream());
                 quote! { ::mabo::buf::size_field(#id, || { #ty }) }
             }
         });
 
+        let unknown =
+            unknown.then(|| quote! { ::mabo::buf::size_unknown_fields(_unknown_fields) + });
+
         quote! {
             #(#calls +)*
+            #unknown
             ::mabo::buf::size_u32(::mabo::buf::END_MARKER)
         }
     }
@@ -74,18 +85,21 @@ fn compile_struct_fields(opts: &Opts, fields: &Fields<'_>) -> TokenStream {
 pub(super) fn compile_enum(
     opts: &Opts,
     Enum {
+        attributes,
         name,
         generics,
         variants,
         ..
     }: &Enum<'_>,
 ) -> TokenStream {
+    let allow_deprecated = super::definition::allow_deprecated_enum(attributes, variants);
+    let (generics, generics_where) = compile_generics(generics, opts.borrowed.contains(*name));
     let name = Ident::new(name, Span::call_site());
-    let (generics, generics_where) = compile_generics(generics);
     let variants = variants.iter().map(|v| compile_variant(opts, v));
 
     quote! {
         #[automatically_derived]
+        #allow_deprecated
         impl #generics ::mabo::buf::Size for #name #generics #generics_where {
             #[allow(
                 clippy::borrow_deref_ref,
@@ -150,12 +164,12 @@ fn compile_variant_fields(opts: &Opts, fields: &Fields<'_>) -> TokenStream {
             let name = proc_macro2::Ident::new(name, Span::call_site());
 
             if let Type::Option(ty) = &ty {
-                let ty = compile_data_type(opts, ty, quote! { v });
+                let ty = compile_field_type(opts, ty, quote! { v });
                 quote! {
                     ::mabo::buf::size_field_option(#id, #name.as_ref(), |v| { #ty })
                 }
             } else {
-                let ty = compile_data_type(opts, ty, name.into_token_stream());
+                let ty = compile_field_type(opts, ty, name.into_token_stream());
                 quote! { ::mabo::buf::size_field(#id, || { #ty }) }
             }
         });
@@ -167,18 +181,27 @@ fn compile_variant_fields(opts: &Opts, fields: &Fields<'_>) -> TokenStream {
     }
 }
 
-fn compile_generics(types: &[&str]) -> (TokenStream, TokenStream) {
-    (!types.is_empty())
+fn compile_generics(types: &[&str], borrowed: bool) -> (TokenStream, TokenStream) {
+    let generics = super::definition::compile_generics(types, borrowed).unwrap_or_default();
+    let generics_where = (!types.is_empty())
         .then(|| {
             let types = types.iter().map(|ty| Ident::new(ty, Span::call_site()));
-            let types2 = types.clone();
-
-            (
-                quote! { <#(#types,)*> },
-                quote! { where #(#types2: ::mabo::buf::Size,)* },
-            )
+            quote! { where #(#types: ::mabo::buf::Size,)* }
         })
-        .unwrap_or_default()
+        .unwrap_or_default();
+
+    (generics, generics_where)
+}
+
+/// Calculate the size of a field's value. Tuples are length-prefixed when they make up a field on
+/// their own, but not when they're nested in other types.
+fn compile_field_type(opts: &Opts, ty: &Type<'_>, name: TokenStream) -> TokenStream {
+    let size = compile_data_type(opts, ty, name);
+    if let Type::Tuple(_) = ty {
+        quote! { ::mabo::buf::size_tuple(|| { #size }) }
+    } else {
+        size
+    }
 }
 
 #[allow(clippy::needless_pass_by_value, clippy::too_many_lines)]
@@ -200,6 +223,9 @@ pub(crate) fn compile_data_type(opts: &Opts, ty: &Type<'_>, name: TokenStream) -
         Type::String | Type::StringRef | Type::BoxString => {
             quote! { ::mabo::buf::size_string(#name) }
         }
+        Type::BytesRef if opts.ref_type == RefType::Borrowed => {
+            quote! { ::mabo::buf::size_bytes_std(#name) }
+        }
         Type::Bytes | Type::BytesRef | Type::BoxBytes => match opts.bytes_type {
             BytesType::VecU8 => quote! { ::mabo::buf::size_bytes_std(#name) },
             BytesType::Bytes => quote! { ::mabo::buf::size_bytes_bytes(#name) },
@@ -217,6 +243,15 @@ pub(crate) fn compile_data_type(opts: &Opts, ty: &Type<'_>, name: TokenStream) -
             let ty = compile_data_type(opts, ty, quote! { v });
             quote! { ::mabo::buf::size_hash_set(#name, |v| { #ty }) }
         }
+        Type::BTreeMap(kv) => {
+            let ty_k = compile_data_type(opts, &kv.0, quote! { k });
+            let ty_v = compile_data_type(opts, &kv.1, quote! { v });
+            quote! { ::mabo::buf::size_btree_map(#name, |k| { #ty_k }, |v| { #ty_v }) }
+        }
+        Type::BTreeSet(ty) => {
+            let ty = compile_data_type(opts, ty, quote! { v });
+            quote! { ::mabo::buf::size_btree_set(#name, |v| { #ty }) }
+        }
         Type::Option(ty) => {
             let ty = compile_data_type(opts, ty, quote! { v });
             quote! { ::mabo::buf::size_option(#name.as_ref(), |v| { #ty }) }
@@ -238,7 +273,9 @@ pub(crate) fn compile_data_type(opts: &Opts, ty: &Type<'_>, name: TokenStream) -
             | Type::BytesRef
             | Type::Vec(_)
             | Type::HashMap(_)
-            | Type::HashSet(_) => compile_data_type(opts, ty, quote! { #name.get() }),
+            | Type::HashSet(_)
+            | Type::BTreeMap(_)
+            | Type::BTreeSet(_) => compile_data_type(opts, ty, quote! { #name.get() }),
             ty => todo!("compiler should catch invalid {ty:?} type"),
         },
         Type::Tuple(types) => match types.len() {
@@ -0,0 +1,120 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+
+use crate::{Error, Result};
+
+/// Directory of the project, with all the schema files and sub-directories it contains.
+#[derive(Default)]
+struct Directory<'a> {
+    directories: BTreeMap<&'a str, Directory<'a>>,
+    schemas: BTreeSet<&'a str>,
+}
+
+/// Generate the entry file, which mirrors the directory layout of the project as module tree and
+/// includes the generated code of each schema in its place.
+///
+/// Each schema is given as the list of directories that contain it (relative to the project),
+/// and its name.
+///
+/// # Errors
+///
+/// Will return `Err` if several entries of the same directory map to the same module name.
+pub(crate) fn compile_entry(schemas: &[(Vec<String>, &str)]) -> Result<TokenStream> {
+    let mut root = Directory::default();
+
+    for (directories, name) in schemas {
+        directories
+            .iter()
+            .fold(&mut root, |dir, name| {
+                dir.directories.entry(name).or_default()
+            })
+            .schemas
+            .insert(name);
+    }
+
+    compile_directory(&root, &mut Vec::new())
+}
+
+fn compile_directory<'a>(dir: &Directory<'a>, path: &mut Vec<&'a str>) -> Result<TokenStream> {
+    let mut modules = BTreeMap::new();
+    let mut claim = |name: &str, entry: String| {
+        let module = module_name(name);
+        match modules.insert(module.to_string(), entry.clone()) {
+            Some(first) => Err(Error::ModuleCollision {
+                first,
+                second: entry,
+                module: module.to_string(),
+            }),
+            None => Ok(module),
+        }
+    };
+
+    let mut directories = Vec::with_capacity(dir.directories.len());
+    for (name, dir) in &dir.directories {
+        path.push(name);
+        let content = compile_directory(dir, path)?;
+        let module = claim(name, format!("{}/", path.join("/")))?;
+        path.pop();
+
+        directories.push(quote! {
+            pub mod #module {
+                #content
+            }
+        });
+    }
+
+    let mut schemas = Vec::with_capacity(dir.schemas.len());
+    for name in &dir.schemas {
+        let file = path
+            .iter()
+            .copied()
+            .chain([*name])
+            .collect::<Vec<_>>()
+            .join("/");
+        let module = claim(name, format!("{file}.mabo"))?;
+        let file = format!("/mabo/{file}.rs");
+
+        schemas.push(quote! {
+            pub mod #module {
+                include!(concat!(env!("OUT_DIR"), #file));
+            }
+        });
+    }
+
+    Ok(quote! {
+        #(#directories)*
+        #(#schemas)*
+    })
+}
+
+/// Turn a directory or file name into a valid Rust module name, by replacing any characters that
+/// aren't allowed in identifiers.
+///
+/// Keywords become raw identifiers like `r#enum`, except for the ones that can't be raw (like
+/// `self`), which get a trailing underscore instead.
+pub(crate) fn module_name(name: &str) -> Ident {
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Ident::new(&format!("_{name}"), Span::call_site());
+    }
+
+    match name.as_str() {
+        "_" | "crate" | "self" | "Self" | "super" => {
+            Ident::new(&format!("{name}_"), Span::call_site())
+        }
+        _ if syn::parse_str::<Ident>(&name).is_err() => Ident::new_raw(&name, Span::call_site()),
+        _ => Ident::new(&name, Span::call_site()),
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections::HashSet;
+
+use mabo_compiler::simplify::{
+    Definition, Enum, ExternalType, Field, FieldKind, Fields, Literal, Struct, Type, Validation,
+    Variant,
+};
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+
+use crate::Opts;
+
+/// Collect the names of all structs, enums and type aliases that don't need to be validated, as
+/// neither they nor any of the types they contain declare rules. These don't get a `Validate`
+/// implementation.
+///
+/// Generic types always need validation, as their type arguments might have rules. For the same
+/// reason, any type that is used as type argument implements `Validate`. Types that aren't
+/// declared in the given schemas, like imports of other schemas, are expected to implement it.
+pub(crate) fn find_unvalidated(schemas: &[&[Definition<'_>]]) -> HashSet<String> {
+    let mut declared = HashSet::new();
+    let mut validated = HashSet::new();
+
+    for definitions in schemas {
+        collect_declared(definitions, &mut declared, &mut validated);
+    }
+
+    loop {
+        let count = validated.len();
+        for definitions in schemas {
+            collect_validated(definitions, &declared, &mut validated);
+        }
+
+        if validated.len() == count {
+            break;
+        }
+    }
+
+    declared.retain(|name| !validated.contains(name));
+    declared
+}
+
+/// Collect the names of all declared types, as well as all types that are used as type
+/// arguments.
+fn collect_declared(
+    definitions: &[Definition<'_>],
+    declared: &mut HashSet<String>,
+    arguments: &mut HashSet<String>,
+) {
+    for definition in definitions {
+        let (name, types) = match definition {
+            Definition::Module(m) => {
+                collect_declared(&m.definitions, declared, arguments);
+                continue;
+            }
+            Definition::Struct(s) => (s.name, s.fields.fields.iter().map(|f| &f.ty).collect()),
+            Definition::Enum(e) => (
+                e.name,
+                e.variants
+                    .iter()
+                    .flat_map(|v| v.fields.fields.iter().map(|f| &f.ty))
+                    .collect(),
+            ),
+            Definition::TypeAlias(a) => (a.name, vec![&a.target]),
+            Definition::Const(_) | Definition::Import(_) => continue,
+        };
+
+        declared.insert(name.to_owned());
+        for ty in types {
+            visit_externals(ty, &mut |external| {
+                for ty in &external.generics {
+                    visit_externals(ty, &mut |argument| {
+                        arguments.insert(argument.name.to_owned());
+                    });
+                }
+            });
+        }
+    }
+}
+
+/// Call the visitor for each external type within the type, including their type arguments.
+fn visit_externals(ty: &Type<'_>, visit: &mut impl FnMut(&ExternalType<'_>)) {
+    match ty {
+        Type::Vec(ty)
+        | Type::HashSet(ty)
+        | Type::BTreeSet(ty)
+        | Type::Option(ty)
+        | Type::NonZero(ty)
+        | Type::Array(ty, _) => visit_externals(ty, visit),
+        Type::HashMap(kv) | Type::BTreeMap(kv) => {
+            visit_externals(&kv.0, visit);
+            visit_externals(&kv.1, visit);
+        }
+        Type::Tuple(types) => types.iter().for_each(|ty| visit_externals(ty, visit)),
+        Type::External(external) => {
+            visit(external);
+            for ty in &external.generics {
+                visit_externals(ty, visit);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn collect_validated(
+    definitions: &[Definition<'_>],
+    declared: &HashSet<String>,
+    validated: &mut HashSet<String>,
+) {
+    for definition in definitions {
+        let nested = |generics: &[&str], ty: &Type<'_>| {
+            contains_validated(ty, &|name| {
+                generics.contains(&name) || validated.contains(name) || !declared.contains(name)
+            })
+        };
+        let checked = |generics: &[&str], fields: &Fields<'_>| {
+            fields.fields.iter().any(|field| {
+                !field.attributes.validation().is_empty() || nested(generics, &field.ty)
+            })
+        };
+
+        let (name, needs_validation) = match definition {
+            Definition::Module(m) => {
+                collect_validated(&m.definitions, declared, validated);
+                continue;
+            }
+            Definition::Struct(s) => (s.name, checked(&s.generics, &s.fields)),
+            Definition::Enum(e) => (
+                e.name,
+                e.variants.iter().any(|v| checked(&e.generics, &v.fields)),
+            ),
+            Definition::TypeAlias(a) => (a.name, nested(&a.generics, &a.target)),
+            Definition::Const(_) | Definition::Import(_) => continue,
+        };
+
+        if needs_validation {
+            validated.insert(name.to_owned());
+        }
+    }
+}
+
+pub(super) fn compile_struct(
+    opts: &Opts,
+    Struct {
+        attributes,
+        name,
+        generics: types,
+        fields,
+        ..
+    }: &Struct<'_>,
+) -> TokenStream {
+    if !opts.validated(name) {
+        return TokenStream::new();
+    }
+
+    let allow_deprecated = super::definition::allow_deprecated_struct(attributes, fields);
+    let (generics, generics_where) = compile_generics(types, opts.borrowed.contains(*name));
+    let name = Ident::new(name, Span::call_site());
+    let core = opts.core();
+
+    let body = if has_checks(opts, types, fields) {
+        let pattern = compile_pattern(opts, types, fields);
+        let checks = compile_checks(opts, types, fields, &TokenStream::new());
+
+        quote! {
+            let Self #pattern = self;
+            #checks
+        }
+    } else {
+        TokenStream::new()
+    };
+
+    quote! {
+        #[automatically_derived]
+        #allow_deprecated
+        impl #generics ::mabo::Validate for #name #generics #generics_where {
+            #[allow(clippy::too_many_lines)]
+            fn validate(&self) -> #core::result::Result<(), ::mabo::ValidationError> {
+                #body
+                Ok(())
+            }
+        }
+    }
+}
+
+pub(super) fn compile_enum(
+    opts: &Opts,
+    Enum {
+        attributes,
+        name,
+        generics: types,
+        variants,
+        ..
+    }: &Enum<'_>,
+) -> TokenStream {
+    if !opts.validated(name) {
+        return TokenStream::new();
+    }
+
+    let allow_deprecated = super::definition::allow_deprecated_enum(attributes, variants);
+    let (generics, generics_where) = compile_generics(types, opts.borrowed.contains(*name));
+    let name = Ident::new(name, Span::call_site());
+    let core = opts.core();
+
+    let body = variants
+        .iter()
+        .any(|variant| has_checks(opts, types, &variant.fields))
+        .then(|| {
+            let variants = variants
+                .iter()
+                .map(|variant| compile_variant(opts, types, variant));
+            quote! {
+                match self {
+                    #(#variants,)*
+                }
+            }
+        });
+
+    quote! {
+        #[automatically_derived]
+        #allow_deprecated
+        impl #generics ::mabo::Validate for #name #generics #generics_where {
+            #[allow(clippy::too_many_lines)]
+            fn validate(&self) -> #core::result::Result<(), ::mabo::ValidationError> {
+                #body
+                Ok(())
+            }
+        }
+    }
+}
+
+fn compile_variant(
+    opts: &Opts,
+    generics: &[&str],
+    Variant { name, fields, .. }: &Variant<'_>,
+) -> TokenStream {
+    let within = proc_macro2::Literal::string(name);
+    let name = Ident::new(name, Span::call_site());
+    let pattern = compile_pattern(opts, generics, fields);
+    let checks = compile_checks(opts, generics, fields, &quote! { .within(#within) });
+
+    quote! { Self::#name #pattern => { #checks } }
+}
+
+/// Whether any of the fields needs to be checked, either for its own rules or for the rules of
+/// values it contains.
+fn has_checks(opts: &Opts, generics: &[&str], fields: &Fields<'_>) -> bool {
+    fields.fields.iter().any(|field| {
+        !field.attributes.validation().is_empty() || is_nested(opts, generics, &field.ty)
+    })
+}
+
+/// Destructure the fields that need to be checked, and ignore the rest.
+fn compile_pattern(opts: &Opts, generics: &[&str], fields: &Fields<'_>) -> TokenStream {
+    let checked = |field: &Field<'_>| {
+        (!field.attributes.validation().is_empty() || is_nested(opts, generics, &field.ty))
+            .then(|| Ident::new(&field.name, Span::call_site()))
+    };
+
+    match fields.kind {
+        FieldKind::Named => {
+            let names = fields.fields.iter().filter_map(checked);
+            quote! { { #(#names,)* .. } }
+        }
+        FieldKind::Unnamed => {
+            let names = fields
+                .fields
+                .iter()
+                .map(|field| checked(field).map_or_else(|| quote! { _ }, |name| quote! { #name }));
+            quote! { (#(#names,)*) }
+        }
+        FieldKind::Unit => TokenStream::new(),
+    }
+}
+
+/// Generate the checks for all fields. The `within` suffix allows to extend the path of any
+/// error, like adding the variant name for enums.
+fn compile_checks(
+    opts: &Opts,
+    generics: &[&str],
+    fields: &Fields<'_>,
+    within: &TokenStream,
+) -> TokenStream {
+    let checks = fields.fields.iter().enumerate().map(|(i, field)| {
+        let path = if fields.kind == FieldKind::Named {
+            proc_macro2::Literal::string(&field.name)
+        } else {
+            proc_macro2::Literal::string(&i.to_string())
+        };
+        let name = Ident::new(&field.name, Span::call_site());
+        let rules = compile_rules(
+            &field.attributes.validation(),
+            &field.ty,
+            &name,
+            &path,
+            within,
+        );
+        let nested = is_nested(opts, generics, &field.ty).then(|| {
+            quote! { ::mabo::Validate::validate(#name).map_err(|e| e.within(#path)#within)?; }
+        });
+
+        quote! {
+            #rules
+            #nested
+        }
+    });
+
+    quote! { #(#checks)* }
+}
+
+/// Generate the checks for the declared rules of a single field, unwrapping optional values and
+/// skipping the checks if they're not present.
+fn compile_rules(
+    validation: &Validation<'_>,
+    ty: &Type<'_>,
+    name: &Ident,
+    path: &proc_macro2::Literal,
+    within: &TokenStream,
+) -> Option<TokenStream> {
+    if validation.is_empty() {
+        return None;
+    }
+
+    if let Type::Option(ty) = ty {
+        let rules = compile_rules(validation, ty, name, path, within);
+        return Some(quote! {
+            if let Some(#name) = #name {
+                #rules
+            }
+        });
+    }
+
+    let (value, is_float) = match ty {
+        Type::NonZero(_) => (quote! { #name.get() }, false),
+        Type::F32 | Type::F64 => (quote! { *#name }, true),
+        _ => (quote! { *#name }, false),
+    };
+
+    let min = validation.min.map(|min| {
+        let min = compile_bound(min, is_float);
+        quote! { ::mabo::validate::min(#path, #value, #min) }
+    });
+    let max = validation.max.map(|max| {
+        let max = compile_bound(max, is_float);
+        quote! { ::mabo::validate::max(#path, #value, #max) }
+    });
+    let min_len = validation.min_len.map(|min| {
+        let min = proc_macro2::Literal::u64_unsuffixed(min);
+        quote! { ::mabo::validate::min_len(#path, #name.len(), #min) }
+    });
+    let max_len = validation.max_len.map(|max| {
+        let max = proc_macro2::Literal::u64_unsuffixed(max);
+        quote! { ::mabo::validate::max_len(#path, #name.len(), #max) }
+    });
+    let pattern = validation.pattern.map(|pattern| {
+        let pattern = proc_macro2::Literal::string(pattern);
+        quote! {{
+            static PATTERN: ::mabo::validate::Pattern = ::mabo::validate::Pattern::new(#pattern);
+            ::mabo::validate::pattern(#path, #name, &PATTERN)
+        }}
+    });
+    let min_items = validation.min_items.map(|min| {
+        let min = proc_macro2::Literal::u64_unsuffixed(min);
+        quote! { ::mabo::validate::min_items(#path, #name.len(), #min) }
+    });
+    let max_items = validation.max_items.map(|max| {
+        let max = proc_macro2::Literal::u64_unsuffixed(max);
+        quote! { ::mabo::validate::max_items(#path, #name.len(), #max) }
+    });
+
+    let checks = [min, max, min_len, max_len, pattern, min_items, max_items]
+        .into_iter()
+        .flatten()
+        .map(|check| {
+            if within.is_empty() {
+                quote! { #check?; }
+            } else {
+                quote! { #check.map_err(|e| e #within)?; }
+            }
+        });
+
+    Some(quote! { #(#checks)* })
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn compile_bound(literal: &Literal, is_float: bool) -> proc_macro2::Literal {
+    match literal {
+        Literal::Int(value) if is_float => proc_macro2::Literal::f64_unsuffixed(*value as f64),
+        Literal::Int(value) => proc_macro2::Literal::i128_unsuffixed(*value),
+        Literal::Float(value) => proc_macro2::Literal::f64_unsuffixed(*value),
+        _ => unreachable!("compiler should catch invalid bounds"),
+    }
+}
+
+/// Whether the type contains other structs or enums (or generic types), which need to be
+/// validated as well.
+fn is_nested(opts: &Opts, generics: &[&str], ty: &Type<'_>) -> bool {
+    contains_validated(ty, &|name| generics.contains(&name) || opts.validated(name))
+}
+
+/// Whether the type contains any external type, for which the check returns `true`.
+fn contains_validated(ty: &Type<'_>, check: &impl Fn(&str) -> bool) -> bool {
+    match ty {
+        Type::Vec(ty)
+        | Type::HashSet(ty)
+        | Type::BTreeSet(ty)
+        | Type::Option(ty)
+        | Type::NonZero(ty)
+        | Type::Array(ty, _) => contains_validated(ty, check),
+        Type::HashMap(kv) | Type::BTreeMap(kv) => {
+            contains_validated(&kv.0, check) || contains_validated(&kv.1, check)
+        }
+        Type::Tuple(types) => types.iter().any(|ty| contains_validated(ty, check)),
+        Type::External(ExternalType { name, .. }) => check(name),
+        _ => false,
+    }
+}
+
+fn compile_generics(types: &[&str], borrowed: bool) -> (TokenStream, TokenStream) {
+    let generics = super::definition::compile_generics(types, borrowed).unwrap_or_default();
+    let generics_where = if types.is_empty() {
+        TokenStream::new()
+    } else {
+        let types = types.iter().map(|ty| Ident::new(ty, Span::call_site()));
+        quote! { where #(#types: ::mabo::Validate,)* }
+    };
+
+    (generics, generics_where)
+}
@@ -1,51 +1,95 @@
-use std::{
-    fs,
-    path::{Path, PathBuf},
-};
+use std::{fs, path::Path};
 
 use insta::{assert_snapshot, glob, with_settings};
-use mabo_build::Opts;
+use mabo_build::{BytesType, Compiler, EnumTagging, Opts, RefType};
 use mabo_parser::Schema;
 
-fn strip_path(path: &Path) -> PathBuf {
-    path.strip_prefix(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/inputs"))
-        .or_else(|_| path.strip_prefix(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/inputs_extra")))
-        .unwrap()
-        .to_owned()
-}
+/// Generate the code for all schemas in the given input directory, and compare it against the
+/// snapshots named after the directory.
+fn compile(dir: &str, opts: &Opts) {
+    let base = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(dir);
+    let name = format!("compile{}", dir.trim_start_matches("inputs"));
 
-#[test]
-fn compile_schema() {
-    glob!("inputs/*.mabo", |path| {
+    glob!(&format!("{dir}/*.mabo"), |path| {
         let input = fs::read_to_string(path).unwrap();
-        let value = Schema::parse(input.as_str(), Some(&strip_path(path))).unwrap();
+        let value = Schema::parse(input.as_str(), Some(path.strip_prefix(&base).unwrap())).unwrap();
         let value = mabo_compiler::simplify_schema(&value);
-        let value = mabo_build::compile_schema(&Opts::default(), &value);
+        let value = mabo_build::compile_schema(opts, &value);
         let value = prettyplease::unparse(&syn::parse2(value.clone()).unwrap());
 
         with_settings!({
             description => input.trim(),
             omit_expression => true,
         }, {
-            assert_snapshot!("compile", value);
+            assert_snapshot!(name.as_str(), value);
         });
     });
 }
 
+#[test]
+fn compile_schema() {
+    compile("inputs", &Opts::default());
+}
+
 #[test]
 fn compile_schema_extra() {
-    glob!("inputs_extra/*.mabo", |path| {
-        let input = fs::read_to_string(path).unwrap();
-        let value = Schema::parse(input.as_str(), Some(&strip_path(path))).unwrap();
-        let value = mabo_compiler::simplify_schema(&value);
-        let value = mabo_build::compile_schema(&Opts::default(), &value);
-        let value = prettyplease::unparse(&syn::parse2(value.clone()).unwrap());
+    compile("inputs_extra", &Opts::default());
+}
 
-        with_settings!({
-            description => input.trim(),
-            omit_expression => true,
-        }, {
-            assert_snapshot!("compile_extra", value);
+#[test]
+fn compile_schema_borrowed() {
+    let opts = Compiler::default().with_ref_type(RefType::Borrowed);
+    compile("inputs_borrowed", &opts.opts().unwrap());
+}
+
+#[test]
+fn compile_schema_unknown_fields() {
+    let opts = Compiler::default().with_unknown_fields(true);
+    compile("inputs_unknown", &opts.opts().unwrap());
+}
+
+#[test]
+fn compile_schema_decode_validation() {
+    let opts = Compiler::default().with_decode_validation(true);
+    compile("inputs_validation", &opts.opts().unwrap());
+}
+
+#[test]
+fn compile_schema_custom_attributes() {
+    let opts = Compiler::default()
+        .with_type_derive("", "Eq")
+        .with_type_derive("custom::Choice", "Hash")
+        .with_type_derive("custom::Choice", "PartialEq")
+        .with_type_attribute("custom::Choice", "#[non_exhaustive]")
+        .with_type_attribute("custom::inner", "#[must_use]")
+        .with_type_derive("custom::ordered", "Hash")
+        .with_type_derive("custom::ordered", "PartialOrd")
+        .with_type_derive("custom::ordered", "Ord");
+    compile("inputs_custom", &opts.opts().unwrap());
+}
+
+#[test]
+fn compile_schema_serde() {
+    let opts = Compiler::default()
+        .with_bytes_type(BytesType::Bytes)
+        .with_serde(true)
+        .with_enum_tagging(EnumTagging::Adjacent {
+            tag: "type".to_owned(),
+            content: "content".to_owned(),
         });
-    });
+    compile("inputs_serde", &opts.opts().unwrap());
+}
+
+#[test]
+fn compile_schema_no_std() {
+    let opts = Compiler::default().with_no_std(true);
+    compile("inputs_no_std", &opts.opts().unwrap());
+}
+
+#[test]
+fn compile_schema_canonical() {
+    let opts = Compiler::default().with_canonical_encoding("canonical::Signed");
+    compile("inputs_canonical", &opts.opts().unwrap());
 }
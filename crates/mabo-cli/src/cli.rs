@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use clap::{Args, Parser, Subcommand, ValueHint};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum, ValueHint};
 
 /// Command line interface to manage and support Mabo schema projects.
 #[derive(Parser)]
@@ -28,6 +28,29 @@ pub enum Command {
     /// Generate documentation for a project.
     #[command(visible_aliases = ["d", "document"])]
     Doc(DocArgs),
+    /// Detect breaking changes between two versions of a project.
+    ///
+    /// The current state of the project is compared against an older version of it, which is
+    /// either taken from a Git revision or a separate directory. Any change that would prevent
+    /// the new version of the schemas from exchanging data with the old one, like changed field
+    /// IDs, altered field types or new required fields, is reported and results in an error.
+    #[command(visible_aliases = ["di"])]
+    Diff(DiffArgs),
+    /// Decode a binary payload and print its content.
+    ///
+    /// The payload is read from a file or the standard input. It is either decoded as a specific
+    /// type from the project's schemas and printed as JSON, or in raw mode without any schema,
+    /// purely based on the field identifiers and encodings it contains.
+    #[command(visible_aliases = ["de"])]
+    Decode(DecodeArgs),
+    /// Encode a JSON or TOML value into a binary payload, using a type from the project's
+    /// schemas.
+    ///
+    /// This is the reverse of the `decode` command and accepts the same JSON structure that it
+    /// outputs. The input is validated against the type, and any mismatch is reported together
+    /// with the path to the offending value.
+    #[command(visible_aliases = ["en"])]
+    Encode(EncodeArgs),
 }
 
 /// Arguments for the [`Command::Init`] subcommand.
@@ -46,6 +69,13 @@ pub struct InitArgs {
     /// point to a directory, not a file.
     #[arg(value_hint = ValueHint::DirPath)]
     pub path: Option<PathBuf>,
+    /// Additionally create a `build.rs` file that generates Rust code from the schemas.
+    ///
+    /// This is meant for projects that live inside a Rust crate. The build script uses the
+    /// `mabo-build` crate to compile all schemas of the project, which must be added to the
+    /// crate's build dependencies separately.
+    #[arg(long)]
+    pub build_script: bool,
 }
 
 /// Arguments for the [`Command::Check`] subcommand.
@@ -82,8 +112,17 @@ pub struct FmtArgs {
     /// Using this will disable the loading of a project and instead locate the files from the glob
     /// patterns, then treat them as one single set. The files will be treated as a single project
     /// but the `Mabo.toml` file is fully ignored.
+    ///
+    /// A single `-` reads the schema from the standard input instead, and writes the formatted
+    /// result to the standard output.
     #[arg(conflicts_with = "project_dir")]
     pub files: Vec<String>,
+    /// Only check whether the files are formatted correctly, without modifying them.
+    ///
+    /// For each file that would be changed by the formatter, a unified diff of the changes is
+    /// printed. If any of the files is not formatted, the command fails with a non-zero exit code.
+    #[arg(long)]
+    pub check: bool,
 }
 
 /// Arguments for the [`Command::Doc`] subcommand.
@@ -104,6 +143,102 @@ pub struct DocArgs {
     pub out_dir: PathBuf,
 }
 
+/// Arguments for the [`Command::Diff`] subcommand.
+#[derive(Args)]
+pub struct DiffArgs {
+    /// Alternative location of the project directory containing a `Mabo.toml` file.
+    ///
+    /// By default, the current directory is assumed to be the project directory. This is the root
+    /// from where the command operates. Therefore, using it has the same effect as moving to the
+    /// project directory and executing the command without it.
+    #[arg(long, value_hint = ValueHint::DirPath)]
+    pub project_dir: Option<PathBuf>,
+    /// Git revision of the old project version to compare against.
+    ///
+    /// This can be anything that Git understands as revision, like a commit hash, branch name or
+    /// tag. The project is looked up at the same location within the repository as the current
+    /// project directory.
+    #[arg(default_value = "HEAD")]
+    pub revision: String,
+    /// Directory containing the old version of the project, instead of a Git revision.
+    ///
+    /// The directory must contain a `Mabo.toml` file, same as the current project directory.
+    #[arg(long, value_hint = ValueHint::DirPath, conflicts_with = "revision")]
+    pub old_dir: Option<PathBuf>,
+}
+
+/// Arguments for the [`Command::Decode`] subcommand.
+#[derive(Args)]
+#[command(group(ArgGroup::new("mode").required(true).args(["raw", "ty"])))]
+pub struct DecodeArgs {
+    /// Alternative location of the project directory containing a `Mabo.toml` file.
+    ///
+    /// By default, the current directory is assumed to be the project directory. This is the root
+    /// from where the command operates. Therefore, using it has the same effect as moving to the
+    /// project directory and executing the command without it.
+    #[arg(long, value_hint = ValueHint::DirPath, conflicts_with = "raw")]
+    pub project_dir: Option<PathBuf>,
+    /// Fully qualified name of the type to decode the payload as, like `schema::module::Type`.
+    ///
+    /// The first element is the name of the schema file without extension, followed by any
+    /// modules and finally the name of a struct or enum. The decoded value is printed as JSON.
+    #[arg(long = "type", value_name = "NAME")]
+    pub ty: Option<String>,
+    /// Decode the payload without a schema, and print the tree of field IDs, encodings and values.
+    ///
+    /// Length-prefixed values are tried as nested structs first, then as UTF-8 strings and
+    /// finally shown as raw bytes. As the payload doesn't carry any type information, the result
+    /// is a best guess and might not always match the actual schema.
+    #[arg(long)]
+    pub raw: bool,
+    /// Binary file containing the encoded payload.
+    ///
+    /// If omitted or set to `-`, the payload is read from the standard input instead.
+    #[arg(value_hint = ValueHint::FilePath)]
+    pub file: Option<PathBuf>,
+}
+
+/// Arguments for the [`Command::Encode`] subcommand.
+#[derive(Args)]
+pub struct EncodeArgs {
+    /// Alternative location of the project directory containing a `Mabo.toml` file.
+    ///
+    /// By default, the current directory is assumed to be the project directory. This is the root
+    /// from where the command operates. Therefore, using it has the same effect as moving to the
+    /// project directory and executing the command without it.
+    #[arg(long, value_hint = ValueHint::DirPath)]
+    pub project_dir: Option<PathBuf>,
+    /// Fully qualified name of the type to encode the value as, like `schema::module::Type`.
+    ///
+    /// The first element is the name of the schema file without extension, followed by any
+    /// modules and finally the name of a struct or enum.
+    #[arg(long = "type", value_name = "NAME")]
+    pub ty: String,
+    /// Format of the input value.
+    ///
+    /// If omitted, the format is derived from the file extension, falling back to JSON for any
+    /// other extension or when reading from the standard input.
+    #[arg(long, value_enum)]
+    pub format: Option<InputFormat>,
+    /// Location to write the encoded payload to, instead of the standard output.
+    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    pub output: Option<PathBuf>,
+    /// File containing the value to encode.
+    ///
+    /// If omitted or set to `-`, the value is read from the standard input instead.
+    #[arg(value_hint = ValueHint::FilePath)]
+    pub file: Option<PathBuf>,
+}
+
+/// Supported formats to describe a value that should be encoded.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum InputFormat {
+    /// JavaScript Object Notation.
+    Json,
+    /// Tom's Obvious Minimal Language.
+    Toml,
+}
+
 impl Cli {
     pub fn parse() -> Self {
         <Self as Parser>::parse()
@@ -2,17 +2,22 @@
 
 use std::{
     fs,
+    io::{self, Read, Write},
     path::{Path, PathBuf},
-    process::ExitCode,
+    process::{Command, ExitCode},
 };
 
-use anyhow::Context;
+use anyhow::{bail, ensure, Context};
 use mabo_parser::Schema;
-use miette::Context as _;
+use miette::{Context as _, NamedSource, Report};
 
-use self::cli::{CheckArgs, Cli, DocArgs, FmtArgs};
+use self::cli::{
+    CheckArgs, Cli, DecodeArgs, DiffArgs, DocArgs, EncodeArgs, FmtArgs, InitArgs, InputFormat,
+};
 
 mod cli;
+mod raw;
+mod typed;
 
 #[global_allocator]
 static ALLOC: mimalloc::MiMalloc = mimalloc::MiMalloc;
@@ -22,13 +27,13 @@ fn main() -> ExitCode {
 
     if let Some(cmd) = cli.cmd {
         let result = match cmd {
-            cli::Command::Init(args) => {
-                println!("TODO: create basic setup at {:?}", args.path);
-                Ok(())
-            }
+            cli::Command::Init(args) => init(args),
             cli::Command::Check(args) => check(args),
             cli::Command::Fmt(args) => format(args),
             cli::Command::Doc(args) => doc(args),
+            cli::Command::Diff(args) => diff(args),
+            cli::Command::Decode(args) => decode(args),
+            cli::Command::Encode(args) => encode(args),
         };
 
         return match result {
@@ -43,25 +48,154 @@ fn main() -> ExitCode {
     ExitCode::SUCCESS
 }
 
+const SAMPLE_SCHEMA: &str = "\
+/// Sample struct to get started.
+struct Sample {
+    /// Unique identifier of the sample.
+    id: u32 @1,
+    /// Human readable name.
+    name: string @2,
+}
+";
+
+const BUILD_SCRIPT: &str = "\
+fn main() -> mabo_build::Result<()> {
+    mabo_build::Compiler::default().compile(env!(\"CARGO_MANIFEST_DIR\"))
+}
+";
+
+fn init(args: InitArgs) -> anyhow::Result<()> {
+    let path = project_dir(args.path)?;
+    let project_file = path.join("Mabo.toml");
+    let schema_file = path.join("schemas/sample.mabo");
+    let build_script = path.join("build.rs");
+
+    ensure!(
+        !project_file.exists(),
+        "a project already exists at {}",
+        path.display()
+    );
+    ensure!(
+        !schema_file.exists(),
+        "the schema file {} already exists",
+        schema_file.display()
+    );
+    ensure!(
+        !args.build_script || !build_script.exists(),
+        "the build script {} already exists",
+        build_script.display()
+    );
+
+    let name = match args.name {
+        Some(name) => name,
+        None => fs::create_dir_all(&path)
+            .and_then(|()| path.canonicalize())
+            .with_context(|| format!("failed finding the directory name of {}", path.display()))?
+            .file_name()
+            .context("project directory has no name")?
+            .to_string_lossy()
+            .into_owned(),
+    };
+
+    ensure!(
+        !name.trim().is_empty(),
+        "the project name must not be empty"
+    );
+
+    fs::create_dir_all(schema_file.parent().unwrap())
+        .with_context(|| format!("failed creating project directory {}", path.display()))?;
+    fs::write(
+        &project_file,
+        format!("[package]\nname = {name:?}\nfiles = [\"schemas/**/*.mabo\"]\n"),
+    )
+    .with_context(|| format!("failed writing {}", project_file.display()))?;
+    fs::write(&schema_file, SAMPLE_SCHEMA)
+        .with_context(|| format!("failed writing {}", schema_file.display()))?;
+
+    if args.build_script {
+        fs::write(&build_script, BUILD_SCRIPT)
+            .with_context(|| format!("failed writing {}", build_script.display()))?;
+    }
+
+    println!("created project {name} at {}", path.display());
+
+    Ok(())
+}
+
 fn check(args: CheckArgs) -> anyhow::Result<()> {
+    let mut inputs = Vec::new();
+
     for file in project_or_files(args.project_dir, args.files)? {
-        let buf = fs::read_to_string(&file).with_context(|| format!("failed reading {file:?}"))?;
+        let buf = fs::read_to_string(&file)
+            .with_context(|| format!("failed reading {}", file.display()))?;
+        inputs.push((file, buf));
+    }
+
+    let mut failures = 0;
+    let mut schemas = Vec::new();
+
+    for (file, buf) in &inputs {
+        let name = file
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .with_context(|| format!("invalid schema file name {}", file.display()))?;
+
+        let schema = match Schema::parse(buf, Some(file)).wrap_err("failed parsing schema file") {
+            Ok(schema) => schema,
+            Err(e) => {
+                eprintln!("{e:?}");
+                failures += 1;
+                continue;
+            }
+        };
 
-        if let Err(e) = Schema::parse(&buf, Some(&file)).wrap_err("failed parsing schema file") {
-            eprintln!("{e:?}");
+        if let Err(e) = mabo_compiler::validate_schema(&schema) {
+            let report = Report::new(e)
+                .with_source_code(NamedSource::new(file.display().to_string(), buf.clone()))
+                .wrap_err("invalid schema file");
+            eprintln!("{report:?}");
+            failures += 1;
         }
+
+        schemas.push((name, schema));
     }
 
+    let schemas = schemas
+        .iter()
+        .map(|(name, schema)| (*name, schema))
+        .collect::<Vec<_>>();
+
+    if let Err(e) = mabo_compiler::resolve_schemas(&schemas).wrap_err("failed resolving types") {
+        eprintln!("{e:?}");
+        failures += 1;
+    }
+
+    ensure!(
+        failures == 0,
+        "checking the schemas failed with {failures} error(s)"
+    );
+
     Ok(())
 }
 
 fn format(args: FmtArgs) -> anyhow::Result<()> {
+    if args.files.iter().any(|file| file == "-") {
+        ensure!(
+            args.files.len() == 1,
+            "standard input can't be combined with other files"
+        );
+        return format_stdin(args.check);
+    }
+
+    let mut failures = 0;
+
     for file in project_or_files(args.project_dir, args.files)? {
         let buf = fs::read_to_string(&file)?;
         let schema = match Schema::parse(&buf, Some(&file)).wrap_err("Failed parsing schema file") {
             Ok(schema) => schema,
             Err(e) => {
                 eprintln!("{e:?}");
+                failures += 1;
                 continue;
             }
         };
@@ -69,13 +203,57 @@ fn format(args: FmtArgs) -> anyhow::Result<()> {
         let formatted = schema.to_string();
 
         if buf != formatted {
-            fs::write(file, &formatted)?;
+            if args.check {
+                print_diff(&file.display().to_string(), &buf, &formatted);
+                failures += 1;
+            } else {
+                fs::write(file, &formatted)?;
+            }
         }
     }
 
+    ensure!(
+        failures == 0,
+        "{failures} file(s) are invalid or not formatted"
+    );
+
+    Ok(())
+}
+
+fn format_stdin(check: bool) -> anyhow::Result<()> {
+    let mut buf = String::new();
+    io::stdin()
+        .read_to_string(&mut buf)
+        .context("failed reading from standard input")?;
+
+    let schema = Schema::parse(&buf, None)
+        .wrap_err("Failed parsing schema file")
+        .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+    let formatted = schema.to_string();
+
+    if check {
+        if buf != formatted {
+            print_diff("<stdin>", &buf, &formatted);
+            bail!("the input is not formatted");
+        }
+    } else {
+        io::stdout()
+            .write_all(formatted.as_bytes())
+            .context("failed writing to standard output")?;
+    }
+
     Ok(())
 }
 
+fn print_diff(name: &str, old: &str, new: &str) {
+    print!(
+        "{}",
+        similar::TextDiff::from_lines(old, new)
+            .unified_diff()
+            .header(name, name)
+    );
+}
+
 fn doc(args: DocArgs) -> anyhow::Result<()> {
     let project = mabo_project::load(project_dir(args.project_dir)?)?;
 
@@ -105,6 +283,212 @@ fn write_doc_output(output: &mabo_doc::Output<'_>, parent: &Path) -> anyhow::Res
     Ok(())
 }
 
+fn diff(args: DiffArgs) -> anyhow::Result<()> {
+    let project_dir = project_dir(args.project_dir)?;
+    let new = read_project_files(&project_dir)?;
+
+    let old = if let Some(old_dir) = args.old_dir {
+        read_project_files(&old_dir)?
+    } else {
+        let old_dir = std::env::temp_dir().join(format!("mabo-diff-{}", std::process::id()));
+        let result = checkout_revision(&project_dir, &args.revision, &old_dir)
+            .and_then(|()| read_project_files(&old_dir));
+
+        fs::remove_dir_all(&old_dir).ok();
+        result?
+    };
+
+    let old = parse_project_files(&old)?;
+    let new = parse_project_files(&new)?;
+
+    let old = old
+        .iter()
+        .map(|(name, schema)| (name.as_str(), mabo_compiler::simplify_schema(schema)))
+        .collect::<Vec<_>>();
+    let new = new
+        .iter()
+        .map(|(name, schema)| (name.as_str(), mabo_compiler::simplify_schema(schema)))
+        .collect::<Vec<_>>();
+
+    let changes = mabo_compiler::compare_schemas(
+        &old.iter()
+            .map(|(name, schema)| (*name, schema))
+            .collect::<Vec<_>>(),
+        &new.iter()
+            .map(|(name, schema)| (*name, schema))
+            .collect::<Vec<_>>(),
+    );
+
+    for change in &changes {
+        println!("{change}");
+    }
+
+    let breaking = changes.iter().filter(|c| c.kind.is_breaking()).count();
+    ensure!(breaking == 0, "found {breaking} breaking change(s)");
+
+    println!("no breaking changes found");
+    Ok(())
+}
+
+fn decode(args: DecodeArgs) -> anyhow::Result<()> {
+    let payload = read_payload(args.file)?;
+
+    let Some(name) = args.ty else {
+        let payload = raw::decode(&payload)
+            .context("the payload could not be decoded as a struct or enum")?;
+        print!("{payload}");
+        return Ok(());
+    };
+
+    with_registry(args.project_dir, |registry| {
+        let value = typed::decode(registry, &name, &payload)?;
+        println!("{}", serde_json::to_string_pretty(&value)?);
+        Ok(())
+    })
+}
+
+fn encode(args: EncodeArgs) -> anyhow::Result<()> {
+    let format =
+        args.format
+            .unwrap_or_else(|| match args.file.as_deref().and_then(Path::extension) {
+                Some(ext) if ext == "toml" => InputFormat::Toml,
+                _ => InputFormat::Json,
+            });
+    let input = String::from_utf8(read_payload(args.file)?).context("input is not valid UTF-8")?;
+    let value: serde_json::Value = match format {
+        InputFormat::Json => serde_json::from_str(&input).context("failed parsing JSON input")?,
+        InputFormat::Toml => toml::from_str(&input).context("failed parsing TOML input")?,
+    };
+
+    let payload = with_registry(args.project_dir, |registry| {
+        typed::encode(registry, &args.ty, &value)
+    })?;
+
+    match args.output {
+        Some(output) => fs::write(&output, payload)
+            .with_context(|| format!("failed writing {}", output.display())),
+        None => io::stdout()
+            .write_all(&payload)
+            .context("failed writing to standard output"),
+    }
+}
+
+/// Load, validate and simplify all schemas of the project, and run the given function with a
+/// registry to look up types in them.
+fn with_registry<T>(
+    project_dir_arg: Option<PathBuf>,
+    f: impl FnOnce(&typed::Registry<'_>) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let files = read_project_files(&project_dir(project_dir_arg)?)?;
+    let schemas = parse_project_files(&files)?;
+
+    mabo_compiler::resolve_schemas(
+        &schemas
+            .iter()
+            .map(|(name, schema)| (name.as_str(), schema))
+            .collect::<Vec<_>>(),
+    )
+    .map_err(|e| anyhow::anyhow!("{:?}", Report::new(e)))?;
+
+    let schemas = schemas
+        .iter()
+        .map(|(name, schema)| (name.as_str(), mabo_compiler::simplify_schema(schema)))
+        .collect::<Vec<_>>();
+
+    f(&typed::Registry::new(&schemas))
+}
+
+/// Read the content of the given file, or the standard input if no file or `-` is given.
+fn read_payload(file: Option<PathBuf>) -> anyhow::Result<Vec<u8>> {
+    match file {
+        Some(file) if file != Path::new("-") => {
+            fs::read(&file).with_context(|| format!("failed reading {}", file.display()))
+        }
+        _ => {
+            let mut buf = Vec::new();
+            io::stdin()
+                .read_to_end(&mut buf)
+                .context("failed reading from standard input")?;
+            Ok(buf)
+        }
+    }
+}
+
+/// Write the `Mabo.toml` file and all schema files of the project at the given Git revision into
+/// the target directory.
+fn checkout_revision(project_dir: &Path, revision: &str, target: &Path) -> anyhow::Result<()> {
+    let output = Command::new("git")
+        .args(["ls-tree", "-r", "--name-only", revision, "--", "."])
+        .current_dir(project_dir)
+        .output()
+        .context("failed running git")?;
+    ensure!(
+        output.status.success(),
+        "failed listing files of revision {revision:?}: {}",
+        String::from_utf8_lossy(&output.stderr).trim(),
+    );
+
+    let files = String::from_utf8(output.stdout).context("file list is not valid UTF-8")?;
+
+    for file in files.lines().filter(|file| {
+        *file == "Mabo.toml" || Path::new(file).extension().is_some_and(|ext| ext == "mabo")
+    }) {
+        let output = Command::new("git")
+            .args(["show", &format!("{revision}:./{file}")])
+            .current_dir(project_dir)
+            .output()
+            .context("failed running git")?;
+        ensure!(
+            output.status.success(),
+            "failed reading {file:?} at revision {revision:?}: {}",
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+
+        let path = target.join(file);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, output.stdout)
+            .with_context(|| format!("failed writing {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Load the project from the given directory and read the content of all its schema files, keyed
+/// by the file name without extension.
+fn read_project_files(project_dir: &Path) -> anyhow::Result<Vec<(String, PathBuf, String)>> {
+    let project = mabo_project::load(project_dir)
+        .with_context(|| format!("failed loading project at {}", project_dir.display()))?;
+
+    project
+        .files
+        .into_iter()
+        .map(|file| {
+            let name = file
+                .file_stem()
+                .context("schema file without name")?
+                .to_string_lossy()
+                .into_owned();
+            let content = fs::read_to_string(&file)
+                .with_context(|| format!("failed reading {}", file.display()))?;
+
+            Ok((name, file, content))
+        })
+        .collect()
+}
+
+fn parse_project_files(
+    files: &[(String, PathBuf, String)],
+) -> anyhow::Result<Vec<(String, Schema<'_>)>> {
+    files
+        .iter()
+        .map(|(name, file, content)| {
+            Schema::parse(content, Some(file))
+                .map(|schema| (name.clone(), schema))
+                .map_err(|e| anyhow::anyhow!("{:?}", miette::Report::new(e)))
+        })
+        .collect()
+}
+
 fn project_or_files(
     project: Option<PathBuf>,
     patterns: Vec<String>,
@@ -0,0 +1,413 @@
+//! Inspection of Mabo encoded payloads without any knowledge about the schema they were encoded
+//! with.
+//!
+//! As each field carries its encoding in the identifier, the payload can be walked without
+//! knowing the actual types. Fields with the `LengthPrefixed` encoding are ambiguous, as they
+//! might contain a string, raw bytes, a collection or a nested struct, and nested structs or enums
+//! are even written without a length prefix. Therefore, all possible interpretations are tried,
+//! until the remaining payload can be fully decoded. Each position of the payload is only decoded
+//! once though, and nesting is limited, so crafted payloads can't stall the decoder.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Display, Write as _},
+    iter,
+    rc::Rc,
+};
+
+use mabo::{buf, FieldEncoding, FieldId};
+
+/// Fully decoded payload, which is either a struct or an enum variant at its root.
+pub enum Payload<'a> {
+    /// Struct, or the fields of a tuple struct.
+    Struct(Rc<[Field<'a>]>),
+    /// Enum variant with its identifier and fields.
+    Variant(u32, Rc<[Field<'a>]>),
+}
+
+/// Single field of a struct or enum variant.
+#[derive(Clone)]
+pub struct Field<'a> {
+    id: FieldId,
+    value: Value<'a>,
+}
+
+/// Possible interpretation of a field's value.
+#[derive(Clone)]
+enum Value<'a> {
+    Varint(&'a [u8]),
+    Fixed1(u8),
+    Fixed4(&'a [u8]),
+    Fixed8(&'a [u8]),
+    String(&'a str),
+    Bytes(&'a [u8]),
+    /// Struct that was wrapped in a length prefix.
+    Prefixed(Rc<[Field<'a>]>),
+    /// Struct that was written directly, without a length prefix.
+    Struct(Rc<[Field<'a>]>),
+    /// Enum variant that was written directly, without a length prefix.
+    Variant(u32, Rc<[Field<'a>]>),
+}
+
+/// Maximum nesting depth of structs and enums. Deeper payloads are rejected, to keep the
+/// recursion of the decoder in bounds.
+const MAX_DEPTH: usize = 32;
+
+/// All possible ways to decode a list of fields, together with the remaining data after each of
+/// them. Every distinct remainder is only kept once, with the interpretation that reached it first.
+type Parses<'a> = Rc<Vec<(Rc<[Field<'a>]>, &'a [u8])>>;
+
+/// Decode the payload as struct or enum variant, without the need for a schema.
+///
+/// Returns `None` if the payload doesn't fully decode as either of the two.
+pub fn decode(payload: &[u8]) -> Option<Payload<'_>> {
+    if payload.is_empty() {
+        return Some(Payload::Struct(Rc::new([])));
+    }
+
+    let mut decoder = Decoder {
+        payload,
+        fields: HashMap::new(),
+    };
+
+    if let Some(fields) = decoder.complete(payload, 0) {
+        return Some(Payload::Struct(fields));
+    }
+
+    decoder
+        .variants(payload, 0)
+        .into_iter()
+        .find_map(|(value, rest)| match value {
+            Value::Variant(id, fields) if rest.is_empty() => Some(Payload::Variant(id, fields)),
+            _ => None,
+        })
+}
+
+struct Decoder<'a> {
+    payload: &'a [u8],
+    /// Results of [`Self::fields`], keyed by the position and length of the input. Ambiguous
+    /// payloads would otherwise be decoded over and over again, for every possible interpretation
+    /// of the values that come before.
+    fields: HashMap<(usize, usize), Parses<'a>>,
+}
+
+impl<'a> Decoder<'a> {
+    /// Decode a list of fields that spans the whole input.
+    fn complete(&mut self, r: &'a [u8], depth: usize) -> Option<Rc<[Field<'a>]>> {
+        self.fields(r, depth)
+            .iter()
+            .find_map(|(fields, rest)| rest.is_empty().then(|| Rc::clone(fields)))
+    }
+
+    /// Decode a variant identifier, followed by its fields. Unit variants only consist of the
+    /// identifier.
+    fn variants(&mut self, mut r: &'a [u8], depth: usize) -> Vec<(Value<'a>, &'a [u8])> {
+        let Ok(id) = buf::decode_variant_id(&mut r) else {
+            return Vec::new();
+        };
+
+        self.fields(r, depth)
+            .iter()
+            .map(|(fields, rest)| (Value::Variant(id.value, Rc::clone(fields)), *rest))
+            .chain(iter::once((Value::Variant(id.value, Rc::from([])), r)))
+            .collect()
+    }
+
+    /// Decode a list of fields up to and including the end marker.
+    ///
+    /// The input is walked from front to back, where each position is only decoded once. If
+    /// several interpretations of the values lead to the same position, the first one is kept.
+    fn fields(&mut self, r: &'a [u8], depth: usize) -> Parses<'a> {
+        if depth > MAX_DEPTH {
+            return Parses::default();
+        }
+
+        let key = (
+            r.as_ptr() as usize - self.payload.as_ptr() as usize,
+            r.len(),
+        );
+        if let Some(parses) = self.fields.get(&key) {
+            return Rc::clone(parses);
+        }
+
+        let mut parses = Vec::new();
+        // keyed by the length of the remaining data, so the state closest to the front is last.
+        let mut pending = BTreeMap::from([(r.len(), (Vec::new(), r))]);
+
+        while let Some((_, (fields, mut rest))) = pending.pop_last() {
+            let Ok(id) = buf::decode_id(&mut rest) else {
+                continue;
+            };
+
+            if id.value == buf::END_MARKER {
+                parses.push((fields.into(), rest));
+                continue;
+            }
+
+            for (value, rest) in self.values(id.encoding, rest, depth) {
+                pending.entry(rest.len()).or_insert_with(|| {
+                    let mut fields = fields.clone();
+                    fields.push(Field { id, value });
+                    (fields, rest)
+                });
+            }
+        }
+
+        let parses = Rc::new(parses);
+        self.fields.insert(key, Rc::clone(&parses));
+        parses
+    }
+
+    /// Decode all possible interpretations of the next value. The most likely interpretation
+    /// comes first.
+    fn values(
+        &mut self,
+        encoding: FieldEncoding,
+        r: &'a [u8],
+        depth: usize,
+    ) -> Vec<(Value<'a>, &'a [u8])> {
+        let mut rest = r;
+        let raw = buf::decode_skip(&mut rest, encoding)
+            .ok()
+            .map(|()| &r[..r.len() - rest.len()]);
+
+        let value = raw.and_then(|raw| {
+            Some(match encoding {
+                FieldEncoding::Varint => Value::Varint(raw),
+                FieldEncoding::Fixed1 => Value::Fixed1(raw[0]),
+                FieldEncoding::Fixed4 => Value::Fixed4(raw),
+                FieldEncoding::Fixed8 => Value::Fixed8(raw),
+                FieldEncoding::LengthPrefixed => {
+                    let mut content = raw;
+                    buf::decode_u64(&mut content).ok()?;
+                    self.blob(content, depth)
+                }
+            })
+        });
+        let mut values = value
+            .map(|value| (value, rest))
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        if encoding == FieldEncoding::LengthPrefixed {
+            values.extend(
+                self.fields(r, depth + 1)
+                    .iter()
+                    .map(|(fields, rest)| (Value::Struct(Rc::clone(fields)), *rest)),
+            );
+            values.extend(self.variants(r, depth + 1));
+        }
+
+        values
+    }
+
+    /// Interpret the content of a length-prefixed value, preferring nested structs over strings
+    /// over raw bytes.
+    fn blob(&mut self, content: &'a [u8], depth: usize) -> Value<'a> {
+        if content.len() > 1 {
+            if let Some(fields) = self.complete(content, depth + 1) {
+                return Value::Prefixed(fields);
+            }
+        }
+
+        match std::str::from_utf8(content) {
+            Ok(s) if !s.chars().any(|c| c.is_control() && !c.is_whitespace()) => Value::String(s),
+            _ => Value::Bytes(content),
+        }
+    }
+}
+
+impl Display for Payload<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Struct(fields) => write_fields(f, fields, 0),
+            Self::Variant(id, fields) => {
+                write!(f, "variant @{id}")?;
+                write_variant_fields(f, fields, 0)?;
+                f.write_char('\n')
+            }
+        }
+    }
+}
+
+fn write_fields(f: &mut fmt::Formatter<'_>, fields: &[Field<'_>], indent: usize) -> fmt::Result {
+    for field in fields {
+        write!(f, "{:indent$}@{} ", "", field.id.value)?;
+        write_value(f, &field.value, indent)?;
+        f.write_char('\n')?;
+    }
+
+    Ok(())
+}
+
+fn write_variant_fields(
+    f: &mut fmt::Formatter<'_>,
+    fields: &[Field<'_>],
+    indent: usize,
+) -> fmt::Result {
+    if fields.is_empty() {
+        return Ok(());
+    }
+
+    f.write_str(" {\n")?;
+    write_fields(f, fields, indent + 4)?;
+    write!(f, "{:indent$}}}", "")
+}
+
+fn write_value(f: &mut fmt::Formatter<'_>, value: &Value<'_>, indent: usize) -> fmt::Result {
+    match value {
+        Value::Varint(raw) => {
+            let unsigned = buf::decode_u128(&mut &**raw).map_err(|_| fmt::Error)?;
+            let signed = buf::decode_i128(&mut &**raw).map_err(|_| fmt::Error)?;
+            write!(f, "varint: {unsigned} (zigzag: {signed})")
+        }
+        Value::Fixed1(value) => write!(f, "fixed1: {value:#04x} ({value})"),
+        Value::Fixed4(raw) => {
+            let value = buf::decode_f32(&mut &**raw).map_err(|_| fmt::Error)?;
+            write!(f, "fixed4: ")?;
+            write_hex(f, raw)?;
+            write!(f, " ({value})")
+        }
+        Value::Fixed8(raw) => {
+            let value = buf::decode_f64(&mut &**raw).map_err(|_| fmt::Error)?;
+            write!(f, "fixed8: ")?;
+            write_hex(f, raw)?;
+            write!(f, " ({value})")
+        }
+        Value::String(value) => write!(f, "length-prefixed: {value:?}"),
+        Value::Bytes(value) => {
+            write!(f, "length-prefixed: [")?;
+            write_hex(f, value)?;
+            f.write_char(']')
+        }
+        Value::Prefixed(fields) => {
+            f.write_str("length-prefixed {\n")?;
+            write_fields(f, fields, indent + 4)?;
+            write!(f, "{:indent$}}}", "")
+        }
+        Value::Struct(fields) => {
+            f.write_str("struct {\n")?;
+            write_fields(f, fields, indent + 4)?;
+            write!(f, "{:indent$}}}", "")
+        }
+        Value::Variant(id, fields) => {
+            write!(f, "variant @{id}")?;
+            write_variant_fields(f, fields, indent)
+        }
+    }
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, value: &[u8]) -> fmt::Result {
+    for (i, b) in value.iter().enumerate() {
+        if i > 0 {
+            f.write_char(' ')?;
+        }
+        write!(f, "{b:02x}")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use mabo::buf;
+
+    use super::*;
+
+    fn field(w: &mut Vec<u8>, id: u32, encoding: FieldEncoding, encode: impl Fn(&mut Vec<u8>)) {
+        buf::encode_field(w, FieldId::new(id, encoding), encode);
+    }
+
+    #[test]
+    fn decode_struct() {
+        let mut payload = Vec::new();
+        field(&mut payload, 1, FieldEncoding::Varint, |w| {
+            buf::encode_i32(w, -5);
+        });
+        field(&mut payload, 2, FieldEncoding::LengthPrefixed, |w| {
+            buf::encode_string(w, "hello");
+        });
+        field(&mut payload, 3, FieldEncoding::LengthPrefixed, |w| {
+            field(w, 1, FieldEncoding::Fixed1, |w| buf::encode_bool(w, true));
+            buf::encode_u32(w, buf::END_MARKER);
+        });
+        field(&mut payload, 4, FieldEncoding::LengthPrefixed, |w| {
+            buf::encode_bytes_std(w, &[0, 1, 2]);
+        });
+        field(&mut payload, 5, FieldEncoding::Fixed8, |w| {
+            buf::encode_f64(w, 1.5);
+        });
+        buf::encode_u32(&mut payload, buf::END_MARKER);
+
+        assert_eq!(
+            "\
+@1 varint: 9 (zigzag: -5)
+@2 length-prefixed: \"hello\"
+@3 struct {
+    @1 fixed1: 0x01 (1)
+}
+@4 length-prefixed: [00 01 02]
+@5 fixed8: 3f f8 00 00 00 00 00 00 (1.5)
+",
+            decode(&payload).unwrap().to_string(),
+        );
+    }
+
+    #[test]
+    fn decode_enum() {
+        let mut payload = Vec::new();
+        buf::encode_variant_id(&mut payload, mabo::VariantId::new(2));
+        field(&mut payload, 1, FieldEncoding::Varint, |w| {
+            buf::encode_u32(w, 300);
+        });
+        buf::encode_u32(&mut payload, buf::END_MARKER);
+
+        assert_eq!(
+            "\
+variant @2 {
+    @1 varint: 300 (zigzag: 150)
+}
+",
+            decode(&payload).unwrap().to_string(),
+        );
+    }
+
+    #[test]
+    fn decode_invalid() {
+        assert!(decode(&[0x0d, 10, 1]).is_none());
+    }
+
+    /// Struct fields that contain the next struct directly, nested `depth` times.
+    fn nested(depth: usize) -> Vec<u8> {
+        let mut payload = Vec::new();
+        for _ in 0..depth {
+            buf::encode_id(&mut payload, FieldId::new(1, FieldEncoding::LengthPrefixed));
+        }
+        for _ in 0..=depth {
+            buf::encode_u32(&mut payload, buf::END_MARKER);
+        }
+        payload
+    }
+
+    #[test]
+    fn decode_ambiguous() {
+        let mut payload = Vec::new();
+        for _ in 0..20 {
+            field(&mut payload, 1, FieldEncoding::LengthPrefixed, |w| {
+                buf::encode_u32(w, buf::END_MARKER);
+            });
+        }
+        buf::encode_u32(&mut payload, buf::END_MARKER);
+        // the trailing byte isn't a valid field identifier, so every interpretation fails, which
+        // used to be tried one by one.
+        payload.push(7);
+
+        assert!(decode(&payload).is_none());
+    }
+
+    #[test]
+    fn decode_depth() {
+        assert!(decode(&nested(MAX_DEPTH)).is_some());
+        assert!(decode(&nested(256)).is_none());
+    }
+}
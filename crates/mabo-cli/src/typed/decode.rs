@@ -0,0 +1,496 @@
+use anyhow::{anyhow, Result};
+use mabo::buf;
+use mabo_compiler::simplify::{FieldKind, Fields, Type};
+use serde_json::{Map, Number, Value};
+
+use super::{is_option, Path, Registry, Resolved, Scope, Segment, Target};
+
+/// Decode the payload as the type with the given fully qualified name, and convert it into JSON.
+///
+/// Structs become JSON objects (or arrays for tuple structs), and enum variants are represented
+/// as an object with the variant name as single key. Unit variants are a plain string instead.
+///
+/// Absent optional values are `null`. If an option directly contains another option, its value is
+/// wrapped in a single-element array, so `Some(None)` becomes `[null]`.
+///
+/// # Errors
+///
+/// Will return `Err` if the type can't be found in the registry, or the payload doesn't match
+/// the type. The error contains the path to the failing value and its byte offset.
+pub fn decode(registry: &Registry<'_>, name: &str, payload: &[u8]) -> Result<Value> {
+    let resolved = registry.find(name)?;
+    let mut decoder = Decoder {
+        registry,
+        payload,
+        name,
+        path: Vec::new(),
+    };
+
+    let mut r = payload;
+    let value = decoder.resolved(&mut r, &resolved)?;
+
+    if !r.is_empty() {
+        return Err(decoder.error(
+            decoder.offset(r),
+            anyhow!("found {} bytes of unexpected trailing data", r.len()),
+        ));
+    }
+
+    Ok(value)
+}
+
+struct Decoder<'a, 'r> {
+    registry: &'r Registry<'a>,
+    payload: &'r [u8],
+    /// Name of the root type.
+    name: &'r str,
+    path: Vec<Segment<'a>>,
+}
+
+impl<'a, 'r> Decoder<'a, 'r> {
+    fn resolved(&mut self, r: &mut &'r [u8], resolved: &Resolved<'a>) -> Result<Value> {
+        match resolved.target {
+            Target::Struct(s) => self.fields(r, &s.fields, &resolved.scope),
+            Target::Enum(e) => {
+                let offset = self.offset(r);
+                let id = self.read(r, buf::decode_variant_id)?.value;
+                let variant = e
+                    .variants
+                    .iter()
+                    .find(|variant| variant.id == id)
+                    .ok_or_else(|| self.error(offset, buf::Error::UnknownVariant(id)))?;
+
+                if variant.fields.kind == FieldKind::Unit {
+                    return Ok(Value::String(variant.name.to_owned()));
+                }
+
+                self.path.push(Segment::Variant(variant.name));
+                let value = self.fields(r, &variant.fields, &resolved.scope)?;
+                self.path.pop();
+
+                Ok(Value::Object(Map::from_iter([(
+                    variant.name.to_owned(),
+                    value,
+                )])))
+            }
+        }
+    }
+
+    fn fields(
+        &mut self,
+        r: &mut &'r [u8],
+        fields: &'a Fields<'a>,
+        scope: &Scope<'a>,
+    ) -> Result<Value> {
+        if fields.kind == FieldKind::Unit {
+            return Ok(Value::Null);
+        }
+
+        let mut values = vec![None; fields.fields.len()];
+
+        let end = loop {
+            let offset = self.offset(r);
+            let id = self.read(r, buf::decode_id)?;
+
+            if id.value == buf::END_MARKER {
+                break offset;
+            }
+
+            match fields.fields.iter().position(|field| field.id == id.value) {
+                Some(i) => {
+                    let field = &fields.fields[i];
+
+                    self.path.push(Segment::Field(&field.name));
+                    values[i] = Some(if let Type::Option(ty) = &field.ty {
+                        let value = self.value(r, ty, scope, true)?;
+                        some(value, ty, scope)
+                    } else {
+                        self.value(r, &field.ty, scope, true)?
+                    });
+                    self.path.pop();
+                }
+                None => self.read(r, |r| buf::decode_skip(r, id.encoding))?,
+            }
+        };
+
+        let values = fields
+            .fields
+            .iter()
+            .zip(values)
+            .map(|(field, value)| match value {
+                Some(value) => Ok(value),
+                None if matches!(field.ty, Type::Option(_)) => Ok(Value::Null),
+                None => Err(self.error(
+                    end,
+                    anyhow!("required field `{}` @{} is missing", field.name, field.id),
+                )),
+            });
+
+        if fields.kind == FieldKind::Named {
+            fields
+                .fields
+                .iter()
+                .zip(values)
+                .map(|(field, value)| Ok((field.name.to_string(), value?)))
+                .collect::<Result<_>>()
+                .map(Value::Object)
+        } else {
+            values.collect::<Result<_>>().map(Value::Array)
+        }
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn value(
+        &mut self,
+        r: &mut &'r [u8],
+        ty: &'a Type<'a>,
+        scope: &Scope<'a>,
+        root: bool,
+    ) -> Result<Value> {
+        Ok(match ty {
+            Type::Bool => self.read(r, buf::decode_bool)?.into(),
+            Type::U8 => self.read(r, buf::decode_u8)?.into(),
+            Type::U16 => self.read(r, buf::decode_u16)?.into(),
+            Type::U32 => self.read(r, buf::decode_u32)?.into(),
+            Type::U64 => self.read(r, buf::decode_u64)?.into(),
+            Type::U128 => {
+                let value = self.read(r, buf::decode_u128)?;
+                u64::try_from(value).map_or_else(|_| Value::String(value.to_string()), Value::from)
+            }
+            Type::I8 => self.read(r, buf::decode_i8)?.into(),
+            Type::I16 => self.read(r, buf::decode_i16)?.into(),
+            Type::I32 => self.read(r, buf::decode_i32)?.into(),
+            Type::I64 => self.read(r, buf::decode_i64)?.into(),
+            Type::I128 => {
+                let value = self.read(r, buf::decode_i128)?;
+                i64::try_from(value).map_or_else(|_| Value::String(value.to_string()), Value::from)
+            }
+            Type::F32 => float(self.read(r, buf::decode_f32)?.into()),
+            Type::F64 => float(self.read(r, buf::decode_f64)?),
+            Type::String | Type::StringRef | Type::BoxString => {
+                self.read(r, buf::decode_string)?.into()
+            }
+            Type::Bytes | Type::BytesRef | Type::BoxBytes => {
+                self.read(r, buf::decode_bytes_std)?.into()
+            }
+            Type::Vec(ty) | Type::HashSet(ty) | Type::BTreeSet(ty) => {
+                Value::Array(self.list(r, |this, r| this.value(r, ty, scope, false))?)
+            }
+            Type::Array(ty, size) => {
+                let offset = self.offset(r);
+                let mut values = self.list(r, |this, r| this.value(r, ty, scope, false))?;
+
+                if values.len() < *size as usize {
+                    return Err(self.error(
+                        offset,
+                        anyhow!("expected {size} array elements, but found {}", values.len()),
+                    ));
+                }
+
+                // ignore any additional values, in case an old array definition was larger.
+                values.truncate(*size as usize);
+                Value::Array(values)
+            }
+            Type::HashMap(kv) | Type::BTreeMap(kv) => {
+                let entries = self.list(r, |this, r| {
+                    let key = this.value(r, &kv.0, scope, false)?;
+                    let value = this.value(r, &kv.1, scope, false)?;
+                    Ok(Value::Array(vec![key, value]))
+                })?;
+
+                map(entries)
+            }
+            Type::Option(ty) => {
+                if self.read(r, buf::decode_u8)? == 1 {
+                    let value = self.value(r, ty, scope, false)?;
+                    some(value, ty, scope)
+                } else {
+                    Value::Null
+                }
+            }
+            Type::NonZero(ty) => {
+                let offset = self.offset(r);
+                let value = self.value(r, ty, scope, root)?;
+
+                if is_zero(&value) {
+                    return Err(self.error(offset, buf::Error::Zero));
+                }
+
+                value
+            }
+            Type::Tuple(types) => {
+                if root {
+                    self.read(r, buf::decode_u64)?;
+                }
+
+                let mut values = Vec::with_capacity(types.len());
+                for (i, ty) in types.iter().enumerate() {
+                    self.path.push(Segment::Index(i));
+                    values.push(self.value(r, ty, scope, false)?);
+                    self.path.pop();
+                }
+
+                Value::Array(values)
+            }
+            Type::External(ty) => {
+                if let Some((bound, bound_scope)) = scope.generic(ty) {
+                    let bound_scope = bound_scope.clone();
+                    return self.value(r, bound, &bound_scope, root);
+                }
+
+                let offset = self.offset(r);
+                let resolved = self
+                    .registry
+                    .resolve(scope, ty)
+                    .map_err(|e| self.error(offset, e))?;
+
+                self.resolved(r, &resolved)?
+            }
+        })
+    }
+
+    /// Decode a length-prefixed list of elements, like the content of a vector or hash map.
+    #[allow(clippy::cast_possible_truncation)]
+    fn list(
+        &mut self,
+        r: &mut &'r [u8],
+        mut decode: impl FnMut(&mut Self, &mut &'r [u8]) -> Result<Value>,
+    ) -> Result<Vec<Value>> {
+        let offset = self.offset(r);
+        let len = self.read(r, buf::decode_u64)? as usize;
+
+        if r.len() < len {
+            return Err(self.error(offset, buf::Error::InsufficientData));
+        }
+
+        let (mut content, rest) = r.split_at(len);
+        *r = rest;
+
+        let mut values = Vec::new();
+        while !content.is_empty() {
+            self.path.push(Segment::Index(values.len()));
+            values.push(decode(self, &mut content)?);
+            self.path.pop();
+        }
+
+        Ok(values)
+    }
+
+    /// Run one of the runtime's decoding functions, and attach the current location in case it
+    /// fails.
+    fn read<T>(
+        &self,
+        r: &mut &'r [u8],
+        decode: impl FnOnce(&mut &'r [u8]) -> buf::Result<T>,
+    ) -> Result<T> {
+        let offset = self.offset(r);
+        decode(r).map_err(|e| self.error(offset, e))
+    }
+
+    /// Calculate the byte offset of the remaining data within the full payload.
+    fn offset(&self, r: &[u8]) -> usize {
+        r.as_ptr() as usize - self.payload.as_ptr() as usize
+    }
+
+    fn error(&self, offset: usize, cause: impl Into<anyhow::Error>) -> anyhow::Error {
+        cause.into().context(format!(
+            "failed decoding `{}{}` at byte offset {offset}",
+            self.name,
+            Path(&self.path)
+        ))
+    }
+}
+
+/// Represent the present value of an option, wrapping it into an array if it's an option itself.
+fn some(value: Value, ty: &Type<'_>, scope: &Scope<'_>) -> Value {
+    if is_option(ty, scope) {
+        Value::Array(vec![value])
+    } else {
+        value
+    }
+}
+
+/// Convert a floating point number into JSON. As JSON doesn't support `NaN` or infinite numbers,
+/// these are represented as strings instead.
+fn float(value: f64) -> Value {
+    Number::from_f64(value).map_or_else(|| Value::String(value.to_string()), Value::Number)
+}
+
+/// Convert a list of key-value pairs into a JSON object, if all keys are strings. Otherwise, the
+/// pairs are kept as list.
+fn map(entries: Vec<Value>) -> Value {
+    if entries
+        .iter()
+        .all(|entry| matches!(entry, Value::Array(pair) if pair[0].is_string()))
+    {
+        Value::Object(
+            entries
+                .into_iter()
+                .filter_map(|entry| match entry {
+                    Value::Array(mut pair) => {
+                        let value = pair.pop()?;
+                        match pair.pop()? {
+                            Value::String(key) => Some((key, value)),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                })
+                .collect(),
+        )
+    } else {
+        Value::Array(entries)
+    }
+}
+
+fn is_zero(value: &Value) -> bool {
+    match value {
+        Value::Number(n) => n.as_u64() == Some(0) || n.as_i64() == Some(0),
+        Value::String(s) => s.is_empty(),
+        Value::Array(values) => values.is_empty(),
+        Value::Object(values) => values.is_empty(),
+        Value::Null | Value::Bool(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mabo::{FieldEncoding, FieldId, VariantId};
+    use mabo_parser::Schema;
+    use serde_json::json;
+
+    use super::*;
+
+    const SHOP: &str = "
+        use other::money::Price;
+
+        struct Order {
+            id: u64 @1,
+            items: vec<Item> @2,
+            note: option<string> @3,
+        }
+
+        struct Item {
+            name: string @1,
+            price: Price @2,
+        }
+    ";
+
+    const OTHER: &str = "
+        mod money {
+            struct Price {
+                cents: u32 @1,
+                currency: Currency @2,
+            }
+
+            enum Currency {
+                Eur @1,
+                Usd @2,
+                Other(string @1) @3,
+            }
+        }
+    ";
+
+    fn decode_shop(payload: &[u8]) -> Result<Value> {
+        let shop = Schema::parse(SHOP, None).unwrap();
+        let other = Schema::parse(OTHER, None).unwrap();
+        let schemas = [
+            ("shop", mabo_compiler::simplify_schema(&shop)),
+            ("other", mabo_compiler::simplify_schema(&other)),
+        ];
+
+        decode(&Registry::new(&schemas), "shop::Order", payload)
+    }
+
+    fn field(w: &mut Vec<u8>, id: u32, encoding: FieldEncoding, encode: impl Fn(&mut Vec<u8>)) {
+        buf::encode_field(w, FieldId::new(id, encoding), encode);
+    }
+
+    fn item(w: &mut Vec<u8>, cents: u32, currency: impl Fn(&mut Vec<u8>)) {
+        field(w, 1, FieldEncoding::LengthPrefixed, |w| {
+            buf::encode_string(w, "apple");
+        });
+        field(w, 2, FieldEncoding::LengthPrefixed, |w| {
+            field(w, 1, FieldEncoding::Varint, |w| buf::encode_u32(w, cents));
+            field(w, 2, FieldEncoding::LengthPrefixed, &currency);
+            buf::encode_u32(w, buf::END_MARKER);
+        });
+        buf::encode_u32(w, buf::END_MARKER);
+    }
+
+    #[test]
+    fn decode_struct() {
+        let mut payload = Vec::new();
+        field(&mut payload, 1, FieldEncoding::Varint, |w| {
+            buf::encode_u64(w, 7);
+        });
+        field(&mut payload, 2, FieldEncoding::LengthPrefixed, |w| {
+            let mut items = Vec::new();
+            item(&mut items, 5, |w| {
+                buf::encode_variant_id(w, VariantId::new(2));
+            });
+            item(&mut items, 10, |w| {
+                buf::encode_variant_id(w, VariantId::new(3));
+                field(w, 1, FieldEncoding::LengthPrefixed, |w| {
+                    buf::encode_string(w, "CHF");
+                });
+                buf::encode_u32(w, buf::END_MARKER);
+            });
+            buf::encode_bytes_std(w, &items);
+        });
+        buf::encode_u32(&mut payload, buf::END_MARKER);
+
+        assert_eq!(
+            json!({
+                "id": 7,
+                "items": [
+                    { "name": "apple", "price": { "cents": 5, "currency": "Usd" } },
+                    { "name": "apple", "price": { "cents": 10, "currency": { "Other": ["CHF"] } } },
+                ],
+                "note": null,
+            }),
+            decode_shop(&payload).unwrap(),
+        );
+    }
+
+    #[test]
+    fn decode_invalid_variant() {
+        let mut payload = Vec::new();
+        field(&mut payload, 1, FieldEncoding::Varint, |w| {
+            buf::encode_u64(w, 7);
+        });
+        field(&mut payload, 2, FieldEncoding::LengthPrefixed, |w| {
+            let mut items = Vec::new();
+            item(&mut items, 5, |w| {
+                buf::encode_variant_id(w, VariantId::new(9));
+            });
+            buf::encode_bytes_std(w, &items);
+        });
+        buf::encode_u32(&mut payload, buf::END_MARKER);
+
+        let error = decode_shop(&payload).unwrap_err();
+        assert_eq!(
+            "failed decoding `shop::Order.items[0].price.currency` at byte offset 15",
+            error.to_string(),
+        );
+    }
+
+    #[test]
+    fn decode_missing_field() {
+        let mut payload = Vec::new();
+        field(&mut payload, 2, FieldEncoding::LengthPrefixed, |w| {
+            buf::encode_bytes_std(w, &[]);
+        });
+        buf::encode_u32(&mut payload, buf::END_MARKER);
+
+        let error = decode_shop(&payload).unwrap_err();
+        assert_eq!(
+            "failed decoding `shop::Order` at byte offset 2",
+            error.to_string(),
+        );
+        assert_eq!(
+            "required field `id` @1 is missing",
+            error.root_cause().to_string(),
+        );
+    }
+}
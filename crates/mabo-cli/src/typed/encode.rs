@@ -0,0 +1,601 @@
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use mabo::{buf, FieldEncoding, FieldId, VariantId};
+use mabo_compiler::simplify::{Field, FieldKind, Fields, Type};
+use serde_json::Value;
+
+use super::{is_option, Path, Registry, Resolved, Scope, Segment, Target};
+
+/// Encode a JSON value as the type with the given fully qualified name.
+///
+/// The value is expected in the same structure that [`super::decode`] produces. Optional fields
+/// can be omitted or set to `null`, and unit enum variants can be given as plain string. An option
+/// that directly contains another option expects its value wrapped in a single-element array, like
+/// `[null]` for `Some(None)`.
+///
+/// # Errors
+///
+/// Will return `Err` if the type can't be found in the registry, or the value doesn't match the
+/// type. The error contains the path to the offending value.
+pub fn encode(registry: &Registry<'_>, name: &str, value: &Value) -> Result<Vec<u8>> {
+    let resolved = registry.find(name)?;
+    let mut encoder = Encoder {
+        registry,
+        name,
+        path: Vec::new(),
+    };
+
+    let mut w = Vec::new();
+    encoder.resolved(&mut w, value, &resolved)?;
+
+    Ok(w)
+}
+
+struct Encoder<'a, 'r> {
+    registry: &'r Registry<'a>,
+    /// Name of the root type.
+    name: &'r str,
+    path: Vec<Segment<'a>>,
+}
+
+impl<'a> Encoder<'a, '_> {
+    fn resolved(&mut self, w: &mut Vec<u8>, value: &Value, resolved: &Resolved<'a>) -> Result<()> {
+        match resolved.target {
+            Target::Struct(s) => self.fields(w, value, &s.fields, &resolved.scope),
+            Target::Enum(e) => {
+                let (name, fields) = match value {
+                    Value::String(name) => (name, None),
+                    Value::Object(map) if map.len() == 1 => {
+                        let (name, fields) = map.iter().next().unwrap();
+                        (name, Some(fields))
+                    }
+                    _ => {
+                        return Err(self.error(anyhow!(
+                            "expected variant name or object with the variant as single key, \
+                             found {}",
+                            describe(value)
+                        )))
+                    }
+                };
+
+                let Some(variant) = e.variants.iter().find(|variant| variant.name == name) else {
+                    let names = e
+                        .variants
+                        .iter()
+                        .map(|variant| format!("`{}`", variant.name))
+                        .collect::<Vec<_>>();
+                    return Err(self.error(anyhow!(
+                        "unknown variant `{name}`, expected one of {}",
+                        names.join(", ")
+                    )));
+                };
+
+                buf::encode_variant_id(w, VariantId::new(variant.id));
+
+                self.path.push(Segment::Variant(variant.name));
+                let result = self.fields(
+                    w,
+                    fields.unwrap_or(&Value::Null),
+                    &variant.fields,
+                    &resolved.scope,
+                );
+                self.path.pop();
+
+                result
+            }
+        }
+    }
+
+    fn fields(
+        &mut self,
+        w: &mut Vec<u8>,
+        value: &Value,
+        fields: &'a Fields<'a>,
+        scope: &Scope<'a>,
+    ) -> Result<()> {
+        match (&fields.kind, value) {
+            (FieldKind::Unit, Value::Null) => return Ok(()),
+            (FieldKind::Unit, _) => {
+                return Err(self.error(anyhow!("expected null, found {}", describe(value))));
+            }
+            (FieldKind::Named, Value::Object(map)) => {
+                if let Some(key) = map
+                    .keys()
+                    .find(|key| !fields.fields.iter().any(|field| field.name == **key))
+                {
+                    return Err(self.error(anyhow!("unknown field `{key}`")));
+                }
+
+                for field in &fields.fields {
+                    self.field(w, map.get(&*field.name), field, scope)?;
+                }
+            }
+            (FieldKind::Unnamed, Value::Array(values)) => {
+                if values.len() != fields.fields.len() {
+                    return Err(self.error(anyhow!(
+                        "expected {} elements, found {}",
+                        fields.fields.len(),
+                        values.len()
+                    )));
+                }
+
+                for (field, value) in fields.fields.iter().zip(values) {
+                    self.field(w, Some(value), field, scope)?;
+                }
+            }
+            (FieldKind::Named, _) => {
+                return Err(self.error(anyhow!("expected object, found {}", describe(value))));
+            }
+            (FieldKind::Unnamed, _) => {
+                return Err(self.error(anyhow!("expected array, found {}", describe(value))));
+            }
+        }
+
+        buf::encode_u32(w, buf::END_MARKER);
+        Ok(())
+    }
+
+    fn field(
+        &mut self,
+        w: &mut Vec<u8>,
+        value: Option<&Value>,
+        field: &'a Field<'a>,
+        scope: &Scope<'a>,
+    ) -> Result<()> {
+        let (ty, value) = match (&field.ty, value) {
+            (Type::Option(_), None | Some(Value::Null)) => return Ok(()),
+            (Type::Option(ty), Some(value)) => (&**ty, value),
+            (ty, Some(value)) => (ty, value),
+            (_, None) => {
+                return Err(self.error(anyhow!(
+                    "required field `{}` @{} is missing",
+                    field.name,
+                    field.id
+                )))
+            }
+        };
+
+        buf::encode_id(w, FieldId::new(field.id, encoding(ty)));
+
+        self.path.push(Segment::Field(&field.name));
+        let value = if matches!(field.ty, Type::Option(_)) {
+            self.some(value, ty, scope)?
+        } else {
+            value
+        };
+        self.value(w, value, ty, scope, true)?;
+        self.path.pop();
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn value(
+        &mut self,
+        w: &mut Vec<u8>,
+        value: &Value,
+        ty: &'a Type<'a>,
+        scope: &Scope<'a>,
+        root: bool,
+    ) -> Result<()> {
+        match ty {
+            Type::Bool => match value {
+                Value::Bool(value) => buf::encode_bool(w, *value),
+                _ => return Err(self.mismatch("boolean", value)),
+            },
+            Type::U8 => buf::encode_u8(w, self.integer(value, "u8")?),
+            Type::U16 => buf::encode_u16(w, self.integer(value, "u16")?),
+            Type::U32 => buf::encode_u32(w, self.integer(value, "u32")?),
+            Type::U64 => buf::encode_u64(w, self.integer(value, "u64")?),
+            Type::U128 => buf::encode_u128(w, self.integer(value, "u128")?),
+            Type::I8 => buf::encode_i8(w, self.integer(value, "i8")?),
+            Type::I16 => buf::encode_i16(w, self.integer(value, "i16")?),
+            Type::I32 => buf::encode_i32(w, self.integer(value, "i32")?),
+            Type::I64 => buf::encode_i64(w, self.integer(value, "i64")?),
+            Type::I128 => buf::encode_i128(w, self.integer(value, "i128")?),
+            #[allow(clippy::cast_possible_truncation)]
+            Type::F32 => buf::encode_f32(w, self.float(value)? as f32),
+            Type::F64 => buf::encode_f64(w, self.float(value)?),
+            Type::String | Type::StringRef | Type::BoxString => match value {
+                Value::String(value) => buf::encode_string(w, value),
+                _ => return Err(self.mismatch("string", value)),
+            },
+            Type::Bytes | Type::BytesRef | Type::BoxBytes => {
+                let values = self.array(value)?;
+                let mut bytes = Vec::with_capacity(values.len());
+                for (i, value) in values.iter().enumerate() {
+                    self.path.push(Segment::Index(i));
+                    bytes.push(self.integer(value, "u8")?);
+                    self.path.pop();
+                }
+
+                buf::encode_bytes_std(w, &bytes);
+            }
+            Type::Vec(ty) | Type::HashSet(ty) | Type::BTreeSet(ty) => {
+                let values = self.array(value)?;
+                self.prefixed(w, |this, w| this.elements(w, values, ty, scope))?;
+            }
+            Type::Array(ty, size) => {
+                let values = self.array(value)?;
+                if values.len() != *size as usize {
+                    return Err(self.error(anyhow!(
+                        "expected {size} array elements, found {}",
+                        values.len()
+                    )));
+                }
+
+                self.prefixed(w, |this, w| this.elements(w, values, ty, scope))?;
+            }
+            Type::HashMap(kv) | Type::BTreeMap(kv) => match value {
+                Value::Object(map) => self.prefixed(w, |this, w| {
+                    for (key, value) in map {
+                        this.path.push(Segment::Key(key.clone()));
+                        this.value(w, &Value::String(key.clone()), &kv.0, scope, false)?;
+                        this.value(w, value, &kv.1, scope, false)?;
+                        this.path.pop();
+                    }
+                    Ok(())
+                })?,
+                Value::Array(entries) => self.prefixed(w, |this, w| {
+                    for (i, entry) in entries.iter().enumerate() {
+                        this.path.push(Segment::Index(i));
+                        match entry.as_array().map(Vec::as_slice) {
+                            Some([key, value]) => {
+                                this.value(w, key, &kv.0, scope, false)?;
+                                this.value(w, value, &kv.1, scope, false)?;
+                            }
+                            _ => return Err(this.mismatch("key-value pair", entry)),
+                        }
+                        this.path.pop();
+                    }
+                    Ok(())
+                })?,
+                _ => return Err(self.mismatch("object or array of key-value pairs", value)),
+            },
+            Type::Option(ty) => {
+                if value.is_null() {
+                    w.push(0);
+                } else {
+                    w.push(1);
+                    let value = self.some(value, ty, scope)?;
+                    self.value(w, value, ty, scope, false)?;
+                }
+            }
+            Type::NonZero(ty) => {
+                if is_zero(value, ty) {
+                    return Err(self.error(anyhow!(
+                        "expected a non-zero value, found {}",
+                        describe(value)
+                    )));
+                }
+
+                self.value(w, value, ty, scope, root)?;
+            }
+            Type::Tuple(types) => {
+                let values = self.array(value)?;
+                if values.len() != types.len() {
+                    return Err(self.error(anyhow!(
+                        "expected {} tuple elements, found {}",
+                        types.len(),
+                        values.len()
+                    )));
+                }
+
+                let encode = |this: &mut Self, w: &mut Vec<u8>| {
+                    for (i, (value, ty)) in values.iter().zip(types).enumerate() {
+                        this.path.push(Segment::Index(i));
+                        this.value(w, value, ty, scope, false)?;
+                        this.path.pop();
+                    }
+                    Ok(())
+                };
+
+                if root {
+                    self.prefixed(w, encode)?;
+                } else {
+                    encode(self, w)?;
+                }
+            }
+            Type::External(ty) => {
+                if let Some((bound, bound_scope)) = scope.generic(ty) {
+                    let bound_scope = bound_scope.clone();
+                    return self.value(w, value, bound, &bound_scope, root);
+                }
+
+                let resolved = self
+                    .registry
+                    .resolve(scope, ty)
+                    .map_err(|e| self.error(e))?;
+
+                self.resolved(w, value, &resolved)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Encode each element of a list, like the content of a vector or hash set.
+    fn elements(
+        &mut self,
+        w: &mut Vec<u8>,
+        values: &[Value],
+        ty: &'a Type<'a>,
+        scope: &Scope<'a>,
+    ) -> Result<()> {
+        for (i, value) in values.iter().enumerate() {
+            self.path.push(Segment::Index(i));
+            self.value(w, value, ty, scope, false)?;
+            self.path.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Encode a value into a separate buffer first, and write it with its length as prefix.
+    fn prefixed(
+        &mut self,
+        w: &mut Vec<u8>,
+        encode: impl FnOnce(&mut Self, &mut Vec<u8>) -> Result<()>,
+    ) -> Result<()> {
+        let mut content = Vec::new();
+        encode(self, &mut content)?;
+
+        buf::encode_u64(w, content.len() as u64);
+        w.extend_from_slice(&content);
+
+        Ok(())
+    }
+
+    /// Get the present value of an option, which is wrapped into a single-element array if the
+    /// option contains another option.
+    fn some<'v>(&self, value: &'v Value, ty: &Type<'_>, scope: &Scope<'_>) -> Result<&'v Value> {
+        if !is_option(ty, scope) {
+            return Ok(value);
+        }
+
+        match value {
+            Value::Array(values) if values.len() == 1 => Ok(&values[0]),
+            _ => Err(self.mismatch("single-element array for a nested option", value)),
+        }
+    }
+
+    fn array<'v>(&self, value: &'v Value) -> Result<&'v [Value]> {
+        match value {
+            Value::Array(values) => Ok(values),
+            _ => Err(self.mismatch("array", value)),
+        }
+    }
+
+    /// Convert a JSON number into an integer of the target type. Strings are accepted as well, as
+    /// they are needed for 128-bit integers and map keys.
+    fn integer<T>(&self, value: &Value, ty: &str) -> Result<T>
+    where
+        T: FromStr + TryFrom<u64> + TryFrom<i64>,
+        <T as FromStr>::Err: Display,
+    {
+        let out_of_range = || self.error(anyhow!("value {value} is out of range for {ty}"));
+
+        match value {
+            Value::Number(n) => {
+                if let Some(n) = n.as_u64() {
+                    T::try_from(n).map_err(|_| out_of_range())
+                } else if let Some(n) = n.as_i64() {
+                    T::try_from(n).map_err(|_| out_of_range())
+                } else {
+                    Err(self.mismatch(ty, value))
+                }
+            }
+            Value::String(s) => s
+                .parse()
+                .map_err(|e| self.error(anyhow!("failed parsing {s:?} as {ty}: {e}"))),
+            _ => Err(self.mismatch(ty, value)),
+        }
+    }
+
+    /// Convert a JSON number into a floating point number. As JSON doesn't support `NaN` or
+    /// infinite numbers, these are accepted as strings.
+    fn float(&self, value: &Value) -> Result<f64> {
+        match value {
+            Value::Number(n) => n.as_f64().ok_or_else(|| self.mismatch("float", value)),
+            Value::String(s) => s
+                .parse()
+                .map_err(|e| self.error(anyhow!("failed parsing {s:?} as float: {e}"))),
+            _ => Err(self.mismatch("float", value)),
+        }
+    }
+
+    fn mismatch(&self, expected: &str, value: &Value) -> anyhow::Error {
+        self.error(anyhow!("expected {expected}, found {}", describe(value)))
+    }
+
+    fn error(&self, cause: impl Into<anyhow::Error>) -> anyhow::Error {
+        cause.into().context(format!(
+            "failed encoding `{}{}`",
+            self.name,
+            Path(&self.path)
+        ))
+    }
+}
+
+/// Determine the field encoding that is used for a value of the given type.
+fn encoding(ty: &Type<'_>) -> FieldEncoding {
+    match ty {
+        Type::Bool | Type::U8 | Type::I8 => FieldEncoding::Fixed1,
+        Type::U16
+        | Type::U32
+        | Type::U64
+        | Type::U128
+        | Type::I16
+        | Type::I32
+        | Type::I64
+        | Type::I128 => FieldEncoding::Varint,
+        Type::F32 => FieldEncoding::Fixed4,
+        Type::F64 => FieldEncoding::Fixed8,
+        Type::NonZero(ty) => encoding(ty),
+        _ => FieldEncoding::LengthPrefixed,
+    }
+}
+
+/// Check whether the value is considered zero for the given `non_zero` type, which is a zero
+/// number for integers and an empty value for strings, bytes and collections.
+fn is_zero(value: &Value, ty: &Type<'_>) -> bool {
+    match value {
+        Value::Number(n) => n.as_u64() == Some(0) || n.as_i64() == Some(0),
+        Value::String(s) if encoding(ty) == FieldEncoding::LengthPrefixed => s.is_empty(),
+        Value::String(s) => s.parse::<i128>() == Ok(0),
+        Value::Array(values) => values.is_empty(),
+        Value::Object(values) => values.is_empty(),
+        Value::Null | Value::Bool(_) => false,
+    }
+}
+
+/// Short description of a JSON value for error messages.
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_owned(),
+        Value::Bool(value) => format!("boolean {value}"),
+        Value::Number(value) => format!("number {value}"),
+        Value::String(value) => format!("string {value:?}"),
+        Value::Array(_) => "array".to_owned(),
+        Value::Object(_) => "object".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mabo_parser::Schema;
+    use serde_json::json;
+
+    use super::*;
+
+    const SCHEMA: &str = "
+        struct Sample {
+            level: u8 @1,
+            count: non_zero<u32> @2,
+            tags: hash_map<u32, string> @3,
+            pair: (i16, Mode) @4,
+            extra: option<option<bool>> @5,
+        }
+
+        enum Mode {
+            Off @1,
+            Limit(u128 @1) @2,
+        }
+    ";
+
+    fn roundtrip(value: &Value) -> Result<Value> {
+        let schema = Schema::parse(SCHEMA, None).unwrap();
+        let schemas = [("sample", mabo_compiler::simplify_schema(&schema))];
+        let registry = Registry::new(&schemas);
+
+        let payload = encode(&registry, "sample::Sample", value)?;
+        super::super::decode(&registry, "sample::Sample", &payload)
+    }
+
+    #[test]
+    fn encode_struct() {
+        let value = json!({
+            "level": 3,
+            "count": 1,
+            "tags": { "5": "five" },
+            "pair": [-2, { "Limit": ["340282366920938463463374607431768211455"] }],
+            "extra": null,
+        });
+
+        assert_eq!(
+            json!({
+                "level": 3,
+                "count": 1,
+                "tags": [[5, "five"]],
+                "pair": [-2, { "Limit": ["340282366920938463463374607431768211455"] }],
+                "extra": null,
+            }),
+            roundtrip(&value).unwrap(),
+        );
+    }
+
+    #[test]
+    fn encode_nested_option() {
+        for extra in [json!(null), json!([null]), json!([true])] {
+            let value = json!({
+                "level": 1,
+                "count": 1,
+                "tags": {},
+                "pair": [0, "Off"],
+                "extra": extra,
+            });
+
+            assert_eq!(value, roundtrip(&value).unwrap());
+        }
+
+        let error = roundtrip(&json!({
+            "level": 1,
+            "count": 1,
+            "tags": {},
+            "pair": [0, "Off"],
+            "extra": true,
+        }))
+        .unwrap_err();
+
+        assert_eq!("failed encoding `sample::Sample.extra`", error.to_string());
+        assert_eq!(
+            "expected single-element array for a nested option, found boolean true",
+            error.root_cause().to_string(),
+        );
+    }
+
+    #[test]
+    fn encode_out_of_range() {
+        let error = roundtrip(&json!({
+            "level": 256,
+            "count": 1,
+            "tags": {},
+            "pair": [0, "Off"],
+        }))
+        .unwrap_err();
+
+        assert_eq!("failed encoding `sample::Sample.level`", error.to_string());
+        assert_eq!(
+            "value 256 is out of range for u8",
+            error.root_cause().to_string(),
+        );
+    }
+
+    #[test]
+    fn encode_zero() {
+        let error = roundtrip(&json!({
+            "level": 1,
+            "count": 0,
+            "tags": {},
+            "pair": [0, "Off"],
+        }))
+        .unwrap_err();
+
+        assert_eq!("failed encoding `sample::Sample.count`", error.to_string());
+        assert_eq!(
+            "expected a non-zero value, found number 0",
+            error.root_cause().to_string(),
+        );
+    }
+
+    #[test]
+    fn encode_wrong_type() {
+        let error = roundtrip(&json!({
+            "level": 1,
+            "count": 1,
+            "tags": { "x": "y" },
+            "pair": [0, "Off"],
+        }))
+        .unwrap_err();
+
+        assert_eq!(
+            "failed encoding `sample::Sample.tags[\"x\"]`",
+            error.to_string()
+        );
+        assert_eq!(
+            "failed parsing \"x\" as u32: invalid digit found in string",
+            error.root_cause().to_string(),
+        );
+    }
+}
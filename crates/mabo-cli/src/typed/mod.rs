@@ -0,0 +1,245 @@
+//! Schema-aware handling of encoded payloads, that converts them from and into human-readable
+//! JSON.
+//!
+//! Instead of relying on generated code, the simplified schema definitions are walked at runtime
+//! to find out how each value is encoded. Types are looked up through the project's definitions,
+//! the same way the compiler resolves them.
+//!
+//! This doesn't go through [`mabo::dynamic`], as its values are keyed by field and variant
+//! identifiers only. Walking the schema directly keeps the names at hand for the JSON output and
+//! the paths in error messages.
+
+use std::{
+    fmt::{self, Display},
+    rc::Rc,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use mabo_compiler::simplify::{Definition, Enum, ExternalType, Schema, Struct, Type};
+
+pub use self::{decode::decode, encode::encode};
+
+mod decode;
+mod encode;
+
+/// Collection of all schemas in a project, that allows to look up type definitions.
+pub struct Registry<'a> {
+    schemas: &'a [(&'a str, Schema<'a>)],
+}
+
+/// Struct or enum definition, that a type name resolved to.
+#[derive(Clone, Copy)]
+enum Target<'a> {
+    Struct(&'a Struct<'a>),
+    Enum(&'a Enum<'a>),
+}
+
+/// Location within the project from where types are looked up, together with the type arguments
+/// that the generics of the current struct or enum are bound to.
+#[derive(Clone)]
+struct Scope<'a> {
+    /// Definitions of the current module, containing the local types and imports.
+    definitions: &'a [Definition<'a>],
+    /// Generic type parameters with their bound type and the scope the type was used in.
+    generics: Rc<[(&'a str, &'a Type<'a>, Scope<'a>)]>,
+}
+
+/// Single element in the path to the currently processed value.
+enum Segment<'a> {
+    Field(&'a str),
+    Variant(&'a str),
+    Index(usize),
+    Key(String),
+}
+
+/// Location of a value within its root type, displayed like `.field[3]::Variant`.
+struct Path<'a, 'b>(&'b [Segment<'a>]);
+
+/// Definition that was found for a type, together with the scope of its declaration.
+struct Resolved<'a> {
+    target: Target<'a>,
+    scope: Scope<'a>,
+}
+
+impl<'a> Registry<'a> {
+    /// Create a new registry from the simplified schemas of a project, keyed by their name.
+    pub fn new(schemas: &'a [(&'a str, Schema<'a>)]) -> Self {
+        Self { schemas }
+    }
+
+    /// Find a struct or enum by its fully qualified name, like `schema::module::Type`.
+    fn find(&self, name: &str) -> Result<Resolved<'a>> {
+        let mut segments = name.split("::").collect::<Vec<_>>();
+        ensure!(
+            segments.len() >= 2,
+            "type name {name:?} must be prefixed with the schema name, like `schema::{name}`"
+        );
+
+        let ty = segments.pop().unwrap();
+        let definitions = self
+            .schema(segments[0])
+            .and_then(|definitions| module(definitions, &segments[1..]))
+            .with_context(|| format!("no schema or module found for {name:?}"))?;
+        let target = lookup(definitions, ty).with_context(|| format!("type {name:?} not found"))?;
+
+        if target.generics() > 0 {
+            bail!("type {name:?} is generic and can't be used directly");
+        }
+
+        Ok(Resolved {
+            target,
+            scope: Scope {
+                definitions,
+                generics: Rc::new([]),
+            },
+        })
+    }
+
+    /// Resolve a type that is used within the given scope, binding its generics to the type
+    /// arguments of the use site.
+    fn resolve(&self, scope: &Scope<'a>, ty: &'a ExternalType<'a>) -> Result<Resolved<'a>> {
+        let (definitions, target) = resolve_local(scope, ty)
+            .or_else(|| self.resolve_import(scope, ty))
+            .with_context(|| format!("failed resolving type {:?}", type_name(ty)))?;
+
+        let generics = target
+            .generic_names()
+            .iter()
+            .zip(&ty.generics)
+            .map(|(name, ty)| (*name, ty, scope.clone()))
+            .collect();
+
+        Ok(Resolved {
+            target,
+            scope: Scope {
+                definitions,
+                generics,
+            },
+        })
+    }
+
+    /// Find a type through the imports of the given scope, either imported directly or through
+    /// one of its parent modules.
+    fn resolve_import(
+        &self,
+        scope: &Scope<'a>,
+        ty: &ExternalType<'_>,
+    ) -> Option<(&'a [Definition<'a>], Target<'a>)> {
+        scope.definitions.iter().find_map(|definition| {
+            let Definition::Import(import) = definition else {
+                return None;
+            };
+
+            let imported = self
+                .schema(import.segments[0])
+                .and_then(|definitions| module(definitions, &import.segments[1..]))?;
+
+            let definitions = match (&import.element, ty.path.split_first()) {
+                (Some(element), None) if **element == *ty.name => imported,
+                (None, Some((first, path))) if import.segments.last() == Some(first) => {
+                    module(imported, path)?
+                }
+                _ => return None,
+            };
+
+            lookup(definitions, ty.name).map(|target| (definitions, target))
+        })
+    }
+
+    fn schema(&self, name: &str) -> Option<&'a [Definition<'a>]> {
+        self.schemas.iter().find_map(|(schema_name, schema)| {
+            (*schema_name == name).then_some(&*schema.definitions)
+        })
+    }
+}
+
+impl<'a> Scope<'a> {
+    /// Find the type argument for a generic type parameter, if the type refers to one.
+    fn generic(&self, ty: &ExternalType<'_>) -> Option<(&'a Type<'a>, &Scope<'a>)> {
+        if !ty.path.is_empty() || !ty.generics.is_empty() {
+            return None;
+        }
+
+        self.generics
+            .iter()
+            .find_map(|(name, bound, scope)| (*name == ty.name).then_some((*bound, scope)))
+    }
+}
+
+impl Display for Path<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for segment in self.0 {
+            match segment {
+                Segment::Field(name) => write!(f, ".{name}")?,
+                Segment::Variant(name) => write!(f, "::{name}")?,
+                Segment::Index(i) => write!(f, "[{i}]")?,
+                Segment::Key(key) => write!(f, "[{key:?}]")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Target<'a> {
+    fn generic_names(&self) -> &'a [&'a str] {
+        match self {
+            Self::Struct(s) => &s.generics,
+            Self::Enum(e) => &e.generics,
+        }
+    }
+
+    fn generics(&self) -> usize {
+        self.generic_names().len()
+    }
+}
+
+/// Whether the type is an `option<T>`, also when bound to a generic type parameter. An option
+/// around such a type wraps its value into a single-element array, as `null` would be ambiguous
+/// otherwise.
+fn is_option(ty: &Type<'_>, scope: &Scope<'_>) -> bool {
+    match ty {
+        Type::Option(_) => true,
+        Type::External(ty) => scope
+            .generic(ty)
+            .is_some_and(|(bound, scope)| is_option(bound, scope)),
+        _ => false,
+    }
+}
+
+/// Find a type relative to the module of the given scope.
+fn resolve_local<'a>(
+    scope: &Scope<'a>,
+    ty: &ExternalType<'_>,
+) -> Option<(&'a [Definition<'a>], Target<'a>)> {
+    let definitions = module(scope.definitions, &ty.path)?;
+    lookup(definitions, ty.name).map(|target| (definitions, target))
+}
+
+/// Walk down the submodules of the given definitions, following the path.
+fn module<'a>(definitions: &'a [Definition<'a>], path: &[&str]) -> Option<&'a [Definition<'a>]> {
+    path.iter().try_fold(definitions, |definitions, name| {
+        definitions.iter().find_map(|definition| match definition {
+            Definition::Module(m) if m.name == *name => Some(&*m.definitions),
+            _ => None,
+        })
+    })
+}
+
+/// Find a struct or enum by its name within a single module.
+fn lookup<'a>(definitions: &'a [Definition<'a>], name: &str) -> Option<Target<'a>> {
+    definitions.iter().find_map(|definition| match definition {
+        Definition::Struct(s) if s.name == name => Some(Target::Struct(s)),
+        Definition::Enum(e) if e.name == name => Some(Target::Enum(e)),
+        _ => None,
+    })
+}
+
+fn type_name(ty: &ExternalType<'_>) -> String {
+    ty.path
+        .iter()
+        .chain(std::iter::once(&ty.name))
+        .copied()
+        .collect::<Vec<_>>()
+        .join("::")
+}
@@ -0,0 +1,541 @@
+//! Detect changes between two versions of the same schemas, that break compatibility of the wire
+//! format.
+//!
+//! Data is always identified by the field and variant IDs on the wire, not their names. Therefore,
+//! renaming an element is considered safe, while changing its ID or the shape of its data is not.
+//! Types are compared by their wire encoding, so switching to a type that is encoded the same way
+//! (like `u32` to `u64`) is safe as well.
+
+use std::fmt::{self, Display};
+
+use crate::simplify::{
+    Definition, Enum, ExternalType, Field, FieldKind, Fields, ParserField, Schema, Struct, Type,
+    TypeAlias,
+};
+
+/// Single change that was found when comparing an old and new schema version.
+///
+/// Most changes break compatibility, but some are reported as compatible additions, which can be
+/// told apart with [`ChangeKind::is_breaking`].
+pub struct Change {
+    /// Location of the changed element, starting with the schema name and followed by module,
+    /// type, variant and field names (for example `sample::Person.age`).
+    pub path: String,
+    /// Specific kind of change that was detected.
+    pub kind: ChangeKind,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.kind)
+    }
+}
+
+/// Possible changes between two versions of a schema.
+pub enum ChangeKind {
+    /// A whole schema file was removed.
+    SchemaRemoved,
+    /// A module, struct, enum or type alias was removed.
+    DefinitionRemoved {
+        /// Kind of the removed definition, like `struct` or `enum`.
+        kind: &'static str,
+    },
+    /// A definition was replaced with one of another kind, like a struct turning into an enum.
+    DefinitionKindChanged {
+        /// Previous kind of the definition.
+        old: &'static str,
+        /// New kind of the definition.
+        new: &'static str,
+    },
+    /// The amount of generic type parameters changed.
+    GenericsChanged {
+        /// Previous amount of generics.
+        old: usize,
+        /// New amount of generics.
+        new: usize,
+    },
+    /// A struct or enum variant turned from a unit type into one with fields, or vice versa.
+    FieldsKindChanged,
+    /// A field kept its name but was assigned a different ID.
+    FieldIdChanged {
+        /// Previous field ID.
+        old: u32,
+        /// New field ID.
+        new: u32,
+    },
+    /// The data type of a field changed its encoding.
+    FieldTypeChanged {
+        /// Identifier of the field.
+        id: u32,
+        /// Previous data type.
+        old: String,
+        /// New data type.
+        new: String,
+    },
+    /// A new required field was added, that is missing in payloads of the old version.
+    RequiredFieldAdded {
+        /// Identifier of the new field.
+        id: u32,
+    },
+    /// A required field was removed, that decoders of the old version still expect.
+    RequiredFieldRemoved {
+        /// Identifier of the removed field.
+        id: u32,
+    },
+    /// A new enum variant was added. Decoders of the old version can't read it, but as the old
+    /// version never produces it, this is a compatible addition.
+    VariantAdded {
+        /// Identifier of the new variant.
+        id: u32,
+    },
+    /// An enum variant was removed.
+    VariantRemoved {
+        /// Identifier of the removed variant.
+        id: u32,
+    },
+    /// An enum variant kept its name but was assigned a different ID.
+    VariantIdChanged {
+        /// Previous variant ID.
+        old: u32,
+        /// New variant ID.
+        new: u32,
+    },
+    /// The target type of a type alias changed its encoding.
+    AliasTargetChanged {
+        /// Previous target type.
+        old: String,
+        /// New target type.
+        new: String,
+    },
+}
+
+impl ChangeKind {
+    /// Whether the change breaks compatibility between the old and new version of the schema.
+    #[must_use]
+    pub fn is_breaking(&self) -> bool {
+        !matches!(self, Self::VariantAdded { .. })
+    }
+}
+
+impl Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SchemaRemoved => f.write_str("schema was removed"),
+            Self::DefinitionRemoved { kind } => write!(f, "{kind} was removed"),
+            Self::DefinitionKindChanged { old, new } => {
+                write!(f, "changed from {old} to {new}")
+            }
+            Self::GenericsChanged { old, new } => {
+                write!(f, "amount of generics changed from {old} to {new}")
+            }
+            Self::FieldsKindChanged => {
+                f.write_str("changed between unit type and type with fields")
+            }
+            Self::FieldIdChanged { old, new } => {
+                write!(f, "field ID changed from @{old} to @{new}")
+            }
+            Self::FieldTypeChanged { id, old, new } => {
+                write!(f, "type of field @{id} changed from `{old}` to `{new}`")
+            }
+            Self::RequiredFieldAdded { id } => write!(f, "required field @{id} was added"),
+            Self::RequiredFieldRemoved { id } => write!(f, "required field @{id} was removed"),
+            Self::VariantAdded { id } => write!(f, "variant @{id} was added"),
+            Self::VariantRemoved { id } => write!(f, "variant @{id} was removed"),
+            Self::VariantIdChanged { old, new } => {
+                write!(f, "variant ID changed from @{old} to @{new}")
+            }
+            Self::AliasTargetChanged { old, new } => {
+                write!(f, "aliased type changed from `{old}` to `{new}`")
+            }
+        }
+    }
+}
+
+/// Compare the old and new versions of a set of schemas and collect all changes between them, that
+/// either break compatibility or are compatible additions.
+///
+/// Schemas are matched by their name, definitions by their name within the same module, and fields
+/// and variants by their ID.
+#[must_use]
+pub fn schemas(old: &[(&str, &Schema<'_>)], new: &[(&str, &Schema<'_>)]) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    for (name, old_schema) in old {
+        match new.iter().find(|(new_name, _)| new_name == name) {
+            Some((_, new_schema)) => compare_definitions(
+                &mut changes,
+                name,
+                &old_schema.definitions,
+                &new_schema.definitions,
+            ),
+            None => changes.push(Change {
+                path: (*name).to_owned(),
+                kind: ChangeKind::SchemaRemoved,
+            }),
+        }
+    }
+
+    changes
+}
+
+fn compare_definitions(
+    changes: &mut Vec<Change>,
+    parent: &str,
+    old: &[Definition<'_>],
+    new: &[Definition<'_>],
+) {
+    for old_def in old {
+        let Some(name) = definition_name(old_def) else {
+            continue;
+        };
+        let path = format!("{parent}::{name}");
+
+        let Some(new_def) = new.iter().find(|def| definition_name(def) == Some(name)) else {
+            changes.push(Change {
+                path,
+                kind: ChangeKind::DefinitionRemoved {
+                    kind: definition_kind(old_def),
+                },
+            });
+            continue;
+        };
+
+        match (old_def, new_def) {
+            (Definition::Module(old), Definition::Module(new)) => {
+                compare_definitions(changes, &path, &old.definitions, &new.definitions);
+            }
+            (Definition::Struct(old), Definition::Struct(new)) => {
+                compare_struct(changes, &path, old, new);
+            }
+            (Definition::Enum(old), Definition::Enum(new)) => {
+                compare_enum(changes, &path, old, new);
+            }
+            (Definition::TypeAlias(old), Definition::TypeAlias(new)) => {
+                compare_alias(changes, path, old, new);
+            }
+            (old, new) => changes.push(Change {
+                path,
+                kind: ChangeKind::DefinitionKindChanged {
+                    old: definition_kind(old),
+                    new: definition_kind(new),
+                },
+            }),
+        }
+    }
+}
+
+fn compare_struct(changes: &mut Vec<Change>, path: &str, old: &Struct<'_>, new: &Struct<'_>) {
+    let generics = Generics {
+        old: &old.generics,
+        new: &new.generics,
+    };
+
+    compare_generics(changes, path, generics);
+    compare_fields(changes, path, generics, &old.fields, &new.fields);
+}
+
+fn compare_enum(changes: &mut Vec<Change>, path: &str, old: &Enum<'_>, new: &Enum<'_>) {
+    let generics = Generics {
+        old: &old.generics,
+        new: &new.generics,
+    };
+
+    compare_generics(changes, path, generics);
+
+    for old_variant in &old.variants {
+        let variant_path = format!("{path}::{}", old_variant.name);
+
+        if let Some(new_variant) = new.variants.iter().find(|v| v.id == old_variant.id) {
+            compare_fields(
+                changes,
+                &variant_path,
+                generics,
+                &old_variant.fields,
+                &new_variant.fields,
+            );
+        } else if let Some(new_variant) = new.variants.iter().find(|v| v.name == old_variant.name) {
+            changes.push(Change {
+                path: variant_path,
+                kind: ChangeKind::VariantIdChanged {
+                    old: old_variant.id,
+                    new: new_variant.id,
+                },
+            });
+        } else {
+            changes.push(Change {
+                path: variant_path,
+                kind: ChangeKind::VariantRemoved { id: old_variant.id },
+            });
+        }
+    }
+
+    for new_variant in &new.variants {
+        let known = old
+            .variants
+            .iter()
+            .any(|v| v.id == new_variant.id || v.name == new_variant.name);
+
+        if !known {
+            changes.push(Change {
+                path: format!("{path}::{}", new_variant.name),
+                kind: ChangeKind::VariantAdded { id: new_variant.id },
+            });
+        }
+    }
+}
+
+fn compare_alias(
+    changes: &mut Vec<Change>,
+    path: String,
+    old: &TypeAlias<'_>,
+    new: &TypeAlias<'_>,
+) {
+    let generics = Generics {
+        old: &old.generics,
+        new: &new.generics,
+    };
+
+    compare_generics(changes, &path, generics);
+
+    if !same_encoding(generics, &old.target, &new.target) {
+        changes.push(Change {
+            path,
+            kind: ChangeKind::AliasTargetChanged {
+                old: old.source.target.to_string(),
+                new: new.source.target.to_string(),
+            },
+        });
+    }
+}
+
+/// Generic type parameters of the old and new version of a definition.
+///
+/// Generics are identified by their position, so they can be renamed without breaking
+/// compatibility.
+#[derive(Clone, Copy)]
+struct Generics<'a> {
+    old: &'a [&'a str],
+    new: &'a [&'a str],
+}
+
+impl Generics<'_> {
+    fn same_position(self, old: &str, new: &str) -> bool {
+        let old = self.old.iter().position(|g| *g == old);
+        let new = self.new.iter().position(|g| *g == new);
+
+        old.is_some() && old == new
+    }
+}
+
+fn compare_generics(changes: &mut Vec<Change>, path: &str, generics: Generics<'_>) {
+    if generics.old.len() != generics.new.len() {
+        changes.push(Change {
+            path: path.to_owned(),
+            kind: ChangeKind::GenericsChanged {
+                old: generics.old.len(),
+                new: generics.new.len(),
+            },
+        });
+    }
+}
+
+fn compare_fields(
+    changes: &mut Vec<Change>,
+    path: &str,
+    generics: Generics<'_>,
+    old: &Fields<'_>,
+    new: &Fields<'_>,
+) {
+    if (old.kind == FieldKind::Unit) != (new.kind == FieldKind::Unit) {
+        changes.push(Change {
+            path: path.to_owned(),
+            kind: ChangeKind::FieldsKindChanged,
+        });
+        return;
+    }
+
+    for old_field in &*old.fields {
+        let field_path = format!("{path}.{}", old_field.name);
+
+        if let Some(new_field) = new.fields.iter().find(|f| f.id == old_field.id) {
+            if !same_encoding(generics, &old_field.ty, &new_field.ty) {
+                changes.push(Change {
+                    path: field_path,
+                    kind: ChangeKind::FieldTypeChanged {
+                        id: old_field.id,
+                        old: field_type(old_field),
+                        new: field_type(new_field),
+                    },
+                });
+            }
+        } else if let Some(new_field) = renamed_field(old, new, old_field) {
+            changes.push(Change {
+                path: field_path,
+                kind: ChangeKind::FieldIdChanged {
+                    old: old_field.id,
+                    new: new_field.id,
+                },
+            });
+        } else if !matches!(old_field.ty, Type::Option(_)) {
+            changes.push(Change {
+                path: field_path,
+                kind: ChangeKind::RequiredFieldRemoved { id: old_field.id },
+            });
+        }
+    }
+
+    for new_field in &*new.fields {
+        let known = old.fields.iter().any(|f| f.id == new_field.id)
+            || renamed_field(new, old, new_field).is_some();
+
+        if !known && !matches!(new_field.ty, Type::Option(_)) {
+            changes.push(Change {
+                path: format!("{path}.{}", new_field.name),
+                kind: ChangeKind::RequiredFieldAdded { id: new_field.id },
+            });
+        }
+    }
+}
+
+/// Locate the field in `to` that has the same name as the given field, but a different ID.
+///
+/// This only applies to named fields, as the names of unnamed fields are derived from their
+/// position and don't carry any meaning.
+fn renamed_field<'a>(
+    from: &Fields<'_>,
+    to: &'a Fields<'a>,
+    field: &Field<'_>,
+) -> Option<&'a Field<'a>> {
+    (from.kind == FieldKind::Named && to.kind == FieldKind::Named)
+        .then(|| {
+            to.fields
+                .iter()
+                .find(|f| f.name == field.name && f.id != field.id)
+        })
+        .flatten()
+}
+
+/// Check whether two types share the same wire encoding.
+///
+/// Plain types are compared by their [`FieldEncoding`], so for example all unsigned integers from
+/// `u16` to `u128`, or strings and bytes in their owned, borrowed and boxed versions, can be
+/// switched freely. Collections and other containers must match, with their element types compared
+/// the same way. The same goes for hash and btree versions of maps and sets.
+///
+/// External types are always length-prefixed, so they only need to match in their generics. Any
+/// changes within them are detected when comparing their own definitions.
+fn same_encoding(generics: Generics<'_>, old: &Type<'_>, new: &Type<'_>) -> bool {
+    let same = |old, new| same_encoding(generics, old, new);
+
+    match (old, new) {
+        (Type::Vec(old), Type::Vec(new))
+        | (Type::HashSet(old) | Type::BTreeSet(old), Type::HashSet(new) | Type::BTreeSet(new))
+        | (Type::Option(old), Type::Option(new))
+        | (Type::NonZero(old), Type::NonZero(new)) => same(old, new),
+        (Type::HashMap(old) | Type::BTreeMap(old), Type::HashMap(new) | Type::BTreeMap(new)) => {
+            same(&old.0, &new.0) && same(&old.1, &new.1)
+        }
+        (Type::Tuple(old), Type::Tuple(new)) => {
+            old.len() == new.len() && old.iter().zip(new.iter()).all(|(o, n)| same(o, n))
+        }
+        (Type::Array(old, old_size), Type::Array(new, new_size)) => {
+            old_size == new_size && same(old, new)
+        }
+        (Type::External(old), Type::External(new)) => {
+            match (
+                generic_param(generics.old, old),
+                generic_param(generics.new, new),
+            ) {
+                (Some(old), Some(new)) => generics.same_position(old, new),
+                (None, None) => {
+                    old.generics.len() == new.generics.len()
+                        && old
+                            .generics
+                            .iter()
+                            .zip(new.generics.iter())
+                            .all(|(o, n)| same(o, n))
+                }
+                _ => false,
+            }
+        }
+        (old, new) => {
+            let old = FieldEncoding::of(old);
+            old.is_some() && old == FieldEncoding::of(new)
+        }
+    }
+}
+
+/// Name of the external type, if it refers to one of the generic type parameters.
+fn generic_param<'a>(params: &[&str], ty: &ExternalType<'a>) -> Option<&'a str> {
+    (ty.path.is_empty() && ty.generics.is_empty() && params.contains(&ty.name)).then_some(ty.name)
+}
+
+/// Wire encoding of plain data types, the same as `mabo::FieldEncoding`.
+///
+/// Signed integers are zigzag encoded before being written as _Varint_, which means they can't be
+/// read as unsigned ones. Therefore, their signedness is tracked as well.
+#[derive(Eq, PartialEq)]
+enum FieldEncoding {
+    Varint { signed: bool },
+    LengthPrefixed,
+    Fixed1,
+    Fixed4,
+    Fixed8,
+}
+
+impl FieldEncoding {
+    /// Determine the encoding of a plain data type, or `None` for containers and external types.
+    fn of(ty: &Type<'_>) -> Option<Self> {
+        Some(match ty {
+            Type::Bool | Type::U8 | Type::I8 => Self::Fixed1,
+            Type::U16 | Type::U32 | Type::U64 | Type::U128 => Self::Varint { signed: false },
+            Type::I16 | Type::I32 | Type::I64 | Type::I128 => Self::Varint { signed: true },
+            Type::F32 => Self::Fixed4,
+            Type::F64 => Self::Fixed8,
+            Type::String
+            | Type::StringRef
+            | Type::BoxString
+            | Type::Bytes
+            | Type::BytesRef
+            | Type::BoxBytes => Self::LengthPrefixed,
+            Type::Vec(_)
+            | Type::HashMap(_)
+            | Type::HashSet(_)
+            | Type::BTreeMap(_)
+            | Type::BTreeSet(_)
+            | Type::Option(_)
+            | Type::NonZero(_)
+            | Type::Tuple(_)
+            | Type::Array(..)
+            | Type::External(_) => return None,
+        })
+    }
+}
+
+fn field_type(field: &Field<'_>) -> String {
+    match field.source {
+        ParserField::Named(f) => f.ty.to_string(),
+        ParserField::Unnamed(f) => f.ty.to_string(),
+    }
+}
+
+fn definition_name<'a>(definition: &Definition<'a>) -> Option<&'a str> {
+    match definition {
+        Definition::Module(m) => Some(m.name),
+        Definition::Struct(s) => Some(s.name),
+        Definition::Enum(e) => Some(e.name),
+        Definition::TypeAlias(a) => Some(a.name),
+        Definition::Const(_) | Definition::Import(_) => None,
+    }
+}
+
+fn definition_kind(definition: &Definition<'_>) -> &'static str {
+    match definition {
+        Definition::Module(_) => "module",
+        Definition::Struct(_) => "struct",
+        Definition::Enum(_) => "enum",
+        Definition::TypeAlias(_) => "type alias",
+        Definition::Const(_) => "constant",
+        Definition::Import(_) => "import",
+    }
+}
@@ -20,11 +20,13 @@
 
 #![allow(clippy::module_name_repetitions)]
 
+pub use compare::schemas as compare_schemas;
 use mabo_parser::Spanned;
 pub use resolve::schemas as resolve_schemas;
 pub use simplify::schema as simplify_schema;
 pub use validate::schema as validate_schema;
 
+pub mod compare;
 mod highlight;
 pub mod resolve;
 pub mod simplify;
@@ -174,6 +174,10 @@ pub enum ResolveImport {
     #[error(transparent)]
     #[diagnostic(transparent)]
     MissingSchema(#[from] MissingSchema),
+    /// Several schemas with the referenced name exist in other directories.
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    AmbiguousSchema(#[from] AmbiguousSchema),
     /// The referenced module inside the schema doesn't exist.
     #[error(transparent)]
     #[diagnostic(transparent)]
@@ -198,6 +202,23 @@ pub struct MissingSchema {
     pub(super) used: Range<usize>,
 }
 
+/// The referenced schema exists in several directories, none of which is the one of the importing
+/// schema.
+#[derive(Debug, Diagnostic, Error)]
+#[error("schema {} is ambiguous", highlight::value(name))]
+#[diagnostic(help(
+    "the name matches the schemas {}, move the schemas or rename one of them",
+    highlight::value(candidates.join(", "))
+))]
+pub struct AmbiguousSchema {
+    /// Name of the referenced schema.
+    pub name: String,
+    /// Keys of all schemas that match the name, including their directories.
+    pub candidates: Vec<String>,
+    #[label("used here")]
+    pub(super) used: Range<usize>,
+}
+
 /// Failed to resolve a type in another schema.
 #[derive(Debug, Diagnostic, Error)]
 pub enum ResolveRemote {
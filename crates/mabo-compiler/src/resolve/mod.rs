@@ -9,9 +9,10 @@ use mabo_parser::{
 use miette::NamedSource;
 
 pub use self::error::{
-    Error, GenericsCount, InvalidKind, MissingDefinition, MissingImport, MissingModule,
-    MissingSchema, RemoteGenericsCount, RemoteGenericsCountDeclaration, RemoteInvalidKind,
-    RemoteInvalidKindDeclaration, ResolveError, ResolveImport, ResolveLocal, ResolveRemote,
+    AmbiguousSchema, Error, GenericsCount, InvalidKind, MissingDefinition, MissingImport,
+    MissingModule, MissingSchema, RemoteGenericsCount, RemoteGenericsCountDeclaration,
+    RemoteInvalidKind, RemoteInvalidKindDeclaration, ResolveError, ResolveImport, ResolveLocal,
+    ResolveRemote,
 };
 
 mod error;
@@ -26,33 +27,38 @@ mod error;
 /// - Lastly, the not-found types from the first steps are checked for in the other schemas by
 ///   utilizing the imports from the second step.
 ///
+/// Schemas are keyed by their name, optionally prefixed with the directories that contain them,
+/// like `a/b/common`. Imports refer to schemas by name only, and prefer the schema from the same
+/// directory. Otherwise, the name must be unique across all directories.
+///
 /// # Errors
 ///
 /// Will return `Err` if any of the resolution steps fails.
 pub fn schemas(values: &[(&str, &Schema<'_>)]) -> Result<(), Error> {
     let modules = values
         .iter()
-        .map(|(name, schema)| (*name, resolve_types(name, schema)))
+        .map(|(key, schema)| (*key, resolve_types(split_key(key).1, schema)))
         .collect::<Vec<_>>();
 
-    for (schema, module) in modules
+    for (key, schema, module) in modules
         .iter()
         .enumerate()
-        .map(|(i, (_, module))| (values[i].1, module))
+        .map(|(i, (key, module))| (key, values[i].1, module))
     {
         let mut missing = Vec::new();
         resolve_module_types(module, &mut missing);
 
-        let imports = resolve_module_imports(module, &modules).map_err(|e| Error {
-            source_code: NamedSource::new(
-                schema
-                    .path
-                    .as_ref()
-                    .map_or_else(|| "<unknown>".to_owned(), |p| p.display().to_string()),
-                schema.source.to_owned(),
-            ),
-            cause: ResolveError::Import(e),
-        })?;
+        let imports =
+            resolve_module_imports(module, split_key(key).0, &modules).map_err(|e| Error {
+                source_code: NamedSource::new(
+                    schema
+                        .path
+                        .as_ref()
+                        .map_or_else(|| "<unknown>".to_owned(), |p| p.display().to_string()),
+                    schema.source.to_owned(),
+                ),
+                cause: ResolveError::Import(e),
+            })?;
 
         for ty in missing {
             resolve_type_remotely(ty, &imports).map_err(|e| Error {
@@ -476,11 +482,12 @@ fn visit_externals<'a>(value: &'a Type<'_>, visit: &mut impl FnMut(&'a ExternalT
         | DataType::BoxBytes => {}
         DataType::Vec(ty)
         | DataType::HashSet(ty)
+        | DataType::BTreeSet(ty)
         | DataType::Option(ty)
         | DataType::Array(ty, _) => {
             visit_externals(ty, visit);
         }
-        DataType::HashMap(kv) => {
+        DataType::HashMap(kv) | DataType::BTreeMap(kv) => {
             visit_externals(&kv.0, visit);
             visit_externals(&kv.1, visit);
         }
@@ -499,8 +506,14 @@ fn visit_externals<'a>(value: &'a Type<'_>, visit: &mut impl FnMut(&'a ExternalT
     }
 }
 
+/// Split a schema key into the directory part and the schema name.
+fn split_key(key: &str) -> (&str, &str) {
+    key.rsplit_once('/').unwrap_or(("", key))
+}
+
 pub(crate) fn resolve_module_imports<'a>(
     module: &Module<'_>,
+    directory: &str,
     schemas: &'a [(&str, Module<'_>)],
 ) -> Result<Vec<ResolvedImport<'a>>, ResolveImport> {
     module
@@ -508,13 +521,38 @@ pub(crate) fn resolve_module_imports<'a>(
         .iter()
         .map(|import| {
             let root = &import.segments[0];
-            let schema = schemas
+            let candidates = schemas
                 .iter()
-                .find_map(|(name, schema)| (*name == root.get()).then_some(schema))
-                .ok_or_else(|| MissingSchema {
-                    name: root.get().to_owned(),
-                    used: root.span().into(),
-                })?;
+                .filter(|(key, _)| split_key(key).1 == root.get())
+                .collect::<Vec<_>>();
+
+            let schema = match candidates
+                .iter()
+                .find(|(key, _)| split_key(key).0 == directory)
+            {
+                Some((_, schema)) => schema,
+                None => match candidates.as_slice() {
+                    [] => {
+                        return Err(MissingSchema {
+                            name: root.get().to_owned(),
+                            used: root.span().into(),
+                        }
+                        .into())
+                    }
+                    [(_, schema)] => schema,
+                    _ => {
+                        return Err(AmbiguousSchema {
+                            name: root.get().to_owned(),
+                            candidates: candidates
+                                .iter()
+                                .map(|(key, _)| (*key).to_owned())
+                                .collect(),
+                            used: root.span().into(),
+                        }
+                        .into())
+                    }
+                },
+            };
 
             schema.resolve_import(import)
         })
@@ -72,6 +72,8 @@ pub struct Struct<'a> {
     pub source: &'a mabo_parser::Struct<'a>,
     /// Optional struct-level comment.
     pub comment: Box<[&'a str]>,
+    /// Optional attributes to customize the behavior.
+    pub attributes: Attributes<'a>,
     /// Unique name for this struct (within its scope).
     pub name: &'a str,
     /// Potential generics.
@@ -88,6 +90,8 @@ pub struct Enum<'a> {
     pub source: &'a mabo_parser::Enum<'a>,
     /// Optional enum-level comment.
     pub comment: Box<[&'a str]>,
+    /// Optional attributes to customize the behavior.
+    pub attributes: Attributes<'a>,
     /// Unique name for this enum, within its current scope.
     pub name: &'a str,
     /// Potential generics.
@@ -104,6 +108,8 @@ pub struct Variant<'a> {
     pub source: &'a mabo_parser::Variant<'a>,
     /// Optional variant-level comment.
     pub comment: Box<[&'a str]>,
+    /// Optional attributes to customize the behavior.
+    pub attributes: Attributes<'a>,
     /// Unique for this variant, within the enum it belongs to.
     pub name: &'a str,
     /// Fields of this variant, if any.
@@ -132,6 +138,8 @@ pub struct Field<'a> {
     pub source: ParserField<'a>,
     /// Optional field-level comment.
     pub comment: Box<[&'a str]>,
+    /// Optional attributes to customize the behavior.
+    pub attributes: Attributes<'a>,
     /// Unique name for this field, within the current element.
     pub name: Cow<'a, str>,
     /// Data type that defines the shape of the contained data.
@@ -160,6 +168,131 @@ pub enum FieldKind {
     Unit,
 }
 
+/// Collection of attributes, that describe metadata for the element they're attached to.
+#[derive(Default)]
+#[cfg_attr(feature = "json", derive(schemars::JsonSchema, serde::Serialize))]
+pub struct Attributes<'a>(pub Box<[Attribute<'a>]>);
+
+/// Single attribute, that describes metadata for the attached element.
+#[cfg_attr(feature = "json", derive(schemars::JsonSchema, serde::Serialize))]
+pub struct Attribute<'a> {
+    /// Identifier of the attribute.
+    pub name: &'a str,
+    /// Potential value(s) associated with the attribute.
+    pub value: AttributeValue<'a>,
+}
+
+/// Value of an [`Attribute`] that can take one of several shapes.
+#[cfg_attr(feature = "json", derive(schemars::JsonSchema, serde::Serialize))]
+pub enum AttributeValue<'a> {
+    /// No value, the attribute is representative by itself.
+    Unit,
+    /// Single literal value.
+    Single(Literal),
+    /// Multiple values, represented as sub-attributes.
+    Multi(Box<[Attribute<'a>]>),
+}
+
+/// Deprecation notice of an element, as declared by the `#[deprecated]` attribute.
+pub struct Deprecated<'a> {
+    /// Optional explanation of the deprecation, like which element to use instead.
+    pub note: Option<&'a str>,
+}
+
+/// Constraints on the value of a field, as declared by the `#[validate(...)]` attribute.
+///
+/// The rules are only collected here, it's the job of the validation step to ensure they fit the
+/// field's type.
+#[derive(Default)]
+pub struct Validation<'a> {
+    /// Smallest allowed value of a number.
+    pub min: Option<&'a Literal>,
+    /// Largest allowed value of a number.
+    pub max: Option<&'a Literal>,
+    /// Minimum length of a string or byte array, in bytes.
+    pub min_len: Option<u64>,
+    /// Maximum length of a string or byte array, in bytes.
+    pub max_len: Option<u64>,
+    /// Regular expression that a string must match.
+    pub pattern: Option<&'a str>,
+    /// Minimum amount of elements in a collection.
+    pub min_items: Option<u64>,
+    /// Maximum amount of elements in a collection.
+    pub max_items: Option<u64>,
+}
+
+impl Validation<'_> {
+    /// Whether no rules are declared at all.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.min.is_none()
+            && self.max.is_none()
+            && self.min_len.is_none()
+            && self.max_len.is_none()
+            && self.pattern.is_none()
+            && self.min_items.is_none()
+            && self.max_items.is_none()
+    }
+}
+
+impl<'a> Attributes<'a> {
+    /// Find the first attribute with the given name.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Attribute<'a>> {
+        self.0.iter().find(|attr| attr.name == name)
+    }
+
+    /// Whether the element is marked as deprecated, either through `#[deprecated]` or with a note
+    /// like `#[deprecated = "..."]`.
+    #[must_use]
+    pub fn deprecated(&self) -> Option<Deprecated<'_>> {
+        self.get("deprecated").map(|attr| Deprecated {
+            note: match &attr.value {
+                AttributeValue::Single(Literal::String(note)) => Some(note),
+                _ => None,
+            },
+        })
+    }
+
+    /// Collect the constraints of the `#[validate(...)]` attribute, that the value of a field
+    /// must fulfill.
+    #[must_use]
+    pub fn validation(&self) -> Validation<'_> {
+        let mut validation = Validation::default();
+        let Some(AttributeValue::Multi(rules)) = self.get("validate").map(|attr| &attr.value)
+        else {
+            return validation;
+        };
+
+        for rule in &**rules {
+            let AttributeValue::Single(value) = &rule.value else {
+                continue;
+            };
+            let size = match value {
+                Literal::Int(value) => u64::try_from(*value).ok(),
+                _ => None,
+            };
+
+            match rule.name {
+                "min" => validation.min = Some(value),
+                "max" => validation.max = Some(value),
+                "min_len" => validation.min_len = size,
+                "max_len" => validation.max_len = size,
+                "pattern" => {
+                    if let Literal::String(pattern) = value {
+                        validation.pattern = Some(pattern);
+                    }
+                }
+                "min_items" => validation.min_items = size,
+                "max_items" => validation.max_items = size,
+                _ => {}
+            }
+        }
+
+        validation
+    }
+}
+
 /// Alias (re-name) from one type to another.
 #[cfg_attr(feature = "json", derive(schemars::JsonSchema, serde::Serialize))]
 pub struct TypeAlias<'a> {
@@ -264,6 +397,10 @@ pub enum Type<'a> {
     HashMap(Box<(Type<'a>, Type<'a>)>),
     /// Hash set of data types (each entry is unique).
     HashSet(Box<Type<'a>>),
+    /// Key-value map of data types, that is sorted by its keys.
+    BTreeMap(Box<(Type<'a>, Type<'a>)>),
+    /// Set of data types (each entry is unique), that is sorted by its entries.
+    BTreeSet(Box<Type<'a>>),
     /// Optional value.
     Option(Box<Type<'a>>),
     /// Non-zero value.
@@ -310,6 +447,25 @@ fn comment<'a>(item: &'a mabo_parser::Comment<'_>) -> Box<[&'a str]> {
     item.0.iter().map(|line| line.value).collect()
 }
 
+fn attributes<'a>(item: &'a mabo_parser::Attributes<'_>) -> Attributes<'a> {
+    Attributes(item.0.iter().map(simplify_attribute).collect())
+}
+
+fn simplify_attribute<'a>(item: &'a mabo_parser::Attribute<'_>) -> Attribute<'a> {
+    Attribute {
+        name: item.name,
+        value: match &item.value {
+            mabo_parser::AttributeValue::Unit => AttributeValue::Unit,
+            mabo_parser::AttributeValue::Single(literal) => {
+                AttributeValue::Single(simplify_literal(literal))
+            }
+            mabo_parser::AttributeValue::Multi(attrs) => {
+                AttributeValue::Multi(attrs.iter().map(simplify_attribute).collect())
+            }
+        },
+    }
+}
+
 #[inline]
 fn generics<'a>(item: &'a mabo_parser::Generics<'_>) -> Box<[&'a str]> {
     item.0.iter().map(mabo_parser::Name::get).collect()
@@ -344,6 +500,7 @@ fn simplify_struct<'a>(item: &'a mabo_parser::Struct<'_>) -> Struct<'a> {
     Struct {
         source: item,
         comment: comment(&item.comment),
+        attributes: attributes(&item.attributes),
         name: item.name.get(),
         generics: generics(&item.generics),
         fields: simplify_fields(&item.fields),
@@ -356,6 +513,7 @@ fn simplify_enum<'a>(item: &'a mabo_parser::Enum<'_>) -> Enum<'a> {
     Enum {
         source: item,
         comment: comment(&item.comment),
+        attributes: attributes(&item.attributes),
         name: item.name.get(),
         generics: generics(&item.generics),
         variants: item
@@ -373,6 +531,7 @@ fn simplify_variant<'a>(
     Variant {
         source: item,
         comment: comment(&item.comment),
+        attributes: attributes(&item.attributes),
         name: item.name.get(),
         fields: simplify_fields(&item.fields),
         id: id_gen.next(item.id.as_ref()),
@@ -390,6 +549,7 @@ fn simplify_fields<'a>(item: &'a mabo_parser::Fields<'_>) -> Fields<'a> {
                 .map(|field| Field {
                     source: ParserField::Named(field),
                     comment: comment(&field.comment),
+                    attributes: attributes(&field.attributes),
                     name: field.name.get().into(),
                     ty: simplify_type(&field.ty),
                     id: id_gen.next(field.id.as_ref()),
@@ -405,6 +565,7 @@ fn simplify_fields<'a>(item: &'a mabo_parser::Fields<'_>) -> Fields<'a> {
                 .map(|(i, field)| Field {
                     source: ParserField::Unnamed(field),
                     comment: Box::default(),
+                    attributes: Attributes::default(),
                     name: format!("n{i}").into(),
                     ty: simplify_type(&field.ty),
                     id: id_gen.next(field.id.as_ref()),
@@ -444,6 +605,10 @@ fn simplify_type<'a>(item: &'a mabo_parser::Type<'_>) -> Type<'a> {
             Type::HashMap((simplify_type(&kv.0), simplify_type(&kv.1)).into())
         }
         mabo_parser::DataType::HashSet(ref ty) => Type::HashSet(simplify_type(ty).into()),
+        mabo_parser::DataType::BTreeMap(ref kv) => {
+            Type::BTreeMap((simplify_type(&kv.0), simplify_type(&kv.1)).into())
+        }
+        mabo_parser::DataType::BTreeSet(ref ty) => Type::BTreeSet(simplify_type(ty).into()),
         mabo_parser::DataType::Option(ref ty) => Type::Option(simplify_type(ty).into()),
         mabo_parser::DataType::NonZero(ref ty) => Type::NonZero(simplify_type(ty).into()),
         mabo_parser::DataType::BoxString => Type::BoxString,
@@ -160,9 +160,10 @@ fn visit_externals(value: &Type<'_>, visit: &mut impl FnMut(&ExternalType<'_>))
         | DataType::BoxBytes => {}
         DataType::Vec(ty)
         | DataType::HashSet(ty)
+        | DataType::BTreeSet(ty)
         | DataType::Option(ty)
         | DataType::Array(ty, _) => visit_externals(ty, visit),
-        DataType::HashMap(kv) => {
+        DataType::HashMap(kv) | DataType::BTreeMap(kv) => {
             visit_externals(&kv.0, visit);
             visit_externals(&kv.1, visit);
         }
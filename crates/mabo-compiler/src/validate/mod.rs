@@ -12,12 +12,14 @@ pub use self::{
         DuplicateVariantId,
     },
     names::{DuplicateFieldName, DuplicateName, DuplicateNameInModule, DuplicateVariantName},
+    rules::{InvalidRule, InvalidRuleValue, RuleTypeMismatch, UnknownRule},
     tuples::{InvalidTupleAmount, TupleSize},
 };
 
 mod generics;
 mod ids;
 mod names;
+mod rules;
 mod tuples;
 
 /// Reason why a schema was invalid.
@@ -39,6 +41,10 @@ pub enum Error {
     #[error("invalid tuple element size found")]
     #[diagnostic(transparent)]
     TupleSize(#[from] TupleSize),
+    /// Validation rules of a field are invalid.
+    #[error("invalid validation rule found")]
+    #[diagnostic(transparent)]
+    InvalidRule(#[from] InvalidRule),
 }
 
 impl From<DuplicateFieldId> for Error {
@@ -63,6 +69,7 @@ impl From<DuplicateFieldName> for Error {
 /// - Fields names in structs or enum variants are unique.
 /// - Generic type parameters in a struct or enum are unique.
 /// - All generic type parameters are used.
+/// - Validation rules of fields are known and fit the field's type.
 ///
 /// # Errors
 ///
@@ -83,12 +90,14 @@ fn definition(value: &Definition<'_>) -> Result<(), Error> {
             names::validate_struct_names(s)?;
             generics::validate_struct_generics(s)?;
             tuples::validate_struct_tuples(s)?;
+            rules::validate_struct_rules(s)?;
         }
         Definition::Enum(e) => {
             ids::validate_enum_ids(e)?;
             names::validate_enum_names(e)?;
             generics::validate_enum_generics(e)?;
             tuples::validate_enum_tuples(e)?;
+            rules::validate_enum_rules(e)?;
         }
         Definition::TypeAlias(a) => {
             tuples::validate_alias_tuples(a)?;
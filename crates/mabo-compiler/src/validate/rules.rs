@@ -0,0 +1,305 @@
+use std::ops::Range;
+
+use mabo_parser::{
+    Attribute, AttributeValue, DataType, Enum, Fields, LiteralValue, NamedField, Spanned, Struct,
+};
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::highlight;
+
+/// Validation rule of a field is considered invalid.
+#[derive(Debug, Diagnostic, Error)]
+pub enum InvalidRule {
+    /// The rule is not known.
+    #[error("unknown validation rule found")]
+    #[diagnostic(transparent)]
+    Unknown(#[from] UnknownRule),
+    /// The rule can't be applied to the field's type.
+    #[error("validation rule doesn't apply to the field type")]
+    #[diagnostic(transparent)]
+    TypeMismatch(#[from] RuleTypeMismatch),
+    /// The rule's value is not usable.
+    #[error("invalid value for a validation rule found")]
+    #[diagnostic(transparent)]
+    Value(#[from] InvalidRuleValue),
+}
+
+/// Rule name that is not part of the supported validation rules.
+#[derive(Debug, Diagnostic, Error)]
+#[error("unknown validation rule `{}`", highlight::value(name))]
+#[diagnostic(help(
+    "supported rules are `min`, `max`, `min_len`, `max_len`, `pattern`, `min_items` and \
+     `max_items`"
+))]
+pub struct UnknownRule {
+    /// Name of the rule.
+    pub name: String,
+    /// Source location of the field the rule is attached to.
+    #[label("used on this field")]
+    pub field: Range<usize>,
+}
+
+/// Rule that is used on a field with a type it can't check.
+#[derive(Debug, Diagnostic, Error)]
+#[error(
+    "validation rule `{}` can't be used with the type `{}`",
+    highlight::value(name),
+    highlight::value(ty)
+)]
+#[diagnostic(help(
+    "`min` and `max` apply to numbers, `min_len` and `max_len` to strings and bytes, `pattern` to \
+     strings, and `min_items` and `max_items` to collections"
+))]
+pub struct RuleTypeMismatch {
+    /// Name of the rule.
+    pub name: String,
+    /// The field's type, stripped from any optional or non-zero wrapper.
+    pub ty: String,
+    /// Source location of the field's type.
+    #[label("declared here")]
+    pub declared: Range<usize>,
+}
+
+/// Rule with a value that doesn't fit the rule or the field's type.
+#[derive(Debug, Diagnostic, Error)]
+#[error("invalid value for the validation rule `{}`", highlight::value(name))]
+pub struct InvalidRuleValue {
+    /// Name of the rule.
+    pub name: String,
+    /// Explanation of the problem.
+    pub reason: String,
+    /// Source location of the value, or of the field if the value is missing.
+    #[label("{reason}")]
+    pub declared: Range<usize>,
+}
+
+/// Kind of value that a rule can be checked against.
+#[derive(Clone, Copy)]
+enum Kind {
+    /// Integer with its lower and upper limit.
+    Int(i128, i128),
+    Float,
+    String,
+    Bytes,
+    Collection,
+    Other,
+}
+
+/// Ensure the validation rules of all fields in a struct are known and fit the field types.
+pub(crate) fn validate_struct_rules(value: &Struct<'_>) -> Result<(), InvalidRule> {
+    validate_field_rules(&value.fields)
+}
+
+/// Ensure the validation rules of all fields in each enum variant are known and fit the field
+/// types.
+pub(crate) fn validate_enum_rules(value: &Enum<'_>) -> Result<(), InvalidRule> {
+    value
+        .variants
+        .iter()
+        .try_for_each(|variant| validate_field_rules(&variant.fields))
+}
+
+fn validate_field_rules(value: &Fields<'_>) -> Result<(), InvalidRule> {
+    match value {
+        Fields::Named(named) => named.iter().try_for_each(validate_rules),
+        Fields::Unnamed(_) | Fields::Unit => Ok(()),
+    }
+}
+
+fn validate_rules(field: &NamedField<'_>) -> Result<(), InvalidRule> {
+    let Some(attribute) = field
+        .attributes
+        .0
+        .iter()
+        .find(|attr| attr.name == "validate")
+    else {
+        return Ok(());
+    };
+
+    let AttributeValue::Multi(rules) = &attribute.value else {
+        return Err(InvalidRuleValue {
+            name: attribute.name.to_owned(),
+            reason: "expected a list of rules, like `#[validate(min = 1)]`".to_owned(),
+            declared: field.span().into(),
+        }
+        .into());
+    };
+
+    let kind = kind(&field.ty.value);
+
+    for rule in rules {
+        let expected = match rule.name {
+            "min" | "max" => matches!(kind, Kind::Int(..) | Kind::Float),
+            "min_len" | "max_len" => matches!(kind, Kind::String | Kind::Bytes),
+            "pattern" => matches!(kind, Kind::String),
+            "min_items" | "max_items" => matches!(kind, Kind::Collection),
+            _ => {
+                return Err(UnknownRule {
+                    name: rule.name.to_owned(),
+                    field: field.span().into(),
+                }
+                .into())
+            }
+        };
+
+        if !expected {
+            return Err(RuleTypeMismatch {
+                name: rule.name.to_owned(),
+                ty: strip(&field.ty.value).to_string(),
+                declared: field.ty.span().into(),
+            }
+            .into());
+        }
+
+        if rules.iter().filter(|r| r.name == rule.name).count() > 1 {
+            return Err(InvalidRuleValue {
+                name: rule.name.to_owned(),
+                reason: "rule is declared more than once".to_owned(),
+                declared: field.span().into(),
+            }
+            .into());
+        }
+
+        validate_value(field, rule, kind)?;
+    }
+
+    validate_order(rules, "min", "max")?;
+    validate_order(rules, "min_len", "max_len")?;
+    validate_order(rules, "min_items", "max_items")?;
+
+    Ok(())
+}
+
+/// Ensure the value of a single rule has the right type and fits the field.
+fn validate_value(
+    field: &NamedField<'_>,
+    rule: &Attribute<'_>,
+    kind: Kind,
+) -> Result<(), InvalidRuleValue> {
+    let error = |reason: &str, declared: Range<usize>| InvalidRuleValue {
+        name: rule.name.to_owned(),
+        reason: reason.to_owned(),
+        declared,
+    };
+
+    let AttributeValue::Single(literal) = &rule.value else {
+        return Err(error(
+            "expected a single value, like `min = 1`",
+            field.span().into(),
+        ));
+    };
+    let declared = literal.span().into();
+
+    match (rule.name, &literal.value, kind) {
+        ("min" | "max", LiteralValue::Int(value), Kind::Int(min, max)) => {
+            if !(min..=max).contains(value) {
+                return Err(error("value is out of range for the field type", declared));
+            }
+        }
+        ("min" | "max", LiteralValue::Int(_) | LiteralValue::Float(_), Kind::Float) => {}
+        ("min" | "max", _, _) => {
+            return Err(error("expected a number matching the field type", declared));
+        }
+        ("pattern", LiteralValue::String(pattern), _) => {
+            if let Err(e) = regex_syntax::Parser::new().parse(pattern) {
+                let reason = match e {
+                    regex_syntax::Error::Parse(e) => e.kind().to_string(),
+                    regex_syntax::Error::Translate(e) => e.kind().to_string(),
+                    e => e.to_string(),
+                };
+                return Err(error(
+                    &format!("invalid regular expression: {reason}"),
+                    declared,
+                ));
+            }
+        }
+        ("pattern", _, _) => {
+            return Err(error("expected a regular expression string", declared));
+        }
+        (_, LiteralValue::Int(value), _) => {
+            if u64::try_from(*value).is_err() {
+                return Err(error("value must not be negative", declared));
+            }
+        }
+        (_, _, _) => {
+            return Err(error("expected a non-negative integer", declared));
+        }
+    }
+
+    Ok(())
+}
+
+/// Ensure the lower limit of a pair of rules is not larger than the upper limit.
+fn validate_order(
+    rules: &[Attribute<'_>],
+    lower: &str,
+    upper: &str,
+) -> Result<(), InvalidRuleValue> {
+    let find = |name: &str| {
+        rules.iter().find_map(|rule| match &rule.value {
+            AttributeValue::Single(literal) if rule.name == name => Some(literal),
+            _ => None,
+        })
+    };
+
+    let (Some(min), Some(max)) = (find(lower), find(upper)) else {
+        return Ok(());
+    };
+
+    #[allow(clippy::cast_precision_loss)]
+    let inverted = match (&min.value, &max.value) {
+        (LiteralValue::Int(min), LiteralValue::Int(max)) => min > max,
+        (LiteralValue::Int(min), LiteralValue::Float(max)) => *min as f64 > *max,
+        (LiteralValue::Float(min), LiteralValue::Int(max)) => *min > *max as f64,
+        (LiteralValue::Float(min), LiteralValue::Float(max)) => min > max,
+        _ => false,
+    };
+
+    if inverted {
+        return Err(InvalidRuleValue {
+            name: upper.to_owned(),
+            reason: format!("value is smaller than `{lower}`"),
+            declared: max.span().into(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Remove optional and non-zero wrappers, as rules apply to the contained value.
+fn strip<'a, 'b>(value: &'b DataType<'a>) -> &'b DataType<'a> {
+    match value {
+        DataType::Option(ty) | DataType::NonZero(ty) => strip(&ty.value),
+        ty => ty,
+    }
+}
+
+fn kind(value: &DataType<'_>) -> Kind {
+    match strip(value) {
+        DataType::U8 => Kind::Int(u8::MIN.into(), u8::MAX.into()),
+        DataType::U16 => Kind::Int(u16::MIN.into(), u16::MAX.into()),
+        DataType::U32 => Kind::Int(u32::MIN.into(), u32::MAX.into()),
+        DataType::U64 => Kind::Int(u64::MIN.into(), u64::MAX.into()),
+        DataType::U128 => Kind::Int(0, i128::MAX),
+        DataType::I8 => Kind::Int(i8::MIN.into(), i8::MAX.into()),
+        DataType::I16 => Kind::Int(i16::MIN.into(), i16::MAX.into()),
+        DataType::I32 => Kind::Int(i32::MIN.into(), i32::MAX.into()),
+        DataType::I64 => Kind::Int(i64::MIN.into(), i64::MAX.into()),
+        DataType::I128 => Kind::Int(i128::MIN, i128::MAX),
+        DataType::F32 | DataType::F64 => Kind::Float,
+        DataType::String | DataType::StringRef | DataType::BoxString => Kind::String,
+        DataType::Bytes | DataType::BytesRef | DataType::BoxBytes => Kind::Bytes,
+        DataType::Vec(_)
+        | DataType::HashMap(_)
+        | DataType::HashSet(_)
+        | DataType::BTreeMap(_)
+        | DataType::BTreeSet(_) => Kind::Collection,
+        DataType::Bool
+        | DataType::Option(_)
+        | DataType::NonZero(_)
+        | DataType::Tuple(_)
+        | DataType::Array(..)
+        | DataType::External(_) => Kind::Other,
+    }
+}
@@ -111,9 +111,10 @@ fn visit_tuples<E>(
         | DataType::BoxBytes => Ok(()),
         DataType::Vec(ty)
         | DataType::HashSet(ty)
+        | DataType::BTreeSet(ty)
         | DataType::Option(ty)
         | DataType::Array(ty, _) => visit_tuples(ty, visit),
-        DataType::HashMap(kv) => {
+        DataType::HashMap(kv) | DataType::BTreeMap(kv) => {
             visit_tuples(&kv.0, visit)?;
             visit_tuples(&kv.1, visit)
         }
@@ -6,6 +6,7 @@ use std::{
 };
 
 use insta::{assert_snapshot, glob, with_settings};
+use mabo_compiler::resolve::{AmbiguousSchema, ResolveError, ResolveImport};
 use mabo_parser::Schema;
 use miette::{Diagnostic, MietteHandler, MietteHandlerOpts, NamedSource, Report, ReportHandler};
 
@@ -135,3 +136,64 @@ fn resolve_schema_remote() {
         });
     });
 }
+
+#[test]
+fn resolve_schema_directories() {
+    let input = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/inputs/resolve/datetime.mabo"
+    ));
+    let datetime = Schema::parse(input, Some(Path::new("resolve/datetime.mabo"))).unwrap();
+    let schema = Schema::parse("use datetime::Date;", None).unwrap();
+
+    let schemas = [
+        ("a/datetime", &datetime),
+        ("b/datetime", &datetime),
+        ("a/test", &schema),
+    ];
+    mabo_compiler::resolve_schemas(&schemas).unwrap();
+
+    let schemas = [
+        ("a/datetime", &datetime),
+        ("b/datetime", &datetime),
+        ("c/test", &schema),
+    ];
+    let result = mabo_compiler::resolve_schemas(&schemas).unwrap_err();
+    assert!(matches!(
+        result.cause,
+        ResolveError::Import(ResolveImport::AmbiguousSchema(AmbiguousSchema { ref candidates, .. }))
+            if candidates == &["a/datetime", "b/datetime"]
+    ));
+}
+
+#[test]
+fn compare_schemas() {
+    glob!("inputs/compare/*.old.mabo", |path| {
+        let new_path = path.with_file_name(
+            path.file_name()
+                .unwrap()
+                .to_string_lossy()
+                .replace(".old.", ".new."),
+        );
+
+        let old_input = fs::read_to_string(path).unwrap();
+        let new_input = fs::read_to_string(&new_path).unwrap();
+        let old = Schema::parse(old_input.as_str(), Some(&strip_path(path))).unwrap();
+        let new = Schema::parse(new_input.as_str(), Some(&strip_path(&new_path))).unwrap();
+        let old = mabo_compiler::simplify_schema(&old);
+        let new = mabo_compiler::simplify_schema(&new);
+
+        let changes = mabo_compiler::compare_schemas(&[("test", &old)], &[("test", &new)])
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        with_settings!({
+            description => format!("{}\n\n---\n\n{}", old_input.trim(), new_input.trim()),
+            omit_expression => true,
+        }, {
+            assert_snapshot!("compare", changes);
+        });
+    });
+}
@@ -270,6 +270,8 @@ impl Display for PrintType<'_> {
             Type::Vec(t) => write!(f, "vec<{}>", Self(t)),
             Type::HashMap(kv) => write!(f, "hash_map<{}, {}>", Self(&kv.0), Self(&kv.1)),
             Type::HashSet(t) => write!(f, "hash_set<{}>", Self(t)),
+            Type::BTreeMap(kv) => write!(f, "btree_map<{}, {}>", Self(&kv.0), Self(&kv.1)),
+            Type::BTreeSet(t) => write!(f, "btree_set<{}>", Self(t)),
             Type::Option(t) => write!(f, "option<{}>", Self(t)),
             Type::NonZero(t) => write!(f, "non_zero<{}>", Self(t)),
             Type::BoxString => f.write_str("box<string>"),
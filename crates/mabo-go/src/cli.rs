@@ -11,6 +11,9 @@ pub struct Cli {
     pub out_dir: Option<PathBuf>,
     #[arg(long)]
     pub no_fmt: bool,
+    /// Validate the constraints of `#[validate(...)]` attributes while decoding.
+    #[arg(long)]
+    pub decode_validation: bool,
 }
 
 impl Cli {
@@ -6,16 +6,20 @@ use mabo_compiler::simplify::{FieldKind, Fields, Struct, Type, Variant};
 
 use crate::definition::{self, RenderGenericNames};
 
-pub(super) struct RenderStruct<'a>(pub(super) &'a Struct<'a>);
+pub(super) struct RenderStruct<'a> {
+    pub(super) value: &'a Struct<'a>,
+    /// Whether to validate the value after decoding it.
+    pub(super) validate: bool,
+}
 
 impl Display for RenderStruct<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(
             f,
             "var _ buf.Decode = (*{}{})(nil)\n",
-            heck::AsUpperCamelCase(&self.0.name),
+            heck::AsUpperCamelCase(&self.value.name),
             RenderGenericNames {
-                generics: &self.0.generics,
+                generics: &self.value.generics,
                 fields_filter: None,
             }
         )?;
@@ -23,13 +27,13 @@ impl Display for RenderStruct<'_> {
         writeln!(
             f,
             "func (v *{}{}) Decode(r []byte) ([]byte, error) {{",
-            heck::AsUpperCamelCase(&self.0.name),
+            heck::AsUpperCamelCase(&self.value.name),
             RenderGenericNames {
-                generics: &self.0.generics,
+                generics: &self.value.generics,
                 fields_filter: None,
             }
         )?;
-        writeln!(f, "{}", RenderFieldVars(&self.0.fields))?;
+        writeln!(f, "{}", RenderFieldVars(&self.value.fields))?;
         writeln!(f, "\tfor len(r) > 0 {{")?;
         writeln!(f, "\t\tr2, id, err := buf.DecodeID(r)")?;
         writeln!(f, "\t\tif err != nil {{")?;
@@ -37,12 +41,13 @@ impl Display for RenderStruct<'_> {
         writeln!(f, "\t\t}}")?;
         writeln!(f, "\t\tr = r2\n")?;
         writeln!(f, "\t\tswitch id {{")?;
-        write!(f, "{}", RenderFields(&self.0.fields))?;
+        write!(f, "{}", RenderFields(&self.value.fields))?;
         writeln!(f, "\t\t\tcase buf.EndMarker:")?;
         writeln!(f, "\t\t\t\tbreak")?;
         writeln!(f, "\t\t}}")?;
         writeln!(f, "\t}}\n")?;
-        write!(f, "{}", RenderFoundChecks(&self.0.fields))?;
+        write!(f, "{}", RenderFoundChecks(&self.value.fields))?;
+        write!(f, "{}", RenderValidate(self.validate))?;
         writeln!(f, "\n\treturn r, nil\n}}")
     }
 }
@@ -51,6 +56,8 @@ pub(super) struct RenderEnumVariant<'a> {
     pub(super) enum_name: &'a str,
     pub(super) generics: &'a [&'a str],
     pub(super) variant: &'a Variant<'a>,
+    /// Whether to validate the value after decoding it.
+    pub(super) validate: bool,
 }
 
 impl Display for RenderEnumVariant<'_> {
@@ -90,6 +97,7 @@ impl Display for RenderEnumVariant<'_> {
         writeln!(f, "\t\t}}")?;
         writeln!(f, "\t}}\n")?;
         write!(f, "{}", RenderFoundChecks(&self.variant.fields))?;
+        write!(f, "{}", RenderValidate(self.validate))?;
         writeln!(f, "\n\treturn r, nil\n}}")
     }
 }
@@ -131,6 +139,20 @@ impl Display for RenderFoundChecks<'_> {
     }
 }
 
+struct RenderValidate(bool);
+
+impl Display for RenderValidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 {
+            writeln!(f, "\tif err := v.Validate(); err != nil {{")?;
+            writeln!(f, "\t\treturn nil, err")?;
+            writeln!(f, "\t}}")?;
+        }
+
+        Ok(())
+    }
+}
+
 struct RenderFields<'a>(&'a Fields<'a>);
 
 impl Display for RenderFields<'_> {
@@ -203,7 +225,7 @@ impl Display for RenderType<'_> {
                     }
                 )
             }
-            Type::HashMap(kv) => {
+            Type::HashMap(kv) | Type::BTreeMap(kv) => {
                 write!(
                     f,
                     "{}",
@@ -214,7 +236,7 @@ impl Display for RenderType<'_> {
                     }
                 )
             }
-            Type::HashSet(ty) => {
+            Type::HashSet(ty) | Type::BTreeSet(ty) => {
                 write!(
                     f,
                     "{}",
@@ -260,7 +282,7 @@ impl Display for RenderType<'_> {
                         }
                     )
                 }
-                Type::HashMap(kv) => {
+                Type::HashMap(kv) | Type::BTreeMap(kv) => {
                     write!(
                         f,
                         "{}",
@@ -271,7 +293,7 @@ impl Display for RenderType<'_> {
                         }
                     )
                 }
-                Type::HashSet(ty) => {
+                Type::HashSet(ty) | Type::BTreeSet(ty) => {
                     write!(
                         f,
                         "{}",
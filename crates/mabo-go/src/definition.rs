@@ -1,11 +1,11 @@
 use std::fmt::{self, Display, Write};
 
 use mabo_compiler::simplify::{
-    Const, Definition, Enum, ExternalType, Fields, Literal, Schema, Struct, Type, TypeAlias,
-    Variant,
+    Attributes, Const, Definition, Deprecated, Enum, ExternalType, Fields, Literal, Schema, Struct,
+    Type, TypeAlias, Variant,
 };
 
-use crate::{decode, encode, size, Opts, Output};
+use crate::{decode, encode, size, validate, Opts, Output};
 
 /// Take a single schema and convert it into Go source code (which can result in multiple files).
 #[must_use]
@@ -17,12 +17,12 @@ pub fn render_schema<'a>(
         "{}{}{}",
         RenderHeader,
         RenderPackage(opts.package, None),
-        RenderImports,
+        RenderImports(definitions),
     );
 
     let modules = definitions
         .iter()
-        .filter_map(|def| render_definition(&mut content, def))
+        .filter_map(|def| render_definition(opts, &mut content, def))
         .collect();
 
     Output {
@@ -32,20 +32,24 @@ pub fn render_schema<'a>(
     }
 }
 
-fn render_definition<'a>(buf: &mut String, definition: &'a Definition<'_>) -> Option<Output<'a>> {
+fn render_definition<'a>(
+    opts: &Opts<'_>,
+    buf: &mut String,
+    definition: &'a Definition<'_>,
+) -> Option<Output<'a>> {
     match definition {
         Definition::Module(m) => {
             let mut content = format!(
                 "{}{}{}",
                 RenderHeader,
                 RenderPackage(m.name, Some(&m.comment)),
-                RenderImports,
+                RenderImports(&m.definitions),
             );
 
             let modules = m
                 .definitions
                 .iter()
-                .filter_map(|def| render_definition(&mut content, def))
+                .filter_map(|def| render_definition(opts, &mut content, def))
                 .collect();
 
             return Some(Output {
@@ -69,14 +73,18 @@ fn render_definition<'a>(buf: &mut String, definition: &'a Definition<'_>) -> Op
             .unwrap();
             writeln!(
                 buf,
-                "\n{}\n{}\n{}",
+                "\n{}\n{}\n{}\n{}",
                 encode::RenderStruct(s),
-                decode::RenderStruct(s),
+                decode::RenderStruct {
+                    value: s,
+                    validate: opts.decode_validation,
+                },
                 size::RenderStruct(s),
+                validate::RenderStruct(s),
             )
             .unwrap();
         }
-        Definition::Enum(e) => writeln!(buf, "{}", RenderEnum(e)).unwrap(),
+        Definition::Enum(e) => writeln!(buf, "{}", RenderEnum(opts, e)).unwrap(),
         Definition::TypeAlias(a) => writeln!(buf, "{}", RenderAlias(a)).unwrap(),
         Definition::Const(c) => write!(buf, "{}", RenderConst(c)).unwrap(),
         Definition::Import(_) => {}
@@ -110,11 +118,26 @@ impl Display for RenderPackage<'_> {
     }
 }
 
-struct RenderImports;
+struct RenderImports<'a>(&'a [Definition<'a>]);
 
-impl Display for RenderImports {
+impl Display for RenderImports<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (fmt, regexp) = validate::imports(self.0);
+        let sort = encode::needs_sort(self.0);
+
         writeln!(f, "import (")?;
+        if fmt {
+            writeln!(f, "\t\"fmt\"")?;
+        }
+        if regexp {
+            writeln!(f, "\t\"regexp\"")?;
+        }
+        if sort {
+            writeln!(f, "\t\"sort\"")?;
+        }
+        if fmt || regexp || sort {
+            writeln!(f)?;
+        }
         writeln!(f, "\tmabo \"github.com/dnaka91/mabo-go\"")?;
         writeln!(f, "\tbuf \"github.com/dnaka91/mabo-go/buf\"")?;
         writeln!(f, ")\n")
@@ -127,11 +150,16 @@ impl Display for RenderStruct<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(
             f,
-            "{}type {}{} {}",
+            "{}{}type {}{} {}",
             RenderComment {
                 indent: 0,
                 comment: &self.0.comment
             },
+            RenderDeprecated {
+                indent: 0,
+                comment: &self.0.comment,
+                attributes: &self.0.attributes,
+            },
             heck::AsUpperCamelCase(&self.0.name),
             RenderGenerics {
                 generics: &self.0.generics,
@@ -291,11 +319,16 @@ impl Display for RenderFields<'_> {
             for field in &*self.0.fields {
                 writeln!(
                     f,
-                    "{}\t{} {}",
+                    "{}{}\t{} {}",
                     RenderComment {
                         indent: 1,
                         comment: &field.comment
                     },
+                    RenderDeprecated {
+                        indent: 1,
+                        comment: &field.comment,
+                        attributes: &field.attributes,
+                    },
                     heck::AsUpperCamelCase(&field.name),
                     RenderType(&field.ty)
                 )?;
@@ -382,6 +415,32 @@ impl Display for RenderComment<'_> {
     }
 }
 
+/// Deprecation notice in the form that Go tooling recognizes, as a separate paragraph after the
+/// regular comment.
+struct RenderDeprecated<'a> {
+    indent: usize,
+    comment: &'a [&'a str],
+    attributes: &'a Attributes<'a>,
+}
+
+impl Display for RenderDeprecated<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(Deprecated { note }) = self.attributes.deprecated() else {
+            return Ok(());
+        };
+
+        if !self.comment.is_empty() {
+            writeln!(f, "{:\t<width$}//", "", width = self.indent)?;
+        }
+
+        write!(f, "{:\t<width$}// Deprecated: ", "", width = self.indent)?;
+        match note {
+            Some(note) => writeln!(f, "{note}"),
+            None => writeln!(f, "should no longer be used."),
+        }
+    }
+}
+
 pub(super) struct RenderType<'a>(pub(super) &'a Type<'a>);
 
 impl Display for RenderType<'_> {
@@ -402,8 +461,12 @@ impl Display for RenderType<'_> {
             Type::String | Type::StringRef | Type::BoxString => write!(f, "string"),
             Type::Bytes | Type::BytesRef | Type::BoxBytes => write!(f, "[]byte"),
             Type::Vec(ty) => write!(f, "[]{}", RenderType(ty)),
-            Type::HashMap(kv) => write!(f, "map[{}]{}", RenderType(&kv.0), RenderType(&kv.1)),
-            Type::HashSet(ty) => write!(f, "map[{}]struct{{}}", RenderType(ty)),
+            Type::HashMap(kv) | Type::BTreeMap(kv) => {
+                write!(f, "map[{}]{}", RenderType(&kv.0), RenderType(&kv.1))
+            }
+            Type::HashSet(ty) | Type::BTreeSet(ty) => {
+                write!(f, "map[{}]struct{{}}", RenderType(ty))
+            }
             Type::Option(ty) => write!(f, "*{}", RenderType(ty)),
             Type::NonZero(ty) => match &**ty {
                 Type::U8 => write!(f, "mabo.NonZeroU8"),
@@ -421,13 +484,15 @@ impl Display for RenderType<'_> {
                 Type::String | Type::StringRef => write!(f, "mabo.NonZeroString"),
                 Type::Bytes | Type::BytesRef => write!(f, "mabo.NonZeroBytes"),
                 Type::Vec(ty) => write!(f, "mabo.NonZeroVec[{}]", RenderType(ty)),
-                Type::HashMap(kv) => write!(
+                Type::HashMap(kv) | Type::BTreeMap(kv) => write!(
                     f,
                     "mabo.NonZeroHashMap[{}, {}]",
                     RenderType(&kv.0),
                     RenderType(&kv.1)
                 ),
-                Type::HashSet(ty) => write!(f, "mabo.NonZeroHashSet[{}]", RenderType(ty)),
+                Type::HashSet(ty) | Type::BTreeSet(ty) => {
+                    write!(f, "mabo.NonZeroHashSet[{}]", RenderType(ty))
+                }
                 ty => todo!("compiler should catch invalid {ty:?} type"),
             },
             Type::Tuple(types) => write!(f, "mabo.Tuple{}{}", types.len(), Concat(types)),
@@ -566,35 +631,41 @@ impl Display for RenderLiteral<'_> {
     }
 }
 
-struct RenderEnum<'a>(&'a Enum<'a>);
+struct RenderEnum<'a>(&'a Opts<'a>, &'a Enum<'a>);
 
 impl Display for RenderEnum<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(
             f,
             "type {}Variant interface {{",
-            heck::AsUpperCamelCase(&self.0.name),
+            heck::AsUpperCamelCase(&self.1.name),
         )?;
         writeln!(f, "\t sealed()")?;
         writeln!(f, "}}")?;
 
         writeln!(
             f,
-            "\n{}type {} {1}Variant",
+            "\n{}{}type {} {2}Variant",
             RenderComment {
                 indent: 0,
-                comment: &self.0.comment
+                comment: &self.1.comment
             },
-            heck::AsUpperCamelCase(&self.0.name),
+            RenderDeprecated {
+                indent: 0,
+                comment: &self.1.comment,
+                attributes: &self.1.attributes,
+            },
+            heck::AsUpperCamelCase(&self.1.name),
         )?;
 
-        for variant in &self.0.variants {
+        for variant in &self.1.variants {
             write!(
                 f,
                 "\n{}",
                 RenderEnumVariant {
-                    enum_name: self.0.name,
-                    generics: &self.0.generics,
+                    opts: self.0,
+                    enum_name: self.1.name,
+                    generics: &self.1.generics,
                     variant
                 }
             )?;
@@ -605,6 +676,7 @@ impl Display for RenderEnum<'_> {
 }
 
 struct RenderEnumVariant<'a> {
+    opts: &'a Opts<'a>,
     enum_name: &'a str,
     generics: &'a [&'a str],
     variant: &'a Variant<'a>,
@@ -614,11 +686,16 @@ impl Display for RenderEnumVariant<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(
             f,
-            "{}type {}_{}{} {}",
+            "{}{}type {}_{}{} {}",
             RenderComment {
                 indent: 0,
                 comment: &self.variant.comment
             },
+            RenderDeprecated {
+                indent: 0,
+                comment: &self.variant.comment,
+                attributes: &self.variant.attributes,
+            },
             heck::AsUpperCamelCase(self.enum_name),
             heck::AsUpperCamelCase(&self.variant.name),
             RenderGenerics {
@@ -656,7 +733,7 @@ impl Display for RenderEnumVariant<'_> {
 
         write!(
             f,
-            "\n{}\n{}\n{}",
+            "\n{}\n{}\n{}\n{}",
             encode::RenderEnumVariant {
                 enum_name: self.enum_name,
                 generics: self.generics,
@@ -666,12 +743,18 @@ impl Display for RenderEnumVariant<'_> {
                 enum_name: self.enum_name,
                 generics: self.generics,
                 variant: self.variant,
+                validate: self.opts.decode_validation,
             },
             size::RenderEnumVariant {
                 enum_name: self.enum_name,
                 generics: self.generics,
                 variant: self.variant,
             },
+            validate::RenderEnumVariant {
+                enum_name: self.enum_name,
+                generics: self.generics,
+                variant: self.variant,
+            },
         )
     }
 }
@@ -700,10 +783,13 @@ fn uses_generic(generic: &str, fields: &Fields<'_>) -> bool {
             | Type::BoxBytes => false,
             Type::Vec(ty)
             | Type::HashSet(ty)
+            | Type::BTreeSet(ty)
             | Type::Option(ty)
             | Type::NonZero(ty)
             | Type::Array(ty, _) => visit_external(ty, visit),
-            Type::HashMap(kv) => visit_external(&kv.0, visit) || visit_external(&kv.1, visit),
+            Type::HashMap(kv) | Type::BTreeMap(kv) => {
+                visit_external(&kv.0, visit) || visit_external(&kv.1, visit)
+            }
             Type::Tuple(types) => types.iter().any(|ty| visit_external(ty, visit)),
             Type::External(ty) => visit(ty),
         }
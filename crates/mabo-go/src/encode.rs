@@ -2,7 +2,7 @@
 
 use std::fmt::{self, Display};
 
-use mabo_compiler::simplify::{FieldKind, Fields, Struct, Type, Variant};
+use mabo_compiler::simplify::{Definition, FieldKind, Fields, Struct, Type, Variant};
 
 use crate::definition::{self, RenderGenericNames};
 
@@ -234,6 +234,93 @@ where
                 )?;
                 write!(f, "{:\t<indent$}}})", "", indent = self.indent)
             }
+            Type::BTreeMap(kv) => {
+                writeln!(f, "func(w []byte) []byte {{")?;
+                writeln!(
+                    f,
+                    "{:\t<indent$}entries := make([][]byte, 0, len({}))",
+                    "",
+                    self.name,
+                    indent = self.indent + 1,
+                )?;
+                writeln!(
+                    f,
+                    "{:\t<indent$}for k, v := range {} {{",
+                    "",
+                    self.name,
+                    indent = self.indent + 1,
+                )?;
+                writeln!(
+                    f,
+                    "{:\t<indent$}entries = append(entries, func(w []byte) []byte {{",
+                    "",
+                    indent = self.indent + 2,
+                )?;
+                writeln!(
+                    f,
+                    "{:\t<indent$}w = {}",
+                    "",
+                    RenderType {
+                        ty: &kv.0,
+                        name: "k",
+                        indent: self.indent + 3,
+                    },
+                    indent = self.indent + 3,
+                )?;
+                writeln!(
+                    f,
+                    "{:\t<indent$}return {}",
+                    "",
+                    RenderType {
+                        ty: &kv.1,
+                        name: "v",
+                        indent: self.indent + 3,
+                    },
+                    indent = self.indent + 3,
+                )?;
+                writeln!(f, "{:\t<indent$}}}(nil))", "", indent = self.indent + 2)?;
+                writeln!(f, "{:\t<indent$}}}", "", indent = self.indent + 1)?;
+                RenderSorted(self.indent + 1).fmt(f)?;
+                write!(f, "{:\t<indent$}}}(w)", "", indent = self.indent)
+            }
+            Type::BTreeSet(ty) => {
+                writeln!(f, "func(w []byte) []byte {{")?;
+                writeln!(
+                    f,
+                    "{:\t<indent$}entries := make([][]byte, 0, len({}))",
+                    "",
+                    self.name,
+                    indent = self.indent + 1,
+                )?;
+                writeln!(
+                    f,
+                    "{:\t<indent$}for v := range {} {{",
+                    "",
+                    self.name,
+                    indent = self.indent + 1,
+                )?;
+                writeln!(
+                    f,
+                    "{:\t<indent$}entries = append(entries, func(w []byte) []byte {{",
+                    "",
+                    indent = self.indent + 2,
+                )?;
+                writeln!(
+                    f,
+                    "{:\t<indent$}return {}",
+                    "",
+                    RenderType {
+                        ty,
+                        name: "v",
+                        indent: self.indent + 3,
+                    },
+                    indent = self.indent + 3,
+                )?;
+                writeln!(f, "{:\t<indent$}}}(nil))", "", indent = self.indent + 2)?;
+                writeln!(f, "{:\t<indent$}}}", "", indent = self.indent + 1)?;
+                RenderSorted(self.indent + 1).fmt(f)?;
+                write!(f, "{:\t<indent$}}}(w)", "", indent = self.indent)
+            }
             Type::Option(ty) => {
                 writeln!(
                     f,
@@ -271,7 +358,9 @@ where
                 | Type::BytesRef
                 | Type::Vec(_)
                 | Type::HashMap(_)
-                | Type::HashSet(_) => write!(
+                | Type::BTreeMap(_)
+                | Type::HashSet(_)
+                | Type::BTreeSet(_) => write!(
                     f,
                     "{}",
                     RenderType {
@@ -330,3 +419,69 @@ where
         }
     }
 }
+
+/// Sort the already encoded `entries` of a B-tree map or set, and write them out in that order.
+///
+/// Go maps have no defined iteration order, so the entries are ordered by their encoded form,
+/// which is the same as the canonical form of maps and sets. Each entry starts with its key, so
+/// this orders them by their encoded keys.
+struct RenderSorted(usize);
+
+impl Display for RenderSorted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let indent = self.0;
+
+        writeln!(
+            f,
+            "{:\t<indent$}sort.Slice(entries, func(i, j int) bool {{",
+            ""
+        )?;
+        writeln!(
+            f,
+            "{:\t<indent$}return string(entries[i]) < string(entries[j])",
+            "",
+            indent = indent + 1
+        )?;
+        writeln!(f, "{:\t<indent$}}})", "")?;
+        writeln!(
+            f,
+            "{:\t<indent$}return buf.EncodeVec[[]byte](w, entries, func(w []byte, v []byte) \
+             []byte {{",
+            ""
+        )?;
+        writeln!(
+            f,
+            "{:\t<indent$}return append(w, v...)",
+            "",
+            indent = indent + 1
+        )?;
+        writeln!(f, "{:\t<indent$}}})", "")
+    }
+}
+
+/// Find out whether the definitions of a single file encode any B-tree maps or sets, which need
+/// the `sort` package.
+pub(super) fn needs_sort(definitions: &[Definition<'_>]) -> bool {
+    definitions.iter().any(|definition| match definition {
+        Definition::Struct(s) => s.fields.fields.iter().any(|field| has_btree(&field.ty)),
+        Definition::Enum(e) => e
+            .variants
+            .iter()
+            .any(|v| v.fields.fields.iter().any(|field| has_btree(&field.ty))),
+        _ => false,
+    })
+}
+
+fn has_btree(ty: &Type<'_>) -> bool {
+    match ty {
+        Type::BTreeMap(_) | Type::BTreeSet(_) => true,
+        Type::Vec(ty)
+        | Type::HashSet(ty)
+        | Type::Option(ty)
+        | Type::NonZero(ty)
+        | Type::Array(ty, _) => has_btree(ty),
+        Type::HashMap(kv) => has_btree(&kv.0) || has_btree(&kv.1),
+        Type::Tuple(types) => types.iter().any(has_btree),
+        _ => false,
+    }
+}
@@ -6,6 +6,7 @@ mod decode;
 mod definition;
 mod encode;
 mod size;
+mod validate;
 
 /// Options for the code generator that can modify the way the code is generated.
 #[derive(Default)]
@@ -13,6 +14,9 @@ pub struct Opts<'a> {
     /// Name of the package for the root schema. Eventual sub-modules will have their package name
     /// the schema's module name.
     pub package: &'a str,
+    /// Check the constraints of the `#[validate(...)]` attributes as part of decoding, so invalid
+    /// payloads are rejected right away.
+    pub decode_validation: bool,
 }
 
 /// The output of generating converting a schema file into one or more Go source code files. The
@@ -68,6 +68,7 @@ fn main() -> Result<()> {
 
     let opts = Opts {
         package: &project.project_file.package.name,
+        decode_validation: cli.decode_validation,
     };
 
     for (_, schema) in validated {
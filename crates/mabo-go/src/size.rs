@@ -160,7 +160,7 @@ where
                 )?;
                 write!(f, "{:\t<indent$}}})", "", indent = self.indent)
             }
-            Type::HashMap(kv) => {
+            Type::HashMap(kv) | Type::BTreeMap(kv) => {
                 writeln!(
                     f,
                     "buf.SizeHashMap[{}, {}](",
@@ -216,7 +216,7 @@ where
                 writeln!(f, "{:\t<indent$}}},", "", indent = self.indent + 1)?;
                 write!(f, "{:\t<indent$})", "", indent = self.indent)
             }
-            Type::HashSet(ty) => {
+            Type::HashSet(ty) | Type::BTreeSet(ty) => {
                 writeln!(
                     f,
                     "buf.SizeHashSet[{}]({}, func(v {0}) int {{",
@@ -273,7 +273,9 @@ where
                 | Type::BytesRef
                 | Type::Vec(_)
                 | Type::HashMap(_)
-                | Type::HashSet(_) => write!(
+                | Type::BTreeMap(_)
+                | Type::HashSet(_)
+                | Type::BTreeSet(_) => write!(
                     f,
                     "{}",
                     RenderType {
@@ -0,0 +1,378 @@
+use std::fmt::{self, Display};
+
+use mabo_compiler::simplify::{
+    Definition, FieldKind, Fields, Literal, Struct, Type, Validation, Variant,
+};
+
+use crate::definition::RenderGenericNames;
+
+pub(super) struct RenderStruct<'a>(pub(super) &'a Struct<'a>);
+
+impl Display for RenderStruct<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = heck::AsUpperCamelCase(&self.0.name).to_string();
+
+        write!(
+            f,
+            "{}",
+            RenderPatterns {
+                prefix: &prefix,
+                fields: &self.0.fields,
+            }
+        )?;
+
+        writeln!(
+            f,
+            "func (v {}{}) Validate() error {{",
+            prefix,
+            RenderGenericNames {
+                generics: &self.0.generics,
+                fields_filter: None,
+            }
+        )?;
+        write!(
+            f,
+            "{}",
+            RenderChecks {
+                prefix: &prefix,
+                fields: &self.0.fields,
+            }
+        )?;
+        writeln!(f, "\treturn nil\n}}")
+    }
+}
+
+pub(super) struct RenderEnumVariant<'a> {
+    pub(super) enum_name: &'a str,
+    pub(super) generics: &'a [&'a str],
+    pub(super) variant: &'a Variant<'a>,
+}
+
+impl Display for RenderEnumVariant<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = format!(
+            "{}_{}",
+            heck::AsUpperCamelCase(self.enum_name),
+            heck::AsUpperCamelCase(&self.variant.name)
+        );
+
+        write!(
+            f,
+            "{}",
+            RenderPatterns {
+                prefix: &prefix,
+                fields: &self.variant.fields,
+            }
+        )?;
+
+        writeln!(
+            f,
+            "func (v {}{}) Validate() error {{",
+            prefix,
+            RenderGenericNames {
+                generics: self.generics,
+                fields_filter: Some(&self.variant.fields),
+            }
+        )?;
+        write!(
+            f,
+            "{}",
+            RenderChecks {
+                prefix: &prefix,
+                fields: &self.variant.fields,
+            }
+        )?;
+        writeln!(f, "\treturn nil\n}}")
+    }
+}
+
+/// Find out whether the definitions of a single file need the `fmt` package for validation
+/// errors, and the `regexp` package for `pattern` rules.
+pub(super) fn imports(definitions: &[Definition<'_>]) -> (bool, bool) {
+    let fields = definitions.iter().flat_map(|definition| match definition {
+        Definition::Struct(s) => vec![&s.fields],
+        Definition::Enum(e) => e.variants.iter().map(|v| &v.fields).collect(),
+        _ => Vec::new(),
+    });
+
+    fields.fold((false, false), |(fmt, regexp), fields| {
+        (
+            fmt || fields
+                .fields
+                .iter()
+                .any(|field| !field.attributes.validation().is_empty() || is_nested(&field.ty)),
+            regexp
+                || fields
+                    .fields
+                    .iter()
+                    .any(|field| field.attributes.validation().pattern.is_some()),
+        )
+    })
+}
+
+/// Package level variables for the compiled regular expressions of `pattern` rules.
+struct RenderPatterns<'a> {
+    prefix: &'a str,
+    fields: &'a Fields<'a>,
+}
+
+impl Display for RenderPatterns<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for field in &*self.fields.fields {
+            if let Some(pattern) = field.attributes.validation().pattern {
+                writeln!(
+                    f,
+                    "var {} = regexp.MustCompile({})\n",
+                    pattern_var(self.prefix, &field.name),
+                    RenderString(pattern),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct RenderChecks<'a> {
+    prefix: &'a str,
+    fields: &'a Fields<'a>,
+}
+
+impl Display for RenderChecks<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, field) in self.fields.fields.iter().enumerate() {
+            let path = if self.fields.kind == FieldKind::Named {
+                field.name.to_string()
+            } else {
+                i.to_string()
+            };
+            let expr = format!("v.{}", heck::AsUpperCamelCase(&field.name));
+            let validation = field.attributes.validation();
+
+            if !validation.is_empty() {
+                let check = Check {
+                    validation: &validation,
+                    path: &path,
+                    pattern: &pattern_var(self.prefix, &field.name),
+                };
+                check.render(f, &field.ty, &expr, 1)?;
+            }
+
+            if is_nested(&field.ty) {
+                render_nested(f, &field.ty, &expr, &path, 1)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Declared rules of a single field, together with the information to report violations.
+struct Check<'a> {
+    validation: &'a Validation<'a>,
+    path: &'a str,
+    pattern: &'a str,
+}
+
+impl Check<'_> {
+    fn render(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        ty: &Type<'_>,
+        expr: &str,
+        indent: usize,
+    ) -> fmt::Result {
+        if let Type::Option(ty) = ty {
+            writeln!(f, "{:\t<indent$}if {expr} != nil {{", "")?;
+            self.render(f, ty, &format!("(*{expr})"), indent + 1)?;
+            return writeln!(f, "{:\t<indent$}}}", "");
+        }
+
+        let (expr, ty) = match ty {
+            Type::NonZero(ty) => (format!("{expr}.Get()"), &**ty),
+            ty => (expr.to_owned(), ty),
+        };
+        let big = matches!(ty, Type::U128 | Type::I128);
+        let compare = |bound: &Literal, op: &str| {
+            if big {
+                format!("{expr}.Cmp({}) {op} 0", RenderBigInt(bound))
+            } else {
+                format!("{expr} {op} {}", RenderBound(bound))
+            }
+        };
+
+        let v = self.validation;
+
+        if let Some(min) = v.min {
+            let message = format!("value is smaller than the minimum of {}", RenderBound(min));
+            self.render_error(f, &compare(min, "<"), &message, indent)?;
+        }
+
+        if let Some(max) = v.max {
+            let message = format!("value is larger than the maximum of {}", RenderBound(max));
+            self.render_error(f, &compare(max, ">"), &message, indent)?;
+        }
+
+        if let Some(min) = v.min_len {
+            let message = format!("length is shorter than the minimum of {min} bytes");
+            self.render_error(f, &format!("len({expr}) < {min}"), &message, indent)?;
+        }
+
+        if let Some(max) = v.max_len {
+            let message = format!("length is longer than the maximum of {max} bytes");
+            self.render_error(f, &format!("len({expr}) > {max}"), &message, indent)?;
+        }
+
+        if let Some(pattern) = v.pattern {
+            let message = format!("value doesn't match the pattern `{pattern}`");
+            let condition = format!("!{}.MatchString({expr})", self.pattern);
+            self.render_error(f, &condition, &message, indent)?;
+        }
+
+        if let Some(min) = v.min_items {
+            let message = format!("collection has less than the minimum of {min} items");
+            self.render_error(f, &format!("len({expr}) < {min}"), &message, indent)?;
+        }
+
+        if let Some(max) = v.max_items {
+            let message = format!("collection has more than the maximum of {max} items");
+            self.render_error(f, &format!("len({expr}) > {max}"), &message, indent)?;
+        }
+
+        Ok(())
+    }
+
+    fn render_error(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        condition: &str,
+        message: &str,
+        indent: usize,
+    ) -> fmt::Result {
+        let message = format!("{}: {message}", self.path).replace('%', "%%");
+
+        writeln!(f, "{:\t<indent$}if {condition} {{", "")?;
+        writeln!(f, "{:\t<indent$}\treturn fmt.Errorf({message:?})", "")?;
+        writeln!(f, "{:\t<indent$}}}", "")
+    }
+}
+
+/// Descend into values of other structs or enums (or generic types), which validate themselves.
+///
+/// All generated `Validate` methods have a value receiver, so the type assertion works for
+/// structs, as well as enum variants that are stored behind their interface.
+fn render_nested(
+    f: &mut fmt::Formatter<'_>,
+    ty: &Type<'_>,
+    expr: &str,
+    path: &str,
+    indent: usize,
+) -> fmt::Result {
+    match ty {
+        Type::External(_) => {
+            writeln!(
+                f,
+                "{:\t<indent$}if n, ok := any({expr}).(interface{{ Validate() error }}); ok {{",
+                ""
+            )?;
+            writeln!(
+                f,
+                "{:\t<indent$}\tif err := n.Validate(); err != nil {{",
+                ""
+            )?;
+            writeln!(
+                f,
+                "{:\t<indent$}\t\treturn fmt.Errorf(\"{}.%w\", err)",
+                "",
+                path.replace('%', "%%")
+            )?;
+            writeln!(f, "{:\t<indent$}\t}}", "")?;
+            writeln!(f, "{:\t<indent$}}}", "")
+        }
+        Type::Option(ty) => {
+            writeln!(f, "{:\t<indent$}if {expr} != nil {{", "")?;
+            render_nested(f, ty, &format!("(*{expr})"), path, indent + 1)?;
+            writeln!(f, "{:\t<indent$}}}", "")
+        }
+        Type::NonZero(ty) => render_nested(f, ty, &format!("{expr}.Get()"), path, indent),
+        Type::Vec(ty) | Type::Array(ty, _) => {
+            writeln!(f, "{:\t<indent$}for _, e{indent} := range {expr} {{", "")?;
+            render_nested(f, ty, &format!("e{indent}"), path, indent + 1)?;
+            writeln!(f, "{:\t<indent$}}}", "")
+        }
+        Type::HashMap(kv) | Type::BTreeMap(kv) => {
+            writeln!(f, "{:\t<indent$}for _, e{indent} := range {expr} {{", "")?;
+            render_nested(f, &kv.1, &format!("e{indent}"), path, indent + 1)?;
+            writeln!(f, "{:\t<indent$}}}", "")
+        }
+        Type::HashSet(ty) | Type::BTreeSet(ty) => {
+            writeln!(f, "{:\t<indent$}for e{indent} := range {expr} {{", "")?;
+            render_nested(f, ty, &format!("e{indent}"), path, indent + 1)?;
+            writeln!(f, "{:\t<indent$}}}", "")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Whether the type contains other structs or enums (or generic types), which need to be
+/// validated as well.
+fn is_nested(ty: &Type<'_>) -> bool {
+    match ty {
+        Type::Vec(ty)
+        | Type::HashSet(ty)
+        | Type::BTreeSet(ty)
+        | Type::Option(ty)
+        | Type::NonZero(ty)
+        | Type::Array(ty, _) => is_nested(ty),
+        Type::HashMap(kv) | Type::BTreeMap(kv) => is_nested(&kv.1),
+        Type::External(_) => true,
+        _ => false,
+    }
+}
+
+fn pattern_var(prefix: &str, field: &str) -> String {
+    format!("pattern{prefix}{}", heck::AsUpperCamelCase(field))
+}
+
+struct RenderBound<'a>(&'a Literal);
+
+impl Display for RenderBound<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Literal::Int(value) => write!(f, "{value}"),
+            Literal::Float(value) => write!(f, "{value}"),
+            _ => unreachable!("compiler should catch invalid bounds"),
+        }
+    }
+}
+
+/// Limit for 128-bit integers, which are represented as `*big.Int`.
+struct RenderBigInt<'a>(&'a Literal);
+
+impl Display for RenderBigInt<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Literal::Int(value) if i64::try_from(*value).is_ok() => {
+                write!(f, "big.NewInt({value})")
+            }
+            Literal::Int(value) => write!(
+                f,
+                "func() *big.Int {{ n, _ := new(big.Int).SetString(\"{value}\", 10); return n }}()"
+            ),
+            _ => unreachable!("compiler should catch invalid bounds"),
+        }
+    }
+}
+
+/// String literal, preferably as raw string so regular expressions stay readable.
+struct RenderString<'a>(&'a str);
+
+impl Display for RenderString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.contains('`') {
+            write!(f, "{:?}", self.0)
+        } else {
+            write!(f, "`{}`", self.0)
+        }
+    }
+}
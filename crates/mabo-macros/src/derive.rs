@@ -0,0 +1,436 @@
+use std::fmt::Write;
+
+use mabo_build::{BytesType, Compiler, Opts};
+use mabo_parser::Schema;
+use miette::{NamedSource, Report};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{
+    Attribute, Data, DeriveInput, Expr, ExprLit, Fields, GenericArgument, GenericParam, Generics,
+    Lit, LitInt, Meta, MetaNameValue, PathArguments, Type, TypePath,
+};
+
+pub(crate) fn expand_encode(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let (schema, opts) = definition(input)?;
+    compile(input, &schema, |schema| {
+        mabo_build::compile_encode(&opts, schema)
+    })
+}
+
+pub(crate) fn expand_decode(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let (schema, opts) = definition(input)?;
+    compile(input, &schema, |schema| {
+        mabo_build::compile_decode(&opts, schema)
+    })
+}
+
+pub(crate) fn expand_schema(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let (schema, _) = definition(input)?;
+    compile(input, &schema, |_| TokenStream::new())?;
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics ::mabo::Schema for #name #ty_generics #where_clause {
+            fn schema() -> &'static str {
+                #schema
+            }
+        }
+    })
+}
+
+/// Verify the equivalent schema the same way as regular schema files, then generate the code for
+/// it. Problems are reported on the type's name, showing the location in the schema.
+fn compile(
+    input: &DeriveInput,
+    schema: &str,
+    generate: impl FnOnce(&mabo_compiler::simplify::Schema<'_>) -> TokenStream,
+) -> syn::Result<TokenStream> {
+    let error = |report: String| syn::Error::new(input.ident.span(), report);
+
+    let parsed = Schema::parse(schema, None).map_err(|e| error(crate::schema::render(&e)))?;
+
+    mabo_compiler::validate_schema(&parsed).map_err(|e| {
+        error(crate::schema::render(
+            Report::new(e)
+                .with_source_code(NamedSource::new(input.ident.to_string(), schema.to_owned()))
+                .as_ref(),
+        ))
+    })?;
+
+    Ok(generate(&mabo_compiler::simplify_schema(&parsed)))
+}
+
+/// Create the schema definition that is equivalent to the Rust type, together with the options
+/// to generate matching code for it.
+fn definition(input: &DeriveInput) -> syn::Result<(String, Opts)> {
+    let mut builder = Builder::default();
+    let mut schema = builder.definition(input)?;
+
+    // Rust's `Vec<u8>` has to become Mabo's `vec<u8>` as soon as `Bytes` represents Mabo's
+    // `bytes` type, so the generated code uses the same types as the original definition.
+    if builder.found_bytes {
+        builder.bytes = true;
+        schema = builder.definition(input)?;
+    }
+
+    let bytes_type = if builder.bytes {
+        BytesType::Bytes
+    } else {
+        BytesType::VecU8
+    };
+    let opts = Compiler::default()
+        .with_bytes_type(bytes_type)
+        .opts()
+        .map_err(|e| syn::Error::new(input.ident.span(), e))?;
+
+    Ok((schema, opts))
+}
+
+#[derive(Default)]
+struct Builder {
+    /// Whether `Bytes` represents Mabo's `bytes` type, instead of `Vec<u8>`.
+    bytes: bool,
+    /// Whether any of the types used `Bytes`.
+    found_bytes: bool,
+}
+
+impl Builder {
+    fn definition(&mut self, input: &DeriveInput) -> syn::Result<String> {
+        let mut out = String::new();
+        let generics = generics(&input.generics)?;
+
+        write_attributes(&mut out, &input.attrs, "");
+
+        match &input.data {
+            Data::Struct(data) => {
+                write!(out, "struct {}{generics}", input.ident).unwrap();
+                self.fields(&mut out, &data.fields, "")?;
+            }
+            Data::Enum(data) => {
+                writeln!(out, "enum {}{generics} {{", input.ident).unwrap();
+                for variant in &data.variants {
+                    write_attributes(&mut out, &variant.attrs, "    ");
+                    write!(out, "    {}", variant.ident).unwrap();
+                    self.fields(&mut out, &variant.fields, "    ")?;
+                    writeln!(out, "{},", id(&variant.attrs)?).unwrap();
+                }
+                out.push('}');
+            }
+            Data::Union(_) => {
+                return Err(syn::Error::new(
+                    input.ident.span(),
+                    "unions can't be represented in Mabo schemas",
+                ))
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn fields(&mut self, out: &mut String, fields: &Fields, indent: &str) -> syn::Result<()> {
+        match fields {
+            Fields::Named(named) => {
+                out.push_str(" {\n");
+                for field in &named.named {
+                    let indent = format!("{indent}    ");
+                    write_attributes(out, &field.attrs, &indent);
+                    writeln!(
+                        out,
+                        "{indent}{}: {}{},",
+                        field.ident.as_ref().expect("named fields have a name"),
+                        self.data_type(&field.ty)?,
+                        id(&field.attrs)?
+                    )
+                    .unwrap();
+                }
+                write!(out, "{indent}}}").unwrap();
+            }
+            Fields::Unnamed(unnamed) => {
+                let fields = unnamed
+                    .unnamed
+                    .iter()
+                    .map(|field| {
+                        Ok(format!(
+                            "{}{}",
+                            self.data_type(&field.ty)?,
+                            id(&field.attrs)?
+                        ))
+                    })
+                    .collect::<syn::Result<Vec<_>>>()?;
+                write!(out, "({})", fields.join(", ")).unwrap();
+            }
+            Fields::Unit => {}
+        }
+
+        Ok(())
+    }
+
+    fn data_type(&mut self, ty: &Type) -> syn::Result<String> {
+        Ok(match ty {
+            Type::Path(path) if path.qself.is_none() => self.path_type(path)?,
+            Type::Array(array) => {
+                let size = match &array.len {
+                    Expr::Lit(ExprLit {
+                        lit: Lit::Int(size),
+                        ..
+                    }) => size.base10_parse::<u32>()?,
+                    size => {
+                        return Err(syn::Error::new_spanned(
+                            size,
+                            "array sizes must be integer literals",
+                        ))
+                    }
+                };
+                format!("[{}; {size}]", self.data_type(&array.elem)?)
+            }
+            Type::Tuple(tuple) if !tuple.elems.is_empty() => {
+                let types = tuple
+                    .elems
+                    .iter()
+                    .map(|ty| self.data_type(ty))
+                    .collect::<syn::Result<Vec<_>>>()?;
+                format!("({})", types.join(", "))
+            }
+            Type::Paren(paren) => self.data_type(&paren.elem)?,
+            Type::Group(group) => self.data_type(&group.elem)?,
+            ty => return Err(unsupported(ty)),
+        })
+    }
+
+    fn path_type(&mut self, ty: &TypePath) -> syn::Result<String> {
+        let segment = ty.path.segments.last().ok_or_else(|| unsupported(ty))?;
+        let args = match &segment.arguments {
+            PathArguments::None => Vec::new(),
+            PathArguments::AngleBracketed(args) => args
+                .args
+                .iter()
+                .map(|arg| match arg {
+                    GenericArgument::Type(ty) => Ok(ty),
+                    arg => Err(syn::Error::new_spanned(
+                        arg,
+                        "only types are supported as generic arguments",
+                    )),
+                })
+                .collect::<syn::Result<_>>()?,
+            PathArguments::Parenthesized(_) => return Err(unsupported(ty)),
+        };
+
+        let name = segment.ident.to_string();
+
+        Ok(match (name.as_str(), args.as_slice()) {
+            (
+                "bool" | "u8" | "u16" | "u32" | "u64" | "u128" | "i8" | "i16" | "i32" | "i64"
+                | "i128" | "f32" | "f64",
+                [],
+            ) => name,
+            ("String", []) => "string".to_owned(),
+            ("Bytes", []) => {
+                self.found_bytes = true;
+                "bytes".to_owned()
+            }
+            ("Box", [Type::Path(inner)]) if inner.path.is_ident("str") => "box<string>".to_owned(),
+            ("Box", [Type::Slice(inner)]) if is_u8(&inner.elem) => "box<bytes>".to_owned(),
+            ("Vec", [inner]) if !self.bytes && is_u8(inner) => "bytes".to_owned(),
+            ("Vec", [inner]) => format!("vec<{}>", self.data_type(inner)?),
+            ("HashMap", [key, value]) => format!(
+                "hash_map<{}, {}>",
+                self.data_type(key)?,
+                self.data_type(value)?
+            ),
+            ("HashSet", [inner]) => format!("hash_set<{}>", self.data_type(inner)?),
+            ("BTreeMap", [key, value]) => format!(
+                "btree_map<{}, {}>",
+                self.data_type(key)?,
+                self.data_type(value)?
+            ),
+            ("BTreeSet", [inner]) => format!("btree_set<{}>", self.data_type(inner)?),
+            ("Option", [inner]) => format!("option<{}>", self.data_type(inner)?),
+            ("NonZero", [inner]) => format!("non_zero<{}>", self.data_type(inner)?),
+            ("NonZeroString", []) => "non_zero<string>".to_owned(),
+            ("NonZeroBytes", []) => "non_zero<bytes>".to_owned(),
+            ("NonZeroVec", [inner]) => format!("non_zero<vec<{}>>", self.data_type(inner)?),
+            ("NonZeroHashMap", [key, value]) => {
+                format!(
+                    "non_zero<hash_map<{}, {}>>",
+                    self.data_type(key)?,
+                    self.data_type(value)?
+                )
+            }
+            ("NonZeroHashSet", [inner]) => {
+                format!("non_zero<hash_set<{}>>", self.data_type(inner)?)
+            }
+            ("NonZeroBTreeMap", [key, value]) => {
+                format!(
+                    "non_zero<btree_map<{}, {}>>",
+                    self.data_type(key)?,
+                    self.data_type(value)?
+                )
+            }
+            ("NonZeroBTreeSet", [inner]) => {
+                format!("non_zero<btree_set<{}>>", self.data_type(inner)?)
+            }
+            (
+                "NonZeroU8" | "NonZeroU16" | "NonZeroU32" | "NonZeroU64" | "NonZeroU128"
+                | "NonZeroI8" | "NonZeroI16" | "NonZeroI32" | "NonZeroI64" | "NonZeroI128",
+                [],
+            ) => format!("non_zero<{}>", name["NonZero".len()..].to_lowercase()),
+            (_, args) => {
+                let path = ty
+                    .path
+                    .segments
+                    .iter()
+                    .map(|segment| segment.ident.to_string())
+                    .collect::<Vec<_>>()
+                    .join("::");
+
+                if args.is_empty() {
+                    path
+                } else {
+                    let args = args
+                        .iter()
+                        .map(|ty| self.data_type(ty))
+                        .collect::<syn::Result<Vec<_>>>()?;
+                    format!("{path}<{}>", args.join(", "))
+                }
+            }
+        })
+    }
+}
+
+fn generics(generics: &Generics) -> syn::Result<String> {
+    if let Some(clause) = &generics.where_clause {
+        return Err(syn::Error::new_spanned(
+            clause,
+            "where clauses can't be represented in Mabo schemas",
+        ));
+    }
+
+    let names = generics
+        .params
+        .iter()
+        .map(|param| match param {
+            GenericParam::Type(ty) if ty.bounds.is_empty() && ty.default.is_none() => {
+                Ok(ty.ident.to_string())
+            }
+            GenericParam::Type(ty) => Err(syn::Error::new_spanned(
+                ty,
+                "bounds and defaults of type parameters can't be represented in Mabo schemas",
+            )),
+            param => Err(syn::Error::new_spanned(
+                param,
+                "only type parameters can be represented in Mabo schemas",
+            )),
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    Ok(if names.is_empty() {
+        String::new()
+    } else {
+        format!("<{}>", names.join(", "))
+    })
+}
+
+/// Carry over the doc comments and deprecation markers, that have an equivalent in schemas.
+fn write_attributes(out: &mut String, attrs: &[Attribute], indent: &str) {
+    for attr in attrs {
+        match &attr.meta {
+            Meta::NameValue(meta) if meta.path.is_ident("doc") => {
+                if let Expr::Lit(ExprLit {
+                    lit: Lit::Str(doc), ..
+                }) = &meta.value
+                {
+                    for line in doc.value().lines() {
+                        writeln!(out, "{indent}///{line}").unwrap();
+                    }
+                }
+            }
+            meta if meta.path().is_ident("deprecated") => {
+                let note = match meta {
+                    Meta::NameValue(MetaNameValue {
+                        value:
+                            Expr::Lit(ExprLit {
+                                lit: Lit::Str(note),
+                                ..
+                            }),
+                        ..
+                    }) => Some(note.value()),
+                    _ => None,
+                };
+                match note {
+                    Some(note) => writeln!(out, "{indent}#[deprecated = {note:?}]").unwrap(),
+                    None => writeln!(out, "{indent}#[deprecated]").unwrap(),
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Get the identifier from the `#[mabo(id = ...)]` attribute, in the form it's appended to fields
+/// and variants in schemas.
+fn id(attrs: &[Attribute]) -> syn::Result<String> {
+    let mut id = None;
+
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("mabo")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                id = Some(meta.value()?.parse::<LitInt>()?.base10_parse::<u32>()?);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported mabo attribute, expected `id`"))
+            }
+        })?;
+    }
+
+    Ok(id.map(|id| format!(" @{id}")).unwrap_or_default())
+}
+
+fn is_u8(ty: &Type) -> bool {
+    matches!(ty, Type::Path(path) if path.qself.is_none() && path.path.is_ident("u8"))
+}
+
+fn unsupported(ty: &impl quote::ToTokens) -> syn::Error {
+    syn::Error::new_spanned(ty, "type can't be represented in Mabo schemas")
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    #[test]
+    fn unsupported_type() {
+        let err = expand_encode(&parse_quote! {
+            struct Sample<'a> {
+                value: &'a str,
+            }
+        })
+        .unwrap_err();
+
+        assert_eq!(
+            "only type parameters can be represented in Mabo schemas",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn validation_error() {
+        let err = expand_encode(&parse_quote! {
+            struct Sample {
+                #[mabo(id = 1)]
+                a: u32,
+                #[mabo(id = 1)]
+                b: u32,
+            }
+        })
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("[Sample:1:1]"), "{err}");
+        assert!(err.contains("used here again"), "{err}");
+    }
+}
@@ -0,0 +1,84 @@
+//! Procedural macros for the Mabo runtime crate, that offer alternatives to generating code in
+//! build scripts.
+//!
+//! These are re-exported from the `mabo` crate through its `macros` feature, and should be used
+//! from there instead of depending on this crate directly.
+
+use syn::{parse_macro_input, DeriveInput, LitStr};
+
+mod derive;
+mod schema;
+
+/// Generate the Rust code for a single schema file at compile time, without the need for a
+/// `build.rs` build script.
+///
+/// The path is relative to the root of the crate (the folder that contains the _Cargo.toml_
+/// file). The schema is parsed, validated and resolved, then expands to the same code that the
+/// `mabo-build` crate generates with default options. Any errors are reported as compile errors
+/// that include the location within the schema.
+///
+/// As each invocation only knows about a single schema, imports from other schemas can't be
+/// resolved. Use `mabo-build` for projects with multiple schemas that depend on each other.
+///
+/// # Example
+///
+/// ```ignore
+/// mod sample {
+///     mabo::schema!("src/sample.mabo");
+/// }
+/// ```
+#[proc_macro]
+pub fn schema(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as LitStr);
+    schema::expand(&input).into()
+}
+
+/// Implement `mabo::Encode` and `mabo::Size` for a struct or enum, as if it was defined in a
+/// schema.
+///
+/// The type is translated into its equivalent schema definition, which is then validated and
+/// compiled the same way as regular schema files. Field and variant identifiers can be set
+/// explicitly with `#[mabo(id = 1)]`, and are otherwise assigned in order.
+///
+/// # Example
+///
+/// ```ignore
+/// #[derive(mabo::Encode, mabo::Decode)]
+/// struct Sample {
+///     #[mabo(id = 1)]
+///     name: String,
+///     #[mabo(id = 2)]
+///     tags: Vec<String>,
+/// }
+/// ```
+#[proc_macro_derive(Encode, attributes(mabo))]
+pub fn encode(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    derive::expand_encode(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implement `mabo::Decode` for a struct or enum, as if it was defined in a schema.
+///
+/// See [`Encode`](macro@Encode) for details about the translation into a schema.
+#[proc_macro_derive(Decode, attributes(mabo))]
+pub fn decode(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    derive::expand_decode(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implement `mabo::Schema` for a struct or enum, which exposes its equivalent schema definition
+/// in the Mabo language.
+///
+/// This allows to share Rust-first type definitions with other languages, by writing the schema
+/// into a file and generating code from it as usual.
+#[proc_macro_derive(Schema, attributes(mabo))]
+pub fn schema_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    derive::expand_schema(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,110 @@
+use std::{env, fs, path::PathBuf};
+
+use mabo_parser::Schema;
+use miette::{Diagnostic, GraphicalReportHandler, GraphicalTheme, NamedSource, Report};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::LitStr;
+
+pub(crate) fn expand(input: &LitStr) -> TokenStream {
+    let path = match env::var_os("CARGO_MANIFEST_DIR") {
+        Some(dir) => PathBuf::from(dir).join(input.value()),
+        None => PathBuf::from(input.value()),
+    };
+
+    let schema = match fs::read_to_string(&path) {
+        Ok(schema) => schema,
+        Err(e) => {
+            return syn::Error::new(
+                input.span(),
+                format!("failed reading schema file at {}: {e}", path.display()),
+            )
+            .into_compile_error()
+        }
+    };
+
+    match compile(&path, &schema) {
+        Ok(code) => {
+            // Let the compiler track the schema file, so changes to it trigger a rebuild.
+            let path = path.to_string_lossy();
+            quote! {
+                const _: &[u8] = include_bytes!(#path);
+                #code
+            }
+        }
+        Err(report) => syn::Error::new(input.span(), report).into_compile_error(),
+    }
+}
+
+/// Parse, validate and resolve the schema, then generate the Rust code for it. Any error is
+/// rendered into a report that shows the problematic location within the schema.
+fn compile(path: &std::path::Path, input: &str) -> Result<TokenStream, String> {
+    let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+
+    let schema = Schema::parse(input, Some(path)).map_err(|e| render(&e))?;
+
+    mabo_compiler::validate_schema(&schema).map_err(|e| {
+        render(
+            Report::new(e)
+                .with_source_code(NamedSource::new(
+                    path.display().to_string(),
+                    input.to_owned(),
+                ))
+                .as_ref(),
+        )
+    })?;
+
+    mabo_compiler::resolve_schemas(&[(name, &schema)]).map_err(|e| render(&e))?;
+
+    let schema = mabo_compiler::simplify_schema(&schema);
+    Ok(mabo_build::compile_schema(
+        &mabo_build::Opts::default(),
+        &schema,
+    ))
+}
+
+pub(crate) fn render(diagnostic: &dyn Diagnostic) -> String {
+    let mut out = String::new();
+
+    match GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+        .with_links(false)
+        .render_report(&mut out, diagnostic)
+    {
+        Ok(()) => out,
+        Err(_) => diagnostic.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn parse_error() {
+        let report = compile(
+            Path::new("sample.mabo"),
+            "struct Sample {\n    value: u32 @\n}",
+        )
+        .unwrap_err();
+
+        assert!(report.contains("[sample.mabo:1:1]"), "{report}");
+        assert!(report.contains("value: u32 @"), "{report}");
+    }
+
+    #[test]
+    fn validation_error() {
+        let report = compile(
+            Path::new("sample.mabo"),
+            "struct Sample {\n    a: u32 @1,\n    b: u32 @1,\n}",
+        )
+        .unwrap_err();
+
+        assert!(report.contains("[sample.mabo:1:1]"), "{report}");
+        assert!(report.contains("used here again"), "{report}");
+    }
+}
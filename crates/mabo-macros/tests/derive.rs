@@ -0,0 +1,134 @@
+#![allow(missing_docs)]
+
+use mabo::{Decode, Encode, Schema};
+
+mod sample {
+    mabo_macros::schema!("tests/inputs/sample.mabo");
+}
+
+/// Sample struct.
+#[derive(
+    Clone, Debug, PartialEq, mabo_macros::Encode, mabo_macros::Decode, mabo_macros::Schema,
+)]
+struct Sample {
+    #[mabo(id = 1)]
+    value: u32,
+    #[mabo(id = 2)]
+    name: String,
+}
+
+#[derive(
+    Clone, Debug, PartialEq, mabo_macros::Encode, mabo_macros::Decode, mabo_macros::Schema,
+)]
+enum Choice {
+    #[mabo(id = 1)]
+    One,
+    #[mabo(id = 2)]
+    Two(#[mabo(id = 1)] Sample),
+}
+
+#[derive(
+    Clone, Debug, PartialEq, mabo_macros::Encode, mabo_macros::Decode, mabo_macros::Schema,
+)]
+struct Collections<T> {
+    list: Vec<T>,
+    raw: Vec<u8>,
+    map: std::collections::HashMap<u32, String>,
+    sorted: std::collections::BTreeSet<i8>,
+    pairs: Vec<(u8, i64)>,
+    optional: Option<i64>,
+    fixed: [u16; 3],
+    non_zero: std::num::NonZeroU32,
+    nested: Option<Choice>,
+}
+
+#[test]
+fn schema() {
+    assert_eq!(
+        "/// Sample struct.\nstruct Sample {\n    value: u32 @1,\n    name: string @2,\n}",
+        Sample::schema()
+    );
+    assert_eq!(
+        "enum Choice {\n    One @1,\n    Two(Sample @1) @2,\n}",
+        Choice::schema()
+    );
+    assert_eq!(
+        "struct Collections<T> {\n    list: vec<T>,\n    raw: bytes,\n    map: hash_map<u32, \
+         string>,\n    sorted: btree_set<i8>,\n    pairs: vec<(u8, i64)>,\n    optional: \
+         option<i64>,\n    fixed: [u16; 3],\n    non_zero: non_zero<u32>,\n    nested: \
+         option<Choice>,\n}",
+        Collections::<u8>::schema()
+    );
+}
+
+#[test]
+fn same_as_schema() {
+    let derived = Choice::Two(Sample {
+        value: 5,
+        name: "test".to_owned(),
+    });
+    let generated = sample::Choice::Two(sample::Sample {
+        value: 5,
+        name: "test".to_owned(),
+    });
+
+    let mut buf = Vec::new();
+    derived.encode(&mut buf);
+    let mut expect = Vec::new();
+    generated.encode(&mut expect);
+
+    assert_eq!(expect, buf);
+    assert_eq!(derived, Choice::decode(&mut &*expect).unwrap());
+}
+
+#[test]
+fn roundtrip() {
+    let value = Collections {
+        list: vec![true, false],
+        raw: vec![1, 2, 3],
+        map: [(1, "one".to_owned())].into_iter().collect(),
+        sorted: [3, -1].into_iter().collect(),
+        pairs: vec![(2, -3)],
+        optional: Some(-4),
+        fixed: [4, 5, 6],
+        non_zero: std::num::NonZeroU32::new(7).unwrap(),
+        nested: Some(Choice::One),
+    };
+
+    let mut buf = Vec::new();
+    value.encode(&mut buf);
+
+    assert_eq!(buf.len(), mabo::buf::Size::size(&value));
+    assert_eq!(value, Collections::decode(&mut &*buf).unwrap());
+}
+
+#[test]
+fn decode_depth_limit() {
+    let value = Choice::Two(Sample {
+        value: 1,
+        name: "test".to_owned(),
+    });
+
+    let mut buf = Vec::new();
+    value.encode(&mut buf);
+
+    let options = mabo::buf::DecodeOptions {
+        max_depth: 1,
+        ..mabo::buf::DecodeOptions::default()
+    };
+    let err = options.decode::<Choice>(&mut &*buf).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        mabo::buf::Error::DepthLimit { max: 1 },
+    ));
+    assert_eq!(
+        "failed decoding `Choice::Two.0` at byte offset 2: nesting exceeds the maximum depth of 1",
+        err.to_string(),
+    );
+
+    let options = mabo::buf::DecodeOptions {
+        max_depth: 2,
+        ..options
+    };
+    assert_eq!(value, options.decode::<Choice>(&mut &*buf).unwrap());
+}
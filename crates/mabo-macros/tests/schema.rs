@@ -0,0 +1,20 @@
+#![allow(missing_docs)]
+
+use mabo::{Decode, Encode};
+
+mod sample {
+    mabo_macros::schema!("tests/inputs/sample.mabo");
+}
+
+#[test]
+fn roundtrip() {
+    let value = sample::Choice::Two(sample::Sample {
+        value: 5,
+        name: "test".to_owned(),
+    });
+
+    let mut buf = Vec::new();
+    value.encode(&mut buf);
+
+    assert_eq!(value, sample::Choice::decode(&mut &*buf).unwrap());
+}
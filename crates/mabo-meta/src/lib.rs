@@ -140,6 +140,25 @@ pub fn wire_size(ty: &Type<'_>) -> Option<WireSize> {
                 ("element".into(), wire_size(ty)),
             ],
         },
+        Type::BTreeMap(kv) => WireSize {
+            label: "btree_map".into(),
+            min: 1,
+            max: None,
+            inner: vec![
+                ("length".into(), wire_size(&Type::U64)),
+                ("key".into(), wire_size(&kv.0)),
+                ("value".into(), wire_size(&kv.1)),
+            ],
+        },
+        Type::BTreeSet(ty) => WireSize {
+            label: "btree_set".into(),
+            min: 1,
+            max: None,
+            inner: vec![
+                ("length".into(), wire_size(&Type::U64)),
+                ("element".into(), wire_size(ty)),
+            ],
+        },
         Type::Option(ty) => {
             let inner = wire_size(ty);
             WireSize {
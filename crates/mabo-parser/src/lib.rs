@@ -385,10 +385,12 @@ impl Display for Enum<'_> {
 }
 
 /// Single variant of an enum.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, PartialEq)]
 pub struct Variant<'a> {
     /// Optional variant-level comment.
     pub comment: Comment<'a>,
+    /// Optional attributes to customize the behavior.
+    pub attributes: Attributes<'a>,
     /// Unique for this variant, within the enum it belongs to.
     pub name: Name<'a>,
     /// Fields of this variant, if any.
@@ -403,6 +405,7 @@ impl Print for Variant<'_> {
     fn print(&self, f: &mut fmt::Formatter<'_>, level: usize) -> fmt::Result {
         let Self {
             comment,
+            attributes,
             name,
             fields,
             id,
@@ -410,6 +413,7 @@ impl Print for Variant<'_> {
         } = self;
 
         comment.print(f, level)?;
+        attributes.print(f, level)?;
 
         Self::indent(f, level)?;
         f.write_str(name.get())?;
@@ -478,7 +482,7 @@ impl Display for TypeAlias<'_> {
 }
 
 /// Possible kinds in which the fields of a struct or enum variant can be represented.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, PartialEq)]
 pub enum Fields<'a> {
     /// List of named fields.
     ///
@@ -539,10 +543,12 @@ impl Display for Fields<'_> {
 /// │      ╰─────── Type
 /// ╰────────────── Name
 /// ```
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, PartialEq)]
 pub struct NamedField<'a> {
     /// Optional field-level comment.
     pub comment: Comment<'a>,
+    /// Optional attributes to customize the behavior.
+    pub attributes: Attributes<'a>,
     /// Unique name for this field, within the current element.
     pub name: Name<'a>,
     /// Data type that defines the shape of the contained data.
@@ -557,6 +563,7 @@ impl Print for NamedField<'_> {
     fn print(&self, f: &mut fmt::Formatter<'_>, level: usize) -> fmt::Result {
         let Self {
             comment,
+            attributes,
             name,
             ty,
             id,
@@ -564,6 +571,7 @@ impl Print for NamedField<'_> {
         } = self;
 
         comment.print(f, level)?;
+        attributes.print(f, level)?;
 
         Self::indent(f, level)?;
 
@@ -696,6 +704,9 @@ impl AsRef<str> for CommentLine<'_> {
 }
 
 /// Collection of attributes, aggregated together into a single declaration block.
+///
+/// Attribute values can hold float literals, so this type (and any type that contains it) only
+/// implements [`PartialEq`], but not [`Eq`].
 #[derive(Debug, Default, PartialEq)]
 pub struct Attributes<'a>(pub Vec<Attribute<'a>>);
 
@@ -843,6 +854,10 @@ pub enum DataType<'a> {
     HashMap(Box<(Type<'a>, Type<'a>)>),
     /// Hash set of data types (each entry is unique).
     HashSet(Box<Type<'a>>),
+    /// Key-value map of data types, that is sorted by its keys.
+    BTreeMap(Box<(Type<'a>, Type<'a>)>),
+    /// Set of data types (each entry is unique), that is sorted by its entries.
+    BTreeSet(Box<Type<'a>>),
     /// Optional value.
     Option(Box<Type<'a>>),
     /// Non-zero value.
@@ -884,6 +899,8 @@ impl Display for DataType<'_> {
             Self::Vec(t) => write!(f, "vec<{t}>"),
             Self::HashMap(kv) => write!(f, "hash_map<{}, {}>", kv.0, kv.1),
             Self::HashSet(t) => write!(f, "hash_set<{t}>"),
+            Self::BTreeMap(kv) => write!(f, "btree_map<{}, {}>", kv.0, kv.1),
+            Self::BTreeSet(t) => write!(f, "btree_set<{t}>"),
             Self::Option(t) => write!(f, "option<{t}>"),
             Self::NonZero(t) => write!(f, "non_zero<{t}>"),
             Self::BoxString => f.write_str("box<string>"),
@@ -48,7 +48,7 @@ pub(super) fn parse<'i>(input: &mut Input<'i>) -> Result<Attributes<'i>, ParseEr
 
     fold_repeat(
         0..,
-        terminated(parse_attribute, '\n'),
+        terminated(preceded(space0, parse_attribute), '\n'),
         Vec::new,
         |mut acc, attrs| {
             acc.extend(attrs);
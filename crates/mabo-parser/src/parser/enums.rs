@@ -10,7 +10,7 @@ use winnow::{
     Parser,
 };
 
-use super::{comments, fields, generics, ids, ws, Input, ParserExt, Result};
+use super::{attributes, comments, fields, generics, ids, ws, Input, ParserExt, Result};
 use crate::{highlight, Attributes, Comment, Enum, Name, Variant};
 
 /// Encountered an invalid `enum` declaration.
@@ -79,6 +79,9 @@ pub enum Cause {
     /// Failed to parse the comments of a variant.
     #[forward]
     Comment(comments::ParseError),
+    /// Failed to parse the attributes of a variant.
+    #[forward]
+    Attribute(attributes::ParseError),
     /// Invalid variant identifier.
     #[forward]
     Id(ids::ParseError),
@@ -136,6 +139,7 @@ fn parse_variants<'i>(input: &mut Input<'i>) -> Result<Vec<Variant<'i>>, Cause>
 fn parse_variant<'i>(input: &mut Input<'i>) -> Result<Variant<'i>, Cause> {
     (
         ws(comments::parse.map_err(Cause::from)),
+        ws(attributes::parse.map_err(Cause::from)),
         (
             preceded(space0, parse_variant_name.with_span()),
             preceded(space0, fields::parse.map_err(Cause::from)),
@@ -144,13 +148,16 @@ fn parse_variant<'i>(input: &mut Input<'i>) -> Result<Variant<'i>, Cause> {
             .with_span(),
     )
         .parse_next(input)
-        .map(|(comment, ((name, fields, id), span))| Variant {
-            comment,
-            name: name.into(),
-            fields,
-            id,
-            span: span.into(),
-        })
+        .map(
+            |(comment, attributes, ((name, fields, id), span))| Variant {
+                comment,
+                attributes,
+                name: name.into(),
+                fields,
+                id,
+                span: span.into(),
+            },
+        )
 }
 
 fn parse_variant_name<'i>(input: &mut Input<'i>) -> Result<&'i str, Cause> {
@@ -11,7 +11,7 @@ use winnow::{
     Parser,
 };
 
-use super::{comments, ids, types, ws, Input, ParserExt, Result};
+use super::{attributes, comments, ids, types, ws, Input, ParserExt, Result};
 use crate::{highlight, location, Fields, Name, NamedField, UnnamedField};
 
 /// Encountered an invalid field declaration.
@@ -66,6 +66,9 @@ pub enum Cause {
     /// Failed parsing field comments.
     #[forward]
     Comment(comments::ParseError),
+    /// Failed parsing field attributes.
+    #[forward]
+    Attribute(attributes::ParseError),
 }
 
 pub(super) fn parse<'i>(input: &mut Input<'i>) -> Result<Fields<'i>, ParseError> {
@@ -129,6 +132,7 @@ fn parse_unnamed_field<'i>(input: &mut Input<'i>) -> Result<UnnamedField<'i>, Ca
 fn parse_named_field<'i>(input: &mut Input<'i>) -> Result<NamedField<'i>, Cause> {
     (
         ws(comments::parse.map_err(Cause::from)),
+        ws(attributes::parse.map_err(Cause::from)),
         (
             delimited(space0, parse_field_name, ':'),
             preceded(space0, types::parse.map_err(Cause::from)),
@@ -137,8 +141,9 @@ fn parse_named_field<'i>(input: &mut Input<'i>) -> Result<NamedField<'i>, Cause>
             .with_span(),
     )
         .parse_next(input)
-        .map(|(comment, ((name, ty, id), span))| NamedField {
+        .map(|(comment, attributes, ((name, ty, id), span))| NamedField {
             comment,
+            attributes,
             name,
             ty,
             id,
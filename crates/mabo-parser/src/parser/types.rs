@@ -113,6 +113,14 @@ fn parse_generic<'i>(input: &mut Input<'i>) -> Result<DataType<'i>, Cause> {
                 .map(|kv| DataType::HashMap(Box::new(kv))),
             "hash_set" => cut_err(parse.map_err(Cause::from))
                 .map(|t| DataType::HashSet(Box::new(t))),
+            "btree_map" => cut_err(separated_pair(
+                    parse.map_err(Cause::from),
+                    (',', space0),
+                    parse.map_err(Cause::from),
+                ))
+                .map(|kv| DataType::BTreeMap(Box::new(kv))),
+            "btree_set" => cut_err(parse.map_err(Cause::from))
+                .map(|t| DataType::BTreeSet(Box::new(t))),
             "option" => cut_err(parse.map_err(Cause::from))
                 .map(|t| DataType::Option(Box::new(t))),
             "non_zero" => cut_err(parse.map_err(Cause::from))
@@ -1,5 +1,90 @@
 #![allow(missing_docs)]
 
-fn main() -> mabo_build::Result<()> {
-    mabo_build::Compiler::default().compile(env!("CARGO_MANIFEST_DIR"))
+use std::{env, error::Error, fmt::Write, fs, path::PathBuf};
+
+use mabo_build::{BytesType, Compiler, EnumTagging, RefType};
+use mabo_parser::Schema;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    Compiler::default()
+        .with_canonical_encoding("sample::signed")
+        .compile(env!("CARGO_MANIFEST_DIR"))?;
+
+    compile_inputs()
+}
+
+/// Generate the code for the test inputs of `mabo-build`, with the same options as their
+/// snapshots, to make sure that each mode of the code generator produces valid code.
+fn compile_inputs() -> Result<(), Box<dyn Error>> {
+    let modes = [
+        (
+            "borrowed",
+            Compiler::default().with_ref_type(RefType::Borrowed),
+        ),
+        ("unknown", Compiler::default().with_unknown_fields(true)),
+        (
+            "validation",
+            Compiler::default().with_decode_validation(true),
+        ),
+        (
+            "custom",
+            Compiler::default()
+                .with_type_derive("", "Eq")
+                .with_type_derive("custom::Choice", "Hash")
+                .with_type_derive("custom::Choice", "PartialEq")
+                .with_type_attribute("custom::Choice", "#[non_exhaustive]")
+                .with_type_attribute("custom::inner", "#[must_use]")
+                .with_type_derive("custom::ordered", "Hash")
+                .with_type_derive("custom::ordered", "PartialOrd")
+                .with_type_derive("custom::ordered", "Ord"),
+        ),
+        (
+            "serde",
+            Compiler::default()
+                .with_bytes_type(BytesType::Bytes)
+                .with_serde(true)
+                .with_enum_tagging(EnumTagging::Adjacent {
+                    tag: "type".to_owned(),
+                    content: "content".to_owned(),
+                }),
+        ),
+        ("no_std", Compiler::default().with_no_std(true)),
+        (
+            "canonical",
+            Compiler::default().with_canonical_encoding("canonical::Signed"),
+        ),
+    ];
+
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").ok_or("missing OUT_DIR")?).join("inputs");
+    fs::create_dir_all(&out_dir)?;
+
+    for (mode, compiler) in modes {
+        let opts = compiler.opts()?;
+        let dir = format!(
+            "{}/../mabo-build/tests/inputs_{mode}",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        println!("cargo:rerun-if-changed={dir}");
+
+        let mut code = String::new();
+
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let input = fs::read_to_string(&path)?;
+            let schema = Schema::parse(&input, Some(&path)).map_err(|e| e.to_string())?;
+            let schema = mabo_compiler::simplify_schema(&schema);
+            let generated = mabo_build::compile_schema(&opts, &schema);
+
+            // Schemas are named after keywords like `enum`, so they always become raw identifiers.
+            let name = path
+                .file_stem()
+                .and_then(|name| name.to_str())
+                .ok_or("invalid file name")?;
+            writeln!(&mut code, "pub mod r#{name} {{ {generated} }}")?;
+        }
+
+        fs::write(out_dir.join(format!("{mode}.rs")), code)?;
+    }
+
+    Ok(())
 }
@@ -1,124 +1,46 @@
 #![allow(missing_docs, clippy::missing_errors_doc)]
 
-mod evolution {
-    mabo::include!("evolution");
-}
+extern crate alloc;
 
-mod sample {
-    mabo::include!("sample");
+mod generated {
+    mabo::include!();
 }
 
-mod schemas {
-    mod alias_basic {
-        mabo::include!("alias_basic");
-    }
-
-    mod attribute_multi {
-        mabo::include!("attribute_multi");
-    }
-
-    mod attribute_single {
-        mabo::include!("attribute_single");
-    }
-
-    mod attribute_unit {
-        mabo::include!("attribute_unit");
-    }
-
-    mod attributes_min_ws {
-        mabo::include!("attributes_min_ws");
-    }
-
-    mod attributes {
-        mabo::include!("attributes");
-    }
-
-    mod const_basic {
-        mabo::include!("const_basic");
-    }
-
-    mod const_string {
-        mabo::include!("const_string");
-    }
-
-    mod enum_basic {
-        mabo::include!("enum_basic");
-    }
-
-    mod enum_generics {
-        mabo::include!("enum_generics");
-    }
-
-    mod enum_many_ws {
-        mabo::include!("enum_many_ws");
-    }
-
-    mod enum_min_ws {
-        mabo::include!("enum_min_ws");
-    }
-
-    mod import_basic {
-        mabo::include!("import_basic");
-    }
-
-    mod mixed {
-        mabo::include!("mixed");
-    }
-
-    mod other {
-        mabo::include!("other");
-    }
-
-    mod second {
-        mabo::include!("second");
-    }
-
-    mod module_basic {
-        mabo::include!("module_basic");
-    }
-
-    mod schema_basic {
-        mabo::include!("schema_basic");
-    }
-
-    mod struct_basic {
-        mabo::include!("struct_basic");
-    }
-
-    mod struct_generics {
-        mabo::include!("struct_generics");
-    }
-
-    mod struct_many_ws {
-        mabo::include!("struct_many_ws");
-    }
+/// Single schemas can still be included on their own, by their name only.
+mod evolution {
+    mabo::include!("evolution");
+}
 
-    mod struct_min_ws {
-        mabo::include!("struct_min_ws");
+/// The test inputs of `mabo-build`, in each mode of the code generator, as the tests of the code
+/// generator only compare the generated code against snapshots.
+#[allow(dead_code, clippy::module_inception, clippy::pedantic)]
+mod inputs {
+    pub mod borrowed {
+        include!(concat!(env!("OUT_DIR"), "/inputs/borrowed.rs"));
     }
 
-    mod struct_tuple {
-        mabo::include!("struct_tuple");
+    pub mod unknown {
+        include!(concat!(env!("OUT_DIR"), "/inputs/unknown.rs"));
     }
 
-    mod types_basic {
-        mabo::include!("types_basic");
+    pub mod validation {
+        include!(concat!(env!("OUT_DIR"), "/inputs/validation.rs"));
     }
 
-    mod types_generic {
-        mabo::include!("types_generic");
+    pub mod custom {
+        include!(concat!(env!("OUT_DIR"), "/inputs/custom.rs"));
     }
 
-    mod types_nested {
-        mabo::include!("types_nested");
+    pub mod serde {
+        include!(concat!(env!("OUT_DIR"), "/inputs/serde.rs"));
     }
 
-    mod types_non_zero {
-        mabo::include!("types_non_zero");
+    pub mod no_std {
+        include!(concat!(env!("OUT_DIR"), "/inputs/no_std.rs"));
     }
 
-    mod types_ref {
-        mabo::include!("types_ref");
+    pub mod canonical {
+        include!(concat!(env!("OUT_DIR"), "/inputs/canonical.rs"));
     }
 }
 
@@ -126,9 +48,12 @@ mod schemas {
 mod tests {
     use std::fmt::Debug;
 
-    use mabo::{Decode, Encode};
+    use mabo::{
+        buf::{self, DecodeOptions},
+        BufMut, Decode, Encode, FieldEncoding, FieldId,
+    };
 
-    use super::{evolution, sample};
+    use super::generated::src::{evolution, sample};
 
     fn roundtrip<T: Debug + PartialEq + Decode + Encode>(value: &T) {
         let mut buf = Vec::new();
@@ -152,6 +77,9 @@ mod tests {
 
         let value = evolution::Version1::decode(&mut &*buf).unwrap();
         assert_eq!(5, value.field1);
+
+        let value = crate::evolution::Version2::decode(&mut &*buf).unwrap();
+        assert_eq!("Test", value.field2);
     }
 
     #[test]
@@ -230,4 +158,112 @@ mod tests {
             vec_maybe: vec![None, None],
         });
     }
+
+    #[test]
+    fn signed_canonical() {
+        let value = |tags: &[&str], scores: &[(&str, u32)]| sample::signed::Signed {
+            tags: tags.iter().map(|&tag| tag.to_owned()).collect(),
+            scores: scores
+                .iter()
+                .map(|&(name, score)| (name.to_owned(), score))
+                .collect(),
+            nested: Some((0..100).map(|i| (i * 7, i % 2 == 0)).collect()),
+        };
+
+        let first = value(&["a", "b", "c"], &[("x", 1), ("y", 200), ("z", 3)]);
+        let second = value(&["c", "b", "a"], &[("z", 3), ("y", 200), ("x", 1)]);
+
+        let mut buf = Vec::new();
+        first.encode(&mut buf);
+        let mut buf2 = Vec::new();
+        second.encode(&mut buf2);
+        assert_eq!(buf, buf2);
+
+        let options = DecodeOptions {
+            canonical: true,
+            ..DecodeOptions::default()
+        };
+        assert_eq!(first, options.decode(&mut &*buf).unwrap());
+    }
+
+    #[test]
+    fn unknown_fields_canonical() {
+        let field = |w: &mut Vec<u8>, id| {
+            buf::encode_field(w, FieldId::new(id, FieldEncoding::Varint), |w| {
+                buf::encode_u32(w, id);
+            });
+        };
+
+        let mut payload = Vec::new();
+        field(&mut payload, 5);
+        buf::encode_field(
+            &mut payload,
+            FieldId::new(2, FieldEncoding::LengthPrefixed),
+            |w| buf::encode_string(w, "x"),
+        );
+        field(&mut payload, 4);
+        field(&mut payload, 1);
+        buf::encode_u32(&mut payload, buf::END_MARKER);
+
+        let value = crate::inputs::unknown::r#struct::Sample::decode(&mut &*payload).unwrap();
+        assert_eq!(2, value._unknown_fields.len());
+
+        let mut expected = Vec::new();
+        field(&mut expected, 1);
+        buf::encode_field(
+            &mut expected,
+            FieldId::new(2, FieldEncoding::LengthPrefixed),
+            |w| buf::encode_string(w, "x"),
+        );
+        field(&mut expected, 4);
+        field(&mut expected, 5);
+        buf::encode_u32(&mut expected, buf::END_MARKER);
+
+        let mut canonical = Vec::new();
+        buf::encode_canonical(&mut canonical, &value);
+        assert_eq!(expected, canonical);
+    }
+
+    #[test]
+    fn shop_error_location() {
+        let item = sample::shop::Item {
+            name: "x".to_owned(),
+            price: 5,
+        };
+        let mut items = Vec::new();
+        for _ in 0..3 {
+            item.encode(&mut items);
+        }
+        // the price of the fourth item is an overlong varint.
+        buf::encode_field(
+            &mut items,
+            FieldId::new(1, FieldEncoding::LengthPrefixed),
+            |w| buf::encode_string(w, "x"),
+        );
+        buf::encode_field(&mut items, FieldId::new(2, FieldEncoding::Varint), |w| {
+            w.put_slice(&[0xff; 6]);
+        });
+        buf::encode_u32(&mut items, buf::END_MARKER);
+
+        let mut payload = Vec::new();
+        buf::encode_field(&mut payload, FieldId::new(1, FieldEncoding::Varint), |w| {
+            buf::encode_u64(w, 7);
+        });
+        buf::encode_field(
+            &mut payload,
+            FieldId::new(2, FieldEncoding::LengthPrefixed),
+            |w| buf::encode_bytes_std(w, &items),
+        );
+        buf::encode_u32(&mut payload, buf::END_MARKER);
+
+        let err = sample::shop::Order::decode(&mut &*payload).unwrap_err();
+        let context = err.context().unwrap();
+        assert_eq!(Some("Order"), context.ty);
+        assert_eq!(26, context.offset);
+        assert_eq!(
+            "failed decoding `Order.items[3].price` at byte offset 26: failed to decode a varint \
+             integer",
+            err.to_string(),
+        );
+    }
 }
@@ -1,32 +1,47 @@
 #![allow(clippy::type_complexity)]
 
-use std::{
-    collections::{HashMap, HashSet},
-    fmt::Debug,
-    hash::Hash,
+use alloc::{
+    borrow::Cow,
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+    string::{FromUtf8Error, String},
+    sync::Arc,
+    vec::Vec,
+};
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+use core::hash::Hash;
+use core::{
+    fmt::{self, Debug},
+    ops::Range,
+    str::Utf8Error,
 };
 
+use bytes::BufMut;
 pub use bytes::{Buf, Bytes};
 
-use crate::{varint, FieldEncoding, FieldId, NonZero, NonZeroBytes, NonZeroString, VariantId};
+use super::{allocate, check_bytes_len, check_collection_len, is_canonical};
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+use crate::collections::{HashMap, HashSet};
+use crate::{
+    varint, FieldEncoding, FieldId, NonZero, NonZeroBytes, NonZeroString, UnknownFields, VariantId,
+};
 
 /// Result type alias for the decoding process, which defaults to the [`Error`] type for errors.
-pub type Result<T, E = Error> = std::result::Result<T, E>;
+pub type Result<T, E = Error> = core::result::Result<T, E>;
 
 /// Error that can happen while trying to decode a Mabo payload.
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug)]
 pub enum Error {
     /// The passed buffer did not contain enough data to fully decode the payload.
-    #[error("not enough remaining data in the buffer to decode the value")]
     InsufficientData,
     /// A _Varint_ integer failed to decode.
-    #[error("failed to decode a varint integer")]
-    DecodeInt(#[from] varint::DecodeIntError),
+    DecodeInt(varint::DecodeIntError),
     /// A string value was not encoded in valid UTF-8.
-    #[error("string is not valid UTF-8")]
-    NonUtf8(#[from] std::string::FromUtf8Error),
+    NonUtf8(FromUtf8Error),
+    /// A borrowed string value was not encoded in valid UTF-8.
+    NonUtf8Borrowed(Utf8Error),
     /// The field of a struct or enum non-optional in the schema, but is missing from the payload.
-    #[error("required field is missing from the payload")]
     MissingField {
         /// Identifier of the field.
         id: u32,
@@ -34,14 +49,210 @@ pub enum Error {
         name: Option<&'static str>,
     },
     /// An enum variant was found that does not exist in the schema.
-    #[error("encountered an unknown enum variant")]
     UnknownVariant(u32),
     /// An unknown field encoding was found.
-    #[error("encountered an unknown field encoding")]
     UnknownEncoding(u32),
     /// The value of a non-zero field was actually zero.
-    #[error("non-zero value was found to be zero")]
     Zero,
+    /// The decoded value violates one of the constraints declared in the schema.
+    Invalid(crate::ValidationError),
+    /// A `string` or `bytes` value is longer than the [`DecodeOptions`] allow.
+    BytesTooLong {
+        /// Byte length of the value, as found in the payload.
+        len: u64,
+        /// Maximum allowed byte length.
+        max: usize,
+    },
+    /// A collection contains more elements than the [`DecodeOptions`] allow.
+    CollectionTooLong {
+        /// Maximum allowed amount of elements.
+        max: usize,
+    },
+    /// The decoded values take up more memory than the [`DecodeOptions`] allow.
+    AllocationLimit {
+        /// Maximum allowed amount of allocated bytes.
+        max: usize,
+    },
+    /// Structs and enums are nested deeper than the [`DecodeOptions`] allow.
+    DepthLimit {
+        /// Maximum allowed nesting depth.
+        max: usize,
+    },
+    /// The payload is not in its canonical form, which the [`DecodeOptions`] require.
+    NonCanonical,
+    /// Any of the other errors, that happened within a nested value. The context describes where
+    /// exactly in the payload the decoding failed.
+    Context(Box<ErrorContext>),
+}
+
+impl Error {
+    /// The actual cause of the error, without any of the context that describes where it
+    /// happened.
+    ///
+    /// Errors from within structs, enums and collections are wrapped in [`Self::Context`], so
+    /// match on the result of this method, instead of the error itself, to check for a specific
+    /// cause.
+    #[must_use]
+    pub fn kind(&self) -> &Self {
+        match self {
+            Self::Context(context) => context.error.kind(),
+            _ => self,
+        }
+    }
+
+    /// Location of the value that failed to decode, if the error happened within a struct, enum
+    /// or collection.
+    #[must_use]
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Self::Context(context) => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Prepend a segment to the path of the error, when it comes from a nested value.
+    #[must_use]
+    pub fn within(self, segment: PathSegment) -> Self {
+        let mut context = self.into_context();
+        context.ty = None;
+        context.path.insert(0, segment);
+        Self::Context(context)
+    }
+
+    /// Set the byte offset of the error, relative to the start of the value that is currently
+    /// decoded, and the type name of that value (if it's a struct or enum).
+    fn located(self, ty: Option<&'static str>, offset: usize) -> Self {
+        let mut context = self.into_context();
+        context.ty = ty.or(context.ty);
+        context.offset = offset;
+        Self::Context(context)
+    }
+
+    #[allow(clippy::unnecessary_box_returns)]
+    fn into_context(self) -> Box<ErrorContext> {
+        match self {
+            Self::Context(context) => context,
+            error => Box::new(ErrorContext {
+                error,
+                ty: None,
+                path: Vec::new(),
+                offset: 0,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InsufficientData => {
+                f.write_str("not enough remaining data in the buffer to decode the value")
+            }
+            Self::DecodeInt(_) => f.write_str("failed to decode a varint integer"),
+            Self::NonUtf8(_) => f.write_str("string is not valid UTF-8"),
+            Self::NonUtf8Borrowed(_) => f.write_str("borrowed string is not valid UTF-8"),
+            Self::MissingField { .. } => f.write_str("required field is missing from the payload"),
+            Self::UnknownVariant(_) => f.write_str("encountered an unknown enum variant"),
+            Self::UnknownEncoding(_) => f.write_str("encountered an unknown field encoding"),
+            Self::Zero => f.write_str("non-zero value was found to be zero"),
+            Self::Invalid(_) => f.write_str("decoded value is invalid"),
+            Self::BytesTooLong { len, max } => write!(
+                f,
+                "value of {len} bytes exceeds the maximum length of {max} bytes"
+            ),
+            Self::CollectionTooLong { max } => {
+                write!(f, "collection exceeds the maximum length of {max} elements")
+            }
+            Self::AllocationLimit { max } => {
+                write!(f, "decoding exceeds the maximum allocation of {max} bytes")
+            }
+            Self::DepthLimit { max } => write!(f, "nesting exceeds the maximum depth of {max}"),
+            Self::NonCanonical => f.write_str("payload is not in its canonical form"),
+            Self::Context(context) => fmt::Display::fmt(context, f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::DecodeInt(e) => Some(e),
+            Self::NonUtf8(e) => Some(e),
+            Self::NonUtf8Borrowed(e) => Some(e),
+            Self::Invalid(e) => Some(e),
+            Self::Context(context) => context.error.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<varint::DecodeIntError> for Error {
+    fn from(value: varint::DecodeIntError) -> Self {
+        Self::DecodeInt(value)
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(value: FromUtf8Error) -> Self {
+        Self::NonUtf8(value)
+    }
+}
+
+impl From<Utf8Error> for Error {
+    fn from(value: Utf8Error) -> Self {
+        Self::NonUtf8Borrowed(value)
+    }
+}
+
+impl From<crate::ValidationError> for Error {
+    fn from(value: crate::ValidationError) -> Self {
+        Self::Invalid(value)
+    }
+}
+
+/// Location within the payload, where the decoding of a nested value failed.
+#[derive(Debug)]
+pub struct ErrorContext {
+    /// The error that happened at this location.
+    pub error: Error,
+    /// Name of the outermost struct or enum, that the path starts at. It's missing if the
+    /// decoding didn't start with a struct or enum, like for a plain `vec<T>`.
+    pub ty: Option<&'static str>,
+    /// Fields, variants and collection elements that lead to the failing value, from the
+    /// outermost to the innermost one.
+    pub path: Vec<PathSegment>,
+    /// Byte offset within the payload, at which the decoding failed.
+    pub offset: usize,
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("failed decoding `")?;
+        if let Some(ty) = self.ty {
+            f.write_str(ty)?;
+        }
+        for segment in &self.path {
+            match segment {
+                PathSegment::Field(name) => write!(f, ".{name}")?,
+                PathSegment::Variant(name) => write!(f, "::{name}")?,
+                PathSegment::Index(i) => write!(f, "[{i}]")?,
+            }
+        }
+        write!(f, "` at byte offset {}: {}", self.offset, self.error)
+    }
+}
+
+/// Single step of the path to a value, that failed to decode.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PathSegment {
+    /// Field of a struct or enum variant, where unnamed fields are named by their position.
+    Field(&'static str),
+    /// Variant of an enum.
+    Variant(&'static str),
+    /// Position of an element in a collection, in the order of the payload. For maps, it's the
+    /// position of the key-value pair.
+    Index(usize),
 }
 
 /// Special field identifier that marks the end of a struct or enum variant.
@@ -55,6 +266,94 @@ macro_rules! ensure_size {
     };
 }
 
+/// Account for another element of a collection that already holds `len` elements, against the
+/// limits of the current [`DecodeOptions`].
+fn grow<T>(len: usize) -> Result<()> {
+    check_collection_len(len + 1)?;
+    allocate(core::mem::size_of::<T>())
+}
+
+/// Verifies that the entries of a map or set are sorted by the encoded bytes of their keys, if the
+/// current [`DecodeOptions`] require the canonical form. The keys are compared within a copy of
+/// the collection's content, which counts towards the allocation limit.
+struct EntryOrder {
+    content: Option<Vec<u8>>,
+    prev: Option<Range<usize>>,
+}
+
+impl EntryOrder {
+    /// Prepare the check for a collection, whose content are the first `len` bytes of the given
+    /// chunk.
+    fn new(chunk: &[u8], len: usize) -> Result<Self> {
+        let content = if is_canonical() {
+            // non-contiguous input can't be checked, as the keys can't be looked up afterwards.
+            let content = chunk.get(..len).ok_or(Error::NonCanonical)?;
+            allocate(content.len())?;
+            Some(content.to_vec())
+        } else {
+            None
+        };
+
+        Ok(Self {
+            content,
+            prev: None,
+        })
+    }
+
+    /// Check the key that was just decoded, given the remaining bytes of the collection before
+    /// and after decoding it.
+    fn check(&mut self, before: usize, after: usize) -> Result<()> {
+        let Some(content) = &self.content else {
+            return Ok(());
+        };
+
+        let key = content.len() - before..content.len() - after;
+        if let Some(prev) = self.prev.replace(key.clone()) {
+            if content[prev] >= content[key] {
+                return Err(Error::NonCanonical);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Decode a struct or enum, and attach its type name and the byte offset to any error that
+/// happens within.
+///
+/// # Errors
+///
+/// Will return `Err` if the value fails to decode.
+pub fn decode_type<R, T, D>(r: &mut R, ty: &'static str, decode: D) -> Result<T>
+where
+    R: Buf,
+    D: FnOnce(&mut R) -> Result<T>,
+{
+    let start = r.remaining();
+    decode(r).map_err(|e| e.located(Some(ty), start - r.remaining()))
+}
+
+/// Position of a collection's end, relative to its start, that locates the elements of the
+/// collection for errors.
+#[derive(Clone, Copy)]
+struct Elements {
+    end: usize,
+}
+
+impl Elements {
+    /// Decode the element at the given position, and attach its location to any error.
+    fn decode<R, T, D>(self, r: &mut R, index: usize, decode: D) -> Result<T>
+    where
+        R: Buf,
+        D: FnOnce(&mut R) -> Result<T>,
+    {
+        decode(r).map_err(|e| {
+            e.within(PathSegment::Index(index))
+                .located(None, self.end - r.remaining())
+        })
+    }
+}
+
 /// Decode a Mabo `bool` (`true` or `false`) value.
 ///
 /// # Errors
@@ -96,6 +395,9 @@ macro_rules! decode_int {
             /// value, or the _Varint_ decoding fails due to a missing end marker.
             pub fn [<decode_ $ty>](r: &mut impl Buf) -> Result<$ty> {
                 let (value, consumed) = varint::[<decode_ $ty>](r.chunk())?;
+                if consumed > varint::[<size_ $ty>](value) && is_canonical() {
+                    return Err(Error::NonCanonical);
+                }
                 r.advance(consumed);
                 Ok(value)
             }
@@ -146,7 +448,9 @@ pub fn decode_string(r: &mut impl Buf) -> Result<String> {
 /// Will return `Err` if the buffer does not have enough remaining data to read the value.
 pub fn decode_bytes_std(r: &mut impl Buf) -> Result<Vec<u8>> {
     let len = decode_u64(r)?;
+    check_bytes_len(len)?;
     ensure_size!(r, len as usize);
+    allocate(len as usize)?;
 
     Ok(r.copy_to_bytes(len as usize).to_vec())
 }
@@ -158,11 +462,63 @@ pub fn decode_bytes_std(r: &mut impl Buf) -> Result<Vec<u8>> {
 /// Will return `Err` if the buffer does not have enough remaining data to read the value.
 pub fn decode_bytes_bytes(r: &mut impl Buf) -> Result<Bytes> {
     let len = decode_u64(r)?;
+    check_bytes_len(len)?;
     ensure_size!(r, len as usize);
+    allocate(len as usize)?;
 
     Ok(r.copy_to_bytes(len as usize))
 }
 
+/// Decode a UTF-8 encoded Mabo `&string`, borrowing it directly from the input buffer.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, or the
+/// string is not valid UTF-8.
+pub fn decode_string_borrowed<'de>(r: &mut &'de [u8]) -> Result<&'de str> {
+    core::str::from_utf8(decode_bytes_borrowed(r)?).map_err(Into::into)
+}
+
+/// Decode a Mabo `&bytes` raw byte array, borrowing it directly from the input buffer.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value.
+pub fn decode_bytes_borrowed<'de>(r: &mut &'de [u8]) -> Result<&'de [u8]> {
+    let len = decode_u64(r)?;
+    check_bytes_len(len)?;
+    split_borrowed(r, len)
+}
+
+/// Decode the byte length of a collection, and ensure that the buffer holds its full content.
+fn decode_collection(r: &mut impl Buf) -> Result<(usize, Elements)> {
+    let start = r.remaining();
+    let len = decode_u64(r)?;
+    ensure_size!(r, len as usize);
+
+    let end = start - r.remaining() + len as usize;
+    Ok((len as usize, Elements { end }))
+}
+
+/// Decode the length prefix of a collection, and split off its content from the input buffer.
+fn decode_collection_borrowed<'de>(r: &mut &'de [u8]) -> Result<(&'de [u8], Elements)> {
+    let start = r.len();
+    let len = decode_u64(r)?;
+    let content = split_borrowed(r, len)?;
+
+    let end = start - r.len();
+    Ok((content, Elements { end }))
+}
+
+fn split_borrowed<'de>(r: &mut &'de [u8], len: u64) -> Result<&'de [u8]> {
+    ensure_size!(r, len as usize);
+
+    let (value, rest) = r.split_at(len as usize);
+    *r = rest;
+
+    Ok(value)
+}
+
 /// Decode a Mabo `vec<T>` vector value.
 ///
 /// # Errors
@@ -174,14 +530,14 @@ where
     R: Buf,
     D: Fn(&mut bytes::buf::Take<&mut R>) -> Result<T>,
 {
-    let len = decode_u64(r)?;
-    ensure_size!(r, len as usize);
+    let (len, elements) = decode_collection(r)?;
 
     let mut vec = Vec::new();
-    let mut r = r.take(len as usize);
+    let mut r = r.take(len);
 
     while r.has_remaining() {
-        vec.push(decode(&mut r)?);
+        grow::<T>(vec.len())?;
+        vec.push(elements.decode(&mut r, vec.len(), &decode)?);
     }
 
     Ok(vec)
@@ -193,6 +549,7 @@ where
 ///
 /// Will return `Err` if the buffer does not have enough remaining data to read the value, or the
 /// `K`/`V` type fails to decode.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 pub fn decode_hash_map<R, K, V, DK, DV>(
     r: &mut R,
     decode_key: DK,
@@ -204,14 +561,21 @@ where
     DK: Fn(&mut bytes::buf::Take<&mut R>) -> Result<K>,
     DV: Fn(&mut bytes::buf::Take<&mut R>) -> Result<V>,
 {
-    let len = decode_u64(r)?;
-    ensure_size!(r, len as usize);
+    let (len, elements) = decode_collection(r)?;
 
     let mut map = HashMap::new();
-    let mut r = r.take(len as usize);
+    let mut order = EntryOrder::new(r.chunk(), len)?;
+    let mut r = r.take(len);
 
     while r.has_remaining() {
-        map.insert(decode_key(&mut r)?, decode_value(&mut r)?);
+        grow::<(K, V)>(map.len())?;
+        let (key, value) = elements.decode(&mut r, map.len(), |r| {
+            let remaining = r.remaining();
+            let key = decode_key(r)?;
+            order.check(remaining, r.remaining())?;
+            Ok((key, decode_value(r)?))
+        })?;
+        map.insert(key, value);
     }
 
     Ok(map)
@@ -223,20 +587,248 @@ where
 ///
 /// Will return `Err` if the buffer does not have enough remaining data to read the value, or the
 /// `T` type fails to decode.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 pub fn decode_hash_set<R, T, D>(r: &mut R, decode: D) -> Result<HashSet<T>>
 where
     R: Buf,
     T: Hash + Eq,
     D: Fn(&mut bytes::buf::Take<&mut R>) -> Result<T>,
 {
-    let len = decode_u64(r)?;
-    ensure_size!(r, len as usize);
+    let (len, elements) = decode_collection(r)?;
 
     let mut set = HashSet::new();
-    let mut r = r.take(len as usize);
+    let mut order = EntryOrder::new(r.chunk(), len)?;
+    let mut r = r.take(len);
+
+    while r.has_remaining() {
+        grow::<T>(set.len())?;
+        let value = elements.decode(&mut r, set.len(), |r| {
+            let remaining = r.remaining();
+            let value = decode(r)?;
+            order.check(remaining, r.remaining())?;
+            Ok(value)
+        })?;
+        set.insert(value);
+    }
+
+    Ok(set)
+}
+
+/// Decode a Mabo `btree_map<K, V>` map value.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, or the
+/// `K`/`V` type fails to decode.
+pub fn decode_btree_map<R, K, V, DK, DV>(
+    r: &mut R,
+    decode_key: DK,
+    decode_value: DV,
+) -> Result<BTreeMap<K, V>>
+where
+    R: Buf,
+    K: Ord,
+    DK: Fn(&mut bytes::buf::Take<&mut R>) -> Result<K>,
+    DV: Fn(&mut bytes::buf::Take<&mut R>) -> Result<V>,
+{
+    let (len, elements) = decode_collection(r)?;
+
+    let mut map = BTreeMap::new();
+    let mut order = EntryOrder::new(r.chunk(), len)?;
+    let mut r = r.take(len);
+
+    while r.has_remaining() {
+        grow::<(K, V)>(map.len())?;
+        let (key, value) = elements.decode(&mut r, map.len(), |r| {
+            let remaining = r.remaining();
+            let key = decode_key(r)?;
+            order.check(remaining, r.remaining())?;
+            Ok((key, decode_value(r)?))
+        })?;
+        map.insert(key, value);
+    }
+
+    Ok(map)
+}
+
+/// Decode a Mabo `btree_set<T>` set value.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, or the
+/// `T` type fails to decode.
+pub fn decode_btree_set<R, T, D>(r: &mut R, decode: D) -> Result<BTreeSet<T>>
+where
+    R: Buf,
+    T: Ord,
+    D: Fn(&mut bytes::buf::Take<&mut R>) -> Result<T>,
+{
+    let (len, elements) = decode_collection(r)?;
+
+    let mut set = BTreeSet::new();
+    let mut order = EntryOrder::new(r.chunk(), len)?;
+    let mut r = r.take(len);
 
     while r.has_remaining() {
-        set.insert(decode(&mut r)?);
+        grow::<T>(set.len())?;
+        let value = elements.decode(&mut r, set.len(), |r| {
+            let remaining = r.remaining();
+            let value = decode(r)?;
+            order.check(remaining, r.remaining())?;
+            Ok(value)
+        })?;
+        set.insert(value);
+    }
+
+    Ok(set)
+}
+
+/// Decode a Mabo `vec<T>` vector value, where `T` may borrow from the input buffer.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, or the
+/// `T` type fails to decode.
+pub fn decode_vec_borrowed<'de, T, D>(r: &mut &'de [u8], decode: D) -> Result<Vec<T>>
+where
+    D: Fn(&mut &'de [u8]) -> Result<T>,
+{
+    let (mut r, elements) = decode_collection_borrowed(r)?;
+    let mut vec = Vec::new();
+
+    while !r.is_empty() {
+        grow::<T>(vec.len())?;
+        vec.push(elements.decode(&mut r, vec.len(), &decode)?);
+    }
+
+    Ok(vec)
+}
+
+/// Decode a Mabo `hash_map<K, V>` hash map value, where `K` and `V` may borrow from the input
+/// buffer.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, or the
+/// `K`/`V` type fails to decode.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub fn decode_hash_map_borrowed<'de, K, V, DK, DV>(
+    r: &mut &'de [u8],
+    decode_key: DK,
+    decode_value: DV,
+) -> Result<HashMap<K, V>>
+where
+    K: Hash + Eq,
+    DK: Fn(&mut &'de [u8]) -> Result<K>,
+    DV: Fn(&mut &'de [u8]) -> Result<V>,
+{
+    let (mut r, elements) = decode_collection_borrowed(r)?;
+    let mut map = HashMap::new();
+    let mut order = EntryOrder::new(r, r.len())?;
+
+    while !r.is_empty() {
+        grow::<(K, V)>(map.len())?;
+        let (key, value) = elements.decode(&mut r, map.len(), |r| {
+            let remaining = r.remaining();
+            let key = decode_key(r)?;
+            order.check(remaining, r.remaining())?;
+            Ok((key, decode_value(r)?))
+        })?;
+        map.insert(key, value);
+    }
+
+    Ok(map)
+}
+
+/// Decode a Mabo `hash_set<T>` hash set value, where `T` may borrow from the input buffer.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, or the
+/// `T` type fails to decode.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub fn decode_hash_set_borrowed<'de, T, D>(r: &mut &'de [u8], decode: D) -> Result<HashSet<T>>
+where
+    T: Hash + Eq,
+    D: Fn(&mut &'de [u8]) -> Result<T>,
+{
+    let (mut r, elements) = decode_collection_borrowed(r)?;
+    let mut set = HashSet::new();
+    let mut order = EntryOrder::new(r, r.len())?;
+
+    while !r.is_empty() {
+        grow::<T>(set.len())?;
+        let value = elements.decode(&mut r, set.len(), |r| {
+            let remaining = r.remaining();
+            let value = decode(r)?;
+            order.check(remaining, r.remaining())?;
+            Ok(value)
+        })?;
+        set.insert(value);
+    }
+
+    Ok(set)
+}
+
+/// Decode a Mabo `btree_map<K, V>` map value, where `K` and `V` may borrow from the input
+/// buffer.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, or the
+/// `K`/`V` type fails to decode.
+pub fn decode_btree_map_borrowed<'de, K, V, DK, DV>(
+    r: &mut &'de [u8],
+    decode_key: DK,
+    decode_value: DV,
+) -> Result<BTreeMap<K, V>>
+where
+    K: Ord,
+    DK: Fn(&mut &'de [u8]) -> Result<K>,
+    DV: Fn(&mut &'de [u8]) -> Result<V>,
+{
+    let (mut r, elements) = decode_collection_borrowed(r)?;
+    let mut map = BTreeMap::new();
+    let mut order = EntryOrder::new(r, r.len())?;
+
+    while !r.is_empty() {
+        grow::<(K, V)>(map.len())?;
+        let (key, value) = elements.decode(&mut r, map.len(), |r| {
+            let remaining = r.remaining();
+            let key = decode_key(r)?;
+            order.check(remaining, r.remaining())?;
+            Ok((key, decode_value(r)?))
+        })?;
+        map.insert(key, value);
+    }
+
+    Ok(map)
+}
+
+/// Decode a Mabo `btree_set<T>` set value, where `T` may borrow from the input buffer.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, or the
+/// `T` type fails to decode.
+pub fn decode_btree_set_borrowed<'de, T, D>(r: &mut &'de [u8], decode: D) -> Result<BTreeSet<T>>
+where
+    T: Ord,
+    D: Fn(&mut &'de [u8]) -> Result<T>,
+{
+    let (mut r, elements) = decode_collection_borrowed(r)?;
+    let mut set = BTreeSet::new();
+    let mut order = EntryOrder::new(r, r.len())?;
+
+    while !r.is_empty() {
+        grow::<T>(set.len())?;
+        let value = elements.decode(&mut r, set.len(), |r| {
+            let remaining = r.remaining();
+            let value = decode(r)?;
+            order.check(remaining, r.remaining())?;
+            Ok(value)
+        })?;
+        set.insert(value);
     }
 
     Ok(set)
@@ -265,31 +857,54 @@ where
 ///
 /// # Errors
 ///
-/// Will return `Err` if the buffer does not have enough remaining data to read the value, or the
-/// `T` type fails to decode.
-#[allow(clippy::missing_panics_doc)]
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, the `T`
+/// type fails to decode, or the array holds fewer than `N` elements.
 pub fn decode_array<const N: usize, R, T, D>(r: &mut R, decode: D) -> Result<[T; N]>
 where
     R: Buf,
     T: Debug,
     D: Fn(&mut bytes::buf::Take<&mut R>) -> Result<T>,
 {
-    let len = decode_u64(r)?;
-    ensure_size!(r, len as usize);
+    let (len, elements) = decode_collection(r)?;
 
     let mut vec = Vec::new();
-    let mut r = r.take(len as usize);
+    let mut r = r.take(len);
 
     while r.has_remaining() && vec.len() < N {
-        vec.push(decode(&mut r)?);
+        vec.push(elements.decode(&mut r, vec.len(), &decode)?);
     }
 
     // skip any remaining values, in case the old array definition was larger.
     r.advance(r.remaining());
 
-    // SAFETY: we can unwrap here, because we ensured the Vec exactly matches
-    // with the length of the array.
-    Ok(vec.try_into().unwrap())
+    // fewer values than the array length mean the payload is incomplete.
+    vec.try_into().map_err(|_| Error::InsufficientData)
+}
+
+/// Decode a Mabo `[T; N]` array value, where `T` may borrow from the input buffer.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, the `T`
+/// type fails to decode, or the array holds fewer than `N` elements.
+pub fn decode_array_borrowed<'de, const N: usize, T, D>(
+    r: &mut &'de [u8],
+    decode: D,
+) -> Result<[T; N]>
+where
+    T: Debug,
+    D: Fn(&mut &'de [u8]) -> Result<T>,
+{
+    let (mut r, elements) = decode_collection_borrowed(r)?;
+    let mut vec = Vec::new();
+
+    // any remaining values are skipped, in case the old array definition was larger.
+    while !r.is_empty() && vec.len() < N {
+        vec.push(elements.decode(&mut r, vec.len(), &decode)?);
+    }
+
+    // fewer values than the array length mean the payload is incomplete.
+    vec.try_into().map_err(|_| Error::InsufficientData)
 }
 
 macro_rules! ensure_not_empty {
@@ -312,8 +927,8 @@ macro_rules! decode_non_zero_int {
             /// value, or the integer value is zero.
             pub fn [<decode_non_zero_ $ty>](
                 r: &mut impl Buf,
-            ) -> Result<std::num::[<NonZero $ty:upper>]> {
-                std::num::[<NonZero $ty:upper>]::new([<decode_ $ty>](r)?)
+            ) -> Result<core::num::[<NonZero $ty:upper>]> {
+                core::num::[<NonZero $ty:upper>]::new([<decode_ $ty>](r)?)
                     .ok_or_else(|| Error::Zero)
             }
         }
@@ -349,7 +964,9 @@ pub fn decode_non_zero_string(r: &mut impl Buf) -> Result<NonZeroString> {
 pub fn decode_non_zero_bytes_std(r: &mut impl Buf) -> Result<NonZeroBytes> {
     let len = decode_u64(r)?;
     ensure_not_empty!(len);
+    check_bytes_len(len)?;
     ensure_size!(r, len as usize);
+    allocate(len as usize)?;
 
     Ok(NonZero::<Vec<_>>::new(r.copy_to_bytes(len as usize).to_vec()).unwrap())
 }
@@ -364,7 +981,9 @@ pub fn decode_non_zero_bytes_std(r: &mut impl Buf) -> Result<NonZeroBytes> {
 pub fn decode_non_zero_bytes_bytes(r: &mut impl Buf) -> Result<NonZero<Bytes>> {
     let len = decode_u64(r)?;
     ensure_not_empty!(len);
+    check_bytes_len(len)?;
     ensure_size!(r, len as usize);
+    allocate(len as usize)?;
 
     Ok(NonZero::<Bytes>::new(r.copy_to_bytes(len as usize)).unwrap())
 }
@@ -381,15 +1000,15 @@ where
     R: Buf,
     D: Fn(&mut bytes::buf::Take<&mut R>) -> Result<T>,
 {
-    let len = decode_u64(r)?;
+    let (len, elements) = decode_collection(r)?;
     ensure_not_empty!(len);
-    ensure_size!(r, len as usize);
 
     let mut vec = Vec::new();
-    let mut r = r.take(len as usize);
+    let mut r = r.take(len);
 
     while r.has_remaining() {
-        vec.push(decode(&mut r)?);
+        grow::<T>(vec.len())?;
+        vec.push(elements.decode(&mut r, vec.len(), &decode)?);
     }
 
     Ok(NonZero::<Vec<_>>::new(vec).unwrap())
@@ -401,6 +1020,7 @@ where
 ///
 /// Will return `Err` if the buffer does not have enough remaining data to read the value, the
 /// collection is empty, or the `K`/`V` type fails to decode.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 #[allow(clippy::missing_panics_doc)]
 pub fn decode_non_zero_hash_map<R, K, V, DK, DV>(
     r: &mut R,
@@ -413,15 +1033,22 @@ where
     DK: Fn(&mut bytes::buf::Take<&mut R>) -> Result<K>,
     DV: Fn(&mut bytes::buf::Take<&mut R>) -> Result<V>,
 {
-    let len = decode_u64(r)?;
+    let (len, elements) = decode_collection(r)?;
     ensure_not_empty!(len);
-    ensure_size!(r, len as usize);
 
     let mut map = HashMap::new();
-    let mut r = r.take(len as usize);
+    let mut order = EntryOrder::new(r.chunk(), len)?;
+    let mut r = r.take(len);
 
     while r.has_remaining() {
-        map.insert(decode_key(&mut r)?, decode_value(&mut r)?);
+        grow::<(K, V)>(map.len())?;
+        let (key, value) = elements.decode(&mut r, map.len(), |r| {
+            let remaining = r.remaining();
+            let key = decode_key(r)?;
+            order.check(remaining, r.remaining())?;
+            Ok((key, decode_value(r)?))
+        })?;
+        map.insert(key, value);
     }
 
     Ok(NonZero::<HashMap<_, _>>::new(map).unwrap())
@@ -433,6 +1060,7 @@ where
 ///
 /// Will return `Err` if the buffer does not have enough remaining data to read the value, the
 /// collection is empty, or the `T` type fails to decode.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 #[allow(clippy::missing_panics_doc)]
 pub fn decode_non_zero_hash_set<R, T, D>(r: &mut R, decode: D) -> Result<NonZero<HashSet<T>>>
 where
@@ -440,20 +1068,194 @@ where
     T: Hash + Eq,
     D: Fn(&mut bytes::buf::Take<&mut R>) -> Result<T>,
 {
-    let len = decode_u64(r)?;
+    let (len, elements) = decode_collection(r)?;
     ensure_not_empty!(len);
-    ensure_size!(r, len as usize);
 
     let mut set = HashSet::new();
-    let mut r = r.take(len as usize);
+    let mut order = EntryOrder::new(r.chunk(), len)?;
+    let mut r = r.take(len);
 
     while r.has_remaining() {
-        set.insert(decode(&mut r)?);
+        grow::<T>(set.len())?;
+        let value = elements.decode(&mut r, set.len(), |r| {
+            let remaining = r.remaining();
+            let value = decode(r)?;
+            order.check(remaining, r.remaining())?;
+            Ok(value)
+        })?;
+        set.insert(value);
     }
 
     Ok(NonZero::<HashSet<_>>::new(set).unwrap())
 }
 
+/// Decode a Mabo `non_zero<btree_map<K, V>>`.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, the
+/// collection is empty, or the `K`/`V` type fails to decode.
+#[allow(clippy::missing_panics_doc)]
+pub fn decode_non_zero_btree_map<R, K, V, DK, DV>(
+    r: &mut R,
+    decode_key: DK,
+    decode_value: DV,
+) -> Result<NonZero<BTreeMap<K, V>>>
+where
+    R: Buf,
+    K: Ord,
+    DK: Fn(&mut bytes::buf::Take<&mut R>) -> Result<K>,
+    DV: Fn(&mut bytes::buf::Take<&mut R>) -> Result<V>,
+{
+    let (len, elements) = decode_collection(r)?;
+    ensure_not_empty!(len);
+
+    let mut map = BTreeMap::new();
+    let mut order = EntryOrder::new(r.chunk(), len)?;
+    let mut r = r.take(len);
+
+    while r.has_remaining() {
+        grow::<(K, V)>(map.len())?;
+        let (key, value) = elements.decode(&mut r, map.len(), |r| {
+            let remaining = r.remaining();
+            let key = decode_key(r)?;
+            order.check(remaining, r.remaining())?;
+            Ok((key, decode_value(r)?))
+        })?;
+        map.insert(key, value);
+    }
+
+    Ok(NonZero::<BTreeMap<_, _>>::new(map).unwrap())
+}
+
+/// Decode a Mabo `non_zero<btree_set<T>>`.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, the
+/// collection is empty, or the `T` type fails to decode.
+#[allow(clippy::missing_panics_doc)]
+pub fn decode_non_zero_btree_set<R, T, D>(r: &mut R, decode: D) -> Result<NonZero<BTreeSet<T>>>
+where
+    R: Buf,
+    T: Ord,
+    D: Fn(&mut bytes::buf::Take<&mut R>) -> Result<T>,
+{
+    let (len, elements) = decode_collection(r)?;
+    ensure_not_empty!(len);
+
+    let mut set = BTreeSet::new();
+    let mut order = EntryOrder::new(r.chunk(), len)?;
+    let mut r = r.take(len);
+
+    while r.has_remaining() {
+        grow::<T>(set.len())?;
+        let value = elements.decode(&mut r, set.len(), |r| {
+            let remaining = r.remaining();
+            let value = decode(r)?;
+            order.check(remaining, r.remaining())?;
+            Ok(value)
+        })?;
+        set.insert(value);
+    }
+
+    Ok(NonZero::<BTreeSet<_>>::new(set).unwrap())
+}
+
+/// Decode a Mabo `non_zero<vec<T>>`, where `T` may borrow from the input buffer.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, the
+/// collection is empty, or the `T` type fails to decode.
+pub fn decode_non_zero_vec_borrowed<'de, T, D>(
+    r: &mut &'de [u8],
+    decode: D,
+) -> Result<NonZero<Vec<T>>>
+where
+    D: Fn(&mut &'de [u8]) -> Result<T>,
+{
+    decode_vec_borrowed(r, decode).and_then(|vec| NonZero::<Vec<_>>::new(vec).ok_or(Error::Zero))
+}
+
+/// Decode a Mabo `non_zero<hash_map<K, V>>`, where `K` and `V` may borrow from the input buffer.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, the
+/// collection is empty, or the `K`/`V` type fails to decode.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub fn decode_non_zero_hash_map_borrowed<'de, K, V, DK, DV>(
+    r: &mut &'de [u8],
+    decode_key: DK,
+    decode_value: DV,
+) -> Result<NonZero<HashMap<K, V>>>
+where
+    K: Hash + Eq,
+    DK: Fn(&mut &'de [u8]) -> Result<K>,
+    DV: Fn(&mut &'de [u8]) -> Result<V>,
+{
+    decode_hash_map_borrowed(r, decode_key, decode_value)
+        .and_then(|map| NonZero::<HashMap<_, _>>::new(map).ok_or(Error::Zero))
+}
+
+/// Decode a Mabo `non_zero<hash_set<T>>`, where `T` may borrow from the input buffer.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, the
+/// collection is empty, or the `T` type fails to decode.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub fn decode_non_zero_hash_set_borrowed<'de, T, D>(
+    r: &mut &'de [u8],
+    decode: D,
+) -> Result<NonZero<HashSet<T>>>
+where
+    T: Hash + Eq,
+    D: Fn(&mut &'de [u8]) -> Result<T>,
+{
+    decode_hash_set_borrowed(r, decode)
+        .and_then(|set| NonZero::<HashSet<_>>::new(set).ok_or(Error::Zero))
+}
+
+/// Decode a Mabo `non_zero<btree_map<K, V>>`, where `K` and `V` may borrow from the input buffer.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, the
+/// collection is empty, or the `K`/`V` type fails to decode.
+pub fn decode_non_zero_btree_map_borrowed<'de, K, V, DK, DV>(
+    r: &mut &'de [u8],
+    decode_key: DK,
+    decode_value: DV,
+) -> Result<NonZero<BTreeMap<K, V>>>
+where
+    K: Ord,
+    DK: Fn(&mut &'de [u8]) -> Result<K>,
+    DV: Fn(&mut &'de [u8]) -> Result<V>,
+{
+    decode_btree_map_borrowed(r, decode_key, decode_value)
+        .and_then(|map| NonZero::<BTreeMap<_, _>>::new(map).ok_or(Error::Zero))
+}
+
+/// Decode a Mabo `non_zero<btree_set<T>>`, where `T` may borrow from the input buffer.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, the
+/// collection is empty, or the `T` type fails to decode.
+pub fn decode_non_zero_btree_set_borrowed<'de, T, D>(
+    r: &mut &'de [u8],
+    decode: D,
+) -> Result<NonZero<BTreeSet<T>>>
+where
+    T: Ord,
+    D: Fn(&mut &'de [u8]) -> Result<T>,
+{
+    decode_btree_set_borrowed(r, decode)
+        .and_then(|set| NonZero::<BTreeSet<_>>::new(set).ok_or(Error::Zero))
+}
+
 /// Decode a Mabo field identifier.
 ///
 /// # Errors
@@ -515,6 +1317,46 @@ pub fn decode_skip(r: &mut impl Buf, encoding: FieldEncoding) -> Result<()> {
     }
 }
 
+/// Decode a field that is not part of the schema, but instead of skipping over its value, retain
+/// the raw encoded data in the given container.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, or the
+/// decoding of data in fails in the process. For example for length-prefixed values, the length
+/// must be decoded first to find the end of the value.
+pub fn decode_unknown(r: &mut impl Buf, id: FieldId, unknown: &mut UnknownFields) -> Result<()> {
+    let mut data = Vec::new();
+
+    let len = match id.encoding {
+        FieldEncoding::Varint => loop {
+            ensure_size!(r, 1);
+            let byte = r.get_u8();
+            data.push(byte);
+
+            if byte & 0x80 == 0 {
+                break 0;
+            }
+        },
+        FieldEncoding::LengthPrefixed => {
+            let len = decode_u64(r)?;
+            check_bytes_len(len)?;
+            super::encode_u64(&mut data, len);
+            len as usize
+        }
+        FieldEncoding::Fixed1 => 1,
+        FieldEncoding::Fixed4 => 4,
+        FieldEncoding::Fixed8 => 8,
+    };
+
+    ensure_size!(r, len);
+    allocate(data.len() + len)?;
+    data.put(r.take(len));
+
+    unknown.push(id, data);
+    Ok(())
+}
+
 /// Values that can decode themselves from Mabo encoded data.
 pub trait Decode: Sized {
     /// Read the encoded data from the provided buffer.
@@ -527,6 +1369,46 @@ pub trait Decode: Sized {
     fn decode(r: &mut impl Buf) -> Result<Self>;
 }
 
+/// Values that can decode themselves from Mabo encoded data, while borrowing parts of it directly
+/// from the input buffer instead of copying them.
+///
+/// This allows to decode `&string` and `&bytes` fields as `&str` and `&[u8]` without any
+/// allocation. Every type that implements [`Decode`] automatically implements this trait as well.
+pub trait DecodeBorrowed<'de>: Sized {
+    /// Read the encoded data from the provided byte slice, advancing it past the decoded value.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the buffer does not have enough remaining data to read the value, or,
+    /// depending on the defined data structure, due to several possible issues that can arise when
+    /// trying to decode.
+    fn decode_borrowed(r: &mut &'de [u8]) -> Result<Self>;
+}
+
+impl<'de, T> DecodeBorrowed<'de> for T
+where
+    T: Decode,
+{
+    #[inline(always)]
+    fn decode_borrowed(r: &mut &'de [u8]) -> Result<Self> {
+        T::decode(r)
+    }
+}
+
+impl<'de> DecodeBorrowed<'de> for &'de str {
+    #[inline(always)]
+    fn decode_borrowed(r: &mut &'de [u8]) -> Result<Self> {
+        decode_string_borrowed(r)
+    }
+}
+
+impl<'de> DecodeBorrowed<'de> for &'de [u8] {
+    #[inline(always)]
+    fn decode_borrowed(r: &mut &'de [u8]) -> Result<Self> {
+        decode_bytes_borrowed(r)
+    }
+}
+
 macro_rules! forward {
     ($ty:ty) => {
         paste::paste! {
@@ -579,6 +1461,7 @@ where
     }
 }
 
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 impl<K, V> Decode for HashMap<K, V>
 where
     K: Hash + Eq + Decode,
@@ -590,6 +1473,7 @@ where
     }
 }
 
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 impl<T> Decode for HashSet<T>
 where
     T: Hash + Eq + Decode,
@@ -600,6 +1484,27 @@ where
     }
 }
 
+impl<K, V> Decode for BTreeMap<K, V>
+where
+    K: Ord + Decode,
+    V: Decode,
+{
+    #[inline(always)]
+    fn decode(r: &mut impl Buf) -> Result<Self> {
+        decode_btree_map(r, |r| K::decode(r), |r| V::decode(r))
+    }
+}
+
+impl<T> Decode for BTreeSet<T>
+where
+    T: Ord + Decode,
+{
+    #[inline(always)]
+    fn decode(r: &mut impl Buf) -> Result<Self> {
+        decode_btree_set(r, |r| T::decode(r))
+    }
+}
+
 impl<T> Decode for Option<T>
 where
     T: Decode,
@@ -620,32 +1525,32 @@ where
     }
 }
 
-impl<T> Decode for std::borrow::Cow<'_, T>
+impl<T> Decode for Cow<'_, T>
 where
     T: Copy + Decode,
 {
     #[inline(always)]
     fn decode(r: &mut impl Buf) -> Result<Self> {
-        T::decode(r).map(std::borrow::Cow::Owned)
+        T::decode(r).map(Cow::Owned)
     }
 }
 
-impl<T> Decode for std::rc::Rc<T>
+impl<T> Decode for Rc<T>
 where
     T: Decode,
 {
     #[inline(always)]
     fn decode(r: &mut impl Buf) -> Result<Self> {
-        T::decode(r).map(std::rc::Rc::new)
+        T::decode(r).map(Rc::new)
     }
 }
 
-impl<T> Decode for std::sync::Arc<T>
+impl<T> Decode for Arc<T>
 where
     T: Decode,
 {
     #[inline(always)]
     fn decode(r: &mut impl Buf) -> Result<Self> {
-        T::decode(r).map(std::sync::Arc::new)
+        T::decode(r).map(Arc::new)
     }
 }
@@ -1,8 +1,19 @@
-use std::collections::{HashMap, HashSet};
+use alloc::{
+    borrow::Cow,
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+use core::{fmt, ops::RangeBounds};
 
 pub use bytes::{BufMut, Bytes};
 
-use crate::{varint, FieldId, NonZero, VariantId};
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+use crate::collections::{HashMap, HashSet};
+use crate::{varint, FieldId, NonZero, UnknownFields, VariantId};
 
 /// Encode a Mabo `bool` (`true` or `false`) value.
 pub fn encode_bool(w: &mut impl BufMut, value: bool) {
@@ -78,6 +89,7 @@ where
 }
 
 /// Encode a Mabo `hash_map<K, V>` hash map value.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 pub fn encode_hash_map<W, K, V, SK, SV, EK, EV>(
     w: &mut W,
     map: &HashMap<K, V>,
@@ -106,6 +118,7 @@ pub fn encode_hash_map<W, K, V, SK, SV, EK, EV>(
 }
 
 /// Encode a Mabo `hash_set<T>` hash set value.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 pub fn encode_hash_set<W, T, S, E>(w: &mut W, set: &HashSet<T>, size: S, encode: E)
 where
     W: BufMut,
@@ -119,6 +132,198 @@ where
     }
 }
 
+/// Encode a Mabo `btree_map<K, V>` map value, in the order of its keys.
+pub fn encode_btree_map<W, K, V, SK, SV, EK, EV>(
+    w: &mut W,
+    map: &BTreeMap<K, V>,
+    size_key: SK,
+    size_value: SV,
+    encode_key: EK,
+    encode_value: EV,
+) where
+    W: BufMut,
+    SK: Fn(&K) -> usize,
+    SV: Fn(&V) -> usize,
+    EK: Fn(&mut W, &K),
+    EV: Fn(&mut W, &V),
+{
+    encode_u64(
+        w,
+        map.iter()
+            .map(|(k, v)| size_key(k) + size_value(v))
+            .sum::<usize>() as u64,
+    );
+
+    for (key, value) in map {
+        encode_key(w, key);
+        encode_value(w, value);
+    }
+}
+
+/// Encode a Mabo `btree_set<T>` set value, in the order of its entries.
+pub fn encode_btree_set<W, T, S, E>(w: &mut W, set: &BTreeSet<T>, size: S, encode: E)
+where
+    W: BufMut,
+    S: Fn(&T) -> usize,
+    E: Fn(&mut W, &T),
+{
+    encode_u64(w, set.iter().map(size).sum::<usize>() as u64);
+
+    for value in set {
+        encode(w, value);
+    }
+}
+
+/// Encode a Mabo `hash_map<K, V>` or `btree_map<K, V>` map value in its canonical form, with the
+/// entries sorted by the encoded bytes of their keys.
+///
+/// The same map always results in the same bytes this way, no matter the iteration order of the
+/// map. Keys and values are encoded into a temporary buffer first, so they can be sorted before
+/// being written out.
+pub fn encode_map_canonical<'a, W, K, V, EK, EV>(
+    w: &mut W,
+    map: impl IntoIterator<Item = (&'a K, &'a V)>,
+    encode_key: EK,
+    encode_value: EV,
+) where
+    W: BufMut,
+    K: 'a,
+    V: 'a,
+    EK: Fn(&mut Vec<u8>, &K),
+    EV: Fn(&mut Vec<u8>, &V),
+{
+    let mut buf = Vec::new();
+    let mut entries = Vec::new();
+
+    for (key, value) in map {
+        let start = buf.len();
+        encode_key(&mut buf, key);
+        let key_end = buf.len();
+        encode_value(&mut buf, value);
+        entries.push((start, key_end, buf.len()));
+    }
+
+    entries.sort_unstable_by(|a, b| buf[a.0..a.1].cmp(&buf[b.0..b.1]));
+
+    encode_u64(w, buf.len() as u64);
+
+    for (start, _, end) in entries {
+        w.put_slice(&buf[start..end]);
+    }
+}
+
+/// Encode a Mabo `hash_set<T>` or `btree_set<T>` set value in its canonical form, with the
+/// entries sorted by their encoded bytes.
+///
+/// The same set always results in the same bytes this way, no matter the iteration order of the
+/// set.
+pub fn encode_set_canonical<'a, W, T, E>(w: &mut W, set: impl IntoIterator<Item = &'a T>, encode: E)
+where
+    W: BufMut,
+    T: 'a,
+    E: Fn(&mut Vec<u8>, &T),
+{
+    let mut buf = Vec::new();
+    let mut entries = Vec::new();
+
+    for value in set {
+        let start = buf.len();
+        encode(&mut buf, value);
+        entries.push((start, buf.len()));
+    }
+
+    entries.sort_unstable_by(|a, b| buf[a.0..a.1].cmp(&buf[b.0..b.1]));
+
+    encode_u64(w, buf.len() as u64);
+
+    for (start, end) in entries {
+        w.put_slice(&buf[start..end]);
+    }
+}
+
+/// Encode a value in its canonical form, no matter whether its type selected the canonical
+/// encoding itself.
+///
+/// For the duration of the call, all maps and sets are encoded as if by [`encode_map_canonical`]
+/// and [`encode_set_canonical`], and generated structs write unknown fields in order with their
+/// known fields. This applies to all nested values as well, including the ones of hand-written
+/// [`Encode`] implementations that check [`is_canonical_encoding`].
+///
+/// The canonical mode is tracked per thread, so it's only available with the `std` feature.
+///
+/// ```
+/// use std::collections::HashSet;
+///
+/// let mut buf = Vec::new();
+/// mabo::buf::encode_canonical(&mut buf, &HashSet::from([3_u32, 1, 2]));
+/// assert_eq!([3, 1, 2, 3], &*buf);
+/// ```
+#[cfg(feature = "std")]
+pub fn encode_canonical<T: Encode>(w: &mut impl BufMut, value: &T) {
+    let _scope = CanonicalScope::enter();
+    value.encode(w);
+}
+
+/// Whether the current thread is within a call to [`encode_canonical`], which means all maps and
+/// sets must be encoded in their canonical form.
+#[cfg(feature = "std")]
+#[must_use]
+pub fn is_canonical_encoding() -> bool {
+    CANONICAL.with(std::cell::Cell::get)
+}
+
+/// Whether the current thread is within a call to `encode_canonical`, which is never the case
+/// without the `std` feature.
+#[cfg(not(feature = "std"))]
+#[inline(always)]
+#[must_use]
+pub fn is_canonical_encoding() -> bool {
+    false
+}
+
+/// Encode a value through the first function while within a call to [`encode_canonical`], or
+/// through the second one otherwise.
+///
+/// Generated code uses this to pick the encoding of maps and sets, as the canonical one needs to
+/// encode their entries into a temporary buffer first.
+#[inline(always)]
+pub fn encode_canonical_or<W, C, R>(w: &mut W, canonical: C, regular: R)
+where
+    W: BufMut,
+    C: FnOnce(&mut W),
+    R: FnOnce(&mut W),
+{
+    if is_canonical_encoding() {
+        canonical(w);
+    } else {
+        regular(w);
+    }
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static CANONICAL: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Enables the canonical encoding for the duration of a single [`encode_canonical`] call, and
+/// restores the previous mode afterwards (even if encoding panics).
+#[cfg(feature = "std")]
+struct CanonicalScope(bool);
+
+#[cfg(feature = "std")]
+impl CanonicalScope {
+    fn enter() -> Self {
+        Self(CANONICAL.with(|canonical| canonical.replace(true)))
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for CanonicalScope {
+    fn drop(&mut self) {
+        CANONICAL.with(|canonical| canonical.set(self.0));
+    }
+}
+
 /// Encode a Mabo `option<T>` option value.
 pub fn encode_option<W, T, E>(w: &mut W, option: &Option<T>, encode: E)
 where
@@ -195,12 +400,99 @@ where
     }
 }
 
+/// Encode all unknown fields that were retained while decoding a struct.
+#[inline]
+pub fn encode_unknown_fields(w: &mut impl BufMut, unknown: &UnknownFields) {
+    for (id, data) in unknown.iter() {
+        encode_id(w, id);
+        w.put_slice(data);
+    }
+}
+
+/// Encode the unknown fields of a struct whose IDs are within the given range, sorted by their
+/// ID.
+///
+/// This is used by the canonical encoding, which writes all fields in the order of their IDs. It's
+/// called before each known field, with the range between the previous known field and the
+/// current one, so the unknown fields end up in between. The IDs of unknown fields never match
+/// the ones of known fields, so the ranges may include the known IDs.
+pub fn encode_unknown_fields_canonical(
+    w: &mut impl BufMut,
+    unknown: &UnknownFields,
+    ids: impl RangeBounds<u32>,
+) {
+    let mut fields = unknown
+        .iter()
+        .filter(|(id, _)| ids.contains(&id.value))
+        .collect::<Vec<_>>();
+    fields.sort_by_key(|(id, _)| id.value);
+
+    for (id, data) in fields {
+        encode_id(w, id);
+        w.put_slice(data);
+    }
+}
+
 /// Values that can encode themselves in the Mabo format.
 pub trait Encode: super::Size {
     /// Write the encoded data in the provided buffer.
     fn encode(&self, w: &mut impl BufMut);
+
+    /// Write the encoded data into the provided slice, without any allocation. Returns the
+    /// amount of bytes written.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the slice is too small to hold the encoded data, which is checked
+    /// upfront. The slice is left untouched in that case.
+    fn encode_to_slice(&self, buf: &mut [u8]) -> Result<usize, BufferTooSmall> {
+        let size = self.size();
+        let available = buf.len();
+        let mut w = buf.get_mut(..size).ok_or(BufferTooSmall {
+            required: size,
+            available,
+        })?;
+
+        self.encode(&mut w);
+        Ok(size)
+    }
+
+    /// Encode into a new vector, that is allocated with the exact encoded size upfront.
+    fn encode_to_vec(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.size());
+        self.encode(&mut buf);
+        buf
+    }
+
+    /// Encode into a new [`Bytes`] buffer, that is allocated with the exact encoded size upfront.
+    fn encode_to_bytes(&self) -> Bytes {
+        self.encode_to_vec().into()
+    }
 }
 
+/// Error that happens when encoding a value into a slice, that is too small to hold the encoded
+/// data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BufferTooSmall {
+    /// Amount of bytes that the encoded data requires.
+    pub required: usize,
+    /// Amount of bytes that the slice provides.
+    pub available: usize,
+}
+
+impl fmt::Display for BufferTooSmall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "buffer of {} bytes is too small for the encoded data of {} bytes",
+            self.available, self.required
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BufferTooSmall {}
+
 macro_rules! forward {
     ($ty:ty) => {
         paste::paste! {
@@ -229,6 +521,13 @@ impl Encode for String {
     }
 }
 
+impl Encode for &'_ str {
+    #[inline(always)]
+    fn encode(&self, w: &mut impl BufMut) {
+        encode_string(w, self);
+    }
+}
+
 impl Encode for Box<str> {
     #[inline(always)]
     fn encode(&self, w: &mut impl BufMut) {
@@ -263,6 +562,7 @@ where
     }
 }
 
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 impl<K, V> Encode for HashMap<K, V>
 where
     K: Encode,
@@ -270,24 +570,69 @@ where
 {
     #[inline(always)]
     fn encode(&self, w: &mut impl BufMut) {
-        encode_hash_map(
-            w,
-            self,
-            K::size,
-            V::size,
-            |w, k| k.encode(w),
-            |w, v| v.encode(w),
-        );
+        if is_canonical_encoding() {
+            encode_map_canonical(w, self, |w, k| k.encode(w), |w, v| v.encode(w));
+        } else {
+            encode_hash_map(
+                w,
+                self,
+                K::size,
+                V::size,
+                |w, k| k.encode(w),
+                |w, v| v.encode(w),
+            );
+        }
     }
 }
 
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 impl<T> Encode for HashSet<T>
 where
     T: Encode,
 {
     #[inline(always)]
     fn encode(&self, w: &mut impl BufMut) {
-        encode_hash_set(w, self, T::size, |w, v| v.encode(w));
+        if is_canonical_encoding() {
+            encode_set_canonical(w, self, |w, v| v.encode(w));
+        } else {
+            encode_hash_set(w, self, T::size, |w, v| v.encode(w));
+        }
+    }
+}
+
+impl<K, V> Encode for BTreeMap<K, V>
+where
+    K: Encode,
+    V: Encode,
+{
+    #[inline(always)]
+    fn encode(&self, w: &mut impl BufMut) {
+        if is_canonical_encoding() {
+            encode_map_canonical(w, self, |w, k| k.encode(w), |w, v| v.encode(w));
+        } else {
+            encode_btree_map(
+                w,
+                self,
+                K::size,
+                V::size,
+                |w, k| k.encode(w),
+                |w, v| v.encode(w),
+            );
+        }
+    }
+}
+
+impl<T> Encode for BTreeSet<T>
+where
+    T: Encode,
+{
+    #[inline(always)]
+    fn encode(&self, w: &mut impl BufMut) {
+        if is_canonical_encoding() {
+            encode_set_canonical(w, self, |w, v| v.encode(w));
+        } else {
+            encode_btree_set(w, self, T::size, |w, v| v.encode(w));
+        }
     }
 }
 
@@ -321,7 +666,7 @@ where
     }
 }
 
-impl<T> Encode for std::borrow::Cow<'_, T>
+impl<T> Encode for Cow<'_, T>
 where
     T: Clone + Encode,
 {
@@ -331,7 +676,7 @@ where
     }
 }
 
-impl<T> Encode for std::rc::Rc<T>
+impl<T> Encode for Rc<T>
 where
     T: Encode,
 {
@@ -341,7 +686,7 @@ where
     }
 }
 
-impl<T> Encode for std::sync::Arc<T>
+impl<T> Encode for Arc<T>
 where
     T: Encode,
 {
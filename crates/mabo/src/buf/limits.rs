@@ -0,0 +1,255 @@
+use std::cell::Cell;
+
+use super::{Buf, Decode, DecodeBorrowed, Error, Result};
+
+/// Limits that protect the decoder against malicious payloads, that would otherwise cause huge
+/// allocations or overflow the stack through deeply nested values.
+///
+/// Optionally, the options require the payload to be in its canonical form as well, like it's
+/// written by [`encode_canonical`](super::encode_canonical).
+///
+/// The limits only apply while decoding through [`DecodeOptions::decode`],
+/// [`DecodeOptions::decode_borrowed`] or [`DecodeOptions::run`]. Decoding directly through the
+/// [`Decode`] trait is not limited in any way, which is the same as using the
+/// [default](Self::default) options. When these calls are nested, like from within a custom
+/// [`Decode`] implementation, the inner call continues with the depth and allocations of the outer
+/// one, and the stricter of both limits apply.
+///
+/// The options are only available with the `std` feature, as the limits are tracked per thread.
+///
+/// ```
+/// use mabo::buf::DecodeOptions;
+///
+/// let options = DecodeOptions {
+///     max_depth: 32,
+///     max_allocation: 1024 * 1024,
+///     ..DecodeOptions::default()
+/// };
+///
+/// let value = options.decode::<Vec<u32>>(&mut &[3, 1, 2, 3][..]).unwrap();
+/// assert_eq!(vec![1, 2, 3], value);
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DecodeOptions {
+    /// Maximum amount of elements in a single collection, like a `vec<T>` or `hash_map<K, V>`.
+    pub max_collection_len: usize,
+    /// Maximum byte length of a single `string` or `bytes` value.
+    pub max_bytes_len: usize,
+    /// Maximum amount of bytes that all decoded strings, bytes and collection elements may take
+    /// up in total.
+    pub max_allocation: usize,
+    /// Maximum nesting depth of structs and enums.
+    pub max_depth: usize,
+    /// Reject payloads that aren't in their canonical form. That means integers must be encoded
+    /// in their shortest _Varint_ form, and the entries of maps and sets must be sorted by the
+    /// encoded bytes of their keys, without duplicates.
+    ///
+    /// Checking the order of entries requires the input to be contiguous in memory, like a byte
+    /// slice or [`Bytes`](super::Bytes). Maps and sets in any other input are rejected.
+    pub canonical: bool,
+}
+
+impl DecodeOptions {
+    /// Options that don't limit the decoding in any way.
+    pub const UNLIMITED: Self = Self {
+        max_collection_len: usize::MAX,
+        max_bytes_len: usize::MAX,
+        max_allocation: usize::MAX,
+        max_depth: usize::MAX,
+        canonical: false,
+    };
+
+    /// Combine these options with the ones of an outer decoding call, keeping the stricter limits.
+    fn stricter(self, other: Self) -> Self {
+        Self {
+            max_collection_len: self.max_collection_len.min(other.max_collection_len),
+            max_bytes_len: self.max_bytes_len.min(other.max_bytes_len),
+            max_allocation: self.max_allocation.min(other.max_allocation),
+            max_depth: self.max_depth.min(other.max_depth),
+            canonical: self.canonical || other.canonical,
+        }
+    }
+
+    /// Decode a value from the buffer, while enforcing these limits.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the value fails to decode, or the payload exceeds any of the limits.
+    pub fn decode<T: Decode>(&self, r: &mut impl Buf) -> Result<T> {
+        let _scope = Scope::enter(*self);
+        T::decode(r)
+    }
+
+    /// Decode a value that may borrow from the input buffer, while enforcing these limits.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the value fails to decode, or the payload exceeds any of the limits.
+    pub fn decode_borrowed<'de, T: DecodeBorrowed<'de>>(&self, r: &mut &'de [u8]) -> Result<T> {
+        let _scope = Scope::enter(*self);
+        T::decode_borrowed(r)
+    }
+
+    /// Run any other decoding logic, like [`decode_value`](crate::dynamic::decode_value), while
+    /// enforcing these limits.
+    pub fn run<T>(&self, decode: impl FnOnce() -> T) -> T {
+        let _scope = Scope::enter(*self);
+        decode()
+    }
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
+}
+
+/// Current limits and the usage so far, of the decoding on this thread.
+#[derive(Clone, Copy)]
+struct State {
+    options: DecodeOptions,
+    allocated: usize,
+    depth: usize,
+}
+
+impl State {
+    const UNLIMITED: Self = Self {
+        options: DecodeOptions::UNLIMITED,
+        allocated: 0,
+        depth: 0,
+    };
+}
+
+thread_local! {
+    static STATE: Cell<State> = const { Cell::new(State::UNLIMITED) };
+}
+
+/// Applies the options for the duration of a single decoding call, and restores the previous
+/// options afterwards (even if decoding panics).
+///
+/// The depth and allocations carry over from an outer call. Allocations are only counted while
+/// they're limited, and carry back to the outer call if it's limited as well.
+struct Scope(State);
+
+impl Scope {
+    fn enter(options: DecodeOptions) -> Self {
+        Self(STATE.with(|state| {
+            let outer = state.get();
+            state.set(State {
+                options: outer.options.stricter(options),
+                ..outer
+            });
+            outer
+        }))
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        STATE.with(|state| {
+            let current = state.get();
+            state.set(State {
+                options: self.0.options,
+                allocated: if self.0.options.max_allocation == usize::MAX {
+                    self.0.allocated
+                } else {
+                    current.allocated
+                },
+                depth: current.depth,
+            });
+        });
+    }
+}
+
+/// Check the length of a `string` or `bytes` value against the current limits, before it's
+/// decoded.
+///
+/// # Errors
+///
+/// Will return `Err` if the value is longer than allowed.
+pub fn check_bytes_len(len: u64) -> Result<()> {
+    let max = STATE.with(|state| state.get().options.max_bytes_len);
+    if usize::try_from(len).map_or(true, |len| len > max) {
+        return Err(Error::BytesTooLong { len, max });
+    }
+
+    Ok(())
+}
+
+/// Check the amount of elements of a collection against the current limits, before adding
+/// another one.
+///
+/// # Errors
+///
+/// Will return `Err` if the collection would contain more elements than allowed.
+pub fn check_collection_len(len: usize) -> Result<()> {
+    let max = STATE.with(|state| state.get().options.max_collection_len);
+    if len > max {
+        return Err(Error::CollectionTooLong { max });
+    }
+
+    Ok(())
+}
+
+/// Track an allocation of the given byte size against the current limits.
+///
+/// # Errors
+///
+/// Will return `Err` if the allocation exceeds the total amount of allowed allocations.
+pub fn allocate(size: usize) -> Result<()> {
+    STATE.with(|state| {
+        let mut current = state.get();
+        let max = current.options.max_allocation;
+        if max == usize::MAX {
+            return Ok(());
+        }
+
+        current.allocated = current
+            .allocated
+            .checked_add(size)
+            .filter(|&allocated| allocated <= max)
+            .ok_or(Error::AllocationLimit { max })?;
+
+        state.set(current);
+        Ok(())
+    })
+}
+
+/// Whether the current options only accept payloads in their canonical form.
+pub(crate) fn is_canonical() -> bool {
+    STATE.with(|state| state.get().options.canonical)
+}
+
+/// Enter a nested struct or enum, which is tracked until the returned guard is dropped.
+///
+/// # Errors
+///
+/// Will return `Err` if the nesting becomes deeper than allowed.
+pub fn enter_nested() -> Result<NestedGuard> {
+    STATE.with(|state| {
+        let mut current = state.get();
+        let max = current.options.max_depth;
+
+        if current.depth >= max {
+            return Err(Error::DepthLimit { max });
+        }
+
+        current.depth += 1;
+        state.set(current);
+        Ok(NestedGuard(()))
+    })
+}
+
+/// Guard that tracks the decoding of a nested struct or enum, created by [`enter_nested`].
+#[must_use]
+pub struct NestedGuard(());
+
+impl Drop for NestedGuard {
+    fn drop(&mut self) {
+        STATE.with(|state| {
+            let mut current = state.get();
+            current.depth = current.depth.saturating_sub(1);
+            state.set(current);
+        });
+    }
+}
@@ -2,17 +2,26 @@
 
 pub use decode::*;
 pub use encode::*;
+#[cfg(feature = "std")]
+pub use limits::*;
 pub use size::*;
+#[cfg(not(feature = "std"))]
+pub use unlimited::*;
 
 mod decode;
 mod encode;
+#[cfg(feature = "std")]
+mod limits;
 mod size;
+#[cfg(not(feature = "std"))]
+mod unlimited;
 
 #[cfg(test)]
 mod tests {
-    use std::collections::{HashMap, HashSet};
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
     use super::*;
+    use crate::{FieldEncoding, FieldId, UnknownFields};
 
     #[test]
     fn non_zero_string_valid() {
@@ -65,6 +74,65 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn string_borrowed() {
+        let mut buf = Vec::new();
+        encode_string(&mut buf, "test");
+        encode_u8(&mut buf, 5);
+
+        let mut r = &*buf;
+        assert_eq!("test", decode_string_borrowed(&mut r).unwrap());
+        assert_eq!(&[5], r);
+    }
+
+    #[test]
+    fn string_borrowed_invalid() {
+        let mut buf = Vec::new();
+        encode_bytes_std(&mut buf, &[0xff, 0xfe]);
+        assert!(matches!(
+            decode_string_borrowed(&mut &*buf),
+            Err(Error::NonUtf8Borrowed(_)),
+        ));
+    }
+
+    #[test]
+    fn vec_borrowed() {
+        let mut buf = Vec::new();
+        encode_vec(
+            &mut buf,
+            &["a", "bc"],
+            |v| size_string(v),
+            |w, v| encode_string(w, v),
+        );
+
+        let value = decode_vec_borrowed(&mut &*buf, |r| decode_string_borrowed(r)).unwrap();
+        assert_eq!(vec!["a", "bc"], value);
+    }
+
+    #[test]
+    fn array_too_short() {
+        let mut buf = Vec::new();
+        encode_array(
+            &mut buf,
+            &[1_u32, 2],
+            |v| size_u32(*v),
+            |w, v| encode_u32(w, *v),
+        );
+
+        assert!(matches!(
+            decode_array::<3, _, _, _>(&mut &*buf, |r| decode_u32(r)),
+            Err(Error::InsufficientData),
+        ));
+        assert!(matches!(
+            decode_array_borrowed::<3, _, _>(&mut &*buf, decode_u32),
+            Err(Error::InsufficientData),
+        ));
+        assert_eq!(
+            [1, 2],
+            decode_array::<2, _, _, _>(&mut &*buf, |r| decode_u32(r)).unwrap()
+        );
+    }
+
     #[test]
     fn non_zero_vec_valid() {
         let mut buf = Vec::new();
@@ -148,4 +216,414 @@ mod tests {
             Err(Error::Zero),
         ));
     }
+
+    #[test]
+    fn btree_map_sorted() {
+        let mut buf = Vec::new();
+        encode_btree_map(
+            &mut buf,
+            &BTreeMap::from_iter([(3, true), (1, false), (2, true)]),
+            |k| size_u32(*k),
+            |v| size_bool(*v),
+            |w, k| encode_u32(w, *k),
+            |w, v| encode_bool(w, *v),
+        );
+        assert_eq!([6, 1, 0, 2, 1, 3, 1], &*buf);
+
+        let value = decode_btree_map(&mut &*buf, |r| decode_u32(r), |r| decode_bool(r)).unwrap();
+        assert_eq!(vec![1, 2, 3], value.into_keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn non_zero_btree_set_invalid() {
+        let mut buf = Vec::new();
+        encode_btree_set(
+            &mut buf,
+            &BTreeSet::new(),
+            |v| size_u32(*v),
+            |w, v| encode_u32(w, *v),
+        );
+        assert!(matches!(
+            decode_non_zero_btree_set(&mut &*buf, |r| decode_u32(r)),
+            Err(Error::Zero),
+        ));
+    }
+
+    #[test]
+    fn map_canonical_sorted() {
+        let mut buf = Vec::new();
+        encode_map_canonical(
+            &mut buf,
+            &HashMap::from([(300, true), (2, false), (1, true)]),
+            |w, k| encode_u32(w, *k),
+            |w, v| encode_bool(w, *v),
+        );
+        assert_eq!([7, 1, 1, 2, 0, 0xac, 0x02, 1], &*buf);
+    }
+
+    #[test]
+    fn set_canonical_sorted() {
+        let mut buf = Vec::new();
+        encode_set_canonical(&mut buf, &HashSet::from(["b", "ab", "a"]), |w, v| {
+            encode_string(w, v);
+        });
+        assert_eq!([7, 1, b'a', 1, b'b', 2, b'a', b'b'], &*buf);
+    }
+
+    #[test]
+    fn canonical_unsorted() {
+        let options = DecodeOptions {
+            canonical: true,
+            ..DecodeOptions::default()
+        };
+        let buf = [4, 2, 0, 1, 0];
+        let err = options
+            .decode::<HashMap<u32, bool>>(&mut &buf[..])
+            .unwrap_err();
+        assert!(matches!(err.kind(), Error::NonCanonical));
+        assert_eq!([PathSegment::Index(1)], *err.context().unwrap().path);
+        assert_eq!(
+            2,
+            HashMap::<u32, bool>::decode(&mut &buf[..]).unwrap().len()
+        );
+
+        let buf = [4, 1, 0, 1, 0];
+        assert!(matches!(
+            options
+                .decode::<HashMap<u32, bool>>(&mut &buf[..])
+                .unwrap_err()
+                .kind(),
+            Error::NonCanonical,
+        ));
+        assert!(options
+            .decode::<HashMap<u32, bool>>(&mut &[4, 1, 0, 2, 0][..])
+            .is_ok());
+    }
+
+    #[test]
+    fn canonical_varint() {
+        let options = DecodeOptions {
+            canonical: true,
+            ..DecodeOptions::default()
+        };
+        let buf = [0x81, 0x00];
+        assert!(matches!(
+            options.decode::<u32>(&mut &buf[..]),
+            Err(Error::NonCanonical),
+        ));
+        assert_eq!(1, u32::decode(&mut &buf[..]).unwrap());
+    }
+
+    #[test]
+    fn encode_canonical_nested() {
+        // `200` sorts before `300` by value, but after it by the encoded bytes.
+        let value = vec![BTreeSet::from([200_u32, 300])];
+        let mut buf = Vec::new();
+        encode_canonical(&mut buf, &value);
+        assert_eq!([5, 4, 0xac, 0x02, 0xc8, 0x01], &*buf);
+        assert!(!is_canonical_encoding());
+
+        let mut buf = Vec::new();
+        value.encode(&mut buf);
+        assert_eq!([5, 4, 0xc8, 0x01, 0xac, 0x02], &*buf);
+    }
+
+    #[test]
+    fn unknown_fields_canonical() {
+        let mut buf = Vec::new();
+        for id in [7, 3, 5] {
+            encode_field(&mut buf, FieldId::new(id, FieldEncoding::Fixed1), |w| {
+                encode_u8(w, id as u8);
+            });
+        }
+
+        let mut r = &*buf;
+        let mut unknown = UnknownFields::default();
+        while r.has_remaining() {
+            let id = decode_id(&mut r).unwrap();
+            decode_unknown(&mut r, id, &mut unknown).unwrap();
+        }
+
+        let id = |id| {
+            let mut out = Vec::new();
+            encode_field(&mut out, FieldId::new(id, FieldEncoding::Fixed1), |w| {
+                encode_u8(w, id as u8);
+            });
+            out
+        };
+
+        let mut out = Vec::new();
+        encode_unknown_fields_canonical(&mut out, &unknown, ..6);
+        assert_eq!([id(3), id(5)].concat(), out);
+
+        let mut out = Vec::new();
+        encode_unknown_fields_canonical(&mut out, &unknown, 6..);
+        assert_eq!(id(7), out);
+
+        let mut out = Vec::new();
+        encode_unknown_fields_canonical(&mut out, &unknown, ..);
+        assert_eq!([id(3), id(5), id(7)].concat(), out);
+    }
+
+    #[test]
+    fn encode_to_slice_exact() {
+        let value = vec!["test".to_owned(), "x".repeat(200)];
+        let mut buf = [0; 256];
+        let len = value.encode_to_slice(&mut buf).unwrap();
+
+        assert_eq!(value.size(), len);
+        assert_eq!(value.encode_to_vec(), buf[..len]);
+        assert_eq!(len, value.encode_to_slice(&mut buf[..len]).unwrap());
+    }
+
+    #[test]
+    fn encode_to_slice_too_small() {
+        let value = "test".to_owned();
+        let mut buf = [0; 4];
+        assert_eq!(
+            Err(BufferTooSmall {
+                required: 5,
+                available: 4,
+            }),
+            value.encode_to_slice(&mut buf),
+        );
+        assert_eq!([0; 4], buf);
+    }
+
+    #[test]
+    fn encode_to_vec_nested() {
+        let value = (0..100)
+            .map(|i| {
+                (0..i)
+                    .map(|k| (k, k % 2 == 0))
+                    .collect::<HashMap<u32, bool>>()
+            })
+            .collect::<Vec<_>>();
+        let buf = value.encode_to_vec();
+
+        assert_eq!(value.size(), buf.len());
+        assert_eq!(buf.len(), buf.capacity());
+        assert_eq!(buf, value.encode_to_bytes());
+        assert_eq!(
+            value,
+            Vec::<HashMap<u32, bool>>::decode(&mut &*buf).unwrap()
+        );
+    }
+
+    #[test]
+    fn error_context_element() {
+        let buf = [5, 1, b'a', 2, 0xff, 0xfe];
+        let err = Vec::<String>::decode(&mut &buf[..]).unwrap_err();
+        assert!(matches!(err.kind(), Error::NonUtf8(_)));
+
+        let context = err.context().unwrap();
+        assert_eq!(None, context.ty);
+        assert_eq!([PathSegment::Index(1)], *context.path);
+        assert_eq!(6, context.offset);
+        assert_eq!(
+            "failed decoding `[1]` at byte offset 6: string is not valid UTF-8",
+            err.to_string(),
+        );
+    }
+
+    #[test]
+    fn error_context_type() {
+        let buf = [3, 5, 0, 7];
+        let err = decode_type(&mut &buf[..], "Sample", |r| {
+            decode_vec(r, |r| decode_non_zero_u8(r))
+                .map_err(|e| e.within(PathSegment::Field("values")))
+        })
+        .unwrap_err();
+        assert!(matches!(err.kind(), Error::Zero));
+
+        let context = err.context().unwrap();
+        assert_eq!(Some("Sample"), context.ty);
+        assert_eq!(
+            [PathSegment::Field("values"), PathSegment::Index(1)],
+            *context.path
+        );
+        assert_eq!(3, context.offset);
+    }
+
+    #[test]
+    fn unknown_fields_roundtrip() {
+        let mut buf = Vec::new();
+        encode_field(&mut buf, FieldId::new(1, FieldEncoding::Varint), |w| {
+            encode_u64(w, 300);
+        });
+        encode_field(
+            &mut buf,
+            FieldId::new(2, FieldEncoding::LengthPrefixed),
+            |w| {
+                encode_string(w, "test");
+            },
+        );
+        encode_field(&mut buf, FieldId::new(3, FieldEncoding::Fixed4), |w| {
+            encode_f32(w, 1.5);
+        });
+
+        let mut r = &*buf;
+        let mut unknown = UnknownFields::default();
+        while r.has_remaining() {
+            let id = decode_id(&mut r).unwrap();
+            decode_unknown(&mut r, id, &mut unknown).unwrap();
+        }
+
+        let mut out = Vec::new();
+        encode_unknown_fields(&mut out, &unknown);
+        assert_eq!(3, unknown.len());
+        assert_eq!(buf, out);
+        assert_eq!(buf.len(), size_unknown_fields(&unknown));
+    }
+
+    #[test]
+    fn unknown_fields_insufficient_data() {
+        let mut buf = Vec::new();
+        encode_u64(&mut buf, 10);
+        buf.push(1);
+
+        assert!(matches!(
+            decode_unknown(
+                &mut &*buf,
+                FieldId::new(1, FieldEncoding::LengthPrefixed),
+                &mut UnknownFields::default(),
+            ),
+            Err(Error::InsufficientData),
+        ));
+    }
+
+    #[test]
+    fn limit_bytes_len() {
+        let mut buf = Vec::new();
+        encode_string(&mut buf, "test");
+
+        let options = DecodeOptions {
+            max_bytes_len: 3,
+            ..DecodeOptions::default()
+        };
+        assert!(matches!(
+            options.decode::<String>(&mut &*buf),
+            Err(Error::BytesTooLong { len: 4, max: 3 }),
+        ));
+        assert!(matches!(
+            options.decode_borrowed::<&str>(&mut &*buf),
+            Err(Error::BytesTooLong { len: 4, max: 3 }),
+        ));
+        assert_eq!("test", decode_string(&mut &*buf).unwrap());
+    }
+
+    #[test]
+    fn limit_collection_len() {
+        let mut buf = Vec::new();
+        encode_vec(
+            &mut buf,
+            &[1, 2, 3],
+            |v| size_u32(*v),
+            |w, v| encode_u32(w, *v),
+        );
+
+        let options = DecodeOptions {
+            max_collection_len: 2,
+            ..DecodeOptions::default()
+        };
+        assert!(matches!(
+            options.decode::<Vec<u32>>(&mut &*buf),
+            Err(Error::CollectionTooLong { max: 2 }),
+        ));
+        assert!(matches!(
+            options.decode::<HashSet<u32>>(&mut &*buf),
+            Err(Error::CollectionTooLong { max: 2 }),
+        ));
+    }
+
+    #[test]
+    fn limit_allocation() {
+        let value = vec!["abc".to_owned(), "def".to_owned()];
+        let mut buf = Vec::new();
+        value.encode(&mut buf);
+
+        let options = DecodeOptions {
+            max_allocation: 2 * std::mem::size_of::<String>() + 5,
+            ..DecodeOptions::default()
+        };
+        assert!(matches!(
+            options
+                .decode::<Vec<String>>(&mut &*buf)
+                .unwrap_err()
+                .kind(),
+            Error::AllocationLimit { .. },
+        ));
+
+        let options = DecodeOptions {
+            max_allocation: 2 * std::mem::size_of::<String>() + 6,
+            ..DecodeOptions::default()
+        };
+        assert_eq!(value, options.decode::<Vec<String>>(&mut &*buf).unwrap());
+    }
+
+    #[test]
+    fn limit_depth() {
+        struct Nested(Option<Box<Nested>>);
+
+        impl Decode for Nested {
+            fn decode(r: &mut impl Buf) -> Result<Self> {
+                let _nested = enter_nested()?;
+                decode_option(r, |r| Self::decode(r).map(Box::new)).map(Self)
+            }
+        }
+
+        let buf = [1, 1, 1, 0];
+        let options = DecodeOptions {
+            max_depth: 3,
+            ..DecodeOptions::default()
+        };
+        assert!(matches!(
+            options.decode::<Nested>(&mut &buf[..]),
+            Err(Error::DepthLimit { max: 3 }),
+        ));
+
+        let options = DecodeOptions {
+            max_depth: 4,
+            ..DecodeOptions::default()
+        };
+        assert!(options.decode::<Nested>(&mut &buf[..]).unwrap().0.is_some());
+        // the limits only apply within the scope of the options.
+        assert!(Nested::decode(&mut &buf[..]).is_ok());
+    }
+
+    #[test]
+    fn limit_nested() {
+        /// Tries to escape the limits, by decoding its first value with new options.
+        #[derive(Debug)]
+        struct Escape(Vec<String>, Vec<String>);
+
+        impl Decode for Escape {
+            fn decode(r: &mut impl Buf) -> Result<Self> {
+                let first = DecodeOptions::UNLIMITED.decode(r)?;
+                Ok(Self(first, Vec::decode(r)?))
+            }
+        }
+
+        let value = vec!["abc".to_owned(), "def".to_owned()];
+        let mut buf = Vec::new();
+        value.encode(&mut buf);
+        value.encode(&mut buf);
+
+        // allocations of the inner call count towards the outer limits.
+        let options = DecodeOptions {
+            max_allocation: 2 * std::mem::size_of::<String>() + 6,
+            ..DecodeOptions::default()
+        };
+        assert!(matches!(
+            options.decode::<Escape>(&mut &*buf).unwrap_err().kind(),
+            Error::AllocationLimit { .. },
+        ));
+
+        let options = DecodeOptions {
+            max_allocation: 4 * std::mem::size_of::<String>() + 12,
+            ..DecodeOptions::default()
+        };
+        let escape = options.decode::<Escape>(&mut &*buf).unwrap();
+        assert_eq!((&value, &value), (&escape.0, &escape.1));
+    }
 }
@@ -1,8 +1,18 @@
-use std::collections::{HashMap, HashSet};
+use alloc::{
+    borrow::Cow,
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
 
 use bytes::Bytes;
 
-use crate::{varint, NonZero};
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+use crate::collections::{HashMap, HashSet};
+use crate::{varint, NonZero, UnknownFields};
 
 macro_rules! size_fixed {
     ($ty:ty => $size:literal) => {
@@ -65,33 +75,62 @@ pub const fn size_bytes_bytes(value: &Bytes) -> usize {
     size_u64(value.len() as u64) + value.len()
 }
 
+/// Calculate the size of a collection's content, together with the length prefix in front of it.
+#[inline(always)]
+fn size_prefixed(content: usize) -> usize {
+    size_u64(content as u64) + content
+}
+
 /// Calculate the size of a Mabo `vec<T>` vector value.
 pub fn size_vec<T, S>(vec: &[T], size: S) -> usize
 where
     S: Fn(&T) -> usize,
 {
-    size_u64(vec.len() as u64) + vec.iter().map(size).sum::<usize>()
+    size_prefixed(vec.iter().map(size).sum())
 }
 
 /// Calculate the size of a Mabo `hash_map<K, V>` hash map value.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 pub fn size_hash_map<K, V, SK, SV>(map: &HashMap<K, V>, size_key: SK, size_value: SV) -> usize
 where
     SK: Fn(&K) -> usize,
     SV: Fn(&V) -> usize,
 {
-    size_u64(map.len() as u64)
-        + map
-            .iter()
+    size_prefixed(
+        map.iter()
             .map(|(key, value)| size_key(key) + size_value(value))
-            .sum::<usize>()
+            .sum(),
+    )
 }
 
 /// Calculate the size of a Mabo `hash_set<T>` hash set value.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 pub fn size_hash_set<T, S>(set: &HashSet<T>, size: S) -> usize
 where
     S: Fn(&T) -> usize,
 {
-    size_u64(set.len() as u64) + set.iter().map(size).sum::<usize>()
+    size_prefixed(set.iter().map(size).sum())
+}
+
+/// Calculate the size of a Mabo `btree_map<K, V>` map value.
+pub fn size_btree_map<K, V, SK, SV>(map: &BTreeMap<K, V>, size_key: SK, size_value: SV) -> usize
+where
+    SK: Fn(&K) -> usize,
+    SV: Fn(&V) -> usize,
+{
+    size_prefixed(
+        map.iter()
+            .map(|(key, value)| size_key(key) + size_value(value))
+            .sum(),
+    )
+}
+
+/// Calculate the size of a Mabo `btree_set<T>` set value.
+pub fn size_btree_set<T, S>(set: &BTreeSet<T>, size: S) -> usize
+where
+    S: Fn(&T) -> usize,
+{
+    size_prefixed(set.iter().map(size).sum())
 }
 
 /// Calculate the size of a Mabo `option<T>` option value.
@@ -107,7 +146,17 @@ pub fn size_array<const N: usize, T, S>(array: &[T; N], size: S) -> usize
 where
     S: Fn(&T) -> usize,
 {
-    size_u64(N as u64) + array.iter().map(size).sum::<usize>()
+    size_prefixed(array.iter().map(size).sum())
+}
+
+/// Calculate the size of a Mabo `(T1, T2, ...)` tuple value, when it's used as a field on its own
+/// and therefore prefixed with its length.
+#[inline(always)]
+pub fn size_tuple<S>(size: S) -> usize
+where
+    S: Fn() -> usize,
+{
+    size_prefixed(size())
 }
 
 /// Calculate the size of a Mabo field identifier.
@@ -142,6 +191,16 @@ where
     option.map_or(0, |value| size_field_id(id) + size(value))
 }
 
+/// Calculate the size of all unknown fields that were retained while decoding a struct.
+#[inline]
+#[must_use]
+pub fn size_unknown_fields(unknown: &UnknownFields) -> usize {
+    unknown
+        .iter()
+        .map(|(id, data)| size_u32(id.into_u32()) + data.len())
+        .sum()
+}
+
 /// Values that are able to calculate their encoded byte size, without actually encoding.
 pub trait Size {
     /// Calculate the encoded byte size.
@@ -176,6 +235,13 @@ impl Size for String {
     }
 }
 
+impl Size for &'_ str {
+    #[inline(always)]
+    fn size(&self) -> usize {
+        size_string(self)
+    }
+}
+
 impl Size for Box<str> {
     #[inline(always)]
     fn size(&self) -> usize {
@@ -210,6 +276,7 @@ where
     }
 }
 
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 impl<K, V> Size for HashMap<K, V>
 where
     K: Size,
@@ -221,6 +288,7 @@ where
     }
 }
 
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 impl<T> Size for HashSet<T>
 where
     T: Size,
@@ -231,6 +299,27 @@ where
     }
 }
 
+impl<K, V> Size for BTreeMap<K, V>
+where
+    K: Size,
+    V: Size,
+{
+    #[inline(always)]
+    fn size(&self) -> usize {
+        size_btree_map(self, Size::size, Size::size)
+    }
+}
+
+impl<T> Size for BTreeSet<T>
+where
+    T: Size,
+{
+    #[inline(always)]
+    fn size(&self) -> usize {
+        size_btree_set(self, Size::size)
+    }
+}
+
 impl<T> Size for Option<T>
 where
     T: Size,
@@ -261,7 +350,7 @@ where
     }
 }
 
-impl<T> Size for std::borrow::Cow<'_, T>
+impl<T> Size for Cow<'_, T>
 where
     T: Clone + Size,
 {
@@ -271,7 +360,7 @@ where
     }
 }
 
-impl<T> Size for std::rc::Rc<T>
+impl<T> Size for Rc<T>
 where
     T: Size,
 {
@@ -281,7 +370,7 @@ where
     }
 }
 
-impl<T> Size for std::sync::Arc<T>
+impl<T> Size for Arc<T>
 where
     T: Size,
 {
@@ -0,0 +1,61 @@
+//! Replacements for the decoding limits, when the `std` feature is disabled.
+//!
+//! The limits are tracked in thread-local storage, which isn't available without the standard
+//! library. Therefore, all checks pass and decoding is never limited. `DecodeOptions` doesn't
+//! exist in this case, so code that tries to configure limits fails to compile, instead of
+//! silently running without them.
+
+use super::Result;
+
+/// Check the length of a `string` or `bytes` value against the current limits, before it's
+/// decoded.
+///
+/// # Errors
+///
+/// Never fails without the `std` feature.
+#[inline(always)]
+pub fn check_bytes_len(_len: u64) -> Result<()> {
+    Ok(())
+}
+
+/// Check the amount of elements of a collection against the current limits, before adding
+/// another one.
+///
+/// # Errors
+///
+/// Never fails without the `std` feature.
+#[inline(always)]
+pub fn check_collection_len(_len: usize) -> Result<()> {
+    Ok(())
+}
+
+/// Track an allocation of the given byte size against the current limits.
+///
+/// # Errors
+///
+/// Never fails without the `std` feature.
+#[inline(always)]
+pub fn allocate(_size: usize) -> Result<()> {
+    Ok(())
+}
+
+/// Whether the current options only accept payloads in their canonical form, which is never
+/// checked without the `std` feature.
+#[inline(always)]
+pub(crate) fn is_canonical() -> bool {
+    false
+}
+
+/// Enter a nested struct or enum, which is tracked until the returned guard is dropped.
+///
+/// # Errors
+///
+/// Never fails without the `std` feature.
+#[inline(always)]
+pub fn enter_nested() -> Result<NestedGuard> {
+    Ok(NestedGuard(()))
+}
+
+/// Guard that tracks the decoding of a nested struct or enum, created by [`enter_nested`].
+#[must_use]
+pub struct NestedGuard(());
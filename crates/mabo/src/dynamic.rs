@@ -0,0 +1,1051 @@
+//! Handling of Mabo payloads whose types are only known at runtime.
+//!
+//! Instead of generated code, a [`Type`] descriptor defines how a value is laid out. It mirrors
+//! the data types of a schema, with any generics already substituted. Structs and enums can be
+//! described inline, or by name through a [`Registry`], which allows types to refer to themselves.
+//! Payloads can then be decoded into a generic [`Value`] with [`decode_value`], and written back
+//! with [`encode_value`].
+//!
+//! # Example
+//!
+//! ```
+//! use std::collections::BTreeMap;
+//!
+//! use mabo::dynamic::{self, Definition, Field, FieldKind, Fields, Registry, Type, Value};
+//!
+//! // struct Node { id: u32 @1, children: vec<Node> @2 }
+//! let mut registry = Registry::new();
+//! registry.insert(
+//!     "Node",
+//!     Definition::Struct(Fields {
+//!         kind: FieldKind::Named,
+//!         fields: vec![
+//!             Field::new("id", 1, Type::U32),
+//!             Field::new("children", 2, Type::Vec(Box::new(Type::Named("Node".to_owned())))),
+//!         ],
+//!     }),
+//! );
+//!
+//! let ty = Type::Named("Node".to_owned());
+//! let leaf = Value::Struct(BTreeMap::from([(1, Value::U32(2)), (2, Value::Vec(Vec::new()))]));
+//! let value = Value::Struct(BTreeMap::from([(1, Value::U32(1)), (2, Value::Vec(vec![leaf]))]));
+//!
+//! let mut buf = Vec::new();
+//! dynamic::encode_value(&mut buf, &value, &ty, &registry).unwrap();
+//!
+//! assert_eq!(
+//!     value,
+//!     dynamic::decode_value(&mut &*buf, &ty, &registry).unwrap(),
+//! );
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::{
+    buf::{self, END_MARKER},
+    FieldEncoding, FieldId, VariantId,
+};
+
+/// Runtime description of a Mabo data type.
+///
+/// References to other structs or enums are either replaced with their definition, or point to a
+/// definition in the [`Registry`] by name. Any generics are already substituted with the concrete
+/// types.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    /// Boolean `true` or `false`.
+    Bool,
+    /// 8-bit unsigned integer.
+    U8,
+    /// 16-bit unsigned integer.
+    U16,
+    /// 32-bit unsigned integer.
+    U32,
+    /// 64-bit unsigned integer.
+    U64,
+    /// 128-bit unsigned integer.
+    U128,
+    /// 8-bit signed integer.
+    I8,
+    /// 16-bit signed integer.
+    I16,
+    /// 32-bit signed integer.
+    I32,
+    /// 64-bit signed integer.
+    I64,
+    /// 128-bit signed integer.
+    I128,
+    /// 32-bit floating point number.
+    F32,
+    /// 64-bit floating point number.
+    F64,
+    /// UTF-8 encoded string.
+    String,
+    /// Arbitrary bytes.
+    Bytes,
+    /// Vector of values of the same type.
+    Vec(Box<Type>),
+    /// Key-value map.
+    HashMap(Box<(Type, Type)>),
+    /// Set of unique values.
+    HashSet(Box<Type>),
+    /// Key-value map, ordered by its keys.
+    BTreeMap(Box<(Type, Type)>),
+    /// Set of unique values, ordered by the values.
+    BTreeSet(Box<Type>),
+    /// Value that might be absent.
+    Option(Box<Type>),
+    /// Value that is guaranteed to not be zero or empty.
+    NonZero(Box<Type>),
+    /// Fixed list of values of different types.
+    Tuple(Vec<Type>),
+    /// Fixed-size list of values of the same type.
+    Array(Box<Type>, u32),
+    /// Struct with its fields.
+    Struct(Fields),
+    /// Enum with all its variants.
+    Enum(Vec<Variant>),
+    /// Struct or enum that is defined in the [`Registry`] under this name.
+    Named(String),
+}
+
+/// Collection of struct and enum definitions, that [`Type::Named`] refers to by name.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Registry {
+    definitions: HashMap<String, Definition>,
+}
+
+/// Definition of a named struct or enum.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Definition {
+    /// Struct with its fields.
+    Struct(Fields),
+    /// Enum with all its variants.
+    Enum(Vec<Variant>),
+}
+
+/// Single variant of an enum.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variant {
+    /// Name of the variant.
+    pub name: String,
+    /// Identifier of the variant.
+    pub id: u32,
+    /// Fields that the variant carries.
+    pub fields: Fields,
+}
+
+/// Fields of a struct or enum variant.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fields {
+    /// How the fields are declared.
+    pub kind: FieldKind,
+    /// List of the fields, which is empty for unit structs and variants.
+    pub fields: Vec<Field>,
+}
+
+/// Possible kinds in which the fields of a struct or enum variant can be declared.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FieldKind {
+    /// Fields with a name.
+    Named,
+    /// Fields that are only identified by their position.
+    Unnamed,
+    /// No fields at all, in which case nothing but the enum variant identifier is encoded.
+    Unit,
+}
+
+/// Single field of a struct or enum variant.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    /// Name of the field, which is empty for unnamed fields.
+    pub name: String,
+    /// Identifier of the field.
+    pub id: u32,
+    /// Data type of the field.
+    pub ty: Type,
+}
+
+/// Dynamically typed value, that mirrors the data types of a schema.
+///
+/// Fields of structs and enum variants are keyed by their identifier. For optional fields, an
+/// absent entry is treated the same as [`Value::Option`] with `None`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// Boolean `true` or `false`.
+    Bool(bool),
+    /// 8-bit unsigned integer.
+    U8(u8),
+    /// 16-bit unsigned integer.
+    U16(u16),
+    /// 32-bit unsigned integer.
+    U32(u32),
+    /// 64-bit unsigned integer.
+    U64(u64),
+    /// 128-bit unsigned integer.
+    U128(u128),
+    /// 8-bit signed integer.
+    I8(i8),
+    /// 16-bit signed integer.
+    I16(i16),
+    /// 32-bit signed integer.
+    I32(i32),
+    /// 64-bit signed integer.
+    I64(i64),
+    /// 128-bit signed integer.
+    I128(i128),
+    /// 32-bit floating point number.
+    F32(f32),
+    /// 64-bit floating point number.
+    F64(f64),
+    /// UTF-8 encoded string.
+    String(String),
+    /// Arbitrary bytes.
+    Bytes(Vec<u8>),
+    /// Vector of values.
+    Vec(Vec<Value>),
+    /// Key-value map, as list of entries.
+    HashMap(Vec<(Value, Value)>),
+    /// Set of unique values.
+    HashSet(Vec<Value>),
+    /// Key-value map, as list of entries. They're encoded in the given order, so they should
+    /// already be sorted by their key.
+    BTreeMap(Vec<(Value, Value)>),
+    /// Set of unique values, that are encoded in the given order and should already be sorted.
+    BTreeSet(Vec<Value>),
+    /// Value that might be absent.
+    Option(Option<Box<Value>>),
+    /// Fixed list of values of different types.
+    Tuple(Vec<Value>),
+    /// Fixed-size list of values.
+    Array(Vec<Value>),
+    /// Struct with its fields, keyed by the field identifier.
+    Struct(BTreeMap<u32, Value>),
+    /// Enum variant identifier with its fields, keyed by the field identifier.
+    Enum(u32, BTreeMap<u32, Value>),
+}
+
+/// Error that can happen while trying to encode a [`Value`].
+#[derive(Debug, thiserror::Error)]
+pub enum EncodeError {
+    /// The value doesn't match the type it is encoded as.
+    #[error("expected a value of type {expected}, but found {found}")]
+    Mismatch {
+        /// Name of the expected type.
+        expected: &'static str,
+        /// Name of the type the value actually has.
+        found: &'static str,
+    },
+    /// The field of a struct or enum is non-optional in the schema, but is missing from the value.
+    #[error("required field {0} is missing from the value")]
+    MissingField(u32),
+    /// The value contains a field that doesn't exist in the schema.
+    #[error("encountered an unknown field {0}")]
+    UnknownField(u32),
+    /// An enum variant was found that does not exist in the schema.
+    #[error("encountered an unknown enum variant {0}")]
+    UnknownVariant(u32),
+    /// A named type was not found in the registry.
+    #[error("type {0} is not defined in the registry")]
+    UnknownType(String),
+    /// The value of a non-zero type was actually zero.
+    #[error("non-zero value was found to be zero")]
+    Zero,
+    /// The number of elements in an array or tuple differs from its type.
+    #[error("expected {expected} elements, but found {found}")]
+    Length {
+        /// Number of elements defined by the type.
+        expected: usize,
+        /// Number of elements in the value.
+        found: usize,
+    },
+}
+
+/// Error that can happen while trying to decode a [`Value`].
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+    /// The payload couldn't be decoded as the type.
+    #[error(transparent)]
+    Buf(#[from] buf::Error),
+    /// A named type was not found in the registry.
+    #[error("type {0} is not defined in the registry")]
+    UnknownType(String),
+}
+
+impl Type {
+    /// Encoding that is used when a value of this type is written as field.
+    #[must_use]
+    pub fn encoding(&self) -> FieldEncoding {
+        match self {
+            Self::Bool | Self::U8 | Self::I8 => FieldEncoding::Fixed1,
+            Self::U16
+            | Self::U32
+            | Self::U64
+            | Self::U128
+            | Self::I16
+            | Self::I32
+            | Self::I64
+            | Self::I128 => FieldEncoding::Varint,
+            Self::F32 => FieldEncoding::Fixed4,
+            Self::F64 => FieldEncoding::Fixed8,
+            Self::NonZero(ty) => ty.encoding(),
+            Self::String
+            | Self::Bytes
+            | Self::Vec(_)
+            | Self::HashMap(_)
+            | Self::HashSet(_)
+            | Self::BTreeMap(_)
+            | Self::BTreeSet(_)
+            | Self::Option(_)
+            | Self::Tuple(_)
+            | Self::Array(..)
+            | Self::Struct(_)
+            | Self::Enum(_)
+            | Self::Named(_) => FieldEncoding::LengthPrefixed,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Bool => "bool",
+            Self::U8 => "u8",
+            Self::U16 => "u16",
+            Self::U32 => "u32",
+            Self::U64 => "u64",
+            Self::U128 => "u128",
+            Self::I8 => "i8",
+            Self::I16 => "i16",
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+            Self::I128 => "i128",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+            Self::String => "string",
+            Self::Bytes => "bytes",
+            Self::Vec(_) => "vec",
+            Self::HashMap(_) => "hash_map",
+            Self::HashSet(_) => "hash_set",
+            Self::BTreeMap(_) => "btree_map",
+            Self::BTreeSet(_) => "btree_set",
+            Self::Option(_) => "option",
+            Self::NonZero(ty) => ty.name(),
+            Self::Tuple(_) => "tuple",
+            Self::Array(..) => "array",
+            Self::Struct(_) => "struct",
+            Self::Enum(_) => "enum",
+            Self::Named(_) => "struct or enum",
+        }
+    }
+}
+
+impl Registry {
+    /// Create a new, empty registry.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a definition under the given name, replacing and returning any previous one.
+    pub fn insert(
+        &mut self,
+        name: impl Into<String>,
+        definition: Definition,
+    ) -> Option<Definition> {
+        self.definitions.insert(name.into(), definition)
+    }
+
+    /// Look up the definition with the given name.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Definition> {
+        self.definitions.get(name)
+    }
+}
+
+impl Definition {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Struct(_) => "struct",
+            Self::Enum(_) => "enum",
+        }
+    }
+}
+
+impl Field {
+    /// Create a new field with the given name, identifier and type.
+    pub fn new(name: impl Into<String>, id: u32, ty: Type) -> Self {
+        Self {
+            name: name.into(),
+            id,
+            ty,
+        }
+    }
+}
+
+impl Value {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Bool(_) => "bool",
+            Self::U8(_) => "u8",
+            Self::U16(_) => "u16",
+            Self::U32(_) => "u32",
+            Self::U64(_) => "u64",
+            Self::U128(_) => "u128",
+            Self::I8(_) => "i8",
+            Self::I16(_) => "i16",
+            Self::I32(_) => "i32",
+            Self::I64(_) => "i64",
+            Self::I128(_) => "i128",
+            Self::F32(_) => "f32",
+            Self::F64(_) => "f64",
+            Self::String(_) => "string",
+            Self::Bytes(_) => "bytes",
+            Self::Vec(_) => "vec",
+            Self::HashMap(_) => "hash_map",
+            Self::HashSet(_) => "hash_set",
+            Self::BTreeMap(_) => "btree_map",
+            Self::BTreeSet(_) => "btree_set",
+            Self::Option(_) => "option",
+            Self::Tuple(_) => "tuple",
+            Self::Array(_) => "array",
+            Self::Struct(_) => "struct",
+            Self::Enum(..) => "enum",
+        }
+    }
+
+    /// Whether the value is considered zero for a `non_zero` type, which means the literal `0` for
+    /// integers and no content for strings, bytes and collections.
+    fn is_zero(&self) -> bool {
+        match self {
+            Self::U8(v) => *v == 0,
+            Self::U16(v) => *v == 0,
+            Self::U32(v) => *v == 0,
+            Self::U64(v) => *v == 0,
+            Self::U128(v) => *v == 0,
+            Self::I8(v) => *v == 0,
+            Self::I16(v) => *v == 0,
+            Self::I32(v) => *v == 0,
+            Self::I64(v) => *v == 0,
+            Self::I128(v) => *v == 0,
+            Self::String(v) => v.is_empty(),
+            Self::Bytes(v) => v.is_empty(),
+            Self::Vec(v) | Self::HashSet(v) | Self::BTreeSet(v) => v.is_empty(),
+            Self::HashMap(v) | Self::BTreeMap(v) => v.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+/// Encode a dynamic value as the given type.
+///
+/// # Errors
+///
+/// Will return `Err` if the value doesn't match the type, or a named type is missing from the
+/// registry. In that case, the buffer may already contain parts of the encoded value.
+pub fn encode_value(
+    w: &mut impl BufMut,
+    value: &Value,
+    ty: &Type,
+    registry: &Registry,
+) -> Result<(), EncodeError> {
+    encode(w, value, ty, registry, true)
+}
+
+fn encode<W: BufMut>(
+    w: &mut W,
+    value: &Value,
+    ty: &Type,
+    registry: &Registry,
+    root: bool,
+) -> Result<(), EncodeError> {
+    match (ty, value) {
+        (Type::Bool, Value::Bool(v)) => buf::encode_bool(w, *v),
+        (Type::U8, Value::U8(v)) => buf::encode_u8(w, *v),
+        (Type::U16, Value::U16(v)) => buf::encode_u16(w, *v),
+        (Type::U32, Value::U32(v)) => buf::encode_u32(w, *v),
+        (Type::U64, Value::U64(v)) => buf::encode_u64(w, *v),
+        (Type::U128, Value::U128(v)) => buf::encode_u128(w, *v),
+        (Type::I8, Value::I8(v)) => buf::encode_i8(w, *v),
+        (Type::I16, Value::I16(v)) => buf::encode_i16(w, *v),
+        (Type::I32, Value::I32(v)) => buf::encode_i32(w, *v),
+        (Type::I64, Value::I64(v)) => buf::encode_i64(w, *v),
+        (Type::I128, Value::I128(v)) => buf::encode_i128(w, *v),
+        (Type::F32, Value::F32(v)) => buf::encode_f32(w, *v),
+        (Type::F64, Value::F64(v)) => buf::encode_f64(w, *v),
+        (Type::String, Value::String(v)) => buf::encode_string(w, v),
+        (Type::Bytes, Value::Bytes(v)) => buf::encode_bytes_std(w, v),
+        (Type::Vec(ty), Value::Vec(values))
+        | (Type::HashSet(ty), Value::HashSet(values))
+        | (Type::BTreeSet(ty), Value::BTreeSet(values)) => {
+            encode_prefixed(w, |w| {
+                values
+                    .iter()
+                    .try_for_each(|value| encode(w, value, ty, registry, false))
+            })?;
+        }
+        (Type::HashMap(kv), Value::HashMap(entries))
+        | (Type::BTreeMap(kv), Value::BTreeMap(entries)) => encode_prefixed(w, |w| {
+            entries.iter().try_for_each(|(key, value)| {
+                encode(w, key, &kv.0, registry, false)?;
+                encode(w, value, &kv.1, registry, false)
+            })
+        })?,
+        (Type::Option(ty), Value::Option(value)) => {
+            if let Some(value) = value {
+                w.put_u8(1);
+                encode(w, value, ty, registry, false)?;
+            } else {
+                w.put_u8(0);
+            }
+        }
+        (Type::NonZero(ty), value) => {
+            if value.is_zero() {
+                return Err(EncodeError::Zero);
+            }
+            encode(w, value, ty, registry, root)?;
+        }
+        (Type::Tuple(types), Value::Tuple(values)) => {
+            ensure_len(types.len(), values.len())?;
+
+            let encode_all = |w: &mut Vec<u8>| {
+                types
+                    .iter()
+                    .zip(values)
+                    .try_for_each(|(ty, value)| encode(w, value, ty, registry, false))
+            };
+
+            if root {
+                encode_prefixed(w, encode_all)?;
+            } else {
+                let mut content = Vec::new();
+                encode_all(&mut content)?;
+                w.put_slice(&content);
+            }
+        }
+        (Type::Array(ty, size), Value::Array(values)) => {
+            ensure_len(*size as usize, values.len())?;
+            encode_prefixed(w, |w| {
+                values
+                    .iter()
+                    .try_for_each(|value| encode(w, value, ty, registry, false))
+            })?;
+        }
+        (Type::Struct(fields), Value::Struct(values)) => {
+            encode_fields(w, values, fields, registry)?;
+        }
+        (Type::Enum(variants), Value::Enum(id, values)) => {
+            encode_variant(w, *id, values, variants, registry)?;
+        }
+        (Type::Named(name), value) => encode_named(w, value, name, registry)?,
+        (ty, value) => {
+            return Err(EncodeError::Mismatch {
+                expected: ty.name(),
+                found: value.name(),
+            })
+        }
+    }
+
+    Ok(())
+}
+
+fn encode_named<W: BufMut>(
+    w: &mut W,
+    value: &Value,
+    name: &str,
+    registry: &Registry,
+) -> Result<(), EncodeError> {
+    let definition = registry
+        .get(name)
+        .ok_or_else(|| EncodeError::UnknownType(name.to_owned()))?;
+
+    match (definition, value) {
+        (Definition::Struct(fields), Value::Struct(values)) => {
+            encode_fields(w, values, fields, registry)
+        }
+        (Definition::Enum(variants), Value::Enum(id, values)) => {
+            encode_variant(w, *id, values, variants, registry)
+        }
+        (definition, value) => Err(EncodeError::Mismatch {
+            expected: definition.name(),
+            found: value.name(),
+        }),
+    }
+}
+
+fn encode_variant<W: BufMut>(
+    w: &mut W,
+    id: u32,
+    values: &BTreeMap<u32, Value>,
+    variants: &[Variant],
+    registry: &Registry,
+) -> Result<(), EncodeError> {
+    let variant = variants
+        .iter()
+        .find(|variant| variant.id == id)
+        .ok_or(EncodeError::UnknownVariant(id))?;
+
+    buf::encode_variant_id(w, VariantId::new(id));
+    encode_fields(w, values, &variant.fields, registry)
+}
+
+fn encode_fields<W: BufMut>(
+    w: &mut W,
+    values: &BTreeMap<u32, Value>,
+    fields: &Fields,
+    registry: &Registry,
+) -> Result<(), EncodeError> {
+    if let Some(id) = values
+        .keys()
+        .find(|id| !fields.fields.iter().any(|field| field.id == **id))
+    {
+        return Err(EncodeError::UnknownField(*id));
+    }
+
+    if fields.kind == FieldKind::Unit {
+        return Ok(());
+    }
+
+    for field in &fields.fields {
+        let (ty, value) = match (&field.ty, values.get(&field.id)) {
+            (Type::Option(_), None | Some(Value::Option(None))) => continue,
+            (Type::Option(ty), Some(Value::Option(Some(value)))) => (&**ty, &**value),
+            (_, Some(value)) => (&field.ty, value),
+            (_, None) => return Err(EncodeError::MissingField(field.id)),
+        };
+
+        buf::encode_id(w, FieldId::new(field.id, ty.encoding()));
+        encode(w, value, ty, registry, true)?;
+    }
+
+    buf::encode_u32(w, END_MARKER);
+    Ok(())
+}
+
+/// Encode the content into a separate buffer first, to write it with its byte length as prefix.
+fn encode_prefixed<W, E>(w: &mut W, encode: E) -> Result<(), EncodeError>
+where
+    W: BufMut,
+    E: FnOnce(&mut Vec<u8>) -> Result<(), EncodeError>,
+{
+    let mut content = Vec::new();
+    encode(&mut content)?;
+
+    buf::encode_u64(w, content.len() as u64);
+    w.put_slice(&content);
+    Ok(())
+}
+
+fn ensure_len(expected: usize, found: usize) -> Result<(), EncodeError> {
+    if expected == found {
+        Ok(())
+    } else {
+        Err(EncodeError::Length { expected, found })
+    }
+}
+
+/// Decode a dynamic value of the given type.
+///
+/// Each struct and enum counts towards the nesting depth, so recursive types should be decoded
+/// within [`DecodeOptions::run`](buf::DecodeOptions::run) to limit how deep payloads can go.
+///
+/// # Errors
+///
+/// Will return `Err` if the buffer does not have enough remaining data to read the value, the
+/// payload doesn't match the type, or a named type is missing from the registry.
+pub fn decode_value(
+    r: &mut impl Buf,
+    ty: &Type,
+    registry: &Registry,
+) -> Result<Value, DecodeError> {
+    decode(r, ty, registry, true)
+}
+
+fn decode<R: Buf>(
+    r: &mut R,
+    ty: &Type,
+    registry: &Registry,
+    root: bool,
+) -> Result<Value, DecodeError> {
+    Ok(match ty {
+        Type::Bool => Value::Bool(buf::decode_bool(r)?),
+        Type::U8 => Value::U8(buf::decode_u8(r)?),
+        Type::U16 => Value::U16(buf::decode_u16(r)?),
+        Type::U32 => Value::U32(buf::decode_u32(r)?),
+        Type::U64 => Value::U64(buf::decode_u64(r)?),
+        Type::U128 => Value::U128(buf::decode_u128(r)?),
+        Type::I8 => Value::I8(buf::decode_i8(r)?),
+        Type::I16 => Value::I16(buf::decode_i16(r)?),
+        Type::I32 => Value::I32(buf::decode_i32(r)?),
+        Type::I64 => Value::I64(buf::decode_i64(r)?),
+        Type::I128 => Value::I128(buf::decode_i128(r)?),
+        Type::F32 => Value::F32(buf::decode_f32(r)?),
+        Type::F64 => Value::F64(buf::decode_f64(r)?),
+        Type::String => Value::String(buf::decode_string(r)?),
+        Type::Bytes => Value::Bytes(buf::decode_bytes_std(r)?),
+        Type::Vec(ty) => Value::Vec(decode_list(r, |r| decode(r, ty, registry, false))?),
+        Type::HashSet(ty) => Value::HashSet(decode_list(r, |r| decode(r, ty, registry, false))?),
+        Type::BTreeSet(ty) => Value::BTreeSet(decode_list(r, |r| decode(r, ty, registry, false))?),
+        Type::HashMap(kv) => Value::HashMap(decode_entries(r, kv, registry)?),
+        Type::BTreeMap(kv) => Value::BTreeMap(decode_entries(r, kv, registry)?),
+        Type::Option(ty) => Value::Option(if buf::decode_u8(r)? == 1 {
+            Some(Box::new(decode(r, ty, registry, false)?))
+        } else {
+            None
+        }),
+        Type::NonZero(ty) => {
+            let value = decode(r, ty, registry, root)?;
+            if value.is_zero() {
+                return Err(buf::Error::Zero.into());
+            }
+            value
+        }
+        Type::Tuple(types) => {
+            if root {
+                buf::decode_u64(r)?;
+            }
+
+            Value::Tuple(
+                types
+                    .iter()
+                    .map(|ty| decode(r, ty, registry, false))
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        Type::Array(ty, size) => {
+            let mut values = decode_list(r, |r| decode(r, ty, registry, false))?;
+            if values.len() < *size as usize {
+                return Err(buf::Error::InsufficientData.into());
+            }
+
+            // ignore any additional values, in case the old array definition was larger.
+            values.truncate(*size as usize);
+            Value::Array(values)
+        }
+        Type::Struct(fields) => Value::Struct(decode_fields(r, fields, registry)?),
+        Type::Enum(variants) => decode_variant(r, variants, registry)?,
+        Type::Named(name) => match registry.get(name) {
+            Some(Definition::Struct(fields)) => Value::Struct(decode_fields(r, fields, registry)?),
+            Some(Definition::Enum(variants)) => decode_variant(r, variants, registry)?,
+            None => return Err(DecodeError::UnknownType(name.clone())),
+        },
+    })
+}
+
+fn decode_entries<R: Buf>(
+    r: &mut R,
+    kv: &(Type, Type),
+    registry: &Registry,
+) -> Result<Vec<(Value, Value)>, DecodeError> {
+    decode_list(r, |r| {
+        Ok((
+            decode(r, &kv.0, registry, false)?,
+            decode(r, &kv.1, registry, false)?,
+        ))
+    })
+}
+
+fn decode_variant<R: Buf>(
+    r: &mut R,
+    variants: &[Variant],
+    registry: &Registry,
+) -> Result<Value, DecodeError> {
+    let id = buf::decode_variant_id(r)?.value;
+    let variant = variants
+        .iter()
+        .find(|variant| variant.id == id)
+        .ok_or(buf::Error::UnknownVariant(id))?;
+
+    Ok(Value::Enum(
+        id,
+        decode_fields(r, &variant.fields, registry)?,
+    ))
+}
+
+fn decode_fields<R: Buf>(
+    r: &mut R,
+    fields: &Fields,
+    registry: &Registry,
+) -> Result<BTreeMap<u32, Value>, DecodeError> {
+    let mut values = BTreeMap::new();
+
+    if fields.kind == FieldKind::Unit {
+        return Ok(values);
+    }
+
+    let _nested = buf::enter_nested()?;
+
+    loop {
+        let id = buf::decode_id(r)?;
+        if id.value == END_MARKER {
+            break;
+        }
+
+        match fields.fields.iter().find(|field| field.id == id.value) {
+            Some(field) => {
+                let value = match &field.ty {
+                    Type::Option(ty) => {
+                        Value::Option(Some(Box::new(decode(r, ty, registry, true)?)))
+                    }
+                    ty => decode(r, ty, registry, true)?,
+                };
+                values.insert(field.id, value);
+            }
+            None => buf::decode_skip(r, id.encoding)?,
+        }
+    }
+
+    for field in &fields.fields {
+        if values.contains_key(&field.id) {
+            continue;
+        }
+
+        if matches!(field.ty, Type::Option(_)) {
+            values.insert(field.id, Value::Option(None));
+        } else {
+            return Err(buf::Error::MissingField {
+                id: field.id,
+                name: None,
+            }
+            .into());
+        }
+    }
+
+    Ok(values)
+}
+
+/// Decode a length-prefixed list of elements. The content is split off first, so the elements
+/// are decoded from a separate buffer.
+fn decode_list<R, T, D>(r: &mut R, mut decode: D) -> Result<Vec<T>, DecodeError>
+where
+    R: Buf,
+    D: FnMut(&mut Bytes) -> Result<T, DecodeError>,
+{
+    let len = buf::decode_u64(r)? as usize;
+    if r.remaining() < len {
+        return Err(buf::Error::InsufficientData.into());
+    }
+
+    let mut content = r.copy_to_bytes(len);
+    let mut values = Vec::new();
+
+    while content.has_remaining() {
+        values.push(decode(&mut content)?);
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_type() -> Type {
+        let shape = Type::Enum(vec![
+            Variant {
+                name: "Point".to_owned(),
+                id: 1,
+                fields: Fields {
+                    kind: FieldKind::Unit,
+                    fields: Vec::new(),
+                },
+            },
+            Variant {
+                name: "Circle".to_owned(),
+                id: 2,
+                fields: Fields {
+                    kind: FieldKind::Unnamed,
+                    fields: vec![Field::new("", 1, Type::F64)],
+                },
+            },
+        ]);
+
+        Type::Struct(Fields {
+            kind: FieldKind::Named,
+            fields: vec![
+                Field::new("id", 1, Type::NonZero(Box::new(Type::U32))),
+                Field::new("shapes", 2, Type::Vec(Box::new(shape))),
+                Field::new(
+                    "tags",
+                    3,
+                    Type::HashMap(Box::new((Type::String, Type::Option(Box::new(Type::I8))))),
+                ),
+                Field::new("pair", 4, Type::Tuple(vec![Type::Bool, Type::Bytes])),
+                Field::new("grid", 5, Type::Array(Box::new(Type::U16), 2)),
+                Field::new("note", 6, Type::Option(Box::new(Type::String))),
+                Field::new(
+                    "ranks",
+                    7,
+                    Type::BTreeMap(Box::new((Type::U8, Type::BTreeSet(Box::new(Type::U8))))),
+                ),
+            ],
+        })
+    }
+
+    fn sample_value() -> Value {
+        Value::Struct(BTreeMap::from([
+            (1, Value::U32(7)),
+            (
+                2,
+                Value::Vec(vec![
+                    Value::Enum(1, BTreeMap::new()),
+                    Value::Enum(2, BTreeMap::from([(1, Value::F64(1.5))])),
+                ]),
+            ),
+            (
+                3,
+                Value::HashMap(vec![(
+                    Value::String("a".to_owned()),
+                    Value::Option(Some(Box::new(Value::I8(-1)))),
+                )]),
+            ),
+            (
+                4,
+                Value::Tuple(vec![Value::Bool(true), Value::Bytes(vec![1, 2])]),
+            ),
+            (5, Value::Array(vec![Value::U16(300), Value::U16(0)])),
+            (6, Value::Option(None)),
+            (
+                7,
+                Value::BTreeMap(vec![(
+                    Value::U8(1),
+                    Value::BTreeSet(vec![Value::U8(2), Value::U8(3)]),
+                )]),
+            ),
+        ]))
+    }
+
+    /// Registry with a recursive `struct Node { children: vec<Node> @1 }`.
+    fn node_registry() -> Registry {
+        let mut registry = Registry::new();
+        registry.insert(
+            "Node",
+            Definition::Struct(Fields {
+                kind: FieldKind::Named,
+                fields: vec![Field::new(
+                    "children",
+                    1,
+                    Type::Vec(Box::new(Type::Named("Node".to_owned()))),
+                )],
+            }),
+        );
+        registry
+    }
+
+    fn node(depth: usize) -> Value {
+        let children = if depth == 0 {
+            Vec::new()
+        } else {
+            vec![node(depth - 1), node(0)]
+        };
+
+        Value::Struct(BTreeMap::from([(1, Value::Vec(children))]))
+    }
+
+    #[test]
+    fn roundtrip() {
+        let registry = Registry::new();
+        let mut buf = Vec::new();
+        encode_value(&mut buf, &sample_value(), &sample_type(), &registry).unwrap();
+
+        assert_eq!(
+            sample_value(),
+            decode_value(&mut &*buf, &sample_type(), &registry).unwrap()
+        );
+    }
+
+    #[test]
+    fn roundtrip_recursive() {
+        let registry = node_registry();
+        let ty = Type::Named("Node".to_owned());
+        let mut buf = Vec::new();
+        encode_value(&mut buf, &node(3), &ty, &registry).unwrap();
+
+        assert_eq!(node(3), decode_value(&mut &*buf, &ty, &registry).unwrap());
+    }
+
+    #[test]
+    fn decode_depth_limit() {
+        let registry = node_registry();
+        let ty = Type::Named("Node".to_owned());
+        let mut buf = Vec::new();
+        encode_value(&mut buf, &node(3), &ty, &registry).unwrap();
+
+        let options = buf::DecodeOptions {
+            max_depth: 3,
+            ..buf::DecodeOptions::default()
+        };
+
+        assert!(matches!(
+            options.run(|| decode_value(&mut &*buf, &ty, &registry)),
+            Err(DecodeError::Buf(buf::Error::DepthLimit { max: 3 })),
+        ));
+    }
+
+    #[test]
+    fn unknown_type() {
+        let ty = Type::Named("Missing".to_owned());
+
+        assert!(matches!(
+            encode_value(&mut Vec::new(), &node(0), &ty, &Registry::new()),
+            Err(EncodeError::UnknownType(name)) if name == "Missing",
+        ));
+        assert!(matches!(
+            decode_value(&mut &[0][..], &ty, &Registry::new()),
+            Err(DecodeError::UnknownType(name)) if name == "Missing",
+        ));
+    }
+
+    #[test]
+    fn encode_mismatch() {
+        let ty = Type::Struct(Fields {
+            kind: FieldKind::Named,
+            fields: vec![Field::new("id", 1, Type::U32)],
+        });
+
+        assert!(matches!(
+            encode_value(
+                &mut Vec::new(),
+                &Value::Struct(BTreeMap::new()),
+                &ty,
+                &Registry::new()
+            ),
+            Err(EncodeError::MissingField(1)),
+        ));
+        assert!(matches!(
+            encode_value(
+                &mut Vec::new(),
+                &Value::Struct(BTreeMap::from([(1, Value::I32(1))])),
+                &ty,
+                &Registry::new()
+            ),
+            Err(EncodeError::Mismatch {
+                expected: "u32",
+                found: "i32",
+            }),
+        ));
+        assert!(matches!(
+            encode_value(
+                &mut Vec::new(),
+                &Value::U8(0),
+                &Type::NonZero(Box::new(Type::U8)),
+                &Registry::new()
+            ),
+            Err(EncodeError::Zero),
+        ));
+    }
+
+    #[test]
+    fn decode_missing_field() {
+        let mut buf = Vec::new();
+        buf::encode_u32(&mut buf, END_MARKER);
+
+        assert!(matches!(
+            decode_value(&mut &*buf, &sample_type(), &Registry::new()),
+            Err(DecodeError::Buf(buf::Error::MissingField {
+                id: 1,
+                name: None
+            })),
+        ));
+    }
+}
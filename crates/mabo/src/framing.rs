@@ -0,0 +1,211 @@
+//! Encoder and decoder pair for streams of length-delimited messages.
+//!
+//! Each message is prefixed with its byte length as _Varint_, in the same [frame](crate::io)
+//! format that the reader and writer helpers use. Compared to these, the [`Codec`] works on
+//! buffers that the caller fills and drains, and keeps the limits for the frame size and decoding
+//! together. It shares the frame handling and the [`FrameError`] with the [`io`](crate::io)
+//! module, so both check the frame size the same way.
+//!
+//! With the `tokio-util` feature, the codec also implements `tokio_util`'s `Encoder` and
+//! `Decoder` traits, to be used with `Framed` streams and sinks.
+
+use std::{fmt, marker::PhantomData};
+
+use bytes::{Buf, BufMut, BytesMut};
+
+use crate::{
+    buf::{Decode, DecodeOptions, Encode},
+    io::{self, FrameError},
+};
+
+/// Encoder and decoder for length-delimited frames of values of type `T`.
+pub struct Codec<T> {
+    max_frame_size: usize,
+    decode_options: DecodeOptions,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Codec<T> {
+    /// Default maximum byte size of a frame's content (8 MiB).
+    pub const DEFAULT_MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+    /// Create a new codec, that accepts frames up to the [default](Self::DEFAULT_MAX_FRAME_SIZE)
+    /// size.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            max_frame_size: Self::DEFAULT_MAX_FRAME_SIZE,
+            decode_options: DecodeOptions::UNLIMITED,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Set the maximum byte size of a frame's content, excluding the length prefix.
+    ///
+    /// Larger frames are rejected while en- and decoding, so a peer can't make the decoder wait
+    /// for (and buffer) an arbitrary amount of data.
+    #[must_use]
+    pub const fn with_max_frame_size(mut self, size: usize) -> Self {
+        self.max_frame_size = size;
+        self
+    }
+
+    /// Set the limits that apply while decoding the content of each frame.
+    #[must_use]
+    pub const fn with_decode_options(mut self, options: DecodeOptions) -> Self {
+        self.decode_options = options;
+        self
+    }
+
+    /// Get the maximum byte size of a frame's content.
+    #[must_use]
+    pub const fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+}
+
+impl<T: Encode> Codec<T> {
+    /// Encode the value as frame into the buffer.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the encoded value exceeds the maximum frame size.
+    pub fn encode(&self, value: &T, dst: &mut impl BufMut) -> Result<(), FrameError> {
+        io::encode_frame_within(dst, value, self.max_frame_size)
+    }
+}
+
+impl<T: Decode> Codec<T> {
+    /// Decode the next frame from the buffer, and advance it past the frame.
+    ///
+    /// Returns `None` if the buffer doesn't hold a complete frame yet. In that case, the buffer is
+    /// left as is, but reserves space for the rest of the frame.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the frame exceeds the maximum frame size, or its content fails to
+    /// decode within the [decode options](Self::with_decode_options).
+    pub fn decode(&self, src: &mut BytesMut) -> Result<Option<T>, FrameError> {
+        let Some((len, prefix)) = io::frame_len(src, self.max_frame_size)? else {
+            return Ok(None);
+        };
+
+        if src.len() < prefix + len {
+            src.reserve(prefix + len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(prefix);
+        let content = src.split_to(len);
+
+        Ok(Some(self.decode_options.decode(&mut &*content)?))
+    }
+}
+
+impl<T> Clone for Codec<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Codec<T> {}
+
+impl<T> Default for Codec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for Codec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Codec")
+            .field("max_frame_size", &self.max_frame_size)
+            .field("decode_options", &self.decode_options)
+            .finish()
+    }
+}
+
+#[cfg(feature = "tokio-util")]
+impl<T: Encode> tokio_util::codec::Encoder<T> for Codec<T> {
+    type Error = FrameError;
+
+    fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        dst.reserve(io::size_frame(&item));
+        Codec::encode(self, &item, dst)
+    }
+}
+
+#[cfg(feature = "tokio-util")]
+impl<T: Decode> tokio_util::codec::Decoder for Codec<T> {
+    type Error = FrameError;
+    type Item = T;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        Codec::decode(self, src)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let codec = Codec::<String>::new();
+        let mut buf = BytesMut::new();
+        codec.encode(&"a".to_owned(), &mut buf).unwrap();
+        codec.encode(&"bc".to_owned(), &mut buf).unwrap();
+
+        assert_eq!(Some("a".to_owned()), codec.decode(&mut buf).unwrap());
+        assert_eq!(Some("bc".to_owned()), codec.decode(&mut buf).unwrap());
+        assert_eq!(None, codec.decode(&mut buf).unwrap());
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn partial() {
+        let codec = Codec::<String>::new();
+        let mut full = BytesMut::new();
+        codec.encode(&"test".to_owned(), &mut full).unwrap();
+
+        let mut buf = BytesMut::new();
+        for &b in &full[..full.len() - 1] {
+            buf.put_u8(b);
+            assert_eq!(None, codec.decode(&mut buf).unwrap());
+        }
+
+        buf.put_u8(full[full.len() - 1]);
+        assert_eq!(Some("test".to_owned()), codec.decode(&mut buf).unwrap());
+    }
+
+    #[test]
+    fn too_large() {
+        let codec = Codec::<String>::new().with_max_frame_size(4);
+        let mut buf = BytesMut::new();
+
+        assert!(matches!(
+            codec.encode(&"hello".to_owned(), &mut buf),
+            Err(FrameError::FrameTooLarge { size: 6, max: 4 }),
+        ));
+
+        Codec::<String>::new()
+            .encode(&"hello".to_owned(), &mut buf)
+            .unwrap();
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(FrameError::FrameTooLarge { size: 6, max: 4 }),
+        ));
+    }
+
+    #[cfg(feature = "tokio-util")]
+    #[test]
+    fn tokio_codec() {
+        use tokio_util::codec::{Decoder, Encoder};
+
+        let mut codec = Codec::<u32>::new();
+        let mut buf = BytesMut::new();
+        Encoder::encode(&mut codec, 300, &mut buf).unwrap();
+
+        assert_eq!(Some(300), Decoder::decode(&mut codec, &mut buf).unwrap());
+    }
+}
@@ -0,0 +1,350 @@
+//! Streaming en- and decoding of length-delimited messages, for payloads that arrive
+//! incrementally from sockets, files or other readers.
+//!
+//! Each message is written as a _frame_, which is the encoded value prefixed with its byte length
+//! as _Varint_ (the same way as Mabo `bytes` values are encoded). Only once a frame is fully
+//! available, its value is decoded.
+
+use std::io::{self, Read, Write};
+
+use bytes::BufMut;
+
+use crate::{
+    buf::{self, Decode, Encode, Size},
+    varint,
+};
+
+/// Error that can happen while reading or decoding frames, and while encoding them through the
+/// [`Codec`](crate::framing::Codec).
+#[derive(Debug, thiserror::Error)]
+pub enum FrameError {
+    /// Reading from the underlying source, or writing to the underlying sink failed, including
+    /// when the source ended within a frame.
+    #[error("failed reading or writing the frame")]
+    Io(#[from] io::Error),
+    /// The frame has more content than the maximum frame size allows.
+    #[error("frame of {size} bytes exceeds the maximum of {max} bytes")]
+    FrameTooLarge {
+        /// Byte size of the frame content.
+        size: usize,
+        /// Maximum allowed byte size.
+        max: usize,
+    },
+    /// The frame was read completely, but its content failed to decode.
+    #[error("failed decoding the frame content")]
+    Decode(#[from] buf::Error),
+}
+
+/// Outcome of trying to decode a frame from a buffer, that might only hold parts of it so far.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Frame<T> {
+    /// The frame was complete and its value decoded.
+    Complete {
+        /// The decoded value.
+        value: T,
+        /// Byte size of the whole frame, including the length prefix, to advance the buffer by.
+        size: usize,
+    },
+    /// The buffer doesn't hold the whole frame yet. Contains the least amount of additional
+    /// bytes that are required before trying again.
+    NeedMoreData(usize),
+}
+
+/// Encode a value as frame, prefixed with its length.
+pub fn encode_frame<T: Encode>(w: &mut impl BufMut, value: &T) {
+    encode_sized(w, value, value.size());
+}
+
+/// Encode a value as frame, but only if its content is within the maximum frame size.
+pub(crate) fn encode_frame_within<T: Encode>(
+    w: &mut impl BufMut,
+    value: &T,
+    max_size: usize,
+) -> Result<(), FrameError> {
+    let size = value.size();
+    check_size(size, max_size)?;
+    encode_sized(w, value, size);
+    Ok(())
+}
+
+fn encode_sized<T: Encode>(w: &mut impl BufMut, value: &T, size: usize) {
+    buf::encode_u64(w, size as u64);
+    value.encode(w);
+}
+
+/// Calculate the size of a value encoded as frame, including the length prefix.
+pub fn size_frame<T: Size>(value: &T) -> usize {
+    let size = value.size();
+    buf::size_u64(size as u64) + size
+}
+
+/// Try to decode a frame from the start of the buffer.
+///
+/// Unlike the regular decoding through [`Decode`], a partial frame is no error, but reported as
+/// [`Frame::NeedMoreData`], so the caller can fill up the buffer and try again. Frames with more
+/// than `max_size` bytes of content are rejected as soon as the length prefix is complete, so a
+/// peer can't make the caller buffer an arbitrary amount of data.
+///
+/// # Errors
+///
+/// Will return `Err` if the length prefix is invalid, the frame exceeds the maximum size, or the
+/// frame content fails to decode. An error of the [kind](buf::Error::kind)
+/// [`buf::Error::InsufficientData`] means, that the content is shorter than the value requires, as
+/// the frame itself is complete at that point.
+pub fn decode_frame<T: Decode>(buf: &[u8], max_size: usize) -> Result<Frame<T>, FrameError> {
+    let Some((len, prefix)) = frame_len(buf, max_size)? else {
+        return Ok(Frame::NeedMoreData(1));
+    };
+
+    let size = prefix.saturating_add(len);
+    if buf.len() < size {
+        return Ok(Frame::NeedMoreData(size - buf.len()));
+    }
+
+    Ok(Frame::Complete {
+        value: T::decode(&mut &buf[prefix..size])?,
+        size,
+    })
+}
+
+/// Decode the length prefix of a frame, returning the content length and the size of the prefix
+/// itself, once the prefix is complete. The content length must be within the maximum frame size.
+pub(crate) fn frame_len(buf: &[u8], max_size: usize) -> Result<Option<(usize, usize)>, FrameError> {
+    let (len, prefix) = match varint::decode_u64(buf) {
+        Ok(decoded) => decoded,
+        Err(_) if buf.len() < varint::size_u64(u64::MAX) => return Ok(None),
+        Err(e) => return Err(buf::Error::from(e).into()),
+    };
+
+    let len = usize::try_from(len).unwrap_or(usize::MAX);
+    check_size(len, max_size)?;
+    Ok(Some((len, prefix)))
+}
+
+/// Read a single frame from the reader and decode its value.
+///
+/// Returns `None` if the reader ended cleanly before the start of another frame. Frames with more
+/// than `max_size` bytes of content are rejected before reading them, so a peer can't make the
+/// reader buffer an arbitrary amount of data. The [`Codec`](crate::framing::Codec) uses
+/// [`DEFAULT_MAX_FRAME_SIZE`](crate::framing::Codec::DEFAULT_MAX_FRAME_SIZE) for the same purpose.
+///
+/// The length prefix is read one byte at a time, to never consume any bytes of the next frame.
+/// For readers where each read is costly, like files or sockets, wrap them in a
+/// [`BufReader`](std::io::BufReader) first.
+///
+/// # Errors
+///
+/// Will return `Err` if reading fails, the reader ends within a frame, the frame exceeds the
+/// maximum size, or the frame content fails to decode.
+pub fn read_frame<T: Decode>(r: &mut impl Read, max_size: usize) -> Result<Option<T>, FrameError> {
+    let mut prefix = [0; varint::size_u64(u64::MAX)];
+
+    for i in 0..prefix.len() {
+        if r.read(&mut prefix[i..=i])? == 0 {
+            return end_of_source(i);
+        }
+
+        if let Some((len, _)) = frame_len(&prefix[..=i], max_size)? {
+            let mut content = Vec::new();
+            r.take(len as u64).read_to_end(&mut content)?;
+            return decode_content(&content, len).map(Some);
+        }
+    }
+
+    Err(buf::Error::DecodeInt(varint::DecodeIntError).into())
+}
+
+/// Handle the end of the reader after reading `read` bytes of a length prefix, which is only
+/// clean if it happened right before the start of a frame.
+fn end_of_source<T>(read: usize) -> Result<Option<T>, FrameError> {
+    if read == 0 {
+        Ok(None)
+    } else {
+        Err(eof().into())
+    }
+}
+
+/// Ensure the content length of a frame is within the maximum frame size.
+fn check_size(size: usize, max_size: usize) -> Result<(), FrameError> {
+    if size > max_size {
+        return Err(FrameError::FrameTooLarge {
+            size,
+            max: max_size,
+        });
+    }
+
+    Ok(())
+}
+
+/// Decode the content of a frame, that was read up to the announced length. The content is
+/// never allocated upfront, so it can only be shorter if the reader ended early.
+fn decode_content<T: Decode>(content: &[u8], len: usize) -> Result<T, FrameError> {
+    if content.len() < len {
+        return Err(eof().into());
+    }
+
+    T::decode(&mut &*content).map_err(Into::into)
+}
+
+/// Encode a value as frame and write it to the writer.
+///
+/// # Errors
+///
+/// Will return `Err` if writing to the writer fails.
+pub fn write_frame<T: Encode>(w: &mut impl Write, value: &T) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(size_frame(value));
+    encode_frame(&mut buf, value);
+    w.write_all(&buf)
+}
+
+/// Read a single frame from the asynchronous reader and decode its value.
+///
+/// This behaves the same as [`read_frame`], but for `tokio`'s readers. Likewise, wrap readers
+/// where each read is costly in a `tokio::io::BufReader` first.
+///
+/// # Errors
+///
+/// Will return `Err` if reading fails, the reader ends within a frame, the frame exceeds the
+/// maximum size, or the frame content fails to decode.
+#[cfg(feature = "tokio")]
+pub async fn read_frame_async<T: Decode>(
+    r: &mut (impl tokio::io::AsyncRead + Unpin),
+    max_size: usize,
+) -> Result<Option<T>, FrameError> {
+    use tokio::io::AsyncReadExt;
+
+    let mut prefix = [0; varint::size_u64(u64::MAX)];
+
+    for i in 0..prefix.len() {
+        if r.read(&mut prefix[i..=i]).await? == 0 {
+            return end_of_source(i);
+        }
+
+        if let Some((len, _)) = frame_len(&prefix[..=i], max_size)? {
+            let mut content = Vec::new();
+            r.take(len as u64).read_to_end(&mut content).await?;
+            return decode_content(&content, len).map(Some);
+        }
+    }
+
+    Err(buf::Error::DecodeInt(varint::DecodeIntError).into())
+}
+
+/// Encode a value as frame and write it to the asynchronous writer.
+///
+/// # Errors
+///
+/// Will return `Err` if writing to the writer fails.
+#[cfg(feature = "tokio")]
+pub async fn write_frame_async<T: Encode>(
+    w: &mut (impl tokio::io::AsyncWrite + Unpin),
+    value: &T,
+) -> io::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut buf = Vec::with_capacity(size_frame(value));
+    encode_frame(&mut buf, value);
+    w.write_all(&buf).await
+}
+
+fn eof() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "reader ended within a frame")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_partial() {
+        let mut buf = Vec::new();
+        encode_frame(&mut buf, &String::from("test"));
+        assert_eq!(buf.len(), size_frame(&String::from("test")));
+
+        assert_eq!(
+            Frame::NeedMoreData(1),
+            decode_frame::<String>(&[], 8).unwrap()
+        );
+        assert_eq!(
+            Frame::NeedMoreData(2),
+            decode_frame::<String>(&buf[..buf.len() - 2], 8).unwrap()
+        );
+        assert_eq!(
+            Frame::Complete {
+                value: "test".to_owned(),
+                size: buf.len()
+            },
+            decode_frame::<String>(&buf, 8).unwrap()
+        );
+    }
+
+    #[test]
+    fn decode_too_large() {
+        let mut buf = Vec::new();
+        encode_frame(&mut buf, &String::from("test"));
+
+        assert!(matches!(
+            decode_frame::<String>(&buf[..1], 4),
+            Err(FrameError::FrameTooLarge { size: 5, max: 4 }),
+        ));
+        assert!(matches!(
+            decode_frame::<String>(&buf, 5),
+            Ok(Frame::Complete { .. }),
+        ));
+    }
+
+    #[test]
+    fn read_multiple() {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, &1_u32).unwrap();
+        write_frame(&mut buf, &300_u32).unwrap();
+
+        let mut r = &*buf;
+        assert_eq!(Some(1), read_frame::<u32>(&mut r, 8).unwrap());
+        assert_eq!(Some(300), read_frame::<u32>(&mut r, 8).unwrap());
+        assert_eq!(None, read_frame::<u32>(&mut r, 8).unwrap());
+    }
+
+    #[test]
+    fn read_truncated() {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, &String::from("test")).unwrap();
+        buf.pop();
+
+        assert!(matches!(
+            read_frame::<String>(&mut &*buf, 8),
+            Err(FrameError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof,
+        ));
+    }
+
+    #[test]
+    fn read_too_large() {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, &String::from("test")).unwrap();
+
+        assert!(matches!(
+            read_frame::<String>(&mut &*buf, 4),
+            Err(FrameError::FrameTooLarge { size: 5, max: 4 }),
+        ));
+        assert_eq!(
+            Some("test".to_owned()),
+            read_frame::<String>(&mut &*buf, 5).unwrap()
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn read_async() {
+        let mut buf = Vec::new();
+        write_frame_async(&mut buf, &String::from("test"))
+            .await
+            .unwrap();
+
+        let mut r = &*buf;
+        assert_eq!(
+            Some("test".to_owned()),
+            read_frame_async::<String>(&mut r, 8).await.unwrap()
+        );
+        assert_eq!(None, read_frame_async::<String>(&mut r, 8).await.unwrap());
+    }
+}
@@ -1,5 +1,11 @@
 //! Runtime support crate for the Mabo encoding format.
+//!
+//! The crate works without the standard library, by disabling the default `std` feature. Only the
+//! `alloc` crate is required then, and the `hashbrown` feature must be enabled to provide the
+//! hash map and set types in [`collections`]. Everything that depends on I/O (like the `io` and
+//! `framing` modules) is only available with `std`.
 
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(
     clippy::cast_possible_truncation,
     clippy::implicit_hasher,
@@ -7,21 +13,58 @@
     clippy::module_name_repetitions
 )]
 
-use std::{
-    collections::{HashMap, HashSet},
-    ops::Deref,
+extern crate alloc;
+
+#[cfg(not(any(feature = "std", feature = "hashbrown")))]
+compile_error!("either the `std` or the `hashbrown` feature must be enabled");
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    vec::Vec,
 };
+use core::ops::Deref;
 
-pub use buf::{Buf, BufMut, Bytes, Decode, Encode};
+pub use buf::{Buf, BufMut, Bytes, Decode, DecodeBorrowed, Encode};
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+use collections::{HashMap, HashSet};
+#[cfg(feature = "macros")]
+pub use mabo_macros::{schema, Decode, Encode, Schema};
+/// Re-export of the `serde` crate, which generated types refer to for their `Serialize` and
+/// `Deserialize` implementations.
+#[cfg(feature = "serde")]
+pub use serde;
+pub use validate::{Validate, ValidationError};
 
 pub mod buf;
+#[cfg(feature = "std")]
+pub mod dynamic;
+#[cfg(feature = "std")]
+pub mod framing;
+#[cfg(feature = "std")]
+pub mod io;
+#[cfg(feature = "serde")]
+mod serde_impls;
+pub mod validate;
 pub mod varint;
 
+/// Hash map and set types, that Mabo's `hash_map<K, V>` and `hash_set<T>` are represented as.
+///
+/// These are the types from the standard library, or the ones from the `hashbrown` crate when
+/// the `std` feature is disabled.
+pub mod collections {
+    #[cfg(feature = "std")]
+    pub use std::collections::{HashMap, HashSet};
+
+    #[cfg(all(not(feature = "std"), feature = "hashbrown"))]
+    pub use hashbrown::{HashMap, HashSet};
+}
+
 /// Identifier for a single struct or enum variant field.
 ///
 /// This type contains the actual identifier, plus additional information that is encoded together
 /// with it. It allows for convenient en- and decoding of the information.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct FieldId {
     /// The real decoded field identifier.
     pub value: u32,
@@ -59,7 +102,7 @@ impl FieldId {
 }
 
 /// Minimum detail about how a field is encoded, which allows to skip over a field if it's unknown.
-#[derive(Clone, Copy, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 #[repr(u32)]
 pub enum FieldEncoding {
     /// Variable-length integer.
@@ -115,19 +158,32 @@ impl VariantId {
 /// `OUT_DIR` environment variable. The `mabo-build` crate additional puts all generated files into
 /// a `mabo/` sub-folder instead of placing the files at the root.
 ///
-/// The final file name is derived from the schema file name as it is the same, except for the
-/// different file extension.
+/// Without any argument, the macro includes the entry file `mod.rs`, which brings in all schemas
+/// of the project at once. It contains a module tree that mirrors the directory layout of the
+/// project, with a module for each schema file. This is the preferred way, as imports between
+/// schemas are resolved through this module tree.
+///
+/// Alternatively, a single schema can be included by its path relative to the project, without
+/// the file extension.
 ///
 /// # Example
 ///
-/// Assuming a schema file named `sample.mabo` that is being compiled the `mabo-build` crate, the
-/// final output destination would be:
+/// Assuming a project with the schema files `src/sample.mabo` and `schemas/other.mabo`, that are
+/// being compiled by the `mabo-build` crate, the final output destinations would be:
 ///
 /// ```txt
-/// $OUT_DIR/mabo/sample.rs
+/// $OUT_DIR/mabo/mod.rs
+/// $OUT_DIR/mabo/src/sample.rs
+/// $OUT_DIR/mabo/schemas/other.rs
 /// ```
+///
+/// Using `mabo::include!()` then provides the modules `src::sample` and `schemas::other`, while
+/// `mabo::include!("src/sample")` only includes the content of the first schema.
 #[macro_export]
 macro_rules! include {
+    () => {
+        include!(concat!(env!("OUT_DIR"), "/mabo/mod.rs"));
+    };
     ($name:literal) => {
         include!(concat!(env!("OUT_DIR"), "/mabo/", $name, ".rs"));
     };
@@ -139,7 +195,7 @@ macro_rules! include {
 /// as value that are not literally `0` (but those are handled by Rust's built-in `NonZeroN` types
 /// anyway), and collections define this as not being empty, meaning to always contain at least one
 /// element. Similarly for strings, it means they always contain at least one character.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct NonZero<T>(T);
 
 impl<T> NonZero<T> {
@@ -182,8 +238,12 @@ macro_rules! non_zero_collection {
 non_zero_collection!(String);
 non_zero_collection!(Vec<T>);
 non_zero_collection!(Bytes);
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 non_zero_collection!(HashMap<K, V>);
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 non_zero_collection!(HashSet<T>);
+non_zero_collection!(BTreeMap<K, V>);
+non_zero_collection!(BTreeSet<T>);
 
 /// String (Mabo's `non_zero<string>`) that is guaranteed to not be empty.
 pub type NonZeroString = NonZero<String>;
@@ -192,6 +252,74 @@ pub type NonZeroBytes = NonZero<Vec<u8>>;
 /// Vector of `T` (Mabo's `non_zero<vec<T>>`) that is guaranteed to not be empty.
 pub type NonZeroVec<T> = NonZero<Vec<T>>;
 /// Hash map (Mabo's `non_zero<hash_map<K ,V>>`) that is guaranteed to not be empty.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 pub type NonZeroHashMap<K, V> = NonZero<HashMap<K, V>>;
 /// Hash set (Mabo's `non_zero<hash_set<T>>`) that is guaranteed to not be empty.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
 pub type NonZeroHashSet<T> = NonZero<HashSet<T>>;
+/// Sorted map (Mabo's `non_zero<btree_map<K, V>>`) that is guaranteed to not be empty.
+pub type NonZeroBTreeMap<K, V> = NonZero<BTreeMap<K, V>>;
+/// Sorted set (Mabo's `non_zero<btree_set<T>>`) that is guaranteed to not be empty.
+pub type NonZeroBTreeSet<T> = NonZero<BTreeSet<T>>;
+
+/// Types that can describe themselves in the Mabo schema language.
+///
+/// This is usually implemented through the `Schema` derive macro (with the `macros` feature), for
+/// types that are defined in Rust first. It allows to share the equivalent schema with other
+/// languages, to generate compatible code from it.
+///
+/// ```
+/// use mabo::Schema;
+///
+/// struct Sample;
+///
+/// impl Schema for Sample {
+///     fn schema() -> &'static str {
+///         "struct Sample"
+///     }
+/// }
+///
+/// // Combine the definitions of all types into a single schema file.
+/// let schema = [Sample::schema()].join("\n\n");
+/// # assert_eq!("struct Sample", schema);
+/// ```
+pub trait Schema {
+    /// Definition of the type, as it would be written in a `.mabo` schema file.
+    fn schema() -> &'static str;
+}
+
+/// Fields of a struct that are not part of the schema, but were found in the decoded payload.
+///
+/// Instead of skipping over these, the fields are retained in their raw encoded form, so they can
+/// be written back out again when encoding the struct. This allows services that work with an
+/// older version of a schema to pass on data from newer versions without losing any of it.
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct UnknownFields(Vec<(FieldId, Vec<u8>)>);
+
+impl UnknownFields {
+    /// Whether no unknown fields were retained.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Number of retained unknown fields.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Iterate over the identifiers and raw encoded values of all retained fields.
+    pub fn iter(&self) -> impl Iterator<Item = (FieldId, &[u8])> {
+        self.0.iter().map(|(id, data)| (*id, data.as_slice()))
+    }
+
+    /// Remove all retained unknown fields.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub(crate) fn push(&mut self, id: FieldId, data: Vec<u8>) {
+        self.0.push((id, data));
+    }
+}
@@ -0,0 +1,106 @@
+//! Support for [`serde`], to expose the runtime types that generated code uses through other
+//! formats like JSON.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    hash::Hash,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{Bytes, NonZero};
+
+impl<T: Serialize> Serialize for NonZero<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+/// Deserialize the inner value, and reject it if it turns out to be empty.
+fn non_zero<'de, D, T>(
+    deserializer: D,
+    new: impl FnOnce(T) -> Option<NonZero<T>>,
+) -> Result<NonZero<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    new(T::deserialize(deserializer)?)
+        .ok_or_else(|| de::Error::invalid_length(0, &"at least one element"))
+}
+
+impl<'de> Deserialize<'de> for NonZero<String> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        non_zero(deserializer, Self::new)
+    }
+}
+
+impl<'de> Deserialize<'de> for NonZero<Bytes> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        non_zero(deserializer, Self::new)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for NonZero<Vec<T>> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        non_zero(deserializer, Self::new)
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for NonZero<HashMap<K, V>>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        non_zero(deserializer, Self::new)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for NonZero<HashSet<T>>
+where
+    T: Deserialize<'de> + Eq + Hash,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        non_zero(deserializer, Self::new)
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for NonZero<BTreeMap<K, V>>
+where
+    K: Deserialize<'de> + Ord,
+    V: Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        non_zero(deserializer, Self::new)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for NonZero<BTreeSet<T>>
+where
+    T: Deserialize<'de> + Ord,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        non_zero(deserializer, Self::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_zero_roundtrip() {
+        let value = NonZero::<Vec<u32>>::new(vec![1, 2, 3]).unwrap();
+        let json = serde_json::to_string(&value).unwrap();
+
+        assert_eq!("[1,2,3]", json);
+        assert_eq!(value, serde_json::from_str(&json).unwrap());
+    }
+
+    #[test]
+    fn non_zero_empty() {
+        assert!(serde_json::from_str::<NonZero<String>>("\"\"").is_err());
+        assert!(serde_json::from_str::<NonZero<Vec<u32>>>("[]").is_err());
+    }
+}
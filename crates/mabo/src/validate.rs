@@ -0,0 +1,547 @@
+//! Checks for the constraints that a schema declares on field values, through the
+//! `#[validate(...)]` attribute.
+//!
+//! The generated code implements the [`Validate`] trait for every struct and enum that has rules,
+//! either its own or the ones of nested types. It checks the declared rules of each field, and
+//! descends into nested types. The functions in this module are the building blocks for these
+//! implementations.
+//!
+//! ```
+//! use mabo::validate::{self, Validate, ValidationError, Violation};
+//!
+//! struct Sample {
+//!     age: u8,
+//!     name: String,
+//! }
+//!
+//! impl Validate for Sample {
+//!     fn validate(&self) -> Result<(), ValidationError> {
+//!         validate::min("age", self.age, 1)?;
+//!         validate::max("age", self.age, 100)?;
+//!         validate::max_len("name", self.name.len(), 5)?;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let sample = Sample {
+//!     age: 0,
+//!     name: "Mabo".to_owned(),
+//! };
+//! let err = sample.validate().unwrap_err();
+//!
+//! assert_eq!(vec!["age"], err.path);
+//! assert!(matches!(err.violation, Violation::Min(_)));
+//! ```
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    vec,
+    vec::Vec,
+};
+use core::{
+    fmt::{self, Display},
+    num::{
+        NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroU128, NonZeroU16,
+        NonZeroU32, NonZeroU64, NonZeroU8,
+    },
+};
+
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+use crate::collections::{HashMap, HashSet};
+use crate::{Bytes, NonZero};
+
+/// Values that can be checked against the constraints declared in the schema.
+pub trait Validate {
+    /// Check the value and all the values it contains against their declared constraints.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` for the first value that violates one of its constraints.
+    fn validate(&self) -> Result<(), ValidationError>;
+}
+
+/// Error that describes which field of a value violates which of its constraints.
+#[derive(Debug, PartialEq)]
+pub struct ValidationError {
+    /// Names of the fields that lead to the invalid value, from the outermost to the innermost
+    /// one. Enum variants are part of the path as well, and unnamed fields are named by their
+    /// position.
+    pub path: Vec<&'static str>,
+    /// The constraint that was violated.
+    pub violation: Violation,
+}
+
+impl ValidationError {
+    /// Create a new error for a single field of the value that is currently validated.
+    #[must_use]
+    pub fn new(field: &'static str, violation: Violation) -> Self {
+        Self {
+            path: vec![field],
+            violation,
+        }
+    }
+
+    /// Prepend the name of a field to the path, when the error comes from a nested value.
+    #[must_use]
+    pub fn within(mut self, field: &'static str) -> Self {
+        self.path.insert(0, field);
+        self
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("field `")?;
+        for (i, field) in self.path.iter().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            f.write_str(field)?;
+        }
+        write!(f, "` is invalid: {}", self.violation)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ValidationError {}
+
+/// Single constraint of the `#[validate(...)]` attribute, that a value failed to fulfill.
+#[derive(Debug, PartialEq)]
+pub enum Violation {
+    /// The number is smaller than the `min` value.
+    Min(Bound),
+    /// The number is larger than the `max` value.
+    Max(Bound),
+    /// The string or byte array is shorter than `min_len` bytes.
+    MinLen(u64),
+    /// The string or byte array is longer than `max_len` bytes.
+    MaxLen(u64),
+    /// The string doesn't match the regular expression of `pattern`.
+    Pattern(&'static str),
+    /// The collection contains less than `min_items` elements.
+    MinItems(u64),
+    /// The collection contains more than `max_items` elements.
+    MaxItems(u64),
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Min(bound) => write!(f, "value is smaller than the minimum of {bound}"),
+            Self::Max(bound) => write!(f, "value is larger than the maximum of {bound}"),
+            Self::MinLen(len) => write!(f, "length is shorter than the minimum of {len} bytes"),
+            Self::MaxLen(len) => write!(f, "length is longer than the maximum of {len} bytes"),
+            Self::Pattern(pattern) => write!(f, "value doesn't match the pattern `{pattern}`"),
+            Self::MinItems(items) => {
+                write!(f, "collection has less than the minimum of {items} items")
+            }
+            Self::MaxItems(items) => {
+                write!(f, "collection has more than the maximum of {items} items")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Violation {}
+
+/// Lower or upper limit of a number, as declared in the schema.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bound {
+    /// Limit of an integer.
+    Int(i128),
+    /// Limit of a floating point number.
+    Float(f64),
+}
+
+impl Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(value) => value.fmt(f),
+            Self::Float(value) => value.fmt(f),
+        }
+    }
+}
+
+macro_rules! bound_int {
+    ($($ty:ty),+ $(,)?) => {
+        $(
+            impl From<$ty> for Bound {
+                #[allow(clippy::cast_possible_wrap, clippy::cast_lossless)]
+                fn from(value: $ty) -> Self {
+                    // Limits come from `i128` literals of the schema, so even `u128` values fit.
+                    Self::Int(value as i128)
+                }
+            }
+        )+
+    };
+}
+
+bound_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl From<f32> for Bound {
+    fn from(value: f32) -> Self {
+        Self::Float(value.into())
+    }
+}
+
+impl From<f64> for Bound {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+/// Ensure a number is not smaller than the `min` limit.
+///
+/// # Errors
+///
+/// Will return `Err` if the value is smaller than the limit.
+pub fn min<T>(field: &'static str, value: T, min: T) -> Result<(), ValidationError>
+where
+    T: Copy + PartialOrd + Into<Bound>,
+{
+    if value < min {
+        return Err(ValidationError::new(field, Violation::Min(min.into())));
+    }
+
+    Ok(())
+}
+
+/// Ensure a number is not larger than the `max` limit.
+///
+/// # Errors
+///
+/// Will return `Err` if the value is larger than the limit.
+pub fn max<T>(field: &'static str, value: T, max: T) -> Result<(), ValidationError>
+where
+    T: Copy + PartialOrd + Into<Bound>,
+{
+    if value > max {
+        return Err(ValidationError::new(field, Violation::Max(max.into())));
+    }
+
+    Ok(())
+}
+
+/// Ensure the byte length of a string or byte array is at least `min`.
+///
+/// # Errors
+///
+/// Will return `Err` if the value is shorter than the limit.
+pub fn min_len(field: &'static str, len: usize, min: u64) -> Result<(), ValidationError> {
+    if (len as u64) < min {
+        return Err(ValidationError::new(field, Violation::MinLen(min)));
+    }
+
+    Ok(())
+}
+
+/// Ensure the byte length of a string or byte array is at most `max`.
+///
+/// # Errors
+///
+/// Will return `Err` if the value is longer than the limit.
+pub fn max_len(field: &'static str, len: usize, max: u64) -> Result<(), ValidationError> {
+    if len as u64 > max {
+        return Err(ValidationError::new(field, Violation::MaxLen(max)));
+    }
+
+    Ok(())
+}
+
+/// Ensure a collection contains at least `min` elements.
+///
+/// # Errors
+///
+/// Will return `Err` if the collection has less elements than the limit.
+pub fn min_items(field: &'static str, len: usize, min: u64) -> Result<(), ValidationError> {
+    if (len as u64) < min {
+        return Err(ValidationError::new(field, Violation::MinItems(min)));
+    }
+
+    Ok(())
+}
+
+/// Ensure a collection contains at most `max` elements.
+///
+/// # Errors
+///
+/// Will return `Err` if the collection has more elements than the limit.
+pub fn max_items(field: &'static str, len: usize, max: u64) -> Result<(), ValidationError> {
+    if len as u64 > max {
+        return Err(ValidationError::new(field, Violation::MaxItems(max)));
+    }
+
+    Ok(())
+}
+
+/// Regular expression of a `pattern` rule, that is compiled on first use and then kept for all
+/// later checks.
+///
+/// Requires the `regex` feature, which must be enabled for schemas that make use of the rule.
+#[cfg(feature = "regex")]
+pub struct Pattern {
+    source: &'static str,
+    regex: std::sync::OnceLock<regex::Regex>,
+}
+
+#[cfg(feature = "regex")]
+impl Pattern {
+    /// Declare a new pattern, without compiling it yet.
+    #[must_use]
+    pub const fn new(source: &'static str) -> Self {
+        Self {
+            source,
+            regex: std::sync::OnceLock::new(),
+        }
+    }
+
+    /// Check whether the value matches the regular expression.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the regular expression is invalid, which the schema compiler already rules
+    /// out for declared patterns.
+    pub fn is_match(&self, value: &str) -> bool {
+        self.regex
+            .get_or_init(|| regex::Regex::new(self.source).expect("pattern should be valid"))
+            .is_match(value)
+    }
+}
+
+/// Stand-in for the `Pattern` type without the `regex` feature, that turns any declared pattern
+/// into a compile error with a clear message, instead of an unresolved import.
+#[cfg(not(feature = "regex"))]
+#[doc(hidden)]
+pub struct Pattern(());
+
+#[cfg(not(feature = "regex"))]
+impl Pattern {
+    /// Fail the constant evaluation of the pattern.
+    ///
+    /// # Panics
+    ///
+    /// Will always panic, which is reported while compiling the generated code.
+    #[must_use]
+    pub const fn new(_source: &'static str) -> Self {
+        panic!("the `pattern` validation rule requires the `regex` feature of the `mabo` crate");
+    }
+}
+
+/// Ensure a string matches the regular expression of a `pattern` rule.
+///
+/// # Errors
+///
+/// Will return `Err` if the value doesn't match the pattern.
+#[cfg(feature = "regex")]
+pub fn pattern(field: &'static str, value: &str, pattern: &Pattern) -> Result<(), ValidationError> {
+    if !pattern.is_match(value) {
+        return Err(ValidationError::new(
+            field,
+            Violation::Pattern(pattern.source),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Stand-in for the `pattern` check without the `regex` feature, which is never reached as the
+/// [`Pattern`] already fails to compile.
+#[cfg(not(feature = "regex"))]
+#[doc(hidden)]
+pub fn pattern(
+    _field: &'static str,
+    _value: &str,
+    _pattern: &Pattern,
+) -> Result<(), ValidationError> {
+    Ok(())
+}
+
+macro_rules! validate_none {
+    ($($ty:ty),+ $(,)?) => {
+        $(
+            impl Validate for $ty {
+                #[inline(always)]
+                fn validate(&self) -> Result<(), ValidationError> {
+                    Ok(())
+                }
+            }
+        )+
+    };
+}
+
+validate_none!(
+    bool,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    f32,
+    f64,
+    String,
+    str,
+    [u8],
+    Bytes,
+    NonZeroU8,
+    NonZeroU16,
+    NonZeroU32,
+    NonZeroU64,
+    NonZeroU128,
+    NonZeroI8,
+    NonZeroI16,
+    NonZeroI32,
+    NonZeroI64,
+    NonZeroI128,
+);
+
+impl<T: Validate + ?Sized> Validate for &T {
+    fn validate(&self) -> Result<(), ValidationError> {
+        (**self).validate()
+    }
+}
+
+impl<T: Validate + ?Sized> Validate for Box<T> {
+    fn validate(&self) -> Result<(), ValidationError> {
+        (**self).validate()
+    }
+}
+
+impl<T: Validate> Validate for Option<T> {
+    fn validate(&self) -> Result<(), ValidationError> {
+        self.as_ref().map_or(Ok(()), Validate::validate)
+    }
+}
+
+impl<T: Validate> Validate for NonZero<T> {
+    fn validate(&self) -> Result<(), ValidationError> {
+        self.get().validate()
+    }
+}
+
+impl<T: Validate> Validate for Vec<T> {
+    fn validate(&self) -> Result<(), ValidationError> {
+        self.iter().try_for_each(Validate::validate)
+    }
+}
+
+impl<T: Validate, const N: usize> Validate for [T; N] {
+    fn validate(&self) -> Result<(), ValidationError> {
+        self.iter().try_for_each(Validate::validate)
+    }
+}
+
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+impl<K: Validate, V: Validate, S> Validate for HashMap<K, V, S> {
+    fn validate(&self) -> Result<(), ValidationError> {
+        self.iter().try_for_each(|(k, v)| {
+            k.validate()?;
+            v.validate()
+        })
+    }
+}
+
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+impl<T: Validate, S> Validate for HashSet<T, S> {
+    fn validate(&self) -> Result<(), ValidationError> {
+        self.iter().try_for_each(Validate::validate)
+    }
+}
+
+impl<K: Validate, V: Validate> Validate for BTreeMap<K, V> {
+    fn validate(&self) -> Result<(), ValidationError> {
+        self.iter().try_for_each(|(k, v)| {
+            k.validate()?;
+            v.validate()
+        })
+    }
+}
+
+impl<T: Validate> Validate for BTreeSet<T> {
+    fn validate(&self) -> Result<(), ValidationError> {
+        self.iter().try_for_each(Validate::validate)
+    }
+}
+
+macro_rules! validate_tuple {
+    ($($ty:ident: $idx:tt),+) => {
+        impl<$($ty: Validate),+> Validate for ($($ty,)+) {
+            fn validate(&self) -> Result<(), ValidationError> {
+                $(self.$idx.validate()?;)+
+                Ok(())
+            }
+        }
+    };
+}
+
+validate_tuple!(T0: 0, T1: 1);
+validate_tuple!(T0: 0, T1: 1, T2: 2);
+validate_tuple!(T0: 0, T1: 1, T2: 2, T3: 3);
+validate_tuple!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4);
+validate_tuple!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5);
+validate_tuple!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5, T6: 6);
+validate_tuple!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5, T6: 6, T7: 7);
+validate_tuple!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5, T6: 6, T7: 7, T8: 8);
+validate_tuple!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5, T6: 6, T7: 7, T8: 8, T9: 9);
+validate_tuple!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5, T6: 6, T7: 7, T8: 8, T9: 9, T10: 10);
+validate_tuple!(
+    T0: 0,
+    T1: 1,
+    T2: 2,
+    T3: 3,
+    T4: 4,
+    T5: 5,
+    T6: 6,
+    T7: 7,
+    T8: 8,
+    T9: 9,
+    T10: 10,
+    T11: 11
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Inner {
+        count: u32,
+    }
+
+    impl Validate for Inner {
+        fn validate(&self) -> Result<(), ValidationError> {
+            max("count", self.count, 10)
+        }
+    }
+
+    #[test]
+    fn nested_path() {
+        let value = vec![Some(Inner { count: 5 }), None, Some(Inner { count: 11 })];
+        let err = value.validate().unwrap_err().within("inner");
+
+        assert_eq!(vec!["inner", "count"], err.path);
+        assert_eq!(Violation::Max(Bound::Int(10)), err.violation);
+        assert_eq!(
+            "field `inner.count` is invalid: value is larger than the maximum of 10",
+            err.to_string()
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn pattern_match() {
+        static PATTERN: Pattern = Pattern::new("^[a-z]+$");
+
+        assert!(pattern("name", "mabo", &PATTERN).is_ok());
+        assert_eq!(
+            Violation::Pattern("^[a-z]+$"),
+            pattern("name", "Mabo", &PATTERN).unwrap_err().violation
+        );
+    }
+}
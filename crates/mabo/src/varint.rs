@@ -1,6 +1,6 @@
 //! Encoding and decoding for variable integers.
 
-use thiserror::Error;
+use core::fmt;
 
 macro_rules! zigzag {
     ($from:ty, $to:ty) => {
@@ -49,12 +49,12 @@ zigzag!(
 /// Calculate the maximum amount of bytes that an integer might require to be encoded as _varint_.
 #[inline]
 const fn max_size<T>() -> usize {
-    (std::mem::size_of::<T>() * 8 + 6) / 7
+    (core::mem::size_of::<T>() * 8 + 6) / 7
 }
 
 #[inline]
 const fn size<T>(leading_zeros: usize) -> usize {
-    max(1, (std::mem::size_of::<T>() * 8 - leading_zeros + 6) / 7)
+    max(1, (core::mem::size_of::<T>() * 8 - leading_zeros + 6) / 7)
 }
 
 #[inline]
@@ -192,10 +192,18 @@ macro_rules! varint {
 varint!((u16, i16), (u32, i32), (u64, i64), (u128, i128));
 
 /// Error that can happen when trying to decode a _Varint_ back into a regular integer.
-#[derive(Debug, Error)]
-#[error("input was lacking a final marker for the end of the integer data")]
+#[derive(Debug)]
 pub struct DecodeIntError;
 
+impl fmt::Display for DecodeIntError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("input was lacking a final marker for the end of the integer data")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeIntError {}
+
 #[cfg(test)]
 mod tests {
     #[test]